@@ -0,0 +1,346 @@
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+
+/// 一括生成の入力のエラー
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError {
+    #[error("Invalid batch input at line {0}: {1}")]
+    Parse(usize, String),
+    #[error("Failed to access batch manifest: {0}")]
+    Io(String),
+}
+
+/// 一括生成の1件（JSONLの1行、CSVの1行）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchItem {
+    /// 再開時に同じ項目を識別するID（省略時は内容から決める）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<String>,
+    /// 保存するファイル名（出力ディレクトリが設定されている場合）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+impl BatchItem {
+    pub fn new(prompt: String) -> Self {
+        Self {
+            id: None,
+            prompt,
+            model: None,
+            aspect_ratio: None,
+            image_size: None,
+            filename: None,
+        }
+    }
+
+    /// 内容から決まる識別子（IDがあればID）
+    fn content_key(&self) -> String {
+        if let Some(id) = &self.id {
+            return id.clone();
+        }
+        let canonical = serde_json::json!(self).to_string();
+        let mut key = crate::domain::models::sha256_hex(canonical.as_bytes());
+        key.truncate(16);
+        key
+    }
+}
+
+/// 項目ごとの再開用のキーを付ける（同じ内容の項目は出現順の番号で区別する）
+pub fn item_keys(items: &[BatchItem]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    items
+        .iter()
+        .map(|item| {
+            let key = item.content_key();
+            let count = seen.entry(key.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                key
+            } else {
+                format!("{}#{}", key, count)
+            }
+        })
+        .collect()
+}
+
+/// JSONL（1行に1つのオブジェクト、または文字列のプロンプト）を読み込む
+pub fn parse_jsonl(input: &str) -> Result<Vec<BatchItem>, BatchError> {
+    strip_bom(input)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let value: serde_json::Value =
+                serde_json::from_str(line).map_err(|e| BatchError::Parse(i + 1, e.to_string()))?;
+            item_from_value(value).map_err(|e| BatchError::Parse(i + 1, e))
+        })
+        .collect()
+}
+
+/// JSONの値（オブジェクト、または文字列のプロンプト）から項目を作成
+pub fn item_from_value(value: serde_json::Value) -> Result<BatchItem, String> {
+    let item = match value {
+        serde_json::Value::String(prompt) => BatchItem::new(prompt),
+        value => serde_json::from_value(value).map_err(|e| e.to_string())?,
+    };
+    if item.prompt.trim().is_empty() {
+        return Err("prompt is empty".to_string());
+    }
+    Ok(item)
+}
+
+/// CSV（1行目は見出し、`prompt`列は必須）を読み込む
+///
+/// `id`・`model`・`aspect_ratio`・`image_size`・`filename`列は任意で、空欄は指定なしとして扱う。
+pub fn parse_csv(input: &str) -> Result<Vec<BatchItem>, BatchError> {
+    let mut records = csv_records(strip_bom(input))?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let prompt_column =
+        column("prompt").ok_or_else(|| BatchError::Parse(1, "missing prompt column".into()))?;
+
+    records
+        .filter(|(_, fields)| fields.iter().any(|f| !f.trim().is_empty()))
+        .map(|(line, fields)| {
+            let field = |name: &str| {
+                column(name)
+                    .and_then(|i| fields.get(i))
+                    .map(|f| f.trim().to_string())
+                    .filter(|f| !f.is_empty())
+            };
+            let prompt = fields
+                .get(prompt_column)
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .ok_or_else(|| BatchError::Parse(line, "prompt is empty".into()))?;
+            Ok(BatchItem {
+                id: field("id"),
+                prompt,
+                model: field("model"),
+                aspect_ratio: field("aspect_ratio"),
+                image_size: field("image_size"),
+                filename: field("filename"),
+            })
+        })
+        .collect()
+}
+
+/// 先頭のUTF-8のBOM（Excelなどが付ける）を取り除く
+fn strip_bom(input: &str) -> &str {
+    input.strip_prefix('\u{feff}').unwrap_or(input)
+}
+
+/// CSVを（開始行番号, フィールド）のレコードに分割する（引用符内のカンマ・改行・`""`に対応）
+fn csv_records(input: &str) -> Result<Vec<(usize, Vec<String>)>, BatchError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                fields.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut fields)));
+                line += 1;
+                record_line = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if in_quotes {
+        return Err(BatchError::Parse(record_line, "unterminated quote".into()));
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((record_line, fields));
+    }
+    Ok(records)
+}
+
+/// 項目の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Succeeded,
+    Failed,
+}
+
+/// 一括生成の項目ごとの結果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    /// 再開時に項目を識別するキー
+    pub key: String,
+    pub prompt: String,
+    pub status: BatchItemStatus,
+    /// 生成結果（モデル・保存先など）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 実行した回数（再開で失敗した項目をやり直した場合は増える）
+    pub attempts: u32,
+}
+
+impl BatchItemResult {
+    pub fn succeeded(item: &BatchItem, output: serde_json::Value) -> Self {
+        Self::new(item, BatchItemStatus::Succeeded, Some(output), None)
+    }
+
+    pub fn failed(item: &BatchItem, error: String) -> Self {
+        Self::new(item, BatchItemStatus::Failed, None, Some(error))
+    }
+
+    fn new(
+        item: &BatchItem,
+        status: BatchItemStatus,
+        output: Option<serde_json::Value>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            index: 0,
+            key: String::new(),
+            prompt: item.prompt.clone(),
+            status,
+            output,
+            error,
+            attempts: 1,
+        }
+    }
+}
+
+/// 一括生成の結果の一覧（ファイルに保存して途中から再開できる）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchManifest {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItemResult>,
+    /// Batch APIで送信したバッチの名前（`batch --gemini-batch`を再実行した場合は送信し直さずに終了を待つ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gemini_batch: Option<String>,
+}
+
+impl BatchManifest {
+    /// ファイルから読み込む（ファイルがない場合は空）
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BatchError> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| BatchError::Io(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(BatchError::Io(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// ファイルに保存する（書き込み途中で中断しても壊れないよう一時ファイルから置き換える）
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BatchError> {
+        let path = path.as_ref();
+        let io_error = |e: std::io::Error| BatchError::Io(format!("{}: {}", path.display(), e));
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| BatchError::Io(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(io_error)?;
+        std::fs::rename(&temp, path).map_err(io_error)
+    }
+
+    /// 結果を追加し、件数を数える（項目の順に並べるのは最後に1回だけ行う）
+    fn push(&mut self, result: BatchItemResult) {
+        match result.status {
+            BatchItemStatus::Succeeded => self.succeeded += 1,
+            BatchItemStatus::Failed => self.failed += 1,
+        }
+        self.items.push(result);
+    }
+}
+
+/// 項目を上限までの並列数で実行し、結果の一覧を返す
+///
+/// `previous`で成功している項目は実行せずに結果を引き継ぐ（失敗した項目はやり直す）。
+/// 1件の失敗で全体を止めることはない。項目が終わるたびに`on_progress`を呼ぶ（途中経過の保存用）。
+pub async fn run_batch<F, Fut>(
+    items: Vec<BatchItem>,
+    previous: BatchManifest,
+    concurrency: usize,
+    generate: F,
+    mut on_progress: impl FnMut(&BatchManifest),
+) -> BatchManifest
+where
+    F: Fn(BatchItem) -> Fut,
+    Fut: Future<Output = BatchItemResult>,
+{
+    let keys = item_keys(&items);
+    let mut manifest = BatchManifest {
+        total: items.len(),
+        ..BatchManifest::default()
+    };
+    let previous: HashMap<String, BatchItemResult> = previous
+        .items
+        .into_iter()
+        .map(|r| (r.key.clone(), r))
+        .collect();
+    let mut pending = Vec::new();
+    for (index, (item, key)) in items.into_iter().zip(keys).enumerate() {
+        match previous.get(&key) {
+            Some(done) if done.status == BatchItemStatus::Succeeded => {
+                manifest.push(BatchItemResult {
+                    index,
+                    ..done.clone()
+                })
+            }
+            other => {
+                let attempts = other.map(|r| r.attempts).unwrap_or_default();
+                pending.push((index, key, attempts, item));
+            }
+        }
+    }
+
+    let generate = &generate;
+    let mut results = stream::iter(pending)
+        .map(|(index, key, attempts, item)| async move {
+            BatchItemResult {
+                index,
+                key,
+                attempts: attempts + 1,
+                ..generate(item).await
+            }
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = results.next().await {
+        manifest.push(result);
+        on_progress(&manifest);
+    }
+    manifest.items.sort_by_key(|r| r.index);
+    manifest
+}
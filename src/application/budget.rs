@@ -0,0 +1,459 @@
+use crate::application::usage::ModelPrice;
+use crate::domain::{GeneratedImage, GenerationOutcome, GenerationRecord, ImageGenerationRequest};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// 期間ごとの利用の上限（`None`の項目は制限しない）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BudgetLimits {
+    /// 1日（UTC）の推定料金の上限（米ドル）
+    pub daily_usd: Option<f64>,
+    /// 1か月（UTC）の推定料金の上限（米ドル）
+    pub monthly_usd: Option<f64>,
+    /// 1日（UTC）の生成枚数の上限
+    pub daily_images: Option<u64>,
+    /// 1か月（UTC）の生成枚数の上限
+    pub monthly_images: Option<u64>,
+}
+
+impl BudgetLimits {
+    /// 上限が設定されていないか
+    pub fn is_unlimited(&self) -> bool {
+        self.daily_usd.is_none()
+            && self.monthly_usd.is_none()
+            && self.daily_images.is_none()
+            && self.monthly_images.is_none()
+    }
+
+    /// 設定された上限の一覧
+    fn caps(&self) -> Vec<Cap> {
+        [
+            (Period::Daily, Metric::Usd, self.daily_usd),
+            (Period::Monthly, Metric::Usd, self.monthly_usd),
+            (
+                Period::Daily,
+                Metric::Images,
+                self.daily_images.map(|n| n as f64),
+            ),
+            (
+                Period::Monthly,
+                Metric::Images,
+                self.monthly_images.map(|n| n as f64),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(period, metric, limit)| {
+            limit.map(|limit| Cap {
+                period,
+                metric,
+                limit,
+            })
+        })
+        .collect()
+    }
+}
+
+/// 予算の設定
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BudgetPolicy {
+    /// サーバー全体の上限
+    pub global: BudgetLimits,
+    /// クライアントごとの上限
+    pub per_client: BudgetLimits,
+    /// 警告を出す上限に対する割合（例: `0.8`）
+    pub warning_thresholds: Vec<f64>,
+}
+
+impl BudgetPolicy {
+    /// 上限が設定されていないか
+    pub fn is_unlimited(&self) -> bool {
+        self.global.is_unlimited() && self.per_client.is_unlimited()
+    }
+}
+
+/// 上限に近づいたことの警告
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetWarning {
+    /// `global`または`client:<クライアント名>`
+    pub scope: String,
+    /// `daily`または`monthly`
+    pub period: &'static str,
+    /// `usd`または`images`
+    pub metric: &'static str,
+    pub used: f64,
+    pub limit: f64,
+    /// 超えたしきい値（上限に対する割合）
+    pub threshold: f64,
+}
+
+impl std::fmt::Display for BudgetWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} budget for {} is at {:.0}% ({} of {})",
+            self.period,
+            self.metric,
+            self.scope,
+            self.used / self.limit * 100.0,
+            format_amount(self.metric, self.used),
+            format_amount(self.metric, self.limit)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Daily,
+    Monthly,
+}
+
+impl Period {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    /// 集計のキー（UTCの日付または年月）
+    fn key(self, now: DateTime<Utc>) -> String {
+        match self {
+            Self::Daily => now.format("%Y-%m-%d").to_string(),
+            Self::Monthly => now.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Usd,
+    Images,
+}
+
+impl Metric {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Usd => "usd",
+            Self::Images => "images",
+        }
+    }
+}
+
+struct Cap {
+    period: Period,
+    metric: Metric,
+    limit: f64,
+}
+
+/// 期間内の利用量
+#[derive(Debug, Clone, Copy, Default)]
+struct Spend {
+    images: u64,
+    cost_usd: f64,
+}
+
+impl Spend {
+    fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Usd => self.cost_usd,
+            Metric::Images => self.images as f64,
+        }
+    }
+
+    fn add(&mut self, other: Spend) {
+        self.images += other.images;
+        self.cost_usd += other.cost_usd;
+    }
+
+    fn subtract(&mut self, other: Spend) {
+        self.images = self.images.saturating_sub(other.images);
+        self.cost_usd = (self.cost_usd - other.cost_usd).max(0.0);
+    }
+}
+
+type WarningHandler = Box<dyn Fn(&BudgetWarning) + Send + Sync>;
+
+/// 推定料金と生成枚数を期間ごとに集計し、上限を超えた後の生成を拒否する
+///
+/// 上限はサーバー全体とクライアントごとに適用する。APIを呼ばずに返した画像（キャッシュなど）は数えない。
+/// 生成前に[`BudgetGuard::reserve`]で見込みの利用量を確保するため、同時に送られたリクエストも上限を超えない。
+pub struct BudgetGuard {
+    policy: BudgetPolicy,
+    prices: HashMap<String, ModelPrice>,
+    /// （スコープ, 期間のキー）ごとの利用量
+    spend: Mutex<HashMap<(String, String), Spend>>,
+    on_warning: Option<WarningHandler>,
+}
+
+impl BudgetGuard {
+    pub fn new(policy: BudgetPolicy, prices: HashMap<String, ModelPrice>) -> Self {
+        Self {
+            policy,
+            prices,
+            spend: Mutex::new(HashMap::new()),
+            on_warning: None,
+        }
+    }
+
+    /// しきい値を超えた場合に呼ぶ処理を設定
+    pub fn with_warning_handler(
+        mut self,
+        handler: impl Fn(&BudgetWarning) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_warning = Some(Box::new(handler));
+        self
+    }
+
+    /// 上限に達していないか確認（達している場合は理由を返す）
+    pub fn check(&self, request: &ImageGenerationRequest) -> Result<(), String> {
+        let spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        self.check_locked(&spend, request, Utc::now())
+    }
+
+    /// 上限に達していないか確認し、見込みの利用量を確保する（確保した分は生成の終了まで使用済みとして数える）
+    pub fn reserve(
+        self: &Arc<Self>,
+        request: &ImageGenerationRequest,
+    ) -> Result<BudgetReservation, String> {
+        let now = Utc::now();
+        let scopes = self.scopes(request);
+        let amount = Spend {
+            images: 1,
+            cost_usd: ModelPrice::for_model(&request.model, &self.prices).estimate_request(),
+        };
+
+        let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        self.check_locked(&spend, request, now)?;
+        Self::retain_current(&mut spend, now);
+        for (scope, _) in &scopes {
+            for period in [Period::Daily, Period::Monthly] {
+                spend
+                    .entry((scope.clone(), period.key(now)))
+                    .or_default()
+                    .add(amount);
+            }
+        }
+        Ok(BudgetReservation {
+            guard: Arc::clone(self),
+            scopes,
+            reserved_at: now,
+            amount,
+            settled: false,
+        })
+    }
+
+    /// 過去の生成の記録から今日・今月の利用量を復元する（起動時）
+    pub fn restore<'a>(&self, records: impl IntoIterator<Item = &'a GenerationRecord>) {
+        let now = Utc::now();
+        let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        for record in records {
+            let Some(cost_usd) = record
+                .estimated_cost_usd
+                .filter(|_| record.outcome == GenerationOutcome::Succeeded)
+            else {
+                continue;
+            };
+            let request = record.to_request();
+            for (scope, _) in self.scopes(&request) {
+                for period in [Period::Daily, Period::Monthly] {
+                    let key = period.key(record.created_at);
+                    if key != period.key(now) {
+                        continue;
+                    }
+                    spend.entry((scope.clone(), key)).or_default().add(Spend {
+                        images: 1,
+                        cost_usd,
+                    });
+                }
+            }
+        }
+    }
+
+    fn check_locked(
+        &self,
+        spend: &HashMap<(String, String), Spend>,
+        request: &ImageGenerationRequest,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        for (scope, limits) in self.scopes(request) {
+            for cap in limits.caps() {
+                let used = spend
+                    .get(&(scope.clone(), cap.period.key(now)))
+                    .map(|s| s.get(cap.metric))
+                    .unwrap_or_default();
+                if used >= cap.limit {
+                    return Err(format!(
+                        "{} {} cap for {} reached ({} of {})",
+                        cap.period.name(),
+                        cap.metric.name(),
+                        scope,
+                        format_amount(cap.metric.name(), used),
+                        format_amount(cap.metric.name(), cap.limit)
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 生成結果を集計に加え、しきい値を超えた場合は警告を返す
+    pub fn record(
+        &self,
+        request: &ImageGenerationRequest,
+        image: &GeneratedImage,
+    ) -> Vec<BudgetWarning> {
+        if !image.is_billable() {
+            return Vec::new();
+        }
+        let now = Utc::now();
+        let warnings = {
+            let mut spend = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+            Self::retain_current(&mut spend, now);
+            self.apply(
+                &mut spend,
+                &self.scopes(request),
+                now,
+                Spend::default(),
+                self.spend_of(image),
+            )
+        };
+
+        if let Some(handler) = &self.on_warning {
+            warnings.iter().for_each(handler);
+        }
+        warnings
+    }
+
+    /// 生成1回の利用量（APIを呼んでいない場合は0）
+    fn spend_of(&self, image: &GeneratedImage) -> Spend {
+        if !image.is_billable() {
+            return Spend::default();
+        }
+        Spend {
+            images: 1,
+            cost_usd: ModelPrice::for_model(&image.model, &self.prices).estimate(image),
+        }
+    }
+
+    /// 過ぎた期間の集計は不要
+    fn retain_current(spend: &mut HashMap<(String, String), Spend>, now: DateTime<Utc>) {
+        let current = [Period::Daily.key(now), Period::Monthly.key(now)];
+        spend.retain(|(_, key), _| current.contains(key));
+    }
+
+    /// 集計から`removed`を引いて`added`を加え、しきい値を超えた場合は警告を返す
+    fn apply(
+        &self,
+        spend: &mut HashMap<(String, String), Spend>,
+        scopes: &[(String, BudgetLimits)],
+        at: DateTime<Utc>,
+        removed: Spend,
+        added: Spend,
+    ) -> Vec<BudgetWarning> {
+        let mut warnings = Vec::new();
+        for (scope, limits) in scopes {
+            for period in [Period::Daily, Period::Monthly] {
+                let entry = spend.entry((scope.clone(), period.key(at))).or_default();
+                entry.subtract(removed);
+                let before = *entry;
+                entry.add(added);
+
+                for cap in limits.caps().into_iter().filter(|c| c.period == period) {
+                    let (used_before, used) = (before.get(cap.metric), entry.get(cap.metric));
+                    // 一度に複数のしきい値を超えた場合は最も高いものだけを警告する
+                    let crossed = self
+                        .policy
+                        .warning_thresholds
+                        .iter()
+                        .copied()
+                        .filter(|t| used_before < t * cap.limit && t * cap.limit <= used)
+                        .reduce(f64::max);
+                    if let Some(threshold) = crossed {
+                        warnings.push(BudgetWarning {
+                            scope: scope.clone(),
+                            period: period.name(),
+                            metric: cap.metric.name(),
+                            used,
+                            limit: cap.limit,
+                            threshold,
+                        });
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// リクエストに適用するスコープと上限
+    fn scopes(&self, request: &ImageGenerationRequest) -> Vec<(String, BudgetLimits)> {
+        let mut scopes = vec![("global".to_string(), self.policy.global)];
+        if let Some(client_id) = request
+            .client_id
+            .as_ref()
+            .filter(|_| !self.policy.per_client.is_unlimited())
+        {
+            scopes.push((format!("client:{}", client_id), self.policy.per_client));
+        }
+        scopes
+    }
+}
+
+/// [`BudgetGuard::reserve`]で確保した利用量
+///
+/// 生成に成功したら[`BudgetReservation::commit`]で実際の利用量に置き換える。
+/// コミットせずに破棄した場合（生成の失敗など）は確保した分を戻す。
+pub struct BudgetReservation {
+    guard: Arc<BudgetGuard>,
+    scopes: Vec<(String, BudgetLimits)>,
+    reserved_at: DateTime<Utc>,
+    amount: Spend,
+    settled: bool,
+}
+
+impl BudgetReservation {
+    /// 確保した分を生成結果の利用量に置き換え、しきい値を超えた場合は警告を返す
+    pub fn commit(mut self, image: &GeneratedImage) -> Vec<BudgetWarning> {
+        self.settled = true;
+        let guard = &self.guard;
+        let warnings = {
+            let mut spend = guard.spend.lock().unwrap_or_else(|e| e.into_inner());
+            guard.apply(
+                &mut spend,
+                &self.scopes,
+                self.reserved_at,
+                self.amount,
+                guard.spend_of(image),
+            )
+        };
+        if let Some(handler) = &guard.on_warning {
+            warnings.iter().for_each(handler);
+        }
+        warnings
+    }
+}
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        let mut spend = self.guard.spend.lock().unwrap_or_else(|e| e.into_inner());
+        for (scope, _) in &self.scopes {
+            for period in [Period::Daily, Period::Monthly] {
+                if let Some(entry) = spend.get_mut(&(scope.clone(), period.key(self.reserved_at))) {
+                    entry.subtract(self.amount);
+                }
+            }
+        }
+    }
+}
+
+fn format_amount(metric: &str, value: f64) -> String {
+    if metric == "usd" {
+        format!("${:.2}", value)
+    } else {
+        format!("{}", value)
+    }
+}
@@ -1,3 +1,9 @@
+pub mod batch;
+pub mod budget;
+pub mod usage;
 pub mod use_cases;
 
+pub use batch::{BatchItem, BatchItemResult, BatchItemStatus, BatchManifest};
+pub use budget::{BudgetGuard, BudgetLimits, BudgetPolicy, BudgetReservation, BudgetWarning};
+pub use usage::{ModelPrice, UsageReport, UsageTotals, UsageTracker};
 pub use use_cases::GenerateImageUseCase;
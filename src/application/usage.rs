@@ -0,0 +1,172 @@
+use crate::domain::{GeminiModel, GeneratedImage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// 生成前に見込む画像1枚の出力トークン数（1024×1024の画像）
+const EXPECTED_IMAGE_OUTPUT_TOKENS: u64 = 1290;
+
+/// モデルの料金（米ドル）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// 入力100万トークンあたり
+    #[serde(default)]
+    pub input_per_million_tokens: f64,
+    /// 出力100万トークンあたり（画像の出力トークンを含む）
+    #[serde(default)]
+    pub output_per_million_tokens: f64,
+    /// 画像1枚あたり（トークン数を返さないImagenなど）
+    #[serde(default)]
+    pub per_image: f64,
+}
+
+impl ModelPrice {
+    /// モデルの料金を取得（料金表 → 組み込みの料金の順、未知のモデルは0）
+    pub fn for_model(model: &GeminiModel, prices: &HashMap<String, ModelPrice>) -> Self {
+        prices
+            .get(model.as_str())
+            .copied()
+            .or_else(|| Self::builtin(model))
+            .unwrap_or_default()
+    }
+
+    /// 既知のモデルファミリーの組み込みの料金（概算）
+    fn builtin(model: &GeminiModel) -> Option<Self> {
+        let name = model.as_str();
+        if name.starts_with("gemini-3-pro-image") {
+            Some(Self {
+                input_per_million_tokens: 2.0,
+                output_per_million_tokens: 120.0,
+                per_image: 0.0,
+            })
+        } else if name.contains("flash-image") {
+            Some(Self {
+                input_per_million_tokens: 0.3,
+                output_per_million_tokens: 30.0,
+                per_image: 0.0,
+            })
+        } else if model.is_imagen() {
+            Some(Self {
+                per_image: 0.04,
+                ..Self::default()
+            })
+        } else {
+            None
+        }
+    }
+
+    /// 生成前に見込む1回の料金（出力トークン数は画像1枚分の目安を使う）
+    pub fn estimate_request(&self) -> f64 {
+        EXPECTED_IMAGE_OUTPUT_TOKENS as f64 * self.output_per_million_tokens / 1_000_000.0
+            + self.per_image
+    }
+
+    /// 生成1回の推定料金
+    pub fn estimate(&self, image: &GeneratedImage) -> f64 {
+        let usage = image.usage.unwrap_or_default();
+        usage.prompt_tokens as f64 * self.input_per_million_tokens / 1_000_000.0
+            + usage.candidate_tokens as f64 * self.output_per_million_tokens / 1_000_000.0
+            + self.per_image
+    }
+}
+
+/// 利用量の合計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub images: u64,
+    pub prompt_tokens: u64,
+    pub candidate_tokens: u64,
+    pub total_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, entry: &UsageTotals) {
+        self.images += entry.images;
+        self.prompt_tokens += entry.prompt_tokens;
+        self.candidate_tokens += entry.candidate_tokens;
+        self.total_tokens += entry.total_tokens;
+        self.estimated_cost_usd += entry.estimated_cost_usd;
+    }
+}
+
+/// 利用量のレポート
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageReport {
+    /// サーバー起動からの合計
+    pub session: UsageTotals,
+    /// 認証情報ごと（APIキーは末尾4文字、Vertex AIはサービスアカウント）
+    pub by_api_key: BTreeMap<String, UsageTotals>,
+    /// モデルごと
+    pub by_model: BTreeMap<String, UsageTotals>,
+    /// 日ごと（UTC、`YYYY-MM-DD`）
+    pub by_day: BTreeMap<String, UsageTotals>,
+}
+
+/// 生成ごとのトークン数と推定料金を集計する
+///
+/// APIを呼ばずに返した画像（キャッシュなど）は数えない。
+pub struct UsageTracker {
+    prices: HashMap<String, ModelPrice>,
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    /// 料金表を指定して作成（指定のないモデルは組み込みの料金、未知のモデルは0）
+    pub fn new(prices: HashMap<String, ModelPrice>) -> Self {
+        Self {
+            prices,
+            report: Mutex::new(UsageReport::default()),
+        }
+    }
+
+    /// モデルの料金を取得
+    pub fn price(&self, model: &GeminiModel) -> ModelPrice {
+        ModelPrice::for_model(model, &self.prices)
+    }
+
+    /// 生成結果を集計に加え、その生成の利用量を返す（APIを呼んでいない場合は`None`）
+    pub fn record(&self, image: &GeneratedImage) -> Option<UsageTotals> {
+        if !image.is_billable() {
+            return None;
+        }
+
+        let usage = image.usage.unwrap_or_default();
+        let entry = UsageTotals {
+            images: 1,
+            prompt_tokens: usage.prompt_tokens,
+            candidate_tokens: usage.candidate_tokens,
+            total_tokens: usage.total_tokens,
+            estimated_cost_usd: self.price(&image.model).estimate(image),
+        };
+
+        let mut report = self.report.lock().unwrap_or_else(|e| e.into_inner());
+        report.session.add(&entry);
+        if let Some(api_key) = &image.api_key {
+            report
+                .by_api_key
+                .entry(api_key.clone())
+                .or_default()
+                .add(&entry);
+        }
+        report
+            .by_model
+            .entry(image.model.to_string())
+            .or_default()
+            .add(&entry);
+        report
+            .by_day
+            .entry(image.generated_at.format("%Y-%m-%d").to_string())
+            .or_default()
+            .add(&entry);
+        Some(entry)
+    }
+
+    /// 現在までの集計を取得
+    pub fn report(&self) -> UsageReport {
+        self.report
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
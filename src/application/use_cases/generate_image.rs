@@ -1,6 +1,8 @@
+use crate::application::budget::BudgetGuard;
 use crate::domain::{
     ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest, ValidationError,
 };
+use std::sync::Arc;
 
 /// 画像生成ユースケース
 pub struct GenerateImageUseCase<R>
@@ -8,6 +10,7 @@ where
     R: ImageGenerationRepository,
 {
     repository: R,
+    budget: Option<Arc<BudgetGuard>>,
 }
 
 impl<R> GenerateImageUseCase<R>
@@ -15,7 +18,21 @@ where
     R: ImageGenerationRepository,
 {
     pub fn new(repository: R) -> Self {
-        Self { repository }
+        Self {
+            repository,
+            budget: None,
+        }
+    }
+
+    /// 予算の上限を設定する
+    pub fn with_budget(mut self, budget: Arc<BudgetGuard>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// 設定された予算の上限（Batch APIなど、このユースケースを通さない生成の集計に使う）
+    pub fn budget(&self) -> Option<&Arc<BudgetGuard>> {
+        self.budget.as_ref()
     }
 
     /// 画像を生成する
@@ -26,11 +43,25 @@ where
         // バリデーション
         request.validate().map_err(UseCaseError::Validation)?;
 
+        // 予算の上限に達している場合はAPIを呼ばない（失敗した場合は確保した分を戻す）
+        let reservation = self
+            .budget
+            .as_ref()
+            .map(|budget| budget.reserve(&request))
+            .transpose()
+            .map_err(UseCaseError::BudgetExceeded)?;
+
         // リポジトリを通じて画像生成
-        self.repository
+        let image = self
+            .repository
             .generate_image(&request)
             .await
-            .map_err(UseCaseError::Repository)
+            .map_err(UseCaseError::Repository)?;
+
+        if let Some(reservation) = reservation {
+            reservation.commit(&image);
+        }
+        Ok(image)
     }
 }
 
@@ -41,4 +72,17 @@ pub enum UseCaseError {
     Validation(#[from] ValidationError),
     #[error("Repository error: {0}")]
     Repository(#[from] ImageGenerationError),
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+}
+
+impl UseCaseError {
+    /// エラーの種類（履歴の記録や集計に使用）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Validation(_) => "validation",
+            Self::Repository(e) => e.kind(),
+            Self::BudgetExceeded(_) => "budget_exceeded",
+        }
+    }
 }
@@ -1,4 +1,137 @@
+use crate::application::{BudgetLimits, BudgetPolicy, ModelPrice};
+use crate::domain::{CapabilityOverride, ImageGenerationError, RetentionPolicy};
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// APIキーの選び方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeySelection {
+    /// 順番に使う
+    #[default]
+    RoundRobin,
+    /// 最後にレート制限されてから最も時間が経っているキーを使う
+    LeastRecentlyThrottled,
+}
+
+impl std::str::FromStr for KeySelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "round_robin" => Ok(Self::RoundRobin),
+            "least_recently_throttled" => Ok(Self::LeastRecentlyThrottled),
+            other => Err(format!("Unknown API key selection: {}", other)),
+        }
+    }
+}
+
+/// モデルごとのレート制限（`None`の項目は制限しない）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub images_per_day: Option<u32>,
+    pub max_concurrency: Option<usize>,
+}
+
+impl RateLimits {
+    /// 制限が設定されていないか
+    pub fn is_unlimited(&self) -> bool {
+        self.requests_per_minute.is_none()
+            && self.images_per_day.is_none()
+            && self.max_concurrency.is_none()
+    }
+}
+
+/// フォールバックするエラーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FallbackTriggers {
+    /// モデルが過負荷（503）
+    pub overloaded: bool,
+    /// レート制限（429、クライアント側の制限を含む）
+    pub rate_limited: bool,
+    /// 画像が返されなかった
+    pub no_image: bool,
+}
+
+impl Default for FallbackTriggers {
+    fn default() -> Self {
+        Self {
+            overloaded: true,
+            rate_limited: true,
+            no_image: true,
+        }
+    }
+}
+
+impl FallbackTriggers {
+    /// エラーの種類（`ImageGenerationError::kind()`）のリストから作成
+    pub fn from_kinds<'a>(kinds: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut triggers = Self {
+            overloaded: false,
+            rate_limited: false,
+            no_image: false,
+        };
+        for kind in kinds {
+            match kind.trim() {
+                "overloaded" => triggers.overloaded = true,
+                "rate_limit" => triggers.rate_limited = true,
+                "no_image" => triggers.no_image = true,
+                other => warn!("Unknown model fallback trigger '{}'", other),
+            }
+        }
+        triggers
+    }
+
+    pub(crate) fn matches(&self, error: &ImageGenerationError) -> bool {
+        match error {
+            ImageGenerationError::ModelOverloaded(_) => self.overloaded,
+            ImageGenerationError::RateLimitError { .. } => self.rate_limited,
+            ImageGenerationError::NoImage(_) => self.no_image,
+            _ => false,
+        }
+    }
+}
+
+/// S3互換ストレージの設定
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// エンドポイントURL（例: `https://s3.amazonaws.com`、`http://localhost:9000`）
+    pub endpoint: String,
+    pub bucket: String,
+    /// オブジェクトキーのプレフィックス（例: `images/`）
+    pub prefix: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// パススタイル（`<endpoint>/<bucket>/<key>`）でアクセスするか
+    pub path_style: bool,
+    /// 署名付きURLの有効期限（秒、`None`の場合は発行しない）
+    pub presign_expires_secs: Option<u64>,
+}
+
+/// 画像生成に使うAPI
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiBackend {
+    /// Google AI StudioのGemini API（APIキーで認証）
+    #[default]
+    AiStudio,
+    /// Vertex AI（サービスアカウントで認証）
+    Vertex,
+}
+
+impl std::str::FromStr for ApiBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ai_studio" | "gemini" => Ok(Self::AiStudio),
+            "vertex" | "vertex_ai" => Ok(Self::Vertex),
+            other => Err(format!("Unknown backend: {}", other)),
+        }
+    }
+}
 
 /// アプリケーション設定
 pub struct Config {
@@ -6,16 +139,108 @@ pub struct Config {
     pub jsonrpc_version: String,
     /// Gemini APIベースURL
     pub gemini_api_base_url: String,
+    /// Gemini APIキー（`GEMINI_API_KEY`・`GEMINI_API_KEYS`・`GEMINI_API_KEYS_FILE`の順、重複は除く）
+    pub gemini_api_keys: Vec<String>,
+    /// 複数のAPIキーの選び方
+    pub api_key_selection: KeySelection,
+    /// 画像生成に使うAPI（AI StudioのGemini APIまたはVertex AI）
+    pub api_backend: ApiBackend,
+    /// Vertex AIの認証に使うサービスアカウントのJSONキーのパス
+    pub vertex_credentials_path: Option<PathBuf>,
+    /// Vertex AIのプロジェクトID（未設定の場合はサービスアカウントのキーの値）
+    pub vertex_project_id: Option<String>,
+    /// Vertex AIのロケーション
+    pub vertex_location: String,
+    /// Vertex AIのベースURL（未設定の場合はロケーションのエンドポイント）
+    pub vertex_api_base_url: Option<String>,
+    /// `GET /models`で利用可能なモデルを取得するか
+    pub model_discovery: bool,
+    /// 取得したモデル一覧をキャッシュする時間（秒）
+    pub model_catalog_ttl_secs: u64,
     /// デフォルトGeminiモデル名
     pub gemini_default_model: String,
     /// 許可されたGeminiモデルリスト（カンマ区切り）
     pub gemini_allowed_models: Vec<String>,
+    /// モデルごとの対応機能の上書き（`MODEL_CAPABILITIES`、モデル名をキーとするJSON）
+    pub model_capability_overrides: HashMap<String, CapabilityOverride>,
+    /// モデルごとの料金の上書き（`MODEL_PRICES`、モデル名をキーとするJSON）
+    pub model_prices: HashMap<String, ModelPrice>,
     /// プロンプトの最大長
     pub max_prompt_length: usize,
+    /// リスト系メソッド（tools/listなど）の1ページあたりの件数
+    pub list_page_size: usize,
+    /// 生成画像の保存先ディレクトリ（未設定の場合はファイルに保存しない）
+    pub image_output_dir: Option<PathBuf>,
+    /// 保存する画像ファイルにメタデータを埋め込むか（PNGのtEXt/iTXt、JPEGのXMP）
+    pub embed_image_metadata: bool,
+    /// 画像ストアのバックエンド（`fs`または`s3`）
+    pub image_store_backend: String,
+    /// 内容アドレス方式の画像ストアのディレクトリ（`fs`バックエンド、未設定の場合はストアを使用しない）
+    pub image_store_dir: Option<PathBuf>,
+    /// S3互換ストレージの設定（`s3`バックエンド）
+    pub s3: S3Settings,
+    /// 画像ストアに保持する最大日数
+    pub image_store_max_age_days: Option<u64>,
+    /// 画像ストアの合計サイズ上限（バイト）
+    pub image_store_max_bytes: Option<u64>,
+    /// 生成履歴のSQLiteデータベースのパス（未設定の場合は履歴を記録しない）
+    pub history_db_path: Option<PathBuf>,
+    /// レスポンスキャッシュの有効期限（秒、未設定の場合はキャッシュしない）
+    pub response_cache_ttl_secs: Option<u64>,
+    /// レスポンスキャッシュの合計サイズ上限（バイト）
+    pub response_cache_max_bytes: Option<u64>,
+    /// 同時に実行された同じ内容のリクエストをまとめるか
+    pub request_coalescing: bool,
+    /// クライアント側のレート制限（全モデル共通）
+    pub rate_limits: RateLimits,
+    /// モデルごとのレート制限（`RATE_LIMIT_MODEL_OVERRIDES`）
+    pub rate_limit_model_overrides: HashMap<String, RateLimits>,
+    /// レート制限に達した場合に待つ時間の上限（秒）
+    pub rate_limit_max_wait_secs: u64,
+    /// 生成できなかった場合に順に試すモデル（空の場合はフォールバックしない）
+    pub model_fallback_chain: Vec<String>,
+    /// フォールバックするエラーの種類
+    pub model_fallback_triggers: FallbackTriggers,
+    /// 同時に実行する生成ジョブの数
+    pub job_max_concurrency: usize,
+    /// 未終了（待機中・実行中）の生成ジョブの数の上限
+    pub job_max_pending: usize,
+    /// 一括生成で同時に実行する数のデフォルト
+    pub batch_concurrency: usize,
+    /// 一括生成の結果の一覧を保存するディレクトリ（未設定の場合はメモリ上のみ）
+    pub batch_manifest_dir: Option<PathBuf>,
+    /// Batch APIのバッチの終了を待つ間の状態確認の間隔（秒）
+    pub gemini_batch_poll_interval_secs: u64,
+    /// Batch APIのバッチの終了を待つ時間の上限（秒）
+    pub gemini_batch_wait_timeout_secs: u64,
+    /// Files APIでアップロードする入力画像のサイズ（バイト、これより大きい画像はbase64で埋め込まない）
+    pub file_upload_threshold_bytes: usize,
+    /// 推定料金・生成枚数の上限と警告のしきい値（`BUDGET_*`）
+    pub budget: BudgetPolicy,
     /// JSON-RPCエラーコード
     pub jsonrpc_error_codes: JsonRpcErrorCodes,
 }
 
+/// S3互換ストレージの設定（環境変数の値）
+pub struct S3Settings {
+    /// エンドポイントURL
+    pub endpoint: Option<String>,
+    /// バケット名
+    pub bucket: Option<String>,
+    /// オブジェクトキーのプレフィックス
+    pub prefix: String,
+    /// リージョン
+    pub region: String,
+    /// アクセスキーID
+    pub access_key_id: Option<String>,
+    /// シークレットアクセスキー
+    pub secret_access_key: Option<String>,
+    /// パススタイルでアクセスするか（MinIOなど）
+    pub force_path_style: bool,
+    /// 署名付きURLの有効期限（秒）
+    pub presign_expires_secs: Option<u64>,
+}
+
 /// JSON-RPCエラーコード設定
 pub struct JsonRpcErrorCodes {
     /// パースエラー
@@ -24,6 +249,8 @@ pub struct JsonRpcErrorCodes {
     pub invalid_request: i32,
     /// メソッドが見つからない
     pub method_not_found: i32,
+    /// 無効なパラメータ
+    pub invalid_params: i32,
     /// 内部エラー
     pub internal_error: i32,
 }
@@ -34,6 +261,7 @@ impl Default for JsonRpcErrorCodes {
             parse_error: -32700,
             invalid_request: -32600,
             method_not_found: -32601,
+            invalid_params: -32602,
             internal_error: -32603,
         }
     }
@@ -41,14 +269,108 @@ impl Default for JsonRpcErrorCodes {
 
 impl Config {
     /// 環境変数から設定を読み込む
+    ///
+    /// `CONFIG_FILE`が設定されている場合は、そのファイル（`KEY=VALUE`形式）の値で環境変数を上書きする。
+    /// ファイルは読み込みのたびに再読み込みされるため、実行中の設定リロードに使用できる。
     pub fn from_env() -> Self {
+        let overrides = env::var("CONFIG_FILE")
+            .ok()
+            .map(|path| load_config_file(Path::new(&path)))
+            .unwrap_or_default();
+        let var = |key: &str| match overrides.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => env::var(key),
+        };
+
+        let rate_limits = RateLimits {
+            requests_per_minute: var("RATE_LIMIT_REQUESTS_PER_MINUTE")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            images_per_day: var("RATE_LIMIT_IMAGES_PER_DAY")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            max_concurrency: var("RATE_LIMIT_MAX_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+        };
+
+        let budget_limits = |prefix: &str| BudgetLimits {
+            daily_usd: var(&format!("{}_DAILY_USD", prefix))
+                .ok()
+                .and_then(|s| s.trim().parse().ok()),
+            monthly_usd: var(&format!("{}_MONTHLY_USD", prefix))
+                .ok()
+                .and_then(|s| s.trim().parse().ok()),
+            daily_images: var(&format!("{}_DAILY_IMAGES", prefix))
+                .ok()
+                .and_then(|s| s.trim().parse().ok()),
+            monthly_images: var(&format!("{}_MONTHLY_IMAGES", prefix))
+                .ok()
+                .and_then(|s| s.trim().parse().ok()),
+        };
+        let budget = BudgetPolicy {
+            global: budget_limits("BUDGET"),
+            per_client: budget_limits("BUDGET_PER_CLIENT"),
+            // パーセントで指定する（例: `80,95`）
+            warning_thresholds: var("BUDGET_WARN_AT_PERCENT")
+                .unwrap_or_else(|_| "80".to_string())
+                .split(',')
+                .filter_map(|p| p.trim().parse::<f64>().ok())
+                .filter(|p| *p > 0.0)
+                .map(|p| p / 100.0)
+                .collect(),
+        };
+
         Self {
-            jsonrpc_version: env::var("JSONRPC_VERSION").unwrap_or_else(|_| "2.0".to_string()),
-            gemini_api_base_url: env::var("GEMINI_API_BASE_URL")
+            jsonrpc_version: var("JSONRPC_VERSION").unwrap_or_else(|_| "2.0".to_string()),
+            gemini_api_base_url: var("GEMINI_API_BASE_URL")
                 .unwrap_or_else(|_| "https://generativelanguage.googleapis.com/v1beta".to_string()),
-            gemini_default_model: env::var("GEMINI_DEFAULT_MODEL")
+            gemini_api_keys: collect_api_keys(
+                var("GEMINI_API_KEY").ok(),
+                var("GEMINI_API_KEYS").ok(),
+                var("GEMINI_API_KEYS_FILE").ok(),
+            ),
+            api_key_selection: var("GEMINI_API_KEY_SELECTION")
+                .ok()
+                .and_then(|s| {
+                    s.parse()
+                        .map_err(|e| tracing::warn!("{}, using round_robin", e))
+                        .ok()
+                })
+                .unwrap_or_default(),
+            api_backend: var("GEMINI_BACKEND")
+                .ok()
+                .and_then(|s| {
+                    s.parse()
+                        .map_err(|e| tracing::warn!("{}, using ai_studio", e))
+                        .ok()
+                })
+                .unwrap_or_default(),
+            vertex_credentials_path: var("GOOGLE_APPLICATION_CREDENTIALS")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            vertex_project_id: var("VERTEX_PROJECT_ID")
+                .or_else(|_| var("GOOGLE_CLOUD_PROJECT"))
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            vertex_location: var("VERTEX_LOCATION")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| "us-central1".to_string()),
+            vertex_api_base_url: var("VERTEX_API_BASE_URL")
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            model_discovery: var("MODEL_DISCOVERY")
+                .map(|s| matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false),
+            model_catalog_ttl_secs: var("MODEL_CATALOG_TTL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3600),
+            gemini_default_model: var("GEMINI_DEFAULT_MODEL")
                 .unwrap_or_else(|_| "gemini-2.5-flash-image".to_string()),
-            gemini_allowed_models: env::var("GEMINI_ALLOWED_MODELS")
+            gemini_allowed_models: var("GEMINI_ALLOWED_MODELS")
                 .ok()
                 .map(|s| {
                     s.split(',')
@@ -57,10 +379,137 @@ impl Config {
                         .collect()
                 })
                 .unwrap_or_default(),
-            max_prompt_length: env::var("MAX_PROMPT_LENGTH")
+            model_capability_overrides: var("MODEL_CAPABILITIES")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .and_then(|s| {
+                    serde_json::from_str(&s)
+                        .map_err(|e| tracing::warn!("Invalid MODEL_CAPABILITIES, ignoring: {}", e))
+                        .ok()
+                })
+                .unwrap_or_default(),
+            model_prices: var("MODEL_PRICES")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .and_then(|s| {
+                    serde_json::from_str(&s)
+                        .map_err(|e| tracing::warn!("Invalid MODEL_PRICES, ignoring: {}", e))
+                        .ok()
+                })
+                .unwrap_or_default(),
+            max_prompt_length: var("MAX_PROMPT_LENGTH")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(10000),
+            list_page_size: var("LIST_PAGE_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&size| size > 0)
+                .unwrap_or(50),
+            image_output_dir: var("IMAGE_OUTPUT_DIR")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            embed_image_metadata: var("IMAGE_EMBED_METADATA")
+                .map(|s| matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(false),
+            image_store_backend: var("IMAGE_STORE_BACKEND")
+                .map(|s| s.trim().to_ascii_lowercase())
+                .unwrap_or_else(|_| "fs".to_string()),
+            s3: S3Settings {
+                endpoint: var("S3_ENDPOINT").ok(),
+                bucket: var("S3_BUCKET").ok(),
+                prefix: var("S3_PREFIX").unwrap_or_default(),
+                region: var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key_id: var("S3_ACCESS_KEY_ID").ok(),
+                secret_access_key: var("S3_SECRET_ACCESS_KEY").ok(),
+                force_path_style: var("S3_FORCE_PATH_STYLE")
+                    .map(|s| matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                    .unwrap_or(false),
+                presign_expires_secs: var("S3_PRESIGN_EXPIRES_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|&secs| secs > 0),
+            },
+            image_store_dir: var("IMAGE_STORE_DIR")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            image_store_max_age_days: var("IMAGE_STORE_MAX_AGE_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            image_store_max_bytes: var("IMAGE_STORE_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            history_db_path: var("HISTORY_DB_PATH")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            response_cache_ttl_secs: var("RESPONSE_CACHE_TTL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&secs| secs > 0),
+            response_cache_max_bytes: var("RESPONSE_CACHE_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+            request_coalescing: var("REQUEST_COALESCING")
+                .map(|s| matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(true),
+            rate_limits,
+            rate_limit_model_overrides: var("RATE_LIMIT_MODEL_OVERRIDES")
+                .map(|s| parse_rate_limit_overrides(&s, rate_limits))
+                .unwrap_or_default(),
+            rate_limit_max_wait_secs: var("RATE_LIMIT_MAX_WAIT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30),
+            model_fallback_chain: var("MODEL_FALLBACK_CHAIN")
+                .map(|s| {
+                    s.split(',')
+                        .map(|m| m.trim().to_string())
+                        .filter(|m| !m.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            model_fallback_triggers: var("MODEL_FALLBACK_ON")
+                .map(|s| {
+                    FallbackTriggers::from_kinds(s.split(',').filter(|k| !k.trim().is_empty()))
+                })
+                .unwrap_or_default(),
+            job_max_concurrency: var("JOB_MAX_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(2),
+            job_max_pending: var("JOB_MAX_PENDING")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(100),
+            batch_concurrency: var("BATCH_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(4),
+            batch_manifest_dir: var("BATCH_MANIFEST_DIR")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            gemini_batch_poll_interval_secs: var("GEMINI_BATCH_POLL_INTERVAL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(30),
+            gemini_batch_wait_timeout_secs: var("GEMINI_BATCH_WAIT_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(48 * 60 * 60),
+            file_upload_threshold_bytes: var("FILE_UPLOAD_THRESHOLD_BYTES")
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(4 * 1024 * 1024),
+            budget,
             jsonrpc_error_codes: JsonRpcErrorCodes::default(),
         }
     }
@@ -75,6 +524,51 @@ impl Config {
         &self.gemini_api_base_url
     }
 
+    /// Gemini APIキーを取得
+    pub fn gemini_api_keys(&self) -> &[String] {
+        &self.gemini_api_keys
+    }
+
+    /// 複数のAPIキーの選び方を取得
+    pub fn api_key_selection(&self) -> KeySelection {
+        self.api_key_selection
+    }
+
+    /// 画像生成に使うAPIを取得
+    pub fn api_backend(&self) -> ApiBackend {
+        self.api_backend
+    }
+
+    /// Vertex AIのサービスアカウントのキーのパスを取得
+    pub fn vertex_credentials_path(&self) -> Option<&Path> {
+        self.vertex_credentials_path.as_deref()
+    }
+
+    /// Vertex AIのプロジェクトIDを取得
+    pub fn vertex_project_id(&self) -> Option<&str> {
+        self.vertex_project_id.as_deref()
+    }
+
+    /// Vertex AIのロケーションを取得
+    pub fn vertex_location(&self) -> &str {
+        &self.vertex_location
+    }
+
+    /// Vertex AIのベースURLを取得
+    pub fn vertex_api_base_url(&self) -> Option<&str> {
+        self.vertex_api_base_url.as_deref()
+    }
+
+    /// `GET /models`で利用可能なモデルを取得するかを取得
+    pub fn model_discovery(&self) -> bool {
+        self.model_discovery
+    }
+
+    /// 取得したモデル一覧をキャッシュする時間を取得
+    pub fn model_catalog_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.model_catalog_ttl_secs)
+    }
+
     /// デフォルトGeminiモデル名を取得
     pub fn gemini_default_model(&self) -> &str {
         &self.gemini_default_model
@@ -85,8 +579,231 @@ impl Config {
         &self.gemini_allowed_models
     }
 
+    /// モデルごとの対応機能の上書きを取得
+    pub fn model_capability_overrides(&self) -> &HashMap<String, CapabilityOverride> {
+        &self.model_capability_overrides
+    }
+
+    /// モデルごとの料金の上書きを取得
+    pub fn model_prices(&self) -> &HashMap<String, ModelPrice> {
+        &self.model_prices
+    }
+
     /// プロンプトの最大長を取得
     pub fn max_prompt_length(&self) -> usize {
         self.max_prompt_length
     }
+
+    /// リスト系メソッドの1ページあたりの件数を取得
+    pub fn list_page_size(&self) -> usize {
+        self.list_page_size
+    }
+
+    /// 生成画像の保存先ディレクトリを取得
+    pub fn image_output_dir(&self) -> Option<&Path> {
+        self.image_output_dir.as_deref()
+    }
+
+    /// 保存する画像ファイルにメタデータを埋め込むかを取得
+    pub fn embed_image_metadata(&self) -> bool {
+        self.embed_image_metadata
+    }
+
+    /// 画像ストアのバックエンドを取得
+    pub fn image_store_backend(&self) -> &str {
+        &self.image_store_backend
+    }
+
+    /// 画像ストアのディレクトリを取得
+    pub fn image_store_dir(&self) -> Option<&Path> {
+        self.image_store_dir.as_deref()
+    }
+
+    /// S3互換ストレージの設定を取得（必須項目が揃っていない場合は`None`）
+    pub fn s3_config(&self) -> Option<S3Config> {
+        Some(S3Config {
+            endpoint: self.s3.endpoint.clone()?,
+            bucket: self.s3.bucket.clone()?,
+            prefix: self.s3.prefix.clone(),
+            region: self.s3.region.clone(),
+            access_key_id: self.s3.access_key_id.clone()?,
+            secret_access_key: self.s3.secret_access_key.clone()?,
+            path_style: self.s3.force_path_style,
+            presign_expires_secs: self.s3.presign_expires_secs,
+        })
+    }
+
+    /// 画像ストアの保持ポリシーを取得
+    pub fn image_store_retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age: self
+                .image_store_max_age_days
+                .and_then(|days| i64::try_from(days).ok())
+                .map(chrono::Duration::days),
+            max_total_bytes: self.image_store_max_bytes,
+        }
+    }
+
+    /// 生成履歴のデータベースのパスを取得
+    pub fn history_db_path(&self) -> Option<&Path> {
+        self.history_db_path.as_deref()
+    }
+
+    /// レスポンスキャッシュの有効期限を取得（キャッシュが無効な場合は`None`）
+    pub fn response_cache_ttl(&self) -> Option<std::time::Duration> {
+        self.response_cache_ttl_secs
+            .map(std::time::Duration::from_secs)
+    }
+
+    /// レスポンスキャッシュの合計サイズ上限を取得
+    pub fn response_cache_max_bytes(&self) -> Option<u64> {
+        self.response_cache_max_bytes
+    }
+
+    /// 同時に実行された同じ内容のリクエストをまとめるかを取得
+    pub fn request_coalescing(&self) -> bool {
+        self.request_coalescing
+    }
+
+    /// 全モデル共通のレート制限を取得
+    pub fn rate_limits(&self) -> RateLimits {
+        self.rate_limits
+    }
+
+    /// モデルごとのレート制限を取得
+    pub fn rate_limit_model_overrides(&self) -> &HashMap<String, RateLimits> {
+        &self.rate_limit_model_overrides
+    }
+
+    /// レート制限に達した場合に待つ時間の上限を取得
+    pub fn rate_limit_max_wait(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.rate_limit_max_wait_secs)
+    }
+
+    /// フォールバックするモデルの順序を取得
+    pub fn model_fallback_chain(&self) -> &[String] {
+        &self.model_fallback_chain
+    }
+
+    /// フォールバックするエラーの種類を取得
+    pub fn model_fallback_triggers(&self) -> FallbackTriggers {
+        self.model_fallback_triggers
+    }
+
+    /// 同時に実行する生成ジョブの数を取得
+    pub fn job_max_concurrency(&self) -> usize {
+        self.job_max_concurrency
+    }
+
+    /// 未終了の生成ジョブの数の上限を取得
+    pub fn job_max_pending(&self) -> usize {
+        self.job_max_pending
+    }
+
+    /// 一括生成で同時に実行する数のデフォルトを取得
+    pub fn batch_concurrency(&self) -> usize {
+        self.batch_concurrency
+    }
+
+    /// 一括生成の結果の一覧を保存するディレクトリを取得
+    pub fn batch_manifest_dir(&self) -> Option<&Path> {
+        self.batch_manifest_dir.as_deref()
+    }
+
+    /// Files APIでアップロードする入力画像のサイズを取得
+    pub fn file_upload_threshold_bytes(&self) -> usize {
+        self.file_upload_threshold_bytes
+    }
+
+    /// Batch APIのバッチの終了を待つ間の状態確認の間隔を取得
+    pub fn gemini_batch_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.gemini_batch_poll_interval_secs)
+    }
+
+    /// Batch APIのバッチの終了を待つ時間の上限を取得
+    pub fn gemini_batch_wait_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.gemini_batch_wait_timeout_secs)
+    }
+
+    /// 予算の上限を取得
+    pub fn budget(&self) -> &BudgetPolicy {
+        &self.budget
+    }
+}
+
+/// APIキーを集める（カンマ・改行区切り、ファイルは1行1キーで`#`で始まる行は無視）
+fn collect_api_keys(
+    single: Option<String>,
+    list: Option<String>,
+    file: Option<String>,
+) -> Vec<String> {
+    let from_file = file
+        .and_then(|path| match std::fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                tracing::warn!("Failed to read API key file {}: {}", path, e);
+                None
+            }
+        })
+        .unwrap_or_default();
+
+    let mut keys: Vec<String> = Vec::new();
+    let candidates = single
+        .into_iter()
+        .chain(list.iter().flat_map(|s| s.split(',').map(str::to_string)))
+        .chain(
+            from_file
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .map(str::to_string),
+        );
+    for key in candidates {
+        let key = key.trim();
+        if !key.is_empty() && !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+    }
+    keys
+}
+
+/// モデルごとのレート制限を読み込む
+///
+/// 形式: `モデル名:rpm=10,images_per_day=100,concurrency=2;別のモデル名:rpm=5`
+/// 指定しなかった項目は全モデル共通の制限を使う。
+fn parse_rate_limit_overrides(value: &str, defaults: RateLimits) -> HashMap<String, RateLimits> {
+    value
+        .split(';')
+        .filter_map(|entry| entry.split_once(':'))
+        .map(|(model, settings)| {
+            let mut limits = defaults;
+            for (key, value) in settings.split(',').filter_map(|s| s.split_once('=')) {
+                match key.trim() {
+                    "rpm" => limits.requests_per_minute = value.trim().parse().ok(),
+                    "images_per_day" => limits.images_per_day = value.trim().parse().ok(),
+                    "concurrency" => limits.max_concurrency = value.trim().parse().ok(),
+                    other => tracing::warn!("Unknown rate limit setting '{}' for {}", other, model),
+                }
+            }
+            (model.trim().to_string(), limits)
+        })
+        .collect()
+}
+
+/// `KEY=VALUE`形式の設定ファイルを読み込む（空行と`#`で始まる行は無視）
+fn load_config_file(path: &Path) -> HashMap<String, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::warn!("Failed to read config file {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
 }
@@ -0,0 +1,229 @@
+use crate::domain::models::{GeminiModel, ImageGenerationRequest, InputImageRole};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// 設定で上書きしたモデルごとの対応機能（実行中に更新可能）
+static OVERRIDES: RwLock<Option<HashMap<String, CapabilityOverride>>> = RwLock::new(None);
+
+/// Geminiの画像モデルが対応するアスペクト比
+const GEMINI_ASPECT_RATIOS: &[&str] = &[
+    "1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9",
+];
+
+/// Imagenが対応するアスペクト比
+const IMAGEN_ASPECT_RATIOS: &[&str] = &["1:1", "3:4", "4:3", "9:16", "16:9"];
+
+fn default_true() -> bool {
+    true
+}
+
+/// モデルが対応する生成パラメータ
+///
+/// 値のリストが`None`の場合は制限しない（未知のモデルはすべて許可する）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// 指定できるアスペクト比
+    #[serde(default)]
+    pub aspect_ratios: Option<Vec<String>>,
+    /// 指定できる解像度（`image_size`、空の場合は指定できない）
+    #[serde(default)]
+    pub image_sizes: Option<Vec<String>>,
+    /// 添付できる入力画像の最大数
+    #[serde(default)]
+    pub max_input_images: Option<usize>,
+    /// 添付できる画風の参照画像（`style_images`）の最大数
+    #[serde(default)]
+    pub max_style_images: Option<usize>,
+    /// 添付できる人物・キャラクターなどの参照画像（`subject_images`）の最大数
+    #[serde(default)]
+    pub max_subject_images: Option<usize>,
+    /// `negative_prompt`に対応するか
+    #[serde(default = "default_true")]
+    pub negative_prompt: bool,
+    /// `person_generation`に対応するか
+    #[serde(default = "default_true")]
+    pub person_generation: bool,
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self {
+            aspect_ratios: None,
+            image_sizes: None,
+            max_input_images: None,
+            max_style_images: None,
+            max_subject_images: None,
+            negative_prompt: true,
+            person_generation: true,
+        }
+    }
+}
+
+/// 設定によるモデルの対応機能の上書き（指定した項目だけを組み込みの既定値に重ねる）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityOverride {
+    #[serde(default)]
+    pub aspect_ratios: Option<Vec<String>>,
+    #[serde(default)]
+    pub image_sizes: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_input_images: Option<usize>,
+    #[serde(default)]
+    pub max_style_images: Option<usize>,
+    #[serde(default)]
+    pub max_subject_images: Option<usize>,
+    #[serde(default)]
+    pub negative_prompt: Option<bool>,
+    #[serde(default)]
+    pub person_generation: Option<bool>,
+}
+
+impl CapabilityOverride {
+    /// 指定された項目を上書きする
+    pub fn apply(&self, capabilities: &mut ModelCapabilities) {
+        if let Some(aspect_ratios) = &self.aspect_ratios {
+            capabilities.aspect_ratios = Some(aspect_ratios.clone());
+        }
+        if let Some(image_sizes) = &self.image_sizes {
+            capabilities.image_sizes = Some(image_sizes.clone());
+        }
+        if let Some(max) = self.max_input_images {
+            capabilities.max_input_images = Some(max);
+        }
+        if let Some(max) = self.max_style_images {
+            capabilities.max_style_images = Some(max);
+        }
+        if let Some(max) = self.max_subject_images {
+            capabilities.max_subject_images = Some(max);
+        }
+        if let Some(supported) = self.negative_prompt {
+            capabilities.negative_prompt = supported;
+        }
+        if let Some(supported) = self.person_generation {
+            capabilities.person_generation = supported;
+        }
+    }
+}
+
+impl ModelCapabilities {
+    /// モデルの対応機能を取得（組み込みの既定値（未知のモデルは制限なし）に設定の上書きを重ねる）
+    pub fn for_model(model: &GeminiModel) -> Self {
+        let mut capabilities = Self::builtin(model).unwrap_or_default();
+        let overrides = OVERRIDES.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(capability_override) = overrides.as_ref().and_then(|o| o.get(model.as_str())) {
+            capability_override.apply(&mut capabilities);
+        }
+        capabilities
+    }
+
+    /// モデルごとの上書きを設定する（設定リロード時など）
+    pub fn set_overrides(overrides: HashMap<String, CapabilityOverride>) {
+        *OVERRIDES.write().unwrap_or_else(|e| e.into_inner()) = Some(overrides);
+    }
+
+    /// 既知のモデルファミリーの組み込みの既定値
+    fn builtin(model: &GeminiModel) -> Option<Self> {
+        let list = |values: &[&str]| Some(values.iter().map(|v| v.to_string()).collect());
+        let name = model.as_str();
+        if model.is_imagen() {
+            Some(Self {
+                aspect_ratios: list(IMAGEN_ASPECT_RATIOS),
+                image_sizes: list(&["1K", "2K"]),
+                max_input_images: Some(0),
+                max_style_images: Some(0),
+                max_subject_images: Some(0),
+                negative_prompt: true,
+                person_generation: true,
+            })
+        } else if name.starts_with("gemini-3-pro-image") {
+            Some(Self {
+                aspect_ratios: list(GEMINI_ASPECT_RATIOS),
+                image_sizes: list(&["1K", "2K", "4K"]),
+                max_input_images: Some(14),
+                max_style_images: Some(6),
+                max_subject_images: Some(5),
+                negative_prompt: false,
+                person_generation: false,
+            })
+        } else if name.contains("flash-image") {
+            Some(Self {
+                aspect_ratios: list(GEMINI_ASPECT_RATIOS),
+                image_sizes: list(&[]),
+                max_input_images: Some(3),
+                max_style_images: None,
+                max_subject_images: None,
+                negative_prompt: false,
+                person_generation: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// リクエストのパラメータに対応しているか検証（対応しない場合は理由を返す）
+    pub fn check(&self, request: &ImageGenerationRequest) -> Result<(), String> {
+        check_value(
+            "aspect_ratio",
+            request.aspect_ratio.as_deref(),
+            &self.aspect_ratios,
+        )?;
+        check_value(
+            "image_size",
+            request.image_size.as_deref(),
+            &self.image_sizes,
+        )?;
+
+        if let Some(max) = self.max_input_images {
+            if request.input_images.len() > max {
+                return Err(format!(
+                    "{} input images given but at most {} are supported",
+                    request.input_images.len(),
+                    max
+                ));
+            }
+        }
+        let references = [
+            ("style", InputImageRole::Style, self.max_style_images),
+            ("subject", InputImageRole::Subject, self.max_subject_images),
+        ];
+        for (name, role, max) in references {
+            let count = request.input_image_count(role);
+            match max {
+                Some(0) if count > 0 => return Err(format!("{} images are not supported", name)),
+                Some(max) if count > max => {
+                    return Err(format!(
+                        "{} {} images given but at most {} are supported",
+                        count, name, max
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if request.negative_prompt.is_some() && !self.negative_prompt {
+            return Err("negative_prompt is not supported".to_string());
+        }
+        if request.person_generation.is_some() && !self.person_generation {
+            return Err("person_generation is not supported".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 値が対応するリストに含まれるか検証
+fn check_value(
+    name: &str,
+    value: Option<&str>,
+    allowed: &Option<Vec<String>>,
+) -> Result<(), String> {
+    match (value, allowed) {
+        (Some(_), Some(allowed)) if allowed.is_empty() => Err(format!("{} is not supported", name)),
+        (Some(value), Some(allowed)) if !allowed.iter().any(|a| a == value) => Err(format!(
+            "{} '{}' is not supported (supported: {})",
+            name,
+            value,
+            allowed.join(", ")
+        )),
+        _ => Ok(()),
+    }
+}
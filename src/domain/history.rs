@@ -0,0 +1,195 @@
+use crate::domain::models::{GeminiModel, GeneratedImage, ImageGenerationRequest};
+use serde::{Deserialize, Serialize};
+
+/// 生成履歴のトレイト
+/// すべての画像生成リクエストとその結果を記録し、一覧・全文検索できるようにする
+#[async_trait::async_trait]
+pub trait GenerationHistory: Send + Sync {
+    /// 生成結果を記録する
+    async fn record(&self, record: &GenerationRecord) -> Result<(), HistoryError>;
+
+    /// 条件に一致する記録を新しい順に取得する（`text`を指定した場合は関連度順）
+    async fn query(&self, query: &HistoryQuery) -> Result<Vec<GenerationRecord>, HistoryError>;
+
+    /// リクエストIDで記録を取得する
+    async fn get(&self, request_id: &str) -> Result<Option<GenerationRecord>, HistoryError>;
+}
+
+/// 生成の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationOutcome {
+    Succeeded,
+    Failed,
+}
+
+impl GenerationOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for GenerationOutcome {
+    type Err = HistoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            other => Err(HistoryError::InvalidQuery(format!(
+                "Unknown outcome: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// 生成履歴の1件分の記録
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationRecord {
+    pub request_id: String,
+    pub prompt: String,
+    pub model: GeminiModel,
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    pub outcome: GenerationOutcome,
+    /// 失敗した場合のエラーの種類（`rate_limit`など）
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+    /// 生成にかかった時間（ミリ秒）
+    pub latency_ms: u64,
+    /// 画像ストアに保存した画像のID
+    pub image_id: Option<String>,
+    /// 出力ディレクトリに保存したファイルのパス
+    pub file_path: Option<String>,
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<u64>,
+    /// APIを呼んで生成した場合の推定料金（米ドル、予算の集計の復元に使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_cost_usd: Option<f64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// 再実行用の元のリクエスト（入力画像を含むため、ツールの結果には含めない）
+    #[serde(skip)]
+    pub request: Option<ImageGenerationRequest>,
+}
+
+impl GenerationRecord {
+    /// 成功した生成の記録を作成
+    pub fn succeeded(
+        request: &ImageGenerationRequest,
+        image: &GeneratedImage,
+        latency: std::time::Duration,
+    ) -> Self {
+        Self {
+            outcome: GenerationOutcome::Succeeded,
+            // フォールバックした場合は実際に生成したモデルを記録する
+            model: image.model.clone(),
+            mime_type: Some(image.mime_type.clone()),
+            size_bytes: Some(image.data.len() as u64),
+            ..Self::base(request, latency)
+        }
+    }
+
+    /// 失敗した生成の記録を作成
+    pub fn failed(
+        request: &ImageGenerationRequest,
+        error_kind: &str,
+        error_message: String,
+        latency: std::time::Duration,
+    ) -> Self {
+        Self {
+            outcome: GenerationOutcome::Failed,
+            error_kind: Some(error_kind.to_string()),
+            error_message: Some(error_message),
+            ..Self::base(request, latency)
+        }
+    }
+
+    pub fn with_image_id(mut self, image_id: String) -> Self {
+        self.image_id = Some(image_id);
+        self
+    }
+
+    pub fn with_file_path(mut self, file_path: String) -> Self {
+        self.file_path = Some(file_path);
+        self
+    }
+
+    pub fn with_estimated_cost(mut self, cost_usd: f64) -> Self {
+        self.estimated_cost_usd = Some(cost_usd);
+        self
+    }
+
+    fn base(request: &ImageGenerationRequest, latency: std::time::Duration) -> Self {
+        Self {
+            request_id: request.request_id.clone(),
+            prompt: request.prompt.clone(),
+            model: request.model.clone(),
+            parameters: request.parameters(),
+            outcome: GenerationOutcome::Succeeded,
+            error_kind: None,
+            error_message: None,
+            latency_ms: u64::try_from(latency.as_millis()).unwrap_or(u64::MAX),
+            image_id: None,
+            file_path: None,
+            mime_type: None,
+            size_bytes: None,
+            estimated_cost_usd: None,
+            created_at: chrono::Utc::now(),
+            request: Some(request.clone()),
+        }
+    }
+
+    /// 記録されたリクエストを復元する（元のリクエストが保存されていない場合はプロンプトとモデルから作り直す）
+    pub fn to_request(&self) -> ImageGenerationRequest {
+        self.request
+            .clone()
+            .unwrap_or_else(|| ImageGenerationRequest {
+                request_id: self.request_id.clone(),
+                ..ImageGenerationRequest::new(self.prompt.clone()).with_model(self.model.clone())
+            })
+    }
+}
+
+/// 生成履歴の検索条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryQuery {
+    /// プロンプトの全文検索（空白区切りの語をすべて含むもの）
+    pub text: Option<String>,
+    pub model: Option<String>,
+    pub outcome: Option<GenerationOutcome>,
+    /// この時刻以降に作成されたもの
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// この時刻より前に作成されたもの
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: usize,
+    pub offset: usize,
+    /// 新しい順の一覧でこの記録（作成日時, リクエストID）より後のもの（キーセット方式のページ分割）
+    pub after: Option<(chrono::DateTime<chrono::Utc>, String)>,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            text: None,
+            model: None,
+            outcome: None,
+            since: None,
+            until: None,
+            limit: 20,
+            offset: 0,
+            after: None,
+        }
+    }
+}
+
+/// 生成履歴エラー
+#[derive(Debug, thiserror::Error)]
+pub enum HistoryError {
+    #[error("History storage error: {0}")]
+    Storage(String),
+    #[error("Invalid history query: {0}")]
+    InvalidQuery(String),
+}
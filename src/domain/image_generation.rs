@@ -11,15 +11,43 @@ pub trait ImageGenerationRepository: Send + Sync {
     ) -> Result<GeneratedImage, ImageGenerationError>;
 }
 
-/// 画像生成エラー
-#[derive(Debug, thiserror::Error)]
+#[async_trait::async_trait]
+impl<T: ImageGenerationRepository + ?Sized> ImageGenerationRepository for Box<T> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        (**self).generate_image(request).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: ImageGenerationRepository + ?Sized> ImageGenerationRepository for std::sync::Arc<T> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        (**self).generate_image(request).await
+    }
+}
+
+/// 画像生成エラー（同じ結果を複数の呼び出し元に返せるようClone可能）
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ImageGenerationError {
     #[error("API authentication error: {0}")]
     AuthenticationError(String),
-    #[error("Rate limit exceeded: {0}")]
-    RateLimitError(String),
+    /// `retry_after`は再試行までの推定待ち時間（APIの`Retry-After`やクライアント側の制限から算出）
+    #[error("Rate limit exceeded: {message}{}", format_retry_after(retry_after))]
+    RateLimitError {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
     #[error("Invalid prompt: {0}")]
     InvalidPromptError(String),
+    #[error("Model overloaded: {0}")]
+    ModelOverloaded(String),
+    #[error("No image generated: {0}")]
+    NoImage(String),
     #[error("Network error: {0}")]
     NetworkError(String),
     #[error("API error: {0}")]
@@ -28,8 +56,50 @@ pub enum ImageGenerationError {
     Unknown(String),
 }
 
+/// 無効なAPIキーの認証エラーのメッセージ（モデルの権限がないなどの認証エラーと区別する）
+const INVALID_API_KEY: &str = "Invalid API key";
+
+impl ImageGenerationError {
+    /// APIキー自体が無効な場合の認証エラー
+    pub fn invalid_api_key(detail: Option<&str>) -> Self {
+        match detail {
+            Some(detail) => Self::AuthenticationError(format!("{}: {}", INVALID_API_KEY, detail)),
+            None => Self::AuthenticationError(INVALID_API_KEY.to_string()),
+        }
+    }
+
+    /// APIキー自体が無効な認証エラーか（キーを切り替えれば成功しうる）
+    pub fn is_invalid_api_key(&self) -> bool {
+        matches!(self, Self::AuthenticationError(message) if message.starts_with(INVALID_API_KEY))
+    }
+
+    /// エラーの種類（履歴の記録や集計に使用）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::AuthenticationError(_) => "authentication",
+            Self::RateLimitError { .. } => "rate_limit",
+            Self::InvalidPromptError(_) => "invalid_prompt",
+            Self::ModelOverloaded(_) => "overloaded",
+            Self::NoImage(_) => "no_image",
+            Self::NetworkError(_) => "network",
+            Self::ApiError(_) => "api",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+
+    /// 再試行までの推定待ち時間（レート制限の場合のみ）
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            Self::RateLimitError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 impl From<reqwest::Error> for ImageGenerationError {
     fn from(err: reqwest::Error) -> Self {
+        // URLにはAPIキーなどが含まれる場合があるため、メッセージには含めない
+        let err = err.without_url();
         if err.is_timeout() {
             Self::NetworkError(format!("Request timeout: {}", err))
         } else if err.is_connect() {
@@ -39,3 +109,10 @@ impl From<reqwest::Error> for ImageGenerationError {
         }
     }
 }
+
+fn format_retry_after(retry_after: &Option<std::time::Duration>) -> String {
+    match retry_after {
+        Some(wait) => format!(" (estimated wait: {:.1}s)", wait.as_secs_f64()),
+        None => String::new(),
+    }
+}
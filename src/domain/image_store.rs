@@ -0,0 +1,144 @@
+use crate::domain::models::{GeneratedImage, ImageMetadata};
+use serde::{Deserialize, Serialize};
+
+/// 画像ストアのトレイト
+/// 生成画像を内容のハッシュ（SHA-256）で保存し、履歴・リソース・キャッシュの保存先として使う
+#[async_trait::async_trait]
+pub trait ImageStore: Send + Sync {
+    /// 画像を保存する（同一内容の画像が既にあれば重複保存せず、既存のエントリを返す）
+    async fn put(
+        &self,
+        image: &GeneratedImage,
+        metadata: &ImageMetadata,
+    ) -> Result<StoredImage, ImageStoreError>;
+
+    /// IDで画像のエントリとデータを取得する
+    async fn get(&self, id: &str) -> Result<Option<(StoredImage, Vec<u8>)>, ImageStoreError>;
+
+    /// 保存されている画像のエントリを新しい順に取得する
+    async fn list(&self) -> Result<Vec<StoredImage>, ImageStoreError>;
+
+    /// 画像を削除する（存在した場合は`true`）
+    async fn delete(&self, id: &str) -> Result<bool, ImageStoreError>;
+
+    /// 保持ポリシーに従って古い画像を削除する
+    async fn collect_garbage(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<GarbageCollectionReport, ImageStoreError>;
+
+    /// 画像をダウンロードできるURL（署名付きURLなど、対応していない場合は`None`）
+    fn download_url(&self, _stored: &StoredImage) -> Option<String> {
+        None
+    }
+}
+
+/// 画像ストアに保存された画像のエントリ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredImage {
+    /// 画像データのSHA-256（16進数）
+    pub id: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    /// 最後に保存（または重複として再保存）された時刻
+    pub stored_at: chrono::DateTime<chrono::Utc>,
+    /// 最初に保存されたときのメタデータ
+    pub metadata: ImageMetadata,
+    /// この画像を生成したリクエストのID（重複排除された分も含む）
+    pub request_ids: Vec<String>,
+    /// 保存先の場所（ファイルパスやオブジェクトキーなど、実装依存）
+    pub location: String,
+}
+
+impl StoredImage {
+    /// 画像IDとして正しい形式（SHA-256の16進数）か
+    pub fn is_valid_id(id: &str) -> bool {
+        id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+
+    /// MCPリソースとして公開する際のURI
+    pub fn resource_uri(&self) -> String {
+        format!("image://{}", self.id)
+    }
+}
+
+/// 画像の保持ポリシー
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// これより古い画像を削除する
+    pub max_age: Option<chrono::Duration>,
+    /// 合計サイズがこれを超えたら古い画像から削除する
+    pub max_total_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// 制限が設定されていないか
+    pub fn is_unbounded(&self) -> bool {
+        self.max_age.is_none() && self.max_total_bytes.is_none()
+    }
+
+    /// 合計サイズの上限を1枚で超える画像は保存できない（保存してもすぐに削除されるため）
+    pub fn check_size(&self, size_bytes: u64) -> Result<(), ImageStoreError> {
+        match self.max_total_bytes {
+            Some(max_bytes) if size_bytes > max_bytes => Err(ImageStoreError::TooLarge {
+                size_bytes,
+                max_bytes,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 削除すべき画像のIDを古い順に選ぶ（期限切れのものと、合計サイズの上限を超える分）
+    pub fn select_expired<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a StoredImage>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<String> {
+        let mut entries: Vec<&StoredImage> = entries.into_iter().collect();
+        entries.sort_by(|a, b| a.stored_at.cmp(&b.stored_at).then(a.id.cmp(&b.id)));
+
+        let mut remaining_bytes: u64 = entries.iter().map(|e| e.size_bytes).sum();
+        let cutoff = self.max_age.map(|age| now - age);
+
+        let mut expired = Vec::new();
+        for entry in entries {
+            let too_old = cutoff.is_some_and(|cutoff| entry.stored_at < cutoff);
+            let over_quota = self
+                .max_total_bytes
+                .is_some_and(|max| remaining_bytes > max);
+            if too_old || over_quota {
+                remaining_bytes -= entry.size_bytes;
+                expired.push(entry.id.clone());
+            }
+        }
+        expired
+    }
+}
+
+/// ガベージコレクションの結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GarbageCollectionReport {
+    pub removed_ids: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+/// 画像ストアエラー
+#[derive(Debug, thiserror::Error)]
+pub enum ImageStoreError {
+    #[error("Storage I/O error: {0}")]
+    Io(String),
+    #[error("Corrupted image index: {0}")]
+    CorruptedIndex(String),
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+    #[error("Invalid image id: {0}")]
+    InvalidId(String),
+    #[error("Image of {size_bytes} bytes exceeds the image store limit of {max_bytes} bytes")]
+    TooLarge { size_bytes: u64, max_bytes: u64 },
+}
+
+impl From<std::io::Error> for ImageStoreError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err.to_string())
+    }
+}
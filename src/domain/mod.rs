@@ -1,5 +1,18 @@
+pub mod capabilities;
+pub mod history;
 pub mod image_generation;
+pub mod image_store;
 pub mod models;
 
+pub use capabilities::{CapabilityOverride, ModelCapabilities};
+pub use history::{
+    GenerationHistory, GenerationOutcome, GenerationRecord, HistoryError, HistoryQuery,
+};
 pub use image_generation::{ImageGenerationError, ImageGenerationRepository};
-pub use models::{GeminiModel, GeneratedImage, ImageGenerationRequest, ValidationError};
+pub use image_store::{
+    GarbageCollectionReport, ImageStore, ImageStoreError, RetentionPolicy, StoredImage,
+};
+pub use models::{
+    GeminiModel, GeneratedImage, ImageGenerationRequest, ImageMetadata, InputImage, InputImageRole,
+    ModelSettings, TokenUsage, ValidationError,
+};
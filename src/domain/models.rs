@@ -1,24 +1,41 @@
+use crate::domain::capabilities::ModelCapabilities;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::sync::OnceLock;
+use std::sync::RwLock;
 
 /// Gemini画像生成モデル（環境変数から設定可能）
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct GeminiModel(String);
 
-/// デフォルトモデル名（環境変数から読み取る）
-pub(crate) static DEFAULT_MODEL: OnceLock<String> = OnceLock::new();
+/// デフォルトモデル名を指定しない場合のモデル
+const BUILTIN_DEFAULT_MODEL: &str = "gemini-2.5-flash-image";
 
-/// 許可されたモデルリスト（環境変数から読み取る、空の場合はすべて許可）
-pub(crate) static ALLOWED_MODELS: OnceLock<Vec<String>> = OnceLock::new();
+/// 実行中に変更されうるモデル設定（環境変数から読み取る）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSettings {
+    /// デフォルトモデル名（空の場合は組み込みのデフォルト）
+    pub default_model: String,
+    /// 許可されたモデルリスト（空の場合はすべて許可）
+    pub allowed_models: Vec<String>,
+}
+
+/// 現在のモデル設定（デフォルトモデルと許可されたモデルリストはここだけで管理する）
+static MODEL_SETTINGS: RwLock<ModelSettings> = RwLock::new(ModelSettings {
+    default_model: String::new(),
+    allowed_models: Vec::new(),
+});
+
+/// モデル設定が初期化済みか（起動時の設定で実行中の変更を上書きしない）
+static SETTINGS_INITIALIZED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
 
 impl GeminiModel {
     /// 環境変数からデフォルトモデル名を初期化
     pub fn init_default() {
         let default_model = std::env::var("GEMINI_DEFAULT_MODEL")
-            .unwrap_or_else(|_| "gemini-2.5-flash-image".to_string());
-        DEFAULT_MODEL.set(default_model).ok();
+            .unwrap_or_else(|_| BUILTIN_DEFAULT_MODEL.to_string());
+        Self::set_default_model(default_model);
     }
 
     /// 環境変数から許可されたモデルリストを初期化
@@ -32,7 +49,7 @@ impl GeminiModel {
                     .collect()
             })
             .unwrap_or_default();
-        ALLOWED_MODELS.set(allowed).ok();
+        Self::set_allowed_models(allowed);
     }
 
     /// 環境変数を初期化（両方）
@@ -41,28 +58,83 @@ impl GeminiModel {
         Self::init_allowed_models();
     }
 
+    /// まだ初期化されていない場合だけモデル設定を初期化する（起動時の設定の反映用）
+    pub fn init_settings(settings: ModelSettings) {
+        if !SETTINGS_INITIALIZED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            Self::update_settings(settings);
+        }
+    }
+
+    /// 現在のモデル設定を取得（デフォルトモデル名は組み込みのデフォルトで補う）
+    pub fn settings() -> ModelSettings {
+        let mut settings = MODEL_SETTINGS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if settings.default_model.is_empty() {
+            settings.default_model = BUILTIN_DEFAULT_MODEL.to_string();
+        }
+        settings
+    }
+
+    /// モデル設定をまとめて更新し、変更があったかを返す（設定リロード時など）
+    pub fn update_settings(settings: ModelSettings) -> bool {
+        SETTINGS_INITIALIZED.store(true, std::sync::atomic::Ordering::SeqCst);
+        let mut current = MODEL_SETTINGS.write().unwrap_or_else(|e| e.into_inner());
+        if *current == settings {
+            return false;
+        }
+        *current = settings;
+        true
+    }
+
+    /// デフォルトモデル名を更新する（設定リロード時など）
+    pub fn set_default_model(model: String) {
+        SETTINGS_INITIALIZED.store(true, std::sync::atomic::Ordering::SeqCst);
+        MODEL_SETTINGS
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .default_model = model;
+    }
+
+    /// 許可されたモデルリストを更新する（設定リロード・モデル検出時など）
+    pub fn set_allowed_models(models: Vec<String>) {
+        SETTINGS_INITIALIZED.store(true, std::sync::atomic::Ordering::SeqCst);
+        MODEL_SETTINGS
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .allowed_models = models;
+    }
+
+    /// 現在の許可されたモデルリストを取得（空の場合はすべて許可）
+    pub fn allowed_models() -> Vec<String> {
+        MODEL_SETTINGS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .allowed_models
+            .clone()
+    }
+
     /// モデル名を取得
     pub fn as_str(&self) -> &str {
         &self.0
     }
 
+    /// Imagenモデル（`:predict`エンドポイントを使う）かどうか
+    pub fn is_imagen(&self) -> bool {
+        self.0.starts_with("imagen-")
+    }
+
     /// モデル名が許可されているかチェック
     pub fn is_allowed(&self) -> bool {
-        if let Some(allowed) = ALLOWED_MODELS.get() {
-            allowed.is_empty() || allowed.contains(&self.0)
-        } else {
-            true // 許可リストが初期化されていない場合はすべて許可
-        }
+        let settings = MODEL_SETTINGS.read().unwrap_or_else(|e| e.into_inner());
+        settings.allowed_models.is_empty() || settings.allowed_models.contains(&self.0)
     }
 }
 
 impl Default for GeminiModel {
     fn default() -> Self {
-        let model_name = DEFAULT_MODEL
-            .get()
-            .cloned()
-            .unwrap_or_else(|| "gemini-2.5-flash-image".to_string());
-        Self(model_name)
+        Self(Self::settings().default_model)
     }
 }
 
@@ -96,18 +168,120 @@ impl From<String> for GeminiModel {
     }
 }
 
-/// 画像生成リクエスト
-#[derive(Debug, Clone)]
+/// 画像生成リクエスト（履歴から再実行できるようシリアライズ可能）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImageGenerationRequest {
+    /// リクエストID（来歴の追跡用、生成時に自動採番）
+    pub request_id: String,
     pub prompt: String,
     pub model: GeminiModel,
+    /// 編集・バリエーション生成の元になる入力画像
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_images: Vec<InputImage>,
+    /// 再実行・バリエーション生成の元になったリクエストのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_request_id: Option<String>,
+    /// レスポンスキャッシュを使わずに生成する
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bypass_cache: bool,
+    /// 画像のアスペクト比（例: `16:9`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<String>,
+    /// 画像の解像度（例: `2K`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<String>,
+    /// 画像に含めたくない内容（Imagenのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_prompt: Option<String>,
+    /// 人物の生成の可否（`dont_allow`・`allow_adult`・`allow_all`、Imagenのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub person_generation: Option<String>,
+    /// リクエストしたクライアント（予算の上限をクライアントごとに適用する）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+/// 入力画像の使い方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputImageRole {
+    /// 編集・バリエーション生成の元画像（プロンプトの後に添付する）
+    #[default]
+    Edit,
+    /// 画風・配色を合わせる参照画像
+    Style,
+    /// 人物・キャラクター・商品などの見た目を揃える参照画像
+    Subject,
+}
+
+impl InputImageRole {
+    pub fn is_edit(&self) -> bool {
+        *self == Self::Edit
+    }
+
+    /// 参照画像の役割（ラベル付きでプロンプトの前に添付する）か
+    pub fn is_reference(&self) -> bool {
+        !self.is_edit()
+    }
+}
+
+/// 生成リクエストに添付する入力画像
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputImage {
+    pub mime_type: String,
+    /// 画像データ（シリアライズ時はbase64、履歴に保存する際は除く）
+    #[serde(default, with = "base64_bytes", skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<u8>,
+    /// 画像データを除いた場合のSHA-256（画像ストアから読み直すために使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "InputImageRole::is_edit")]
+    pub role: InputImageRole,
+}
+
+impl InputImage {
+    pub fn new(data: Vec<u8>, mime_type: String) -> Self {
+        Self {
+            mime_type,
+            data,
+            sha256: None,
+            role: InputImageRole::Edit,
+        }
+    }
+
+    pub fn with_role(mut self, role: InputImageRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// 画像データを除き、SHA-256だけを残した入力画像
+    pub fn without_data(&self) -> Self {
+        if self.data.is_empty() {
+            return self.clone();
+        }
+        Self {
+            mime_type: self.mime_type.clone(),
+            data: Vec::new(),
+            sha256: Some(sha256_hex(&self.data)),
+            role: self.role,
+        }
+    }
 }
 
 impl ImageGenerationRequest {
     pub fn new(prompt: String) -> Self {
         Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
             prompt,
             model: GeminiModel::default(),
+            input_images: Vec::new(),
+            parent_request_id: None,
+            bypass_cache: false,
+            aspect_ratio: None,
+            image_size: None,
+            negative_prompt: None,
+            person_generation: None,
+            client_id: None,
         }
     }
 
@@ -116,6 +290,157 @@ impl ImageGenerationRequest {
         self
     }
 
+    pub fn with_input_image(mut self, image: InputImage) -> Self {
+        self.input_images.push(image);
+        self
+    }
+
+    /// 画風を合わせる参照画像を追加
+    pub fn with_style_image(self, image: InputImage) -> Self {
+        self.with_input_image(image.with_role(InputImageRole::Style))
+    }
+
+    /// 見た目を揃える人物・キャラクターなどの参照画像を追加
+    pub fn with_subject_image(self, image: InputImage) -> Self {
+        self.with_input_image(image.with_role(InputImageRole::Subject))
+    }
+
+    /// 指定した役割の入力画像の数
+    pub fn input_image_count(&self, role: InputImageRole) -> usize {
+        self.input_images.iter().filter(|i| i.role == role).count()
+    }
+
+    pub fn with_parent_request_id(mut self, parent_request_id: String) -> Self {
+        self.parent_request_id = Some(parent_request_id);
+        self
+    }
+
+    pub fn with_bypass_cache(mut self, bypass_cache: bool) -> Self {
+        self.bypass_cache = bypass_cache;
+        self
+    }
+
+    pub fn with_aspect_ratio(mut self, aspect_ratio: String) -> Self {
+        self.aspect_ratio = Some(aspect_ratio);
+        self
+    }
+
+    pub fn with_image_size(mut self, image_size: String) -> Self {
+        self.image_size = Some(image_size);
+        self
+    }
+
+    pub fn with_negative_prompt(mut self, negative_prompt: String) -> Self {
+        self.negative_prompt = Some(negative_prompt);
+        self
+    }
+
+    pub fn with_person_generation(mut self, person_generation: String) -> Self {
+        self.person_generation = Some(person_generation);
+        self
+    }
+
+    pub fn with_client_id(mut self, client_id: String) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// 生成結果を決める内容（モデル・プロンプト・パラメータ・入力画像）の正規化したハッシュ
+    ///
+    /// リクエストIDや親リクエストなど結果に影響しない値は含めないため、同じ内容のリクエストは同じ値になる。
+    pub fn fingerprint(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut parameters = self.parameters();
+        parameters.remove("parent_request_id");
+        parameters.remove("input_images");
+        let input_images: Vec<serde_json::Value> = self
+            .input_images
+            .iter()
+            .map(|image| {
+                let mut value = serde_json::json!({
+                    "mime_type": image.mime_type,
+                    "sha256": to_hex(&Sha256::digest(&image.data))
+                });
+                if image.role.is_reference() {
+                    value["role"] = serde_json::json!(image.role);
+                }
+                value
+            })
+            .collect();
+
+        // serde_json::Mapはキー順に並ぶため、同じ内容なら同じ文字列になる
+        let canonical = serde_json::json!({
+            "model": self.model,
+            "prompt": self.prompt,
+            "parameters": parameters,
+            "input_images": input_images
+        });
+        to_hex(&Sha256::digest(canonical.to_string().as_bytes()))
+    }
+
+    /// 同じ内容で再実行するリクエストを作成（新しいリクエストIDを採番し、元のIDを親として記録）
+    pub fn rerun(&self) -> Self {
+        Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            parent_request_id: Some(self.request_id.clone()),
+            ..self.clone()
+        }
+    }
+
+    /// 入力画像のデータを除いたリクエスト（履歴への保存用）
+    pub fn without_image_data(&self) -> Self {
+        Self {
+            input_images: self
+                .input_images
+                .iter()
+                .map(InputImage::without_data)
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// プロンプト・モデル以外の生成パラメータ（メタデータ記録用、入力画像はサイズのみ）
+    pub fn parameters(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut parameters = serde_json::Map::new();
+        if !self.input_images.is_empty() {
+            parameters.insert(
+                "input_images".to_string(),
+                self.input_images
+                    .iter()
+                    .map(|image| {
+                        let mut value = serde_json::json!({
+                            "mime_type": image.mime_type,
+                            "size_bytes": image.data.len()
+                        });
+                        if image.role.is_reference() {
+                            value["role"] = serde_json::json!(image.role);
+                        }
+                        value
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(parent) = &self.parent_request_id {
+            parameters.insert(
+                "parent_request_id".to_string(),
+                serde_json::Value::String(parent.clone()),
+            );
+        }
+        let options = [
+            ("aspect_ratio", &self.aspect_ratio),
+            ("image_size", &self.image_size),
+            ("negative_prompt", &self.negative_prompt),
+            ("person_generation", &self.person_generation),
+        ];
+        for (key, value) in options {
+            if let Some(value) = value {
+                parameters.insert(key.to_string(), serde_json::Value::String(value.clone()));
+            }
+        }
+        parameters
+    }
+
     /// プロンプトの検証
     pub fn validate(&self) -> Result<(), ValidationError> {
         if self.prompt.trim().is_empty() {
@@ -131,26 +456,152 @@ impl ImageGenerationRequest {
             return Err(ValidationError::PromptTooLong(self.prompt.len()));
         }
 
+        // モデルが対応しないパラメータはAPIを呼ぶ前に拒否する
+        ModelCapabilities::for_model(&self.model)
+            .check(self)
+            .map_err(|reason| ValidationError::UnsupportedParameter(self.model.clone(), reason))?;
+
         Ok(())
     }
 }
 
+/// 生成画像のデフォルトMIMEタイプ（APIレスポンスに含まれない場合）
+pub const DEFAULT_IMAGE_MIME_TYPE: &str = "image/png";
+
+/// 生成に使ったトークン数（APIレスポンスの`usageMetadata`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub candidate_tokens: u64,
+    pub total_tokens: u64,
+}
+
 /// 生成された画像データ
 #[derive(Debug, Clone)]
 pub struct GeneratedImage {
     pub data: Vec<u8>,
+    pub mime_type: String,
     pub model: GeminiModel,
     pub generated_at: chrono::DateTime<chrono::Utc>,
+    /// トークン数（APIが返さない場合や、キャッシュなどAPIを呼ばずに返した場合は`None`）
+    pub usage: Option<TokenUsage>,
+    /// 生成に使った認証情報（APIキーは末尾4文字、Vertex AIはサービスアカウント。APIを呼ばずに返した場合は`None`）
+    pub api_key: Option<String>,
 }
 
 impl GeneratedImage {
     pub fn new(data: Vec<u8>, model: GeminiModel) -> Self {
         Self {
             data,
+            mime_type: DEFAULT_IMAGE_MIME_TYPE.to_string(),
             model,
             generated_at: chrono::Utc::now(),
+            usage: None,
+            api_key: None,
         }
     }
+
+    pub fn with_mime_type(mut self, mime_type: String) -> Self {
+        self.mime_type = mime_type;
+        self
+    }
+
+    pub fn with_usage(mut self, usage: TokenUsage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// APIを呼んで生成した画像か（キャッシュなどから返した画像は利用量・認証情報を持たない）
+    pub fn is_billable(&self) -> bool {
+        self.usage.is_some() || self.api_key.is_some()
+    }
+
+    /// APIを呼ばずに返す場合（キャッシュ・同時実行のまとめ）の複製（利用量を二重に数えないよう除く）
+    pub fn without_usage(&self) -> Self {
+        Self {
+            usage: None,
+            api_key: None,
+            ..self.clone()
+        }
+    }
+
+    /// MIMEタイプに対応するファイル拡張子を取得
+    pub fn file_extension(&self) -> &'static str {
+        extension_for_mime_type(&self.mime_type)
+    }
+}
+
+/// 生成画像の来歴メタデータ（サイドカーJSONや画像への埋め込みに使用）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub request_id: String,
+    pub prompt: String,
+    pub model: GeminiModel,
+    pub generated_at: chrono::DateTime<chrono::Utc>,
+    pub mime_type: String,
+    pub size_bytes: usize,
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ImageMetadata {
+    /// リクエストと生成結果からメタデータを作成
+    pub fn new(request: &ImageGenerationRequest, image: &GeneratedImage) -> Self {
+        Self {
+            request_id: request.request_id.clone(),
+            prompt: request.prompt.clone(),
+            model: image.model.clone(),
+            generated_at: image.generated_at,
+            mime_type: image.mime_type.clone(),
+            size_bytes: image.data.len(),
+            parameters: request.parameters(),
+        }
+    }
+}
+
+/// MIMEタイプからファイル拡張子を決定する（不明な場合は`bin`）
+pub fn extension_for_mime_type(mime_type: &str) -> &'static str {
+    match mime_type.to_ascii_lowercase().as_str() {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "image/heic" => "heic",
+        "image/heif" => "heif",
+        _ => "bin",
+    }
+}
+
+/// バイト列を16進数文字列にする
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// データのSHA-256を16進数文字列で取得
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    to_hex(&Sha256::digest(data))
+}
+
+/// バイト列をbase64文字列としてシリアライズする
+mod base64_bytes {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 /// モデルパースエラー
@@ -167,4 +618,6 @@ pub enum ValidationError {
     EmptyPrompt,
     #[error("Prompt too long: {0} characters (max: 10000)")]
     PromptTooLong(usize),
+    #[error("Model {0} does not support this request: {1}")]
+    UnsupportedParameter(GeminiModel, String),
 }
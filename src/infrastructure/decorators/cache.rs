@@ -0,0 +1,165 @@
+use crate::domain::{
+    GeneratedImage, ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// キャッシュの有効期限のデフォルト
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+/// キャッシュの合計サイズ上限のデフォルト（バイト）
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// 同じ内容のリクエストに保存済みの結果を返すキャッシュ
+///
+/// リクエストの`fingerprint()`をキーにし、成功した結果だけを保持する。
+/// エージェントが同じ呼び出しを再試行した場合に、APIの呼び出しと課金を避けるために使う。
+pub struct CachingRepository<R> {
+    inner: R,
+    ttl: Duration,
+    max_bytes: u64,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+}
+
+struct CacheEntry {
+    image: GeneratedImage,
+    stored_at: Instant,
+    last_used: Instant,
+}
+
+impl<R: ImageGenerationRepository> CachingRepository<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            ttl: DEFAULT_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    /// キャッシュした結果の有効期限を設定
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// キャッシュの合計サイズ上限（画像データのバイト数）を設定
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// キャッシュされている結果の件数
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.entries.len()
+    }
+
+    /// キャッシュが空か
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    async fn lookup(&self, key: &str) -> Option<GeneratedImage> {
+        let mut cache = self.entries.lock().await;
+        let now = Instant::now();
+        let expired = match cache.entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.stored_at) < self.ttl => {
+                entry.last_used = now;
+                return Some(entry.image.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            cache.remove(key);
+        }
+        None
+    }
+
+    async fn store(&self, key: String, image: &GeneratedImage) {
+        let size = image.data.len() as u64;
+        // 上限を超える画像はキャッシュしない
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut cache = self.entries.lock().await;
+        cache.remove(&key);
+
+        // 期限切れのものを捨て、それでも上限を超える場合は最も長く使われていないものから捨てる
+        let now = Instant::now();
+        let ttl = self.ttl;
+        let expired: Vec<String> = cache
+            .entries
+            .iter()
+            .filter(|(_, entry)| now.duration_since(entry.stored_at) >= ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            cache.remove(&key);
+        }
+        while cache.total_bytes + size > self.max_bytes {
+            let Some(oldest) = cache
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            cache.remove(&oldest);
+        }
+
+        cache.total_bytes += size;
+        cache.entries.insert(
+            key,
+            CacheEntry {
+                image: image.clone(),
+                stored_at: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+impl CacheEntries {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.image.data.len() as u64;
+        }
+    }
+}
+
+#[async_trait]
+impl<R: ImageGenerationRepository> ImageGenerationRepository for CachingRepository<R> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let key = request.fingerprint();
+
+        // bypass_cacheの場合は保存済みの結果を使わないが、新しい結果でキャッシュを更新する
+        if !request.bypass_cache {
+            if let Some(image) = self.lookup(&key).await {
+                debug!("Response cache hit for request {}", request.request_id);
+                return Ok(image.without_usage());
+            }
+        }
+
+        let image = self.inner.generate_image(request).await?;
+        // フォールバックにより別のモデルで生成した結果は、要求されたモデルの結果としてキャッシュしない
+        if image.model == request.model {
+            self.store(key, &image).await;
+        }
+        Ok(image)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::domain::{
+    GeneratedImage, ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+type SharedResult = Arc<OnceCell<Result<GeneratedImage, ImageGenerationError>>>;
+
+/// 同じ内容のリクエストが同時に実行された場合に、APIの呼び出しを1回にまとめる
+///
+/// リクエストの`fingerprint()`ごとに実行中の結果を共有し、待っているすべての呼び出し元に同じ結果を返す。
+/// 最初の呼び出し元がキャンセルされた場合は、待っている呼び出し元のひとつが代わりに実行する。
+pub struct CoalescingRepository<R> {
+    inner: R,
+    in_flight: Mutex<HashMap<String, SharedResult>>,
+}
+
+impl<R: ImageGenerationRepository> CoalescingRepository<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 実行中のリクエストの数
+    pub fn in_flight(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, SharedResult>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl<R: ImageGenerationRepository> ImageGenerationRepository for CoalescingRepository<R> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        // キャッシュを使わない指定のリクエストは、常に新しい画像を生成する
+        if request.bypass_cache {
+            return self.inner.generate_image(request).await;
+        }
+
+        let key = request.fingerprint();
+        let shared = {
+            let mut in_flight = self.lock();
+            match in_flight.get(&key) {
+                Some(shared) => {
+                    debug!(
+                        "Coalescing request {} with an identical in-flight request",
+                        request.request_id
+                    );
+                    Arc::clone(shared)
+                }
+                None => {
+                    let shared = SharedResult::default();
+                    in_flight.insert(key.clone(), Arc::clone(&shared));
+                    shared
+                }
+            }
+        };
+
+        let mut executed = false;
+        let result = shared
+            .get_or_init(|| {
+                executed = true;
+                self.inner.generate_image(request)
+            })
+            .await
+            .clone();
+        // まとめられた側はAPIを呼んでいないため、利用量を含めない
+        let result = if executed {
+            result
+        } else {
+            result.map(|image| image.without_usage())
+        };
+
+        // 完了したら取り除く（その後に始まった同じ内容のリクエストのエントリは残す）
+        let mut in_flight = self.lock();
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &shared))
+        {
+            in_flight.remove(&key);
+        }
+        result
+    }
+}
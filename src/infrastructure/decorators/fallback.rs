@@ -0,0 +1,94 @@
+use crate::config::FallbackTriggers;
+use crate::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest, ModelCapabilities,
+};
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+/// 要求されたモデルで生成できなかった場合に、設定された順にほかのモデルで再試行する
+///
+/// 要求されたモデルがチェーンに含まれている場合はそれより後のモデルを、
+/// 含まれていない場合はチェーンのすべてのモデルを順に試す。
+/// 実際に生成したモデルは`GeneratedImage::model`で分かる。
+pub struct FallbackRepository<R> {
+    inner: R,
+    chain: Vec<String>,
+    triggers: FallbackTriggers,
+}
+
+impl<R: ImageGenerationRepository> FallbackRepository<R> {
+    pub fn new(inner: R, chain: Vec<String>) -> Self {
+        Self {
+            inner,
+            chain,
+            triggers: FallbackTriggers::default(),
+        }
+    }
+
+    /// フォールバックするエラーの種類を設定
+    pub fn with_triggers(mut self, triggers: FallbackTriggers) -> Self {
+        self.triggers = triggers;
+        self
+    }
+
+    /// 要求されたモデルの次に試すモデル（許可されていないモデルと、リクエストに対応しないモデルは除く）
+    fn fallbacks_for(&self, request: &ImageGenerationRequest) -> Vec<GeminiModel> {
+        let model = &request.model;
+        let start = self
+            .chain
+            .iter()
+            .position(|m| m == model.as_str())
+            .map_or(0, |i| i + 1);
+        self.chain[start..]
+            .iter()
+            .filter(|m| m.as_str() != model.as_str())
+            .map(|m| GeminiModel::from(m.clone()))
+            .filter(GeminiModel::is_allowed)
+            .filter(|m| match ModelCapabilities::for_model(m).check(request) {
+                Ok(()) => true,
+                Err(reason) => {
+                    debug!("Skipping fallback model {}: {}", m, reason);
+                    false
+                }
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<R: ImageGenerationRepository> ImageGenerationRepository for FallbackRepository<R> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let mut error = match self.inner.generate_image(request).await {
+            Ok(image) => return Ok(image),
+            Err(e) => e,
+        };
+
+        let mut failed_model = request.model.clone();
+        for model in self.fallbacks_for(request) {
+            if !self.triggers.matches(&error) {
+                break;
+            }
+            warn!(
+                "Model {} failed for request {} ({}), falling back to {}",
+                failed_model, request.request_id, error, model
+            );
+
+            let fallback = ImageGenerationRequest {
+                model: model.clone(),
+                ..request.clone()
+            };
+            match self.inner.generate_image(&fallback).await {
+                Ok(image) => return Ok(image),
+                Err(e) => {
+                    error = e;
+                    failed_model = model;
+                }
+            }
+        }
+        Err(error)
+    }
+}
@@ -0,0 +1,9 @@
+pub mod cache;
+pub mod coalesce;
+pub mod fallback;
+pub mod rate_limit;
+
+pub use cache::CachingRepository;
+pub use coalesce::CoalescingRepository;
+pub use fallback::FallbackRepository;
+pub use rate_limit::RateLimitedRepository;
@@ -0,0 +1,220 @@
+use crate::config::RateLimits;
+use crate::domain::{
+    GeneratedImage, ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// 制限を超えた場合に待つ時間の上限のデフォルト
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// クライアント側でAPIの呼び出しを制限するトークンバケット方式のレートリミッター
+///
+/// 制限はモデルごとに適用する。制限に達したリクエストは順番に待ち、
+/// 待ち時間が上限を超える場合は推定待ち時間付きの`RateLimitError`を返す。
+pub struct RateLimitedRepository<R> {
+    inner: R,
+    default_limits: RateLimits,
+    model_limits: HashMap<String, RateLimits>,
+    max_wait: Duration,
+    limiters: Mutex<HashMap<String, Arc<ModelLimiter>>>,
+}
+
+/// 1モデル分の制限の状態
+struct ModelLimiter {
+    concurrency: Option<(usize, Semaphore)>,
+    buckets: Mutex<Vec<TokenBucket>>,
+}
+
+/// トークンバケット（残りが負の場合は、その分だけ先に予約されている）
+struct TokenBucket {
+    description: &'static str,
+    limit: u32,
+    tokens: f64,
+    per_second: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(description: &'static str, limit: u32, period: Duration) -> Self {
+        Self {
+            description,
+            limit,
+            tokens: f64::from(limit),
+            per_second: f64::from(limit) / period.as_secs_f64(),
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(f64::from(self.limit));
+        self.updated_at = now;
+    }
+
+    /// トークンを1つ予約し、使えるようになるまでの待ち時間を返す
+    fn reserve(&mut self) -> Duration {
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+impl ModelLimiter {
+    fn new(limits: RateLimits) -> Self {
+        let mut buckets = Vec::new();
+        if let Some(limit) = limits.requests_per_minute.filter(|&l| l > 0) {
+            buckets.push(TokenBucket::new(
+                "requests per minute",
+                limit,
+                Duration::from_secs(60),
+            ));
+        }
+        if let Some(limit) = limits.images_per_day.filter(|&l| l > 0) {
+            buckets.push(TokenBucket::new(
+                "images per day",
+                limit,
+                Duration::from_secs(24 * 60 * 60),
+            ));
+        }
+        Self {
+            concurrency: limits
+                .max_concurrency
+                .filter(|&n| n > 0)
+                .map(|n| (n, Semaphore::new(n))),
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    /// すべてのバケットからトークンを予約する
+    ///
+    /// 待ち時間が`max_wait`を超える場合は予約を取り消し、最も長い待ち時間と制限の説明を返す。
+    fn reserve(&self, max_wait: Duration) -> Result<Duration, (Duration, &'static str, u32)> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        let mut wait = Duration::ZERO;
+        let mut binding = None;
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+            let bucket_wait = bucket.reserve();
+            if bucket_wait > wait {
+                wait = bucket_wait;
+                binding = Some((bucket.description, bucket.limit));
+            }
+        }
+
+        match binding {
+            Some((description, limit)) if wait > max_wait => {
+                for bucket in buckets.iter_mut() {
+                    bucket.tokens += 1.0;
+                }
+                Err((wait, description, limit))
+            }
+            _ => Ok(wait),
+        }
+    }
+}
+
+impl<R: ImageGenerationRepository> RateLimitedRepository<R> {
+    /// すべてのモデルに`limits`を適用するレートリミッターを作成
+    pub fn new(inner: R, limits: RateLimits) -> Self {
+        Self {
+            inner,
+            default_limits: limits,
+            model_limits: HashMap::new(),
+            max_wait: DEFAULT_MAX_WAIT,
+            limiters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 特定のモデルの制限を設定
+    pub fn with_model_limits(mut self, model: impl Into<String>, limits: RateLimits) -> Self {
+        self.model_limits.insert(model.into(), limits);
+        self
+    }
+
+    /// 制限に達した場合に待つ時間の上限を設定
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    fn limiter(&self, model: &str) -> Arc<ModelLimiter> {
+        let mut limiters = self.limiters.lock().unwrap_or_else(|e| e.into_inner());
+        let limits = self
+            .model_limits
+            .get(model)
+            .copied()
+            .unwrap_or(self.default_limits);
+        Arc::clone(
+            limiters
+                .entry(model.to_string())
+                .or_insert_with(|| Arc::new(ModelLimiter::new(limits))),
+        )
+    }
+}
+
+#[async_trait]
+impl<R: ImageGenerationRepository> ImageGenerationRepository for RateLimitedRepository<R> {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let model = request.model.as_str();
+        let limiter = self.limiter(model);
+        let deadline = Instant::now() + self.max_wait;
+
+        // 同時実行数の上限に達している場合は、空くまで待つ
+        let _permit = match &limiter.concurrency {
+            Some((max, semaphore)) => {
+                match tokio::time::timeout_at(deadline, semaphore.acquire()).await {
+                    Ok(permit) => Some(permit.map_err(|e| {
+                        ImageGenerationError::Unknown(format!("Rate limiter closed: {}", e))
+                    })?),
+                    // 待った時間を再試行までの目安として返す
+                    Err(_) => {
+                        return Err(ImageGenerationError::RateLimitError {
+                            message: format!(
+                                "client-side limit of {} concurrent requests reached for model {}",
+                                max, model
+                            ),
+                            retry_after: Some(self.max_wait),
+                        })
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let wait = limiter
+            .reserve(remaining)
+            .map_err(
+                |(wait, description, limit)| ImageGenerationError::RateLimitError {
+                    message: format!(
+                        "client-side limit of {} {} reached for model {}",
+                        limit, description, model
+                    ),
+                    retry_after: Some(wait),
+                },
+            )?;
+        if !wait.is_zero() {
+            debug!(
+                "Request {} queued for {:?} by the rate limiter",
+                request.request_id, wait
+            );
+            tokio::time::sleep(wait).await;
+        }
+
+        self.inner.generate_image(request).await
+    }
+}
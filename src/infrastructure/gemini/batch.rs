@@ -0,0 +1,492 @@
+use crate::domain::{GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRequest};
+use crate::infrastructure::gemini::client::{
+    error_from_response, image_from_json, service_error_from_response, GeminiRequest,
+    API_KEY_HEADER,
+};
+use crate::infrastructure::gemini::files::FilesClient;
+use crate::infrastructure::gemini::key_pool::{key_id, mask_key, ApiKeyLease, ApiKeyPool};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// インラインで送るリクエストの合計サイズの上限（自動選択の場合、超えるとファイルで送る）
+const INLINE_MAX_BYTES: usize = 20 * 1024 * 1024;
+
+/// 完了を待つ間の状態確認の間隔のデフォルト
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 完了を待つ時間の上限のデフォルト（Batch APIは48時間で期限切れにする）
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// バッチのリクエストの送り方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchInputMode {
+    /// サイズに応じてインラインかファイルを選ぶ
+    #[default]
+    Auto,
+    /// リクエストボディに含めて送る
+    Inline,
+    /// JSONLファイルをFiles APIにアップロードして送る
+    File,
+}
+
+impl std::str::FromStr for BatchInputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "inline" => Ok(Self::Inline),
+            "file" => Ok(Self::File),
+            other => Err(format!("Unknown batch input mode: {}", other)),
+        }
+    }
+}
+
+/// バッチの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Expired,
+    Unspecified,
+}
+
+impl BatchState {
+    /// APIの状態（`BATCH_STATE_RUNNING`、旧形式の`JOB_STATE_RUNNING`など）から変換
+    fn from_api(state: &str) -> Self {
+        let state = state
+            .strip_prefix("BATCH_STATE_")
+            .or_else(|| state.strip_prefix("JOB_STATE_"))
+            .unwrap_or(state);
+        match state {
+            "PENDING" | "QUEUED" => Self::Pending,
+            "RUNNING" => Self::Running,
+            "SUCCEEDED" => Self::Succeeded,
+            "FAILED" => Self::Failed,
+            "CANCELLED" => Self::Cancelled,
+            "EXPIRED" => Self::Expired,
+            _ => Self::Unspecified,
+        }
+    }
+
+    /// 終了した状態か
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Self::Succeeded | Self::Failed | Self::Cancelled | Self::Expired
+        )
+    }
+}
+
+/// Batch APIのバッチ（長時間実行オペレーション）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchJob {
+    /// バッチ名（`batches/...`）
+    pub name: String,
+    pub state: BatchState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub succeeded_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub create_time: Option<String>,
+    /// 結果のファイル（ファイルで送ったバッチの場合）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responses_file: Option<String>,
+    /// オペレーションのエラー
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// インラインの結果（インラインで送ったバッチの場合）
+    #[serde(skip)]
+    inlined_responses: Vec<Value>,
+}
+
+impl BatchJob {
+    /// オペレーションのJSONから変換（結果は`response`、新しい形式では`metadata.output`にある）
+    fn from_operation(operation: &Value) -> Result<Self, ImageGenerationError> {
+        let metadata = &operation["metadata"];
+        let name = operation["name"]
+            .as_str()
+            .or_else(|| metadata["name"].as_str())
+            .ok_or_else(|| {
+                ImageGenerationError::ApiError("Batch operation has no name".to_string())
+            })?
+            .to_string();
+        let error = operation["error"]["message"].as_str().map(str::to_string);
+        let mut state = BatchState::from_api(metadata["state"].as_str().unwrap_or_default());
+        if state == BatchState::Unspecified && operation["done"].as_bool() == Some(true) {
+            state = if error.is_some() {
+                BatchState::Failed
+            } else {
+                BatchState::Succeeded
+            };
+        }
+        let output = if operation["response"].is_object() {
+            &operation["response"]
+        } else {
+            &metadata["output"]
+        };
+        let string = |value: &Value| value.as_str().map(str::to_string);
+        // int64は文字列で返される
+        let count = |value: &Value| {
+            value
+                .as_u64()
+                .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        };
+        let stats = &metadata["batchStats"];
+
+        Ok(Self {
+            name,
+            state,
+            display_name: string(&metadata["displayName"]),
+            model: string(&metadata["model"]),
+            request_count: count(&stats["requestCount"]),
+            succeeded_count: count(&stats["successfulRequestCount"]),
+            failed_count: count(&stats["failedRequestCount"]),
+            create_time: string(&metadata["createTime"]),
+            responses_file: string(&output["responsesFile"]),
+            error,
+            inlined_responses: output["inlinedResponses"]["inlinedResponses"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// バッチのモデル（`models/`の接頭辞は除く）
+    pub fn gemini_model(&self) -> Option<GeminiModel> {
+        self.model
+            .as_deref()
+            .map(|m| GeminiModel::from(m.strip_prefix("models/").unwrap_or(m).to_string()))
+    }
+}
+
+/// バッチの1件の結果
+#[derive(Debug)]
+pub struct BatchResponse {
+    /// 送信時のキー（リクエストID）
+    pub key: String,
+    pub result: Result<GeneratedImage, ImageGenerationError>,
+}
+
+/// Gemini APIのBatch API（`batchGenerateContent`）のクライアント
+///
+/// 通常の半額程度の料金で、多数のリクエストを非同期にまとめて処理する。結果は数分〜24時間で得られる。
+pub struct BatchClient {
+    keys: Arc<ApiKeyPool>,
+    api_base_url: String,
+    http_client: reqwest::Client,
+    files: FilesClient,
+    poll_interval: Duration,
+    wait_timeout: Duration,
+    /// バッチを作成したキー（状態の確認・結果の取得にも同じキーを使う）
+    leases: Mutex<HashMap<String, ApiKeyLease>>,
+}
+
+impl BatchClient {
+    pub fn new(keys: Arc<ApiKeyPool>, api_base_url: String) -> Self {
+        Self {
+            keys,
+            files: FilesClient::new(api_base_url.clone()),
+            api_base_url,
+            http_client: reqwest::Client::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            wait_timeout: DEFAULT_WAIT_TIMEOUT,
+            leases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 完了を待つ間の状態確認の間隔を設定
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// 完了を待つ時間の上限を設定
+    pub fn with_wait_timeout(mut self, wait_timeout: Duration) -> Self {
+        self.wait_timeout = wait_timeout;
+        self
+    }
+
+    /// リクエストをまとめてバッチを作成する（すべて同じGeminiモデルである必要がある）
+    ///
+    /// 各リクエストのIDを結果の対応付けのキーとして送る。
+    pub async fn submit(
+        &self,
+        requests: &[ImageGenerationRequest],
+        display_name: &str,
+        mode: BatchInputMode,
+    ) -> Result<BatchJob, ImageGenerationError> {
+        let model = &requests
+            .first()
+            .ok_or_else(|| {
+                ImageGenerationError::InvalidPromptError("Batch has no requests".to_string())
+            })?
+            .model;
+        if requests.iter().any(|r| r.model != *model) {
+            return Err(ImageGenerationError::InvalidPromptError(
+                "All requests in a batch must use the same model".to_string(),
+            ));
+        }
+        if model.is_imagen() {
+            return Err(ImageGenerationError::InvalidPromptError(format!(
+                "{} does not support batch generation",
+                model
+            )));
+        }
+
+        let inline: Vec<Value> = requests
+            .iter()
+            .map(|r| {
+                serde_json::json!({
+                    "request": GeminiRequest::new(r),
+                    "metadata": { "key": r.request_id }
+                })
+            })
+            .collect();
+        let use_file = match mode {
+            BatchInputMode::Auto => {
+                Value::Array(inline.clone()).to_string().len() > INLINE_MAX_BYTES
+            }
+            BatchInputMode::Inline => false,
+            BatchInputMode::File => true,
+        };
+
+        self.keys
+            .call_with_failover(|lease| {
+                let inline = &inline;
+                async move {
+                    let input_config = if use_file {
+                        let file = self
+                            .files
+                            .upload(
+                                &lease.key,
+                                requests_jsonl(requests).into_bytes(),
+                                "application/jsonl",
+                                display_name,
+                            )
+                            .await?;
+                        serde_json::json!({ "fileName": file.name })
+                    } else {
+                        serde_json::json!({ "requests": { "requests": inline } })
+                    };
+                    let url = format!(
+                        "{}/models/{}:batchGenerateContent",
+                        self.api_base_url, model
+                    );
+                    let response = self
+                        .http_client
+                        .post(&url)
+                        .header(API_KEY_HEADER, &lease.key)
+                        .json(&serde_json::json!({
+                            "batch": {
+                                "displayName": display_name,
+                                "inputConfig": input_config
+                            }
+                        }))
+                        .send()
+                        .await?;
+                    if !response.status().is_success() {
+                        return Err(error_from_response(response, model).await);
+                    }
+                    let operation: Value = response.json().await?;
+                    let job = BatchJob::from_operation(&operation)?;
+                    self.leases
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(job.name.clone(), lease.clone());
+                    Ok(job)
+                }
+            })
+            .await
+    }
+
+    /// バッチの状態を取得
+    pub async fn get(&self, name: &str) -> Result<BatchJob, ImageGenerationError> {
+        let lease = self.lease_for(name)?;
+        let response = self
+            .http_client
+            .get(format!("{}/{}", self.api_base_url, name))
+            .header(API_KEY_HEADER, &lease.key)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(service_error_from_response(response).await);
+        }
+        let operation: Value = response.json().await?;
+        BatchJob::from_operation(&operation)
+    }
+
+    /// バッチを取り消す
+    pub async fn cancel(&self, name: &str) -> Result<(), ImageGenerationError> {
+        let lease = self.lease_for(name)?;
+        let response = self
+            .http_client
+            .post(format!("{}/{}:cancel", self.api_base_url, name))
+            .header(API_KEY_HEADER, &lease.key)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(service_error_from_response(response).await);
+        }
+        Ok(())
+    }
+
+    /// バッチが終了するまで一定間隔で状態を確認する（上限の時間を過ぎたらエラー）
+    pub async fn wait(&self, name: &str) -> Result<BatchJob, ImageGenerationError> {
+        let deadline = tokio::time::Instant::now() + self.wait_timeout;
+        loop {
+            let job = self.get(name).await?;
+            if job.state.is_finished() {
+                return Ok(job);
+            }
+            tracing::debug!("Batch {} is {:?}", name, job.state);
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Err(ImageGenerationError::ApiError(format!(
+                    "Batch {} did not finish within {}s (still {:?})",
+                    name,
+                    self.wait_timeout.as_secs(),
+                    job.state
+                )));
+            }
+            tokio::time::sleep(self.poll_interval.min(deadline - now)).await;
+        }
+    }
+
+    /// 終了したバッチの結果を取得し、画像をデコードする（ファイルの結果はダウンロードする）
+    pub async fn results(
+        &self,
+        job: &BatchJob,
+    ) -> Result<Vec<BatchResponse>, ImageGenerationError> {
+        if !job.state.is_finished() {
+            return Err(ImageGenerationError::ApiError(format!(
+                "Batch {} has not finished yet",
+                job.name
+            )));
+        }
+        let model = job.gemini_model().unwrap_or_default();
+        let lease = self.lease_for(&job.name)?;
+
+        let entries = match &job.responses_file {
+            Some(file) => {
+                let data = self.files.download(&lease.key, file).await?;
+                String::from_utf8_lossy(&data)
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| {
+                        serde_json::from_str(line).map_err(|e| {
+                            ImageGenerationError::ApiError(format!(
+                                "Invalid batch result line: {}",
+                                e
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<Value>, _>>()?
+            }
+            None => job.inlined_responses.clone(),
+        };
+
+        Ok(entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let key = entry["key"]
+                    .as_str()
+                    .or_else(|| entry["metadata"]["key"].as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| index.to_string());
+                let result = match entry.get("error").filter(|e| !e.is_null()) {
+                    Some(error) => Err(ImageGenerationError::ApiError(
+                        error["message"]
+                            .as_str()
+                            .map(str::to_string)
+                            .unwrap_or_else(|| error.to_string()),
+                    )),
+                    None => image_from_json(entry["response"].clone(), &model)
+                        .map(|image| image.with_api_key(mask_key(&lease.key))),
+                };
+                BatchResponse { key, result }
+            })
+            .collect())
+    }
+
+    /// バッチを作成したキーの識別子（保存しておき、別のプロセスで[`BatchClient::restore_key`]に渡す）
+    pub fn key_id(&self, name: &str) -> Option<String> {
+        self.leases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .map(|lease| key_id(&lease.key))
+    }
+
+    /// 保存しておいた識別子からバッチを作成したキーを復元する（プールにない場合は`false`）
+    pub fn restore_key(&self, name: &str, key_id: &str) -> bool {
+        match self.keys.lease_by_id(key_id) {
+            Some(lease) => {
+                self.leases
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(name.to_string(), lease);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// バッチに使うキー（作成したキーが分からない場合はプールから選ぶ）
+    fn lease_for(&self, name: &str) -> Result<ApiKeyLease, ImageGenerationError> {
+        if !is_valid_batch_name(name) {
+            return Err(ImageGenerationError::InvalidPromptError(format!(
+                "Invalid batch name: {}",
+                name
+            )));
+        }
+        let known = self
+            .leases
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned();
+        known
+            .or_else(|| self.keys.acquire())
+            .ok_or_else(|| ImageGenerationError::RateLimitError {
+                message: format!("all {} API keys are quarantined", self.keys.len()),
+                retry_after: self.keys.next_available_in(),
+            })
+    }
+}
+
+/// ファイルで送る場合のJSONL（1行に1件、`key`と`request`）
+fn requests_jsonl(requests: &[ImageGenerationRequest]) -> String {
+    requests
+        .iter()
+        .map(|r| {
+            serde_json::json!({ "key": r.request_id, "request": GeminiRequest::new(r) }).to_string()
+                + "\n"
+        })
+        .collect()
+}
+
+/// バッチ名は`batches/`に英数字・`-`・`_`が続く形式のみ許可する（URLに使うため）
+fn is_valid_batch_name(name: &str) -> bool {
+    name.strip_prefix("batches/").is_some_and(|id| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
@@ -0,0 +1,286 @@
+use crate::domain::{GeminiModel, ImageGenerationError};
+use crate::infrastructure::gemini::client::{service_error_from_response, API_KEY_HEADER};
+use crate::infrastructure::gemini::key_pool::{ApiKeyLease, ApiKeyPool};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// 取得したモデル一覧をキャッシュする時間のデフォルト
+const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 取得に失敗した後、再取得せずに同じエラーを返す時間
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+
+/// `models.list`の1ページあたりの件数
+const PAGE_SIZE: u32 = 1000;
+
+/// APIから取得したモデルの情報
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    /// モデル名（`models/`の接頭辞は除く）
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub input_token_limit: Option<u64>,
+    #[serde(default)]
+    pub output_token_limit: Option<u64>,
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+}
+
+impl ModelInfo {
+    /// 画像を生成できるモデルか
+    ///
+    /// Imagenは`predict`、Geminiは`generateContent`に対応し名前に`image`を含むモデルを対象とする。
+    pub fn is_image_capable(&self) -> bool {
+        let supports = |method: &str| {
+            self.supported_generation_methods
+                .iter()
+                .any(|m| m == method)
+        };
+        let model = GeminiModel::from(self.name.clone());
+        if model.is_imagen() {
+            supports("predict")
+        } else {
+            supports("generateContent") && self.name.contains("image")
+        }
+    }
+}
+
+/// モデル一覧が変わったときに呼ばれるハンドラ
+type ChangeHandler = Arc<dyn Fn(&[ModelInfo]) + Send + Sync>;
+
+/// キャッシュの状態（ロックは取得中に保持しない）
+#[derive(Default)]
+struct CatalogState {
+    /// 最後に取得できた一覧と取得時刻
+    models: Option<(Instant, Vec<ModelInfo>)>,
+    /// 最後に取得に失敗した時刻とエラー
+    failure: Option<(Instant, ImageGenerationError)>,
+    /// APIから取得中か
+    fetching: bool,
+}
+
+/// `GET /models`で画像生成モデルの一覧を取得し、一定時間キャッシュする
+pub struct ModelCatalog {
+    keys: Arc<ApiKeyPool>,
+    api_base_url: String,
+    ttl: Duration,
+    failure_ttl: Duration,
+    http_client: reqwest::Client,
+    state: Mutex<CatalogState>,
+    change_handler: RwLock<Option<ChangeHandler>>,
+}
+
+impl ModelCatalog {
+    pub fn new(keys: Arc<ApiKeyPool>, api_base_url: String) -> Self {
+        Self {
+            keys,
+            api_base_url,
+            ttl: DEFAULT_TTL,
+            failure_ttl: FAILURE_TTL,
+            http_client: reqwest::Client::new(),
+            state: Mutex::new(CatalogState::default()),
+            change_handler: RwLock::new(None),
+        }
+    }
+
+    /// キャッシュの有効期間を設定
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// 取得に失敗した後、再取得を控える時間を設定
+    pub fn with_failure_ttl(mut self, failure_ttl: Duration) -> Self {
+        self.failure_ttl = failure_ttl;
+        self
+    }
+
+    /// 取得した一覧のモデル名が前回から変わったときに呼ばれるハンドラを設定
+    pub fn set_change_handler(&self, handler: impl Fn(&[ModelInfo]) + Send + Sync + 'static) {
+        *self
+            .change_handler
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(handler));
+    }
+
+    /// 画像生成モデルの一覧を取得（キャッシュが切れている場合はAPIから取得）
+    ///
+    /// 直前に取得に失敗している場合は、しばらく再取得せずに同じエラーを返す。
+    pub async fn models(&self) -> Result<Vec<ModelInfo>, ImageGenerationError> {
+        {
+            let state = self.lock_state();
+            if let Some((fetched_at, models)) = &state.models {
+                if fetched_at.elapsed() < self.ttl {
+                    return Ok(models.clone());
+                }
+            }
+            if let Some((failed_at, error)) = &state.failure {
+                if failed_at.elapsed() < self.failure_ttl {
+                    return Err(error.clone());
+                }
+            }
+        }
+        self.load().await
+    }
+
+    /// キャッシュを無視してAPIから取得し直す
+    pub async fn refresh(&self) -> Result<Vec<ModelInfo>, ImageGenerationError> {
+        self.load().await
+    }
+
+    /// 最後に取得できた一覧のモデル名（APIは呼ばない）
+    pub fn cached_names(&self) -> Option<Vec<String>> {
+        self.lock_state()
+            .models
+            .as_ref()
+            .map(|(_, models)| models.iter().map(|m| m.name.clone()).collect())
+    }
+
+    /// 一覧にモデルが含まれるか（一覧を取得できない場合は検証しないよう`None`を返す）
+    ///
+    /// 取得中や取得に失敗した直後は待たずに、古い一覧があればそれで判断する。
+    pub async fn contains(&self, model: &GeminiModel) -> Option<bool> {
+        let (stale, should_fetch) = {
+            let state = self.lock_state();
+            let known = state.models.as_ref().map(|(fetched_at, models)| {
+                let found = models.iter().any(|m| m.name == model.as_str());
+                (fetched_at.elapsed() < self.ttl, found)
+            });
+            if let Some((true, found)) = known {
+                return Some(found);
+            }
+            let recently_failed = state
+                .failure
+                .as_ref()
+                .is_some_and(|(failed_at, _)| failed_at.elapsed() < self.failure_ttl);
+            (
+                known.map(|(_, found)| found),
+                !state.fetching && !recently_failed,
+            )
+        };
+        if !should_fetch {
+            return stale;
+        }
+
+        match self.load().await {
+            Ok(models) => Some(models.iter().any(|m| m.name == model.as_str())),
+            Err(e) => {
+                warn!("Failed to fetch the model list, skipping validation: {}", e);
+                stale
+            }
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, CatalogState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// APIから取得してキャッシュを更新し、モデル名が変わった場合はハンドラを呼ぶ
+    async fn load(&self) -> Result<Vec<ModelInfo>, ImageGenerationError> {
+        self.lock_state().fetching = true;
+        let result = self.fetch().await;
+
+        let changed = {
+            let mut state = self.lock_state();
+            state.fetching = false;
+            match &result {
+                Ok(models) => {
+                    let names = |models: &[ModelInfo]| -> Vec<String> {
+                        models.iter().map(|m| m.name.clone()).collect()
+                    };
+                    let changed = state
+                        .models
+                        .as_ref()
+                        .is_none_or(|(_, previous)| names(previous) != names(models));
+                    state.models = Some((Instant::now(), models.clone()));
+                    state.failure = None;
+                    changed
+                }
+                Err(error) => {
+                    state.failure = Some((Instant::now(), error.clone()));
+                    false
+                }
+            }
+        };
+
+        if let (true, Ok(models)) = (changed, &result) {
+            let handler = self
+                .change_handler
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            if let Some(handler) = handler {
+                handler(models);
+            }
+        }
+        result
+    }
+
+    async fn fetch(&self) -> Result<Vec<ModelInfo>, ImageGenerationError> {
+        self.keys
+            .call_with_failover(|lease| async move { self.fetch_with(&lease).await })
+            .await
+    }
+
+    /// 1つのキーですべてのページを取得
+    async fn fetch_with(
+        &self,
+        lease: &ApiKeyLease,
+    ) -> Result<Vec<ModelInfo>, ImageGenerationError> {
+        let url = format!("{}/models", self.api_base_url);
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .http_client
+                .get(&url)
+                .header(API_KEY_HEADER, &lease.key)
+                .query(&[("pageSize", PAGE_SIZE.to_string())]);
+            if let Some(token) = &page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                return Err(service_error_from_response(response).await);
+            }
+
+            let page: ListModelsResponse = response.json().await?;
+            models.extend(
+                page.models
+                    .into_iter()
+                    .map(|mut model| {
+                        model.name = model
+                            .name
+                            .strip_prefix("models/")
+                            .map(str::to_string)
+                            .unwrap_or(model.name);
+                        model
+                    })
+                    .filter(ModelInfo::is_image_capable),
+            );
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+}
+
+/// `models.list`のレスポンスボディ
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelInfo>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
@@ -1,36 +1,67 @@
 use crate::domain::{
     GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
-    ImageGenerationRequest,
+    ImageGenerationRequest, InputImage, InputImageRole, TokenUsage,
 };
+use crate::infrastructure::gemini::files::{FilesClient, UploadedFile};
+use crate::infrastructure::gemini::key_pool::{mask_key, ApiKeyLease, ApiKeyPool};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// APIキーを送るヘッダー（URLに含めるとプロキシのログなどに残るため）
+pub(crate) const API_KEY_HEADER: &str = "x-goog-api-key";
+
+/// Files APIでアップロードする入力画像のサイズのデフォルト（これより大きい画像はbase64で埋め込まない）
+const DEFAULT_FILE_UPLOAD_THRESHOLD: usize = 4 * 1024 * 1024;
 
 /// Gemini APIクライアント
 pub struct GeminiClient {
-    api_key: String,
+    keys: Arc<ApiKeyPool>,
     api_base_url: String,
     http_client: reqwest::Client,
+    files: FilesClient,
+    file_upload_threshold: usize,
 }
 
 impl GeminiClient {
     pub fn new(api_key: String) -> Self {
         let api_base_url = std::env::var("GEMINI_API_BASE_URL")
             .unwrap_or_else(|_| "https://generativelanguage.googleapis.com/v1beta".to_string());
-        Self {
-            api_key,
-            api_base_url,
-            http_client: reqwest::Client::new(),
-        }
+        Self::with_base_url(api_key, api_base_url)
     }
 
     pub fn with_base_url(api_key: String, api_base_url: String) -> Self {
+        Self::with_key_pool(Arc::new(ApiKeyPool::new(vec![api_key])), api_base_url)
+    }
+
+    /// 複数のAPIキーを切り替えて使うクライアントを作成
+    pub fn with_key_pool(keys: Arc<ApiKeyPool>, api_base_url: String) -> Self {
         Self {
-            api_key,
+            keys,
+            files: FilesClient::new(api_base_url.clone()),
             api_base_url,
             http_client: reqwest::Client::new(),
+            file_upload_threshold: DEFAULT_FILE_UPLOAD_THRESHOLD,
         }
     }
 
+    /// Files APIでアップロードする入力画像のサイズ（バイト）を設定
+    pub fn with_file_upload_threshold(mut self, threshold: usize) -> Self {
+        self.file_upload_threshold = threshold;
+        self
+    }
+
+    /// Files APIのクライアントを設定（アップロードのチャンクサイズの変更など）
+    pub fn with_files_client(mut self, files: FilesClient) -> Self {
+        self.files = files;
+        self
+    }
+
+    /// APIキーのプールを取得
+    pub fn key_pool(&self) -> &Arc<ApiKeyPool> {
+        &self.keys
+    }
+
     /// URLを構築する（テスト用）
     #[doc(hidden)]
     pub fn build_url(&self, model: &GeminiModel) -> String {
@@ -43,62 +74,297 @@ impl ImageGenerationRepository for GeminiClient {
     async fn generate_image(
         &self,
         request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        self.keys
+            .call_with_failover(|lease| async move { self.send(request, &lease).await })
+            .await
+    }
+}
+
+impl GeminiClient {
+    /// 1つのキーでAPIを呼び出す
+    async fn send(
+        &self,
+        request: &ImageGenerationRequest,
+        lease: &ApiKeyLease,
     ) -> Result<GeneratedImage, ImageGenerationError> {
         let url = format!(
             "{}/models/{}:generateContent",
             self.api_base_url, request.model
         );
 
-        // Gemini APIのリクエストボディ
-        let request_body = GeminiRequest {
-            contents: vec![Content {
-                parts: vec![Part {
-                    text: request.prompt.clone(),
-                }],
-            }],
-        };
+        let mut files = self.upload_large_images(request, lease).await?;
+        let mut response = self.post(&url, request, &files, lease).await?;
+        // アップロード済みとして再利用したファイルが期限切れで削除されていた場合は、アップロードし直して1回だけ再試行する
+        if response.status() == reqwest::StatusCode::NOT_FOUND && files.iter().any(Option::is_some)
+        {
+            tracing::warn!("Uploaded input files were not found, uploading them again");
+            for file in files.iter().flatten() {
+                self.files.forget(&file.name);
+            }
+            files = self.upload_large_images(request, lease).await?;
+            response = self.post(&url, request, &files, lease).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response, &request.model).await);
+        }
+        let image = image_from_response(response, &request.model).await?;
+        Ok(image.with_api_key(mask_key(&lease.key)))
+    }
 
-        let response = self
+    /// アップロードしたファイルを参照してリクエストを送る
+    async fn post(
+        &self,
+        url: &str,
+        request: &ImageGenerationRequest,
+        files: &[Option<UploadedFile>],
+        lease: &ApiKeyLease,
+    ) -> Result<reqwest::Response, ImageGenerationError> {
+        Ok(self
             .http_client
-            .post(&url)
-            .query(&[("key", &self.api_key)])
-            .json(&request_body)
+            .post(url)
+            .header(API_KEY_HEADER, &lease.key)
+            .json(&GeminiRequest::with_files(request, files))
             .send()
-            .await?;
-
-        let status = response.status();
-
-        // エラーレスポンスの処理
-        if !status.is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return match status.as_u16() {
-                401 => Err(ImageGenerationError::AuthenticationError(
-                    "Invalid API key".to_string(),
-                )),
-                429 => Err(ImageGenerationError::RateLimitError(
-                    "Rate limit exceeded".to_string(),
-                )),
-                400 => Err(ImageGenerationError::InvalidPromptError(error_text)),
-                _ => Err(ImageGenerationError::ApiError(format!(
-                    "API returned status {}: {}",
-                    status, error_text
-                ))),
+            .await?)
+    }
+
+    /// しきい値より大きい入力画像をFiles APIにアップロードする（アップロード済みの内容は再利用）
+    async fn upload_large_images(
+        &self,
+        request: &ImageGenerationRequest,
+        lease: &ApiKeyLease,
+    ) -> Result<Vec<Option<UploadedFile>>, ImageGenerationError> {
+        let mut files = Vec::with_capacity(request.input_images.len());
+        for image in &request.input_images {
+            let file = if image.data.len() > self.file_upload_threshold {
+                Some(
+                    self.files
+                        .upload_cached(&lease.key, &image.data, &image.mime_type)
+                        .await?,
+                )
+            } else {
+                None
             };
+            files.push(file);
         }
+        Ok(files)
+    }
+}
 
-        let response_body: GeminiResponse = response.json().await?;
+/// エラーレスポンスをエラーに変換する（Vertex AIも同じ形式）
+pub(crate) async fn error_from_response(
+    response: reqwest::Response,
+    model: &GeminiModel,
+) -> ImageGenerationError {
+    response_error(response, Some(model)).await
+}
 
-        // レスポンスから画像データを抽出
-        let image_data = extract_image_data(&response_body)?;
+/// モデルに対するリクエストでないエラーレスポンス（バッチ・ファイル・モデル一覧）をエラーに変換する
+///
+/// 503はモデルの過負荷として扱わない（フォールバックの対象にしない）。
+pub(crate) async fn service_error_from_response(
+    response: reqwest::Response,
+) -> ImageGenerationError {
+    response_error(response, None).await
+}
 
-        Ok(GeneratedImage::new(image_data, request.model.clone()))
+async fn response_error(
+    response: reqwest::Response,
+    model: Option<&GeminiModel>,
+) -> ImageGenerationError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(std::time::Duration::from_secs);
+    let error_text = response.text().await.unwrap_or_default();
+    match status.as_u16() {
+        401 => ImageGenerationError::invalid_api_key(None),
+        // 無効なキーは400・403で`API_KEY_INVALID`の理由が返される
+        400 | 403 if error_text.contains("API_KEY_INVALID") => {
+            ImageGenerationError::invalid_api_key(Some(&error_text))
+        }
+        403 if model.is_none() => ImageGenerationError::AuthenticationError(format!(
+            "API key is not permitted: {}",
+            error_text
+        )),
+        403 => ImageGenerationError::AuthenticationError(format!(
+            "API key is not permitted to use this model: {}",
+            error_text
+        )),
+        429 => ImageGenerationError::RateLimitError {
+            message: "Gemini API returned 429 Too Many Requests".to_string(),
+            retry_after,
+        },
+        400 => ImageGenerationError::InvalidPromptError(error_text),
+        503 => match model {
+            Some(model) => ImageGenerationError::ModelOverloaded(format!(
+                "{} is unavailable: {}",
+                model, error_text
+            )),
+            None => {
+                ImageGenerationError::ApiError(format!("Service is unavailable: {}", error_text))
+            }
+        },
+        _ => ImageGenerationError::ApiError(format!(
+            "API returned status {}: {}",
+            status, error_text
+        )),
     }
 }
 
+/// 成功レスポンスから生成画像を取り出す（Vertex AIも同じ形式）
+pub(crate) async fn image_from_response(
+    response: reqwest::Response,
+    model: &GeminiModel,
+) -> Result<GeneratedImage, ImageGenerationError> {
+    let response_body: GeminiResponse = response.json().await?;
+    image_from_body(response_body, model)
+}
+
+/// JSONのレスポンスボディから生成画像を取り出す（Batch APIの結果など）
+pub(crate) fn image_from_json(
+    body: serde_json::Value,
+    model: &GeminiModel,
+) -> Result<GeneratedImage, ImageGenerationError> {
+    let response_body: GeminiResponse = serde_json::from_value(body)
+        .map_err(|e| ImageGenerationError::ApiError(format!("Invalid response body: {}", e)))?;
+    image_from_body(response_body, model)
+}
+
+fn image_from_body(
+    response_body: GeminiResponse,
+    model: &GeminiModel,
+) -> Result<GeneratedImage, ImageGenerationError> {
+    // レスポンスから画像データを抽出
+    let (image_data, mime_type) = extract_image_data(&response_body)?;
+
+    let mut image = GeneratedImage::new(image_data, model.clone());
+    if let Some(mime_type) = mime_type {
+        image = image.with_mime_type(mime_type);
+    }
+    if let Some(usage) = response_body.usage_metadata {
+        image = image.with_usage(TokenUsage {
+            prompt_tokens: usage.prompt_token_count,
+            candidate_tokens: usage.candidates_token_count,
+            total_tokens: usage.total_token_count,
+        });
+    }
+    Ok(image)
+}
+
 /// Gemini APIリクエストボディ
 #[derive(Debug, Serialize)]
-struct GeminiRequest {
+pub(crate) struct GeminiRequest {
     contents: Vec<Content>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+impl GeminiRequest {
+    /// プロンプトと入力画像からリクエストボディを作成
+    ///
+    /// 参照画像（画風・人物など）は役割のラベルを付けてプロンプトの前に、編集する画像はプロンプトの後に添付する。
+    pub(crate) fn new(request: &ImageGenerationRequest) -> Self {
+        Self::with_files(request, &[])
+    }
+
+    /// アップロード済みの入力画像（`files`の同じ位置）は`fileData`で参照し、残りはbase64で埋め込む
+    pub(crate) fn with_files(
+        request: &ImageGenerationRequest,
+        files: &[Option<UploadedFile>],
+    ) -> Self {
+        use base64::Engine;
+        let image_part = |i: usize, image: &InputImage| match files.get(i).and_then(Option::as_ref)
+        {
+            Some(file) => Part::FileData {
+                file_data: RequestFileData {
+                    mime_type: file
+                        .mime_type
+                        .clone()
+                        .unwrap_or_else(|| image.mime_type.clone()),
+                    file_uri: file.uri.clone().unwrap_or_else(|| file.name.clone()),
+                },
+            },
+            None => Part::InlineData {
+                inline_data: RequestInlineData {
+                    mime_type: image.mime_type.clone(),
+                    data: base64::engine::general_purpose::STANDARD.encode(&image.data),
+                },
+            },
+        };
+
+        // 参照画像は役割ごとにまとめ、使い方を添えたラベルの後に添付する
+        let references = [
+            (
+                InputImageRole::Style,
+                "Style reference image",
+                "match its visual style, color palette and rendering, not its content.",
+            ),
+            (
+                InputImageRole::Subject,
+                "Subject reference image",
+                "keep this subject's identity and appearance consistent in the generated image.",
+            ),
+        ];
+        let mut parts = Vec::new();
+        for (role, label, guidance) in references {
+            let images = request
+                .input_images
+                .iter()
+                .enumerate()
+                .filter(|(_, image)| image.role == role);
+            for (number, (i, image)) in images.enumerate() {
+                parts.push(Part::Text {
+                    text: format!("{} {}: {}", label, number + 1, guidance),
+                });
+                parts.push(image_part(i, image));
+            }
+        }
+        let text = if parts.is_empty() {
+            request.prompt.clone()
+        } else {
+            format!("Instruction: {}", request.prompt)
+        };
+        parts.push(Part::Text { text });
+        parts.extend(
+            request
+                .input_images
+                .iter()
+                .enumerate()
+                .filter(|(_, image)| image.role.is_edit())
+                .map(|(i, image)| image_part(i, image)),
+        );
+        Self {
+            contents: vec![Content { parts }],
+            generation_config: (request.aspect_ratio.is_some() || request.image_size.is_some())
+                .then(|| GenerationConfig {
+                    image_config: ImageConfig {
+                        aspect_ratio: request.aspect_ratio.clone(),
+                        image_size: request.image_size.clone(),
+                    },
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct GenerationConfig {
+    #[serde(rename = "imageConfig")]
+    image_config: ImageConfig,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aspect_ratio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_size: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -107,14 +373,55 @@ struct Content {
 }
 
 #[derive(Debug, Serialize)]
-struct Part {
-    text: String,
+#[serde(untagged)]
+enum Part {
+    Text {
+        text: String,
+    },
+    InlineData {
+        #[serde(rename = "inlineData")]
+        inline_data: RequestInlineData,
+    },
+    FileData {
+        #[serde(rename = "fileData")]
+        file_data: RequestFileData,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct RequestInlineData {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    data: String, // base64エンコードされた画像データ
+}
+
+/// Files APIでアップロードしたファイルの参照
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestFileData {
+    mime_type: String,
+    file_uri: String,
 }
 
 /// Gemini APIレスポンスボディ
 #[derive(Debug, Deserialize)]
 struct GeminiResponse {
+    // 安全フィルターでブロックされた場合などは候補が返されない
+    #[serde(default)]
     candidates: Vec<Candidate>,
+    #[serde(rename = "usageMetadata", default)]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    #[serde(default)]
+    total_token_count: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -136,33 +443,36 @@ struct ResponsePart {
 #[derive(Debug, Deserialize)]
 struct InlineData {
     #[serde(rename = "mimeType")]
-    #[allow(dead_code)]
-    mime_type: Option<String>, // APIレスポンスに含まれる可能性があるが、現在は未使用（将来の拡張用）
+    mime_type: Option<String>,
     data: String, // base64エンコードされた画像データ
 }
 
-/// レスポンスから画像データを抽出
-fn extract_image_data(response: &GeminiResponse) -> Result<Vec<u8>, ImageGenerationError> {
+/// レスポンスから画像データとMIMEタイプを抽出
+fn extract_image_data(
+    response: &GeminiResponse,
+) -> Result<(Vec<u8>, Option<String>), ImageGenerationError> {
     let candidate = response
         .candidates
         .first()
-        .ok_or_else(|| ImageGenerationError::ApiError("No candidates in response".to_string()))?;
+        .ok_or_else(|| ImageGenerationError::NoImage("No candidates in response".to_string()))?;
 
     let part = candidate
         .content
         .parts
         .iter()
         .find(|p| p.inline_data.is_some())
-        .ok_or_else(|| ImageGenerationError::ApiError("No image data in response".to_string()))?;
+        .ok_or_else(|| ImageGenerationError::NoImage("No image data in response".to_string()))?;
 
     let inline_data = part
         .inline_data
         .as_ref()
-        .ok_or_else(|| ImageGenerationError::ApiError("No inline data".to_string()))?;
+        .ok_or_else(|| ImageGenerationError::NoImage("No inline data".to_string()))?;
 
     // base64デコード
     use base64::Engine;
-    base64::engine::general_purpose::STANDARD
+    let data = base64::engine::general_purpose::STANDARD
         .decode(&inline_data.data)
-        .map_err(|e| ImageGenerationError::ApiError(format!("Failed to decode base64: {}", e)))
+        .map_err(|e| ImageGenerationError::ApiError(format!("Failed to decode base64: {}", e)))?;
+
+    Ok((data, inline_data.mime_type.clone()))
 }
@@ -0,0 +1,332 @@
+use crate::domain::models::sha256_hex;
+use crate::domain::ImageGenerationError;
+use crate::infrastructure::gemini::client::{service_error_from_response, API_KEY_HEADER};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// アップロードで1回に送るサイズの単位（最後以外のチャンクはこの倍数である必要がある）
+const CHUNK_GRANULARITY: usize = 256 * 1024;
+
+/// アップロードで1回に送るサイズのデフォルト
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// 送信に失敗したチャンクを再開する回数の上限
+const MAX_RESUME_ATTEMPTS: u32 = 3;
+
+/// 送信に失敗したチャンクを再開するまでの待ち時間の初期値（再開するごとに倍にする）
+const DEFAULT_RESUME_DELAY: Duration = Duration::from_millis(500);
+
+/// 有効期限が返されなかった場合の保持期間（Files APIは48時間で削除する）
+const DEFAULT_FILE_LIFETIME: chrono::Duration = chrono::Duration::hours(48);
+
+/// アップロード済みのファイルを再利用する場合に残っている必要がある有効期間
+const REUSE_MARGIN: chrono::Duration = chrono::Duration::hours(1);
+
+/// Files APIにアップロードしたファイル
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    /// ファイル名（`files/...`）
+    pub name: String,
+    /// リクエストの`fileData.fileUri`に指定するURI
+    #[serde(default)]
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_int64")]
+    pub size_bytes: Option<u64>,
+    /// 内容のSHA-256（base64）
+    #[serde(default)]
+    pub sha256_hash: Option<String>,
+    /// 削除される日時
+    #[serde(default)]
+    pub expiration_time: Option<DateTime<Utc>>,
+    /// `PROCESSING`・`ACTIVE`・`FAILED`
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+impl UploadedFile {
+    /// 削除される日時（返されなかった場合はアップロードから48時間後とみなす）
+    fn expires_at(&self, uploaded_at: DateTime<Utc>) -> DateTime<Utc> {
+        self.expiration_time
+            .unwrap_or(uploaded_at + DEFAULT_FILE_LIFETIME)
+    }
+}
+
+/// Gemini APIのFiles API（`/upload`・`/download`のエンドポイント）のクライアント
+///
+/// アップロードしたファイルは内容のハッシュごとに記録し、有効期限内であれば同じ内容を再アップロードしない。
+pub struct FilesClient {
+    api_base_url: String,
+    http_client: reqwest::Client,
+    chunk_size: usize,
+    resume_delay: Duration,
+    /// （APIキー, 内容のSHA-256）ごとのアップロード済みのファイル
+    uploaded: Mutex<HashMap<(String, String), CachedFile>>,
+}
+
+/// アップロード済みのファイルと削除される日時
+struct CachedFile {
+    file: UploadedFile,
+    expires_at: DateTime<Utc>,
+}
+
+impl FilesClient {
+    pub fn new(api_base_url: String) -> Self {
+        Self {
+            api_base_url,
+            http_client: reqwest::Client::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            resume_delay: DEFAULT_RESUME_DELAY,
+            uploaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// アップロードで1回に送るサイズを設定（256KiBの倍数に切り上げる）
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1).div_ceil(CHUNK_GRANULARITY) * CHUNK_GRANULARITY;
+        self
+    }
+
+    /// 送信に失敗したチャンクを再開するまでの待ち時間の初期値を設定
+    pub fn with_resume_delay(mut self, resume_delay: Duration) -> Self {
+        self.resume_delay = resume_delay;
+        self
+    }
+
+    /// 同じ内容のファイルが有効期限内にアップロード済みであれば再利用し、なければアップロードする
+    ///
+    /// ファイルはプロジェクトごとに管理されるため、再利用はアップロードしたAPIキーに限る。
+    pub async fn upload_cached(
+        &self,
+        api_key: &str,
+        data: &[u8],
+        mime_type: &str,
+    ) -> Result<UploadedFile, ImageGenerationError> {
+        let cache_key = (api_key.to_string(), sha256_hex(data));
+        {
+            let now = Utc::now();
+            let mut uploaded = self.uploaded.lock().unwrap_or_else(|e| e.into_inner());
+            uploaded.retain(|_, cached| cached.expires_at > now);
+            if let Some(cached) = uploaded.get(&cache_key) {
+                if cached.expires_at - now > REUSE_MARGIN {
+                    tracing::debug!("Reusing uploaded file {}", cached.file.name);
+                    return Ok(cached.file.clone());
+                }
+            }
+        }
+
+        let uploaded_at = Utc::now();
+        let display_name = format!("input-{}", &cache_key.1[..16]);
+        let file = self
+            .upload(api_key, data.to_vec(), mime_type, &display_name)
+            .await?;
+        let expires_at = file.expires_at(uploaded_at);
+        self.uploaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                cache_key,
+                CachedFile {
+                    file: file.clone(),
+                    expires_at,
+                },
+            );
+        Ok(file)
+    }
+
+    /// アップロード済みとして記録したファイルを忘れる（期限切れなどで使えなくなった場合に、次回は再アップロードする）
+    pub fn forget(&self, name: &str) {
+        self.uploaded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, cached| cached.file.name != name);
+    }
+
+    /// ファイルを再開可能なアップロードでアップロードする
+    ///
+    /// チャンクに分けて送り、送信に失敗した場合は受信済みのサイズを問い合わせて続きから送り直す。
+    pub async fn upload(
+        &self,
+        api_key: &str,
+        data: Vec<u8>,
+        mime_type: &str,
+        display_name: &str,
+    ) -> Result<UploadedFile, ImageGenerationError> {
+        let response = self
+            .http_client
+            .post(format!(
+                "{}/files",
+                service_url(&self.api_base_url, "upload")
+            ))
+            .header(API_KEY_HEADER, api_key)
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", data.len())
+            .header("X-Goog-Upload-Header-Content-Type", mime_type)
+            .json(&serde_json::json!({ "file": { "displayName": display_name } }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(service_error_from_response(response).await);
+        }
+        let upload_url = header(&response, "x-goog-upload-url").ok_or_else(|| {
+            ImageGenerationError::ApiError("Upload URL missing from response".to_string())
+        })?;
+
+        let mut offset = 0;
+        let mut attempts = 0;
+        loop {
+            let end = (offset + self.chunk_size).min(data.len());
+            let command = if end == data.len() {
+                "upload, finalize"
+            } else {
+                "upload"
+            };
+            let sent = self
+                .http_client
+                .post(&upload_url)
+                .header(API_KEY_HEADER, api_key)
+                .header("X-Goog-Upload-Offset", offset)
+                .header("X-Goog-Upload-Command", command)
+                .body(data[offset..end].to_vec())
+                .send()
+                .await;
+
+            match sent {
+                Ok(response) if response.status().is_success() => {
+                    if end == data.len() {
+                        let body: FileResponse = response.json().await?;
+                        return Ok(body.file);
+                    }
+                    offset = end;
+                }
+                // 再開しても成功しないエラーはそのまま返す
+                Ok(response) if !response.status().is_server_error() => {
+                    return Err(service_error_from_response(response).await);
+                }
+                failed => {
+                    attempts += 1;
+                    if attempts > MAX_RESUME_ATTEMPTS {
+                        return Err(match failed {
+                            Ok(response) => service_error_from_response(response).await,
+                            Err(e) => e.into(),
+                        });
+                    }
+                    tracing::warn!("Upload of {} interrupted at {} bytes", display_name, offset);
+                    tokio::time::sleep(self.resume_delay * 2u32.pow(attempts - 1)).await;
+                    match self.query_upload(api_key, &upload_url).await? {
+                        UploadStatus::Final(file) => return Ok(file),
+                        UploadStatus::Received(received) => offset = received.min(data.len()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// 中断したアップロードの受信済みのサイズを問い合わせる
+    async fn query_upload(
+        &self,
+        api_key: &str,
+        upload_url: &str,
+    ) -> Result<UploadStatus, ImageGenerationError> {
+        let response = self
+            .http_client
+            .post(upload_url)
+            .header(API_KEY_HEADER, api_key)
+            .header("X-Goog-Upload-Command", "query")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(service_error_from_response(response).await);
+        }
+        if header(&response, "x-goog-upload-status").as_deref() == Some("final") {
+            let body: FileResponse = response.json().await?;
+            return Ok(UploadStatus::Final(body.file));
+        }
+        let received = header(&response, "x-goog-upload-size-received")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        Ok(UploadStatus::Received(received))
+    }
+
+    /// ファイルの内容をダウンロードする（`name`は`files/...`）
+    pub async fn download(
+        &self,
+        api_key: &str,
+        name: &str,
+    ) -> Result<Vec<u8>, ImageGenerationError> {
+        let url = format!(
+            "{}/{}:download",
+            service_url(&self.api_base_url, "download"),
+            name
+        );
+        let response = self
+            .http_client
+            .get(&url)
+            .header(API_KEY_HEADER, api_key)
+            .query(&[("alt", "media")])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(service_error_from_response(response).await);
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// 中断したアップロードの状態
+enum UploadStatus {
+    /// 受信済みのサイズ
+    Received(usize),
+    /// アップロードは完了している
+    Final(UploadedFile),
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// `upload`・`download`のURL（`.../v1beta`は`.../upload/v1beta`になる）
+fn service_url(api_base_url: &str, service: &str) -> String {
+    let api_base_url = api_base_url.trim_end_matches('/');
+    match api_base_url.rsplit_once('/') {
+        Some((root, version)) if is_api_version(version) => {
+            format!("{}/{}/{}", root, service, version)
+        }
+        _ => format!("{}/{}/v1beta", api_base_url, service),
+    }
+}
+
+fn is_api_version(segment: &str) -> bool {
+    segment
+        .strip_prefix('v')
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// int64の値（JSONでは文字列で返される）
+fn deserialize_int64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.and_then(|v| {
+        v.as_u64()
+            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct FileResponse {
+    file: UploadedFile,
+}
@@ -0,0 +1,170 @@
+use crate::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest,
+};
+use crate::infrastructure::gemini::client::{error_from_response, API_KEY_HEADER};
+use crate::infrastructure::gemini::key_pool::{mask_key, ApiKeyLease, ApiKeyPool};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Imagenモデル（`:predict`エンドポイント）のクライアント
+pub struct ImagenClient {
+    keys: Arc<ApiKeyPool>,
+    api_base_url: String,
+    http_client: reqwest::Client,
+}
+
+impl ImagenClient {
+    pub fn with_base_url(api_key: String, api_base_url: String) -> Self {
+        Self::with_key_pool(Arc::new(ApiKeyPool::new(vec![api_key])), api_base_url)
+    }
+
+    /// 複数のAPIキーを切り替えて使うクライアントを作成（Geminiのクライアントとプールを共有できる）
+    pub fn with_key_pool(keys: Arc<ApiKeyPool>, api_base_url: String) -> Self {
+        Self {
+            keys,
+            api_base_url,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// 1つのキーでAPIを呼び出す
+    async fn send(
+        &self,
+        request: &ImageGenerationRequest,
+        lease: &ApiKeyLease,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let url = format!("{}/models/{}:predict", self.api_base_url, request.model);
+
+        let response = self
+            .http_client
+            .post(&url)
+            .header(API_KEY_HEADER, &lease.key)
+            .json(&PredictRequest::new(request)?)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response, &request.model).await);
+        }
+        let image = image_from_predict_response(response, &request.model).await?;
+        Ok(image.with_api_key(mask_key(&lease.key)))
+    }
+}
+
+#[async_trait]
+impl ImageGenerationRepository for ImagenClient {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        self.keys
+            .call_with_failover(|lease| async move { self.send(request, &lease).await })
+            .await
+    }
+}
+
+/// Imagenの`:predict`リクエストボディ（Vertex AIも同じ形式）
+#[derive(Debug, Serialize)]
+pub(crate) struct PredictRequest {
+    instances: Vec<Instance>,
+    parameters: PredictParameters,
+}
+
+impl PredictRequest {
+    /// リクエストからボディを作成（Imagenは入力画像に対応しない）
+    pub(crate) fn new(request: &ImageGenerationRequest) -> Result<Self, ImageGenerationError> {
+        if !request.input_images.is_empty() {
+            return Err(ImageGenerationError::InvalidPromptError(format!(
+                "{} does not accept input images",
+                request.model
+            )));
+        }
+        Ok(Self {
+            instances: vec![Instance {
+                prompt: request.prompt.clone(),
+            }],
+            parameters: PredictParameters {
+                // 1回の生成で返すのは1枚
+                sample_count: 1,
+                aspect_ratio: request.aspect_ratio.clone(),
+                sample_image_size: request.image_size.clone(),
+                person_generation: request.person_generation.clone(),
+                negative_prompt: request.negative_prompt.clone(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Instance {
+    prompt: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PredictParameters {
+    sample_count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aspect_ratio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sample_image_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    person_generation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    negative_prompt: Option<String>,
+}
+
+/// Imagenの`:predict`レスポンスボディ
+#[derive(Debug, Deserialize)]
+struct PredictResponse {
+    // 安全フィルターですべて除外された場合は返されない
+    #[serde(default)]
+    predictions: Vec<Prediction>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Prediction {
+    bytes_base64_encoded: Option<String>,
+    mime_type: Option<String>,
+    rai_filtered_reason: Option<String>,
+}
+
+/// `:predict`の成功レスポンスから生成画像を取り出す
+pub(crate) async fn image_from_predict_response(
+    response: reqwest::Response,
+    model: &GeminiModel,
+) -> Result<GeneratedImage, ImageGenerationError> {
+    let body: PredictResponse = response.json().await?;
+
+    let prediction = body
+        .predictions
+        .iter()
+        .find(|p| p.bytes_base64_encoded.is_some());
+    let Some(prediction) = prediction else {
+        let reason = body
+            .predictions
+            .iter()
+            .find_map(|p| p.rai_filtered_reason.clone())
+            .unwrap_or_else(|| "No predictions in response".to_string());
+        return Err(ImageGenerationError::NoImage(reason));
+    };
+
+    use base64::Engine;
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(
+            prediction
+                .bytes_base64_encoded
+                .as_deref()
+                .unwrap_or_default(),
+        )
+        .map_err(|e| ImageGenerationError::ApiError(format!("Failed to decode base64: {}", e)))?;
+
+    let image = GeneratedImage::new(data, model.clone());
+    Ok(match &prediction.mime_type {
+        Some(mime_type) => image.with_mime_type(mime_type.clone()),
+        None => image,
+    })
+}
@@ -0,0 +1,295 @@
+use crate::config::KeySelection;
+use crate::domain::ImageGenerationError;
+use crate::infrastructure::redaction;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 429でRetry-Afterがない場合にキーを隔離する時間のデフォルト
+const DEFAULT_THROTTLE_QUARANTINE: Duration = Duration::from_secs(60);
+/// 401/403が返されたキーを隔離する時間のデフォルト
+const DEFAULT_AUTH_QUARANTINE: Duration = Duration::from_secs(10 * 60);
+
+/// キーを隔離する理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuarantineReason {
+    /// 401/403（無効・権限のないキー）
+    Unauthorized,
+    /// 429（レート制限）
+    Throttled,
+}
+
+/// APIキーのプール
+///
+/// 401/403/429を返したキーは一定時間隔離し、他のキーに切り替える。
+pub struct ApiKeyPool {
+    keys: Vec<String>,
+    selection: KeySelection,
+    throttle_quarantine: Duration,
+    auth_quarantine: Duration,
+    state: Mutex<PoolState>,
+}
+
+struct PoolState {
+    next: usize,
+    keys: Vec<KeyState>,
+}
+
+#[derive(Default)]
+struct KeyState {
+    requests: u64,
+    successes: u64,
+    failures: u64,
+    throttled: u64,
+    unauthorized: u64,
+    last_used: Option<Instant>,
+    last_throttled: Option<Instant>,
+    quarantined_until: Option<Instant>,
+}
+
+/// プールから選ばれたキー
+#[derive(Debug, Clone)]
+pub struct ApiKeyLease {
+    pub index: usize,
+    pub key: String,
+}
+
+/// キーごとの利用状況（キー自体は末尾4文字のみ）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiKeyMetrics {
+    pub key: String,
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub throttled: u64,
+    pub unauthorized: u64,
+    /// 隔離が解除されるまでの秒数（隔離されていない場合は`None`）
+    pub quarantined_for_secs: Option<u64>,
+}
+
+impl ApiKeyPool {
+    pub fn new(keys: Vec<String>) -> Self {
+        for key in &keys {
+            redaction::register_secret(key);
+        }
+        let state = PoolState {
+            next: 0,
+            keys: keys.iter().map(|_| KeyState::default()).collect(),
+        };
+        Self {
+            keys,
+            selection: KeySelection::default(),
+            throttle_quarantine: DEFAULT_THROTTLE_QUARANTINE,
+            auth_quarantine: DEFAULT_AUTH_QUARANTINE,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// キーの選び方を設定
+    pub fn with_selection(mut self, selection: KeySelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// 隔離する時間を設定（レート制限はRetry-Afterがない場合に使う）
+    pub fn with_quarantine(mut self, throttled: Duration, unauthorized: Duration) -> Self {
+        self.throttle_quarantine = throttled;
+        self.auth_quarantine = unauthorized;
+        self
+    }
+
+    /// キーの数
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// キーがないか
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// 隔離されていないキーを1つ選ぶ（すべて隔離されている場合は`None`）
+    pub fn acquire(&self) -> Option<ApiKeyLease> {
+        let mut state = self.lock();
+        let now = Instant::now();
+        let available = |key: &KeyState| key.quarantined_until.is_none_or(|until| until <= now);
+
+        let index = match self.selection {
+            KeySelection::RoundRobin => {
+                let count = state.keys.len();
+                let start = state.next;
+                let index = (0..count)
+                    .map(|offset| (start + offset) % count)
+                    .find(|&i| available(&state.keys[i]))?;
+                state.next = (index + 1) % count;
+                index
+            }
+            KeySelection::LeastRecentlyThrottled => state
+                .keys
+                .iter()
+                .enumerate()
+                .filter(|(_, key)| available(key))
+                .min_by_key(|(_, key)| (key.last_throttled, key.last_used))
+                .map(|(i, _)| i)?,
+        };
+
+        let key = &mut state.keys[index];
+        key.quarantined_until = None;
+        key.requests += 1;
+        key.last_used = Some(now);
+        Some(ApiKeyLease {
+            index,
+            key: self.keys[index].clone(),
+        })
+    }
+
+    /// 成功を記録
+    pub fn record_success(&self, lease: &ApiKeyLease) {
+        self.lock().keys[lease.index].successes += 1;
+    }
+
+    /// 隔離の対象にならない失敗を記録
+    pub fn record_failure(&self, lease: &ApiKeyLease) {
+        self.lock().keys[lease.index].failures += 1;
+    }
+
+    /// キーを隔離する（`retry_after`はレート制限時にAPIが示した待ち時間）
+    pub fn quarantine(
+        &self,
+        lease: &ApiKeyLease,
+        reason: QuarantineReason,
+        retry_after: Option<Duration>,
+    ) {
+        let mut state = self.lock();
+        let now = Instant::now();
+        let key = &mut state.keys[lease.index];
+        key.failures += 1;
+        let duration = match reason {
+            QuarantineReason::Unauthorized => {
+                key.unauthorized += 1;
+                self.auth_quarantine
+            }
+            QuarantineReason::Throttled => {
+                key.throttled += 1;
+                key.last_throttled = Some(now);
+                retry_after.unwrap_or(self.throttle_quarantine)
+            }
+        };
+        key.quarantined_until = Some(now + duration);
+        tracing::warn!(
+            "API key {} quarantined for {:?} ({:?})",
+            mask_key(&lease.key),
+            duration,
+            reason
+        );
+    }
+
+    /// キーを選んで呼び出し、無効なキー・429を返したキーは隔離して残りのキーで再試行する
+    pub(crate) async fn call_with_failover<T, F, Fut>(
+        &self,
+        mut call: F,
+    ) -> Result<T, ImageGenerationError>
+    where
+        F: FnMut(ApiKeyLease) -> Fut,
+        Fut: std::future::Future<Output = Result<T, ImageGenerationError>>,
+    {
+        let mut last_error = None;
+        for _ in 0..self.len() {
+            let Some(lease) = self.acquire() else {
+                break;
+            };
+            match call(lease.clone()).await {
+                Ok(value) => {
+                    self.record_success(&lease);
+                    return Ok(value);
+                }
+                // モデルを使う権限がないなどの認証エラーはキーを隔離せずに返す
+                Err(e) if e.is_invalid_api_key() => {
+                    self.quarantine(&lease, QuarantineReason::Unauthorized, None);
+                    last_error = Some(e);
+                }
+                Err(e @ ImageGenerationError::RateLimitError { .. }) => {
+                    self.quarantine(&lease, QuarantineReason::Throttled, e.retry_after());
+                    last_error = Some(e);
+                }
+                Err(e) => {
+                    self.record_failure(&lease);
+                    return Err(e);
+                }
+            }
+        }
+
+        Err(
+            last_error.unwrap_or_else(|| ImageGenerationError::RateLimitError {
+                message: format!("all {} API keys are quarantined", self.len()),
+                retry_after: self.next_available_in(),
+            }),
+        )
+    }
+
+    /// 最も早く隔離が解除されるキーまでの待ち時間
+    pub fn next_available_in(&self) -> Option<Duration> {
+        let state = self.lock();
+        let now = Instant::now();
+        state
+            .keys
+            .iter()
+            .map(|key| {
+                key.quarantined_until
+                    .map_or(Duration::ZERO, |until| until.saturating_duration_since(now))
+            })
+            .min()
+    }
+
+    /// キーごとの利用状況
+    pub fn metrics(&self) -> Vec<ApiKeyMetrics> {
+        let state = self.lock();
+        let now = Instant::now();
+        self.keys
+            .iter()
+            .zip(&state.keys)
+            .map(|(key, s)| ApiKeyMetrics {
+                key: mask_key(key),
+                requests: s.requests,
+                successes: s.successes,
+                failures: s.failures,
+                throttled: s.throttled,
+                unauthorized: s.unauthorized,
+                quarantined_for_secs: s
+                    .quarantined_until
+                    .filter(|&until| until > now)
+                    .map(|until| until.duration_since(now).as_secs().max(1)),
+            })
+            .collect()
+    }
+
+    /// 識別子（[`key_id`]）に対応するキー（プールにない場合は`None`）
+    pub fn lease_by_id(&self, id: &str) -> Option<ApiKeyLease> {
+        self.keys
+            .iter()
+            .position(|key| key_id(key) == id)
+            .map(|index| ApiKeyLease {
+                index,
+                key: self.keys[index].clone(),
+            })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// ファイルなどに保存するためのキーの識別子（キーのSHA-256の先頭16文字）
+pub fn key_id(key: &str) -> String {
+    let mut id = crate::domain::models::sha256_hex(key.as_bytes());
+    id.truncate(16);
+    id
+}
+
+/// ログやメトリクス用にキーを末尾4文字だけにする
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("…{}", tail)
+}
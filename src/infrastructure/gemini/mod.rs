@@ -1,3 +1,15 @@
+pub mod batch;
+pub mod catalog;
 pub mod client;
+pub mod files;
+pub mod imagen;
+pub mod key_pool;
+pub mod router;
 
+pub use batch::{BatchClient, BatchInputMode, BatchJob, BatchResponse, BatchState};
+pub use catalog::{ModelCatalog, ModelInfo};
 pub use client::GeminiClient;
+pub use files::{FilesClient, UploadedFile};
+pub use imagen::ImagenClient;
+pub use key_pool::{ApiKeyLease, ApiKeyMetrics, ApiKeyPool, QuarantineReason};
+pub use router::ModelRouter;
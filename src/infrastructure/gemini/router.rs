@@ -0,0 +1,38 @@
+use crate::domain::{
+    GeneratedImage, ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest,
+};
+use async_trait::async_trait;
+
+/// モデル名に応じてGeminiとImagenのクライアントを切り替える
+pub struct ModelRouter<G, I> {
+    gemini: G,
+    imagen: I,
+}
+
+impl<G, I> ModelRouter<G, I>
+where
+    G: ImageGenerationRepository,
+    I: ImageGenerationRepository,
+{
+    pub fn new(gemini: G, imagen: I) -> Self {
+        Self { gemini, imagen }
+    }
+}
+
+#[async_trait]
+impl<G, I> ImageGenerationRepository for ModelRouter<G, I>
+where
+    G: ImageGenerationRepository,
+    I: ImageGenerationRepository,
+{
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        if request.model.is_imagen() {
+            self.imagen.generate_image(request).await
+        } else {
+            self.gemini.generate_image(request).await
+        }
+    }
+}
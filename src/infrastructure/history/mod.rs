@@ -0,0 +1,3 @@
+pub mod sqlite;
+
+pub use sqlite::SqliteHistory;
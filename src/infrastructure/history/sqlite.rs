@@ -0,0 +1,306 @@
+use crate::domain::{GeminiModel, GenerationHistory, GenerationRecord, HistoryError, HistoryQuery};
+use async_trait::async_trait;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, Row};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// スキーマのバージョン（`PRAGMA user_version`に記録する）
+const SCHEMA_VERSION: i64 = 3;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS generations (
+    request_id TEXT NOT NULL UNIQUE,
+    prompt TEXT NOT NULL,
+    model TEXT NOT NULL,
+    parameters TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    error_kind TEXT,
+    error_message TEXT,
+    latency_ms INTEGER NOT NULL,
+    image_id TEXT,
+    file_path TEXT,
+    mime_type TEXT,
+    size_bytes INTEGER,
+    created_at TEXT NOT NULL,
+    request TEXT,
+    cost_usd REAL
+);
+CREATE INDEX IF NOT EXISTS generations_created_at ON generations (created_at);
+CREATE VIRTUAL TABLE IF NOT EXISTS generations_fts USING fts5 (
+    prompt,
+    content = 'generations',
+    content_rowid = 'rowid',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TRIGGER IF NOT EXISTS generations_ai AFTER INSERT ON generations BEGIN
+    INSERT INTO generations_fts (rowid, prompt) VALUES (new.rowid, new.prompt);
+END;
+CREATE TRIGGER IF NOT EXISTS generations_ad AFTER DELETE ON generations BEGIN
+    INSERT INTO generations_fts (generations_fts, rowid, prompt) VALUES ('delete', old.rowid, old.prompt);
+END;
+";
+
+const COLUMNS: &str = "g.request_id, g.prompt, g.model, g.parameters, g.outcome, g.error_kind, \
+     g.error_message, g.latency_ms, g.image_id, g.file_path, g.mime_type, g.size_bytes, g.created_at, g.request, g.cost_usd";
+
+/// SQLiteに保存する生成履歴（プロンプトはFTS5で全文検索できる）
+pub struct SqliteHistory {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteHistory {
+    /// データベースファイルを開く（なければ作成し、スキーマを初期化する）
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent).map_err(|e| HistoryError::Storage(e.to_string()))?;
+        }
+        Self::init(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// メモリ上のデータベースを開く（テスト用）
+    pub fn open_in_memory() -> Result<Self, HistoryError> {
+        Self::init(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, HistoryError> {
+        let version: i64 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(HistoryError::Storage(format!(
+                "History database schema version {} is newer than supported version {}",
+                version, SCHEMA_VERSION
+            )));
+        }
+
+        connection.execute_batch(SCHEMA).map_err(storage_error)?;
+        // バージョン1のデータベースには再実行用のリクエストの列がない
+        if version == 1 {
+            connection
+                .execute_batch("ALTER TABLE generations ADD COLUMN request TEXT")
+                .map_err(storage_error)?;
+        }
+        // バージョン2以前のデータベースには推定料金の列がない
+        if (1..=2).contains(&version) {
+            connection
+                .execute_batch("ALTER TABLE generations ADD COLUMN cost_usd REAL")
+                .map_err(storage_error)?;
+        }
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(storage_error)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// ブロッキングスレッドで接続を使った処理を実行する
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, HistoryError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, HistoryError> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&connection)
+        })
+        .await
+        .map_err(|e| HistoryError::Storage(e.to_string()))?
+    }
+}
+
+#[async_trait]
+impl GenerationHistory for SqliteHistory {
+    async fn record(&self, record: &GenerationRecord) -> Result<(), HistoryError> {
+        let record = record.clone();
+        let parameters = serde_json::to_string(&record.parameters)
+            .map_err(|e| HistoryError::Storage(e.to_string()))?;
+        // 入力画像のデータはSHA-256だけを残して保存する
+        let request = record
+            .request
+            .as_ref()
+            .map(|request| serde_json::to_string(&request.without_image_data()))
+            .transpose()
+            .map_err(|e| HistoryError::Storage(e.to_string()))?;
+
+        self.with_connection(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO generations (request_id, prompt, model, parameters, outcome, \
+                     error_kind, error_message, latency_ms, image_id, file_path, mime_type, \
+                     size_bytes, created_at, request, cost_usd) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    rusqlite::params![
+                        record.request_id,
+                        record.prompt,
+                        record.model.as_str(),
+                        parameters,
+                        record.outcome.as_str(),
+                        record.error_kind,
+                        record.error_message,
+                        record.latency_ms as i64,
+                        record.image_id,
+                        record.file_path,
+                        record.mime_type,
+                        record.size_bytes.map(|size| size as i64),
+                        format_timestamp(record.created_at),
+                        request,
+                        record.estimated_cost_usd,
+                    ],
+                )
+                .map_err(storage_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn query(&self, query: &HistoryQuery) -> Result<Vec<GenerationRecord>, HistoryError> {
+        let match_expression = query
+            .text
+            .as_deref()
+            .map(fts_match_expression)
+            .transpose()?;
+
+        let mut from = "generations g".to_string();
+        let mut conditions = Vec::new();
+        let mut params: Vec<Value> = Vec::new();
+        if let Some(expression) = &match_expression {
+            from.push_str(" JOIN generations_fts ON generations_fts.rowid = g.rowid");
+            conditions.push("generations_fts MATCH ?");
+            params.push(Value::Text(expression.clone()));
+        }
+        if let Some(model) = &query.model {
+            conditions.push("g.model = ?");
+            params.push(Value::Text(model.clone()));
+        }
+        if let Some(outcome) = query.outcome {
+            conditions.push("g.outcome = ?");
+            params.push(Value::Text(outcome.as_str().to_string()));
+        }
+        if let Some(since) = query.since {
+            conditions.push("g.created_at >= ?");
+            params.push(Value::Text(format_timestamp(since)));
+        }
+        if let Some(until) = query.until {
+            conditions.push("g.created_at < ?");
+            params.push(Value::Text(format_timestamp(until)));
+        }
+
+        if let Some((created_at, request_id)) = &query.after {
+            // 全文検索は関連度順のため、新しい順の位置では続きを決められない
+            if match_expression.is_some() {
+                return Err(HistoryError::InvalidQuery(
+                    "A cursor cannot be combined with a text search".to_string(),
+                ));
+            }
+            conditions.push("(g.created_at < ? OR (g.created_at = ? AND g.request_id < ?))");
+            params.push(Value::Text(format_timestamp(*created_at)));
+            params.push(Value::Text(format_timestamp(*created_at)));
+            params.push(Value::Text(request_id.clone()));
+        }
+
+        let mut sql = format!("SELECT {} FROM {}", COLUMNS, from);
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(if match_expression.is_some() {
+            " ORDER BY bm25(generations_fts), g.created_at DESC LIMIT ? OFFSET ?"
+        } else {
+            " ORDER BY g.created_at DESC, g.request_id DESC LIMIT ? OFFSET ?"
+        });
+        params.push(Value::Integer(query.limit as i64));
+        params.push(Value::Integer(query.offset as i64));
+
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(&sql).map_err(storage_error)?;
+            let rows = statement
+                .query_map(rusqlite::params_from_iter(params), read_row)
+                .map_err(storage_error)?;
+            rows.map(|row| row.map_err(storage_error)?).collect()
+        })
+        .await
+    }
+
+    async fn get(&self, request_id: &str) -> Result<Option<GenerationRecord>, HistoryError> {
+        let request_id = request_id.to_string();
+        self.with_connection(move |connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM generations g WHERE g.request_id = ?1",
+                        COLUMNS
+                    ),
+                    [request_id],
+                    read_row,
+                )
+                .optional()
+                .map_err(storage_error)?
+                .transpose()
+        })
+        .await
+    }
+}
+
+/// 行を記録に変換する（JSONや結果の値が不正な場合は`HistoryError`）
+fn read_row(row: &Row<'_>) -> rusqlite::Result<Result<GenerationRecord, HistoryError>> {
+    let parameters: String = row.get(3)?;
+    let outcome: String = row.get(4)?;
+    let latency_ms: i64 = row.get(7)?;
+    let size_bytes: Option<i64> = row.get(11)?;
+    let created_at: String = row.get(12)?;
+    let request: Option<String> = row.get(13)?;
+
+    let parse = || -> Result<GenerationRecord, HistoryError> {
+        Ok(GenerationRecord {
+            request_id: row.get(0).map_err(storage_error)?,
+            prompt: row.get(1).map_err(storage_error)?,
+            model: GeminiModel::from(row.get::<_, String>(2).map_err(storage_error)?),
+            parameters: serde_json::from_str(&parameters)
+                .map_err(|e| HistoryError::Storage(e.to_string()))?,
+            outcome: outcome.parse()?,
+            error_kind: row.get(5).map_err(storage_error)?,
+            error_message: row.get(6).map_err(storage_error)?,
+            latency_ms: latency_ms.max(0) as u64,
+            image_id: row.get(8).map_err(storage_error)?,
+            file_path: row.get(9).map_err(storage_error)?,
+            mime_type: row.get(10).map_err(storage_error)?,
+            size_bytes: size_bytes.map(|size| size.max(0) as u64),
+            estimated_cost_usd: row.get(14).map_err(storage_error)?,
+            created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                .map_err(|e| HistoryError::Storage(e.to_string()))?
+                .with_timezone(&chrono::Utc),
+            request: request
+                .map(|json| serde_json::from_str(&json))
+                .transpose()
+                .map_err(|e| HistoryError::Storage(e.to_string()))?,
+        })
+    };
+    Ok(parse())
+}
+
+/// 検索語をFTS5のクエリに変換する（各語を引用符で囲み、前方一致のAND検索にする）
+fn fts_match_expression(text: &str) -> Result<String, HistoryError> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return Err(HistoryError::InvalidQuery(
+            "Search text cannot be empty".to_string(),
+        ));
+    }
+    Ok(terms.join(" "))
+}
+
+/// 文字列として比較できるよう、時刻を固定長のRFC 3339形式にする
+fn format_timestamp(timestamp: chrono::DateTime<chrono::Utc>) -> String {
+    timestamp.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+fn storage_error(err: rusqlite::Error) -> HistoryError {
+    HistoryError::Storage(err.to_string())
+}
@@ -0,0 +1,319 @@
+use crate::application::{GenerateImageUseCase, UsageTotals, UsageTracker};
+use crate::domain::{
+    GeminiModel, GenerationHistory, GenerationRecord, ImageGenerationRepository,
+    ImageGenerationRequest, ImageMetadata, ImageStore,
+};
+use crate::infrastructure::redaction::redact;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// 終了したジョブを保持する件数の上限（超えた分は古いものから破棄する）
+const MAX_FINISHED_JOBS: usize = 1000;
+
+/// 未終了のジョブの数の上限のデフォルト
+const DEFAULT_MAX_PENDING_JOBS: usize = 100;
+
+/// ジョブの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// 終了した状態か
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// ジョブで生成し、画像ストアに保存した画像
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobResult {
+    pub image_id: String,
+    pub resource_uri: String,
+    pub mime_type: String,
+    pub model: GeminiModel,
+    pub size_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageTotals>,
+}
+
+/// 画像生成ジョブ
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageJob {
+    pub job_id: String,
+    pub request_id: String,
+    pub status: JobStatus,
+    /// 進行状況の説明
+    pub progress: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<JobResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// ジョブ操作のエラー
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("Job not found: {0}")]
+    NotFound(String),
+    #[error("Job {0} has already finished")]
+    AlreadyFinished(String),
+    #[error("Too many unfinished jobs (limit: {0}), try again after some have finished")]
+    QueueFull(usize),
+}
+
+struct JobEntry {
+    job: ImageJob,
+    abort: Option<AbortHandle>,
+}
+
+/// ワーカーと共有する状態
+struct JobContext {
+    use_case: Arc<GenerateImageUseCase<Box<dyn ImageGenerationRepository>>>,
+    store: Arc<dyn ImageStore>,
+    history: Option<Arc<dyn GenerationHistory>>,
+    usage: Arc<UsageTracker>,
+    workers: Semaphore,
+    jobs: Mutex<HashMap<String, JobEntry>>,
+}
+
+impl JobContext {
+    /// ジョブを更新する（取り消されたなど、終了済みのジョブは変更しない）
+    fn update(&self, job_id: &str, f: impl FnOnce(&mut ImageJob)) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = jobs.get_mut(job_id) {
+            if !entry.job.status.is_finished() {
+                f(&mut entry.job);
+                if entry.job.status.is_finished() {
+                    entry.abort = None;
+                }
+            }
+        }
+    }
+
+    async fn record_history(&self, record: GenerationRecord) {
+        if let Some(history) = &self.history {
+            if let Err(e) = history.record(&record).await {
+                warn!("Failed to record generation {}: {}", record.request_id, e);
+            }
+        }
+    }
+}
+
+/// 画像生成をバックグラウンドで実行するジョブの管理
+///
+/// 同時に実行するジョブの数は上限までとし、残りは順番を待つ。生成した画像は画像ストアに保存する。
+pub struct JobManager {
+    context: Arc<JobContext>,
+    max_pending: usize,
+}
+
+impl JobManager {
+    pub fn new(
+        use_case: Arc<GenerateImageUseCase<Box<dyn ImageGenerationRepository>>>,
+        store: Arc<dyn ImageStore>,
+        history: Option<Arc<dyn GenerationHistory>>,
+        usage: Arc<UsageTracker>,
+        max_concurrency: usize,
+    ) -> Self {
+        Self {
+            context: Arc::new(JobContext {
+                use_case,
+                store,
+                history,
+                usage,
+                workers: Semaphore::new(max_concurrency.max(1)),
+                jobs: Mutex::new(HashMap::new()),
+            }),
+            max_pending: DEFAULT_MAX_PENDING_JOBS,
+        }
+    }
+
+    /// 未終了（待機中・実行中）のジョブの数の上限を設定
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+
+    /// ジョブを登録して実行を始める（待たずに登録したジョブを返す、未終了のジョブが上限に達している場合はエラー）
+    pub fn submit(&self, request: ImageGenerationRequest) -> Result<ImageJob, JobError> {
+        let job = ImageJob {
+            job_id: uuid::Uuid::new_v4().to_string(),
+            request_id: request.request_id.clone(),
+            status: JobStatus::Queued,
+            progress: "waiting for a worker".to_string(),
+            created_at: chrono::Utc::now(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error_kind: None,
+            error: None,
+        };
+        let job_id = job.job_id.clone();
+
+        // ワーカーが先に終了しても状態を更新できるよう、起動前に登録する
+        let mut jobs = self.context.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        prune_finished(&mut jobs);
+        let pending = jobs
+            .values()
+            .filter(|entry| !entry.job.status.is_finished())
+            .count();
+        if pending >= self.max_pending {
+            return Err(JobError::QueueFull(self.max_pending));
+        }
+        let handle = tokio::spawn(run(Arc::clone(&self.context), job_id.clone(), request));
+        jobs.insert(
+            job_id,
+            JobEntry {
+                job: job.clone(),
+                abort: Some(handle.abort_handle()),
+            },
+        );
+        Ok(job)
+    }
+
+    /// ジョブの状態を取得
+    pub fn get(&self, job_id: &str) -> Option<ImageJob> {
+        let jobs = self.context.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(job_id).map(|entry| entry.job.clone())
+    }
+
+    /// ジョブを取り消す（実行中の場合は生成を中断する）
+    pub fn cancel(&self, job_id: &str) -> Result<ImageJob, JobError> {
+        let mut jobs = self.context.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = jobs
+            .get_mut(job_id)
+            .ok_or_else(|| JobError::NotFound(job_id.to_string()))?;
+        if entry.job.status.is_finished() {
+            return Err(JobError::AlreadyFinished(job_id.to_string()));
+        }
+
+        if let Some(abort) = entry.abort.take() {
+            abort.abort();
+        }
+        entry.job.status = JobStatus::Cancelled;
+        entry.job.progress = "cancelled".to_string();
+        entry.job.finished_at = Some(chrono::Utc::now());
+        info!("Cancelled image job {}", job_id);
+        Ok(entry.job.clone())
+    }
+}
+
+/// ジョブを実行する（ワーカーの空きを待ってから生成し、画像ストアに保存する）
+async fn run(context: Arc<JobContext>, job_id: String, request: ImageGenerationRequest) {
+    let Ok(_permit) = context.workers.acquire().await else {
+        return;
+    };
+    context.update(&job_id, |job| {
+        job.status = JobStatus::Running;
+        job.progress = "generating image".to_string();
+        job.started_at = Some(chrono::Utc::now());
+    });
+
+    let started = Instant::now();
+    let image = match context.use_case.execute(request.clone()).await {
+        Ok(image) => image,
+        Err(e) => {
+            let message = redact(&e.to_string());
+            context
+                .record_history(GenerationRecord::failed(
+                    &request,
+                    e.kind(),
+                    message.clone(),
+                    started.elapsed(),
+                ))
+                .await;
+            context.update(&job_id, |job| {
+                job.status = JobStatus::Failed;
+                job.progress = "failed".to_string();
+                job.finished_at = Some(chrono::Utc::now());
+                job.error_kind = Some(e.kind().to_string());
+                job.error = Some(message);
+            });
+            return;
+        }
+    };
+    let mut record = GenerationRecord::succeeded(&request, &image, started.elapsed());
+    let usage = context.usage.record(&image);
+    if let Some(usage) = &usage {
+        record = record.with_estimated_cost(usage.estimated_cost_usd);
+    }
+
+    context.update(&job_id, |job| {
+        job.progress = "storing image".to_string();
+    });
+    let metadata = ImageMetadata::new(&request, &image);
+    match context.store.put(&image, &metadata).await {
+        Ok(stored) => {
+            context
+                .record_history(record.with_image_id(stored.id.clone()))
+                .await;
+            let result = JobResult {
+                image_id: stored.id.clone(),
+                resource_uri: stored.resource_uri(),
+                mime_type: image.mime_type.clone(),
+                model: image.model.clone(),
+                size_bytes: image.data.len(),
+                image_url: context.store.download_url(&stored),
+                usage,
+            };
+            context.update(&job_id, |job| {
+                job.status = JobStatus::Succeeded;
+                job.progress = "completed".to_string();
+                job.finished_at = Some(chrono::Utc::now());
+                job.result = Some(result);
+            });
+        }
+        Err(e) => {
+            warn!("Failed to store image of job {}: {}", job_id, e);
+            context.record_history(record).await;
+            context.update(&job_id, |job| {
+                job.status = JobStatus::Failed;
+                job.progress = "failed".to_string();
+                job.finished_at = Some(chrono::Utc::now());
+                job.error_kind = Some("storage".to_string());
+                job.error = Some(redact(&format!("Failed to store image: {}", e)));
+            });
+        }
+    }
+}
+
+/// 終了したジョブが上限を超えた場合は古いものから破棄する
+fn prune_finished(jobs: &mut HashMap<String, JobEntry>) {
+    let mut finished: Vec<(chrono::DateTime<chrono::Utc>, String)> = jobs
+        .values()
+        .filter_map(|entry| {
+            entry
+                .job
+                .finished_at
+                .map(|at| (at, entry.job.job_id.clone()))
+        })
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, job_id) in finished.iter().take(finished.len() - MAX_FINISHED_JOBS) {
+        jobs.remove(job_id);
+    }
+}
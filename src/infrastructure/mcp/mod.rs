@@ -1,5 +1,9 @@
+pub mod jobs;
+pub mod pagination;
 pub mod server;
 pub mod types;
 
+pub use jobs::{ImageJob, JobError, JobManager, JobResult, JobStatus};
+pub use pagination::{CursorCodec, KeysetPosition, Page, PaginationError};
 pub use server::McpServer;
-pub use types::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+pub use types::{JsonRpcError, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse};
@@ -0,0 +1,216 @@
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256の出力長
+const TAG_LENGTH: usize = 32;
+
+/// ページネーションエラー
+#[derive(Debug, thiserror::Error)]
+pub enum PaginationError {
+    #[error("Invalid cursor")]
+    InvalidCursor,
+}
+
+/// ページ分割されたリストの1ページ
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// 新しい順のリストでの項目の位置（キーセット方式のカーソルに使う）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysetPosition {
+    pub timestamp: DateTime<Utc>,
+    pub id: String,
+}
+
+impl KeysetPosition {
+    pub fn new(timestamp: DateTime<Utc>, id: impl Into<String>) -> Self {
+        Self {
+            timestamp,
+            id: id.into(),
+        }
+    }
+
+    /// 新しい順（同じ時刻はID順）のリストで`other`より後に並ぶか
+    fn is_after(&self, other: &Self) -> bool {
+        self.timestamp < other.timestamp
+            || (self.timestamp == other.timestamp && self.id > other.id)
+    }
+}
+
+/// 改ざん防止付きの不透明なカーソルを生成・検証する
+///
+/// カーソルはリスト名と位置（オフセット、または新しい順のリストでは最後の項目の時刻とID）を
+/// HMAC-SHA256で署名し、base64urlでエンコードしたもの。
+/// 署名鍵はプロセスごとに生成されるため、サーバー再起動後のカーソルは無効になる。
+pub struct CursorCodec {
+    key: [u8; 32],
+}
+
+impl CursorCodec {
+    /// ランダムな署名鍵でコーデックを作成
+    pub fn new() -> Self {
+        Self {
+            key: rand::random(),
+        }
+    }
+
+    /// 指定した署名鍵でコーデックを作成
+    pub fn with_key(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    /// リスト名とオフセットからカーソルを生成
+    pub fn encode(&self, list: &str, offset: usize) -> String {
+        self.seal(&format!("{}:{}", list, offset))
+    }
+
+    /// カーソルを検証してオフセットを取り出す
+    pub fn decode(&self, list: &str, cursor: &str) -> Result<usize, PaginationError> {
+        let payload = self.open(cursor)?;
+        // 別のリスト用に発行されたカーソルは受け付けない
+        let (cursor_list, offset) = payload
+            .rsplit_once(':')
+            .ok_or(PaginationError::InvalidCursor)?;
+        if cursor_list != list {
+            return Err(PaginationError::InvalidCursor);
+        }
+        offset.parse().map_err(|_| PaginationError::InvalidCursor)
+    }
+
+    /// リスト名と最後に返した項目の位置からカーソルを生成
+    pub fn encode_keyset(&self, list: &str, position: &KeysetPosition) -> String {
+        let nanos = position
+            .timestamp
+            .timestamp_nanos_opt()
+            .unwrap_or_else(|| position.timestamp.timestamp_micros().saturating_mul(1000));
+        self.seal(&format!("{}:{}:{}", list, nanos, position.id))
+    }
+
+    /// カーソルを検証して最後に返した項目の位置を取り出す
+    pub fn decode_keyset(
+        &self,
+        list: &str,
+        cursor: &str,
+    ) -> Result<KeysetPosition, PaginationError> {
+        let payload = self.open(cursor)?;
+        let mut fields = payload.splitn(3, ':');
+        let (Some(cursor_list), Some(nanos), Some(id)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(PaginationError::InvalidCursor);
+        };
+        if cursor_list != list {
+            return Err(PaginationError::InvalidCursor);
+        }
+        let nanos: i64 = nanos.parse().map_err(|_| PaginationError::InvalidCursor)?;
+        Ok(KeysetPosition::new(
+            DateTime::from_timestamp_nanos(nanos),
+            id,
+        ))
+    }
+
+    /// アイテム列からカーソル位置の1ページを切り出す
+    pub fn paginate<T: Clone>(
+        &self,
+        list: &str,
+        items: &[T],
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> Result<Page<T>, PaginationError> {
+        let offset = cursor
+            .map(|c| self.decode(list, c))
+            .transpose()?
+            .unwrap_or(0);
+        if offset > items.len() {
+            return Err(PaginationError::InvalidCursor);
+        }
+
+        let end = offset.saturating_add(page_size.max(1)).min(items.len());
+        let next_cursor = (end < items.len()).then(|| self.encode(list, end));
+
+        Ok(Page {
+            items: items[offset..end].to_vec(),
+            next_cursor,
+        })
+    }
+
+    /// 新しい順のリストから、カーソルが指す項目より後の1ページを切り出す
+    ///
+    /// オフセットと違い、ページの間に項目が追加・削除されても飛ばしたり重複したりしない。
+    pub fn paginate_newest_first<T: Clone>(
+        &self,
+        list: &str,
+        items: &[T],
+        position: impl Fn(&T) -> KeysetPosition,
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> Result<Page<T>, PaginationError> {
+        let start = match cursor {
+            Some(cursor) => {
+                let last = self.decode_keyset(list, cursor)?;
+                items
+                    .iter()
+                    .position(|item| position(item).is_after(&last))
+                    .unwrap_or(items.len())
+            }
+            None => 0,
+        };
+
+        let end = start.saturating_add(page_size.max(1)).min(items.len());
+        let next_cursor =
+            (end < items.len()).then(|| self.encode_keyset(list, &position(&items[end - 1])));
+
+        Ok(Page {
+            items: items[start..end].to_vec(),
+            next_cursor,
+        })
+    }
+
+    /// ペイロードに署名してカーソルにする
+    fn seal(&self, payload: &str) -> String {
+        let mut token = payload.as_bytes().to_vec();
+        let tag = self.sign(&token);
+        token.extend_from_slice(&tag);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// カーソルの署名を検証してペイロードを取り出す
+    fn open(&self, cursor: &str) -> Result<String, PaginationError> {
+        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| PaginationError::InvalidCursor)?;
+        if token.len() <= TAG_LENGTH {
+            return Err(PaginationError::InvalidCursor);
+        }
+
+        let (payload, tag) = token.split_at(token.len() - TAG_LENGTH);
+        let mut mac = self.mac();
+        mac.update(payload);
+        mac.verify_slice(tag)
+            .map_err(|_| PaginationError::InvalidCursor)?;
+        String::from_utf8(payload.to_vec()).map_err(|_| PaginationError::InvalidCursor)
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(payload);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+}
+
+impl Default for CursorCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,80 +1,593 @@
-use crate::application::GenerateImageUseCase;
-use crate::domain::{GeminiModel, ImageGenerationRequest};
-use crate::infrastructure::gemini::GeminiClient;
-use crate::infrastructure::mcp::types::{CallToolResult, Content, Tool};
+use crate::application::batch::{self, BatchItem, BatchItemResult, BatchItemStatus, BatchManifest};
+use crate::application::{BudgetGuard, BudgetReservation, GenerateImageUseCase, UsageTracker};
+use crate::config::Config;
+use crate::domain::models::DEFAULT_IMAGE_MIME_TYPE;
+use crate::domain::{
+    GeminiModel, GeneratedImage, GenerationHistory, GenerationOutcome, GenerationRecord,
+    HistoryQuery, ImageGenerationRepository, ImageGenerationRequest, ImageMetadata, ImageStore,
+    InputImage, InputImageRole, ModelCapabilities, ModelSettings, StoredImage,
+};
+use crate::infrastructure::decorators::{
+    CachingRepository, CoalescingRepository, FallbackRepository, RateLimitedRepository,
+};
+use crate::infrastructure::gemini::{
+    ApiKeyPool, BatchClient, BatchInputMode, BatchJob, GeminiClient, ImagenClient, ModelCatalog,
+    ModelRouter,
+};
+use crate::infrastructure::history::SqliteHistory;
+use crate::infrastructure::mcp::jobs::JobManager;
+use crate::infrastructure::mcp::pagination::{CursorCodec, KeysetPosition};
+use crate::infrastructure::mcp::types::{
+    CallToolResult, Content, JsonRpcNotification, Resource, ResourceContents, Tool,
+};
+use crate::infrastructure::redaction::redact;
+use crate::infrastructure::storage::{FsImageStore, ImageFileWriter, S3ImageStore};
+use crate::infrastructure::vertex::{ServiceAccountKey, VertexClient};
 use anyhow::Result;
-use std::sync::Arc;
-use tracing::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+/// 通知チャネルのバッファサイズ
+const NOTIFICATION_CHANNEL_CAPACITY: usize = 64;
+
+/// 履歴ツールで一度に返す件数の上限
+const HISTORY_MAX_LIMIT: usize = 100;
+
+/// Batch APIに送信したバッチ（結果を画像ストアに保存するまで保持し、`BATCH_MANIFEST_DIR`があればファイルにも保存する）
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GeminiBatch {
+    /// バッチを作成したAPIキーの識別子（別のプロセスでも同じキーで結果を取得する）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    /// 送信したリクエスト（入力画像のデータは除く）
+    requests: Vec<ImageGenerationRequest>,
+    submitted_at: chrono::DateTime<chrono::Utc>,
+    /// 結果を保存した後の一覧（同じ結果を二度保存・集計しない）
+    manifest: Option<BatchManifest>,
+    /// リクエストIDごとに確保した予算（結果を受け取ったらコミットし、結果のないものは戻す）
+    #[serde(skip)]
+    reservations: HashMap<String, BudgetReservation>,
+    /// 結果を保存している間保持するロック（同じバッチの結果を同時に二度保存しない）
+    #[serde(skip)]
+    collecting: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl GeminiBatch {
+    /// 送信した内容の分からないバッチ（別のプロセスが送信し、情報のファイルもないもの）
+    fn unknown(key_id: Option<String>) -> Self {
+        Self {
+            key_id,
+            requests: Vec::new(),
+            submitted_at: chrono::Utc::now(),
+            manifest: None,
+            reservations: HashMap::new(),
+            collecting: Arc::default(),
+        }
+    }
+}
 
 /// MCPサーバー
 pub struct McpServer {
-    use_case: Arc<GenerateImageUseCase<GeminiClient>>,
-    default_model: String,
-    allowed_models: Vec<String>,
+    use_case: Arc<GenerateImageUseCase<Box<dyn ImageGenerationRepository>>>,
+    jsonrpc_version: String,
+    notifications: broadcast::Sender<JsonRpcNotification>,
+    file_writer: Option<ImageFileWriter>,
+    image_store: Option<Arc<dyn ImageStore>>,
+    history: Option<Arc<dyn GenerationHistory>>,
+    /// 履歴の一覧のカーソル
+    cursors: CursorCodec,
+    key_pool: Arc<ApiKeyPool>,
+    model_catalog: Option<Arc<ModelCatalog>>,
+    usage: Arc<UsageTracker>,
+    /// 画像ストアに保存するバックグラウンドの生成ジョブ（画像ストアが設定されている場合のみ）
+    jobs: Option<JobManager>,
+    job_max_concurrency: usize,
+    job_max_pending: usize,
+    batch_concurrency: usize,
+    batch_manifest_dir: Option<PathBuf>,
+    /// 保存先のディレクトリがない場合の一括生成の結果（`batch_id`ごと）
+    batch_manifests: tokio::sync::Mutex<HashMap<String, BatchManifest>>,
+    /// Batch APIのクライアント（AI StudioのAPIキーを使う場合のみ）
+    batch_client: Option<Arc<BatchClient>>,
+    /// Batch APIに送信したバッチ（バッチ名ごと）
+    gemini_batches: tokio::sync::Mutex<HashMap<String, GeminiBatch>>,
+    /// `initialize`で通知されたクライアント名（予算の上限をクライアントごとに適用する）
+    client_id: RwLock<Option<String>>,
 }
 
+/// リソース名に使うプロンプトの最大文字数
+const RESOURCE_NAME_MAX_CHARS: usize = 60;
+
+/// 予算の集計を復元する際に生成履歴から一度に読む件数
+const BUDGET_RESTORE_PAGE_SIZE: usize = 500;
+
 impl McpServer {
     pub fn new(api_key: String) -> Self {
+        Self::with_api_keys(vec![api_key])
+    }
+
+    /// 複数のAPIキーを切り替えて使うサーバーを作成
+    pub fn with_api_keys(api_keys: Vec<String>) -> Self {
         // 環境変数から設定を読み取る
-        GeminiModel::init_from_env();
+        let config = Config::from_env();
+        let key_pool =
+            Arc::new(ApiKeyPool::new(api_keys).with_selection(config.api_key_selection()));
+        let base_url = config.gemini_api_base_url().to_string();
+        let client = ModelRouter::new(
+            GeminiClient::with_key_pool(Arc::clone(&key_pool), base_url.clone())
+                .with_file_upload_threshold(config.file_upload_threshold_bytes()),
+            ImagenClient::with_key_pool(Arc::clone(&key_pool), base_url.clone()),
+        );
+        let batch_client = BatchClient::new(Arc::clone(&key_pool), base_url.clone())
+            .with_poll_interval(config.gemini_batch_poll_interval())
+            .with_wait_timeout(config.gemini_batch_wait_timeout());
+        let server = Self::with_client(Box::new(client), Arc::clone(&key_pool), &config)
+            .with_batch_client(Arc::new(batch_client));
+        if config.model_discovery() {
+            let catalog =
+                ModelCatalog::new(key_pool, base_url).with_ttl(config.model_catalog_ttl());
+            server.with_model_catalog(Arc::new(catalog))
+        } else {
+            server
+        }
+    }
 
-        let default_model = std::env::var("GEMINI_DEFAULT_MODEL")
-            .unwrap_or_else(|_| "gemini-2.5-flash-image".to_string());
+    /// Vertex AIを使うサーバーを作成（サービスアカウントのキーは`GOOGLE_APPLICATION_CREDENTIALS`）
+    pub fn with_vertex() -> Result<Self> {
+        let config = Config::from_env();
+        let client = open_vertex_client(&config)?;
+        Ok(Self::with_client(
+            Box::new(client),
+            Arc::new(ApiKeyPool::new(Vec::new())),
+            &config,
+        ))
+    }
 
-        let allowed_models = std::env::var("GEMINI_ALLOWED_MODELS")
-            .ok()
-            .map(|s| {
-                s.split(',')
-                    .map(|m| m.trim().to_string())
-                    .filter(|m| !m.is_empty())
-                    .collect()
-            })
-            .unwrap_or_default();
+    fn with_client(
+        client: Box<dyn ImageGenerationRepository>,
+        key_pool: Arc<ApiKeyPool>,
+        config: &Config,
+    ) -> Self {
+        GeminiModel::init_settings(ModelSettings {
+            default_model: config.gemini_default_model().to_string(),
+            allowed_models: config.gemini_allowed_models().to_vec(),
+        });
+        ModelCapabilities::set_overrides(config.model_capability_overrides().clone());
 
-        let client = GeminiClient::new(api_key);
-        let use_case = Arc::new(GenerateImageUseCase::new(client));
-        Self {
+        let file_writer = open_file_writer(config);
+        let image_store = open_image_store(config);
+        let history = open_history(config);
+
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CHANNEL_CAPACITY);
+        let repository = build_repository(client, config);
+        let mut use_case = GenerateImageUseCase::new(repository);
+        if !config.budget().is_unlimited() {
+            // しきい値を超えたらMCPのログ通知で警告する
+            let sender = notifications.clone();
+            let jsonrpc_version = config.jsonrpc_version().to_string();
+            let budget = BudgetGuard::new(config.budget().clone(), config.model_prices().clone())
+                .with_warning_handler(move |warning| {
+                    warn!("Budget warning: {}", warning);
+                    let _ = sender.send(JsonRpcNotification {
+                        jsonrpc: jsonrpc_version.clone(),
+                        method: "notifications/message".to_string(),
+                        params: Some(serde_json::json!({
+                            "level": "warning",
+                            "logger": "budget",
+                            "data": {
+                                "message": warning.to_string(),
+                                "budget": warning
+                            }
+                        })),
+                    });
+                });
+            use_case = use_case.with_budget(Arc::new(budget));
+        }
+        let use_case = Arc::new(use_case);
+        let mut server = Self {
             use_case,
+            jsonrpc_version: config.jsonrpc_version().to_string(),
+            notifications,
+            file_writer,
+            image_store,
+            history,
+            cursors: CursorCodec::new(),
+            key_pool,
+            model_catalog: None,
+            usage: Arc::new(UsageTracker::new(config.model_prices().clone())),
+            jobs: None,
+            job_max_concurrency: config.job_max_concurrency(),
+            job_max_pending: config.job_max_pending(),
+            batch_concurrency: config.batch_concurrency(),
+            batch_manifest_dir: config.batch_manifest_dir().map(PathBuf::from),
+            batch_manifests: tokio::sync::Mutex::new(HashMap::new()),
+            batch_client: None,
+            gemini_batches: tokio::sync::Mutex::new(HashMap::new()),
+            client_id: RwLock::new(None),
+        };
+        server.rebuild_jobs();
+        server
+    }
+
+    /// 画像ストアを設定する
+    pub fn with_image_store(mut self, image_store: Arc<dyn ImageStore>) -> Self {
+        self.image_store = Some(image_store);
+        self.rebuild_jobs();
+        self
+    }
+
+    /// 生成履歴を設定する
+    pub fn with_history(mut self, history: Arc<dyn GenerationHistory>) -> Self {
+        self.history = Some(history);
+        self.rebuild_jobs();
+        self
+    }
+
+    /// 画像ストア・生成履歴に合わせてジョブの管理を作り直す（ジョブは結果を画像ストアに保存する）
+    fn rebuild_jobs(&mut self) {
+        self.jobs = self.image_store.as_ref().map(|store| {
+            JobManager::new(
+                Arc::clone(&self.use_case),
+                Arc::clone(store),
+                self.history.clone(),
+                Arc::clone(&self.usage),
+                self.job_max_concurrency,
+            )
+            .with_max_pending(self.job_max_pending)
+        });
+    }
+
+    /// APIから取得するモデル一覧を設定する
+    ///
+    /// 取得した一覧が変わるとツールのモデルの選択肢も変わるため、`notifications/tools/list_changed`を送信する。
+    pub fn with_model_catalog(mut self, model_catalog: Arc<ModelCatalog>) -> Self {
+        let sender = self.notifications.clone();
+        let jsonrpc_version = self.jsonrpc_version.clone();
+        model_catalog.set_change_handler(move |_| {
+            info!("Model catalog changed, notifying tools/list_changed");
+            let _ = sender.send(JsonRpcNotification {
+                jsonrpc: jsonrpc_version.clone(),
+                method: "notifications/tools/list_changed".to_string(),
+                params: None,
+            });
+        });
+        self.model_catalog = Some(model_catalog);
+        self
+    }
+
+    /// 一括生成・Batch APIのバッチの結果を保存するディレクトリを設定
+    pub fn with_batch_manifest_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.batch_manifest_dir = Some(dir.into());
+        self
+    }
+
+    /// 一括生成・Batch APIのバッチの結果を保存するディレクトリ
+    pub fn batch_manifest_dir(&self) -> Option<&Path> {
+        self.batch_manifest_dir.as_deref()
+    }
+
+    /// Batch APIのクライアントを設定する（結果の保存には画像ストアも必要）
+    pub fn with_batch_client(mut self, batch_client: Arc<BatchClient>) -> Self {
+        self.batch_client = Some(batch_client);
+        self
+    }
+
+    /// 生成履歴から今月の利用量を読み込み、予算の集計を復元する（起動時に呼ぶ）
+    pub async fn restore_budget(&self) {
+        let (Some(budget), Some(history)) = (self.use_case.budget(), &self.history) else {
+            return;
+        };
+        use chrono::Datelike;
+        let today = chrono::Utc::now().date_naive();
+        let month_start = today
+            .with_day(1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|time| time.and_utc());
+        let mut query = HistoryQuery {
+            outcome: Some(GenerationOutcome::Succeeded),
+            since: month_start,
+            limit: BUDGET_RESTORE_PAGE_SIZE,
+            ..HistoryQuery::default()
+        };
+        let mut restored = 0;
+        loop {
+            let records = match history.query(&query).await {
+                Ok(records) => records,
+                Err(e) => {
+                    warn!("Failed to restore budget spend from history: {}", e);
+                    return;
+                }
+            };
+            budget.restore(&records);
+            restored += records.len();
+            match records.last() {
+                Some(last) if records.len() == BUDGET_RESTORE_PAGE_SIZE => {
+                    query.after = Some((last.created_at, last.request_id.clone()));
+                }
+                _ => break,
+            }
+        }
+        info!("Restored budget spend from {} generations", restored);
+    }
+
+    /// 接続したクライアントの名前を設定する（`initialize`の`clientInfo.name`）
+    pub fn set_client_id(&self, client_id: Option<String>) {
+        *self.client_id.write().unwrap_or_else(|e| e.into_inner()) = client_id;
+    }
+
+    /// サーバーからクライアントへの通知を購読する
+    pub fn subscribe_notifications(&self) -> broadcast::Receiver<JsonRpcNotification> {
+        self.notifications.subscribe()
+    }
+
+    /// 設定を再読み込みし、モデル設定が変わった場合はツールリストの変更を通知する
+    pub fn reload_config(&self) -> bool {
+        let config = Config::from_env();
+        ModelCapabilities::set_overrides(config.model_capability_overrides().clone());
+        self.update_models(
+            config.gemini_default_model().to_string(),
+            config.gemini_allowed_models().to_vec(),
+        )
+    }
+
+    /// デフォルトモデルと許可されたモデルリストを更新する
+    ///
+    /// 変更があった場合は`notifications/tools/list_changed`を送信し、`true`を返す。
+    pub fn update_models(&self, default_model: String, allowed_models: Vec<String>) -> bool {
+        let new_settings = ModelSettings {
             default_model,
             allowed_models,
+        };
+        if !GeminiModel::update_settings(new_settings) {
+            return false;
+        }
+
+        info!("Model settings changed, notifying tools/list_changed");
+        self.notify_tools_list_changed();
+        true
+    }
+
+    /// ツールリストが変更されたことをクライアントに通知する
+    pub fn notify_tools_list_changed(&self) {
+        self.send_notification("notifications/tools/list_changed", None);
+    }
+
+    fn send_notification(&self, method: &str, params: Option<serde_json::Value>) {
+        // 購読者がいない場合は送信エラーになるが、通知は破棄してよい
+        let _ = self.notifications.send(JsonRpcNotification {
+            jsonrpc: self.jsonrpc_version.clone(),
+            method: method.to_string(),
+            params,
+        });
+    }
+
+    fn model_settings(&self) -> ModelSettings {
+        GeminiModel::settings()
+    }
+
+    /// ツールで選択できるモデル（モデル一覧を取得済みの場合は、許可されたモデルのうち一覧にあるもの）
+    fn selectable_models(&self, settings: &ModelSettings) -> Vec<String> {
+        let Some(names) = self
+            .model_catalog
+            .as_ref()
+            .and_then(|catalog| catalog.cached_names())
+        else {
+            return settings.allowed_models.clone();
+        };
+        if settings.allowed_models.is_empty() {
+            return names;
+        }
+        let available: Vec<String> = settings
+            .allowed_models
+            .iter()
+            .filter(|model| names.contains(model))
+            .cloned()
+            .collect();
+        // 一覧に1つもない場合は選択肢をなくさないよう許可リストのままにする
+        if available.is_empty() {
+            settings.allowed_models.clone()
+        } else {
+            available
         }
     }
 
     /// MCPツールのリストを取得
     pub fn list_tools(&self) -> Vec<Tool> {
-        // 許可されたモデルリストが設定されている場合はenumとして、そうでない場合は文字列として
-        let model_schema = if self.allowed_models.is_empty() {
+        let settings = self.model_settings();
+
+        // 選択できるモデルが決まっている場合はenumとして、そうでない場合は文字列として
+        let models = self.selectable_models(&settings);
+        let model_schema = if models.is_empty() {
             serde_json::json!({
                 "type": "string",
                 "description": "Gemini model name to use (can be restricted via GEMINI_ALLOWED_MODELS environment variable)",
-                "default": self.default_model
+                "default": settings.default_model
             })
         } else {
             serde_json::json!({
                 "type": "string",
                 "description": "Gemini model name to use",
-                "enum": self.allowed_models,
-                "default": self.default_model
+                "enum": models,
+                "default": settings.default_model
             })
         };
 
-        vec![Tool {
-            name: "generate_image".to_string(),
-            description: Some(
-                "Generate images from text prompts using Google Gemini's Banana (image generation feature).".to_string(),
-            ),
-            input_schema: Some(serde_json::json!({
+        let generate_schema = serde_json::json!({
                 "type": "object",
                 "properties": {
                     "prompt": {
                         "type": "string",
                         "description": "Text prompt for image generation"
                     },
-                    "model": model_schema
+                    "model": model_schema,
+                    "output_path": {
+                        "type": "string",
+                        "description": "Directory to save the image in, relative to the configured output directory (IMAGE_OUTPUT_DIR)"
+                    },
+                    "filename": {
+                        "type": "string",
+                        "description": "File name for the saved image; the extension is derived from the image MIME type"
+                    },
+                    "aspect_ratio": {
+                        "type": "string",
+                        "description": "Aspect ratio of the image, e.g. 1:1, 3:4, 4:3, 9:16, 16:9"
+                    },
+                    "image_size": {
+                        "type": "string",
+                        "description": "Resolution of the image, e.g. 1K, 2K, 4K (supported values depend on the model)"
+                    },
+                    "negative_prompt": {
+                        "type": "string",
+                        "description": "Content to keep out of the image (Imagen models only)"
+                    },
+                    "person_generation": {
+                        "type": "string",
+                        "description": "Whether people may be generated (Imagen models only)",
+                        "enum": ["dont_allow", "allow_adult", "allow_all"]
+                    },
+                    "style_images": reference_images_schema(
+                        "Reference images whose visual style, color palette and rendering the image should match"
+                    ),
+                    "subject_images": reference_images_schema(
+                        "Reference images of characters, people or products whose appearance should stay consistent across a series"
+                    ),
+                    "bypass_cache": bypass_cache_schema()
                 },
                 "required": ["prompt"]
+        });
+        // ジョブの結果は画像ストアに保存するため、ファイルの保存先は指定できない
+        let mut job_schema = generate_schema.clone();
+        if let Some(properties) = job_schema["properties"].as_object_mut() {
+            properties.remove("output_path");
+            properties.remove("filename");
+        }
+
+        let mut tools = vec![Tool {
+            name: "generate_image".to_string(),
+            description: Some(
+                "Generate images from text prompts using Google Gemini's Banana (image generation feature).".to_string(),
+            ),
+            input_schema: Some(generate_schema),
+        }];
+
+        tools.push(Tool {
+            name: "usage_report".to_string(),
+            description: Some(
+                "Show token usage and estimated cost of image generation for this session, per API key, per model and per day."
+                    .to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {}
             })),
-        }]
+        });
+        if self.jobs.is_some() {
+            tools.extend(job_tools(job_schema));
+        }
+        if self.file_writer.is_some() || self.image_store.is_some() {
+            tools.push(batch_tool());
+        }
+        if self.batch_client.is_some() && self.image_store.is_some() {
+            tools.extend(gemini_batch_tools());
+        }
+        if self.history.is_some() {
+            tools.extend(history_tools());
+        }
+        if self.model_catalog.is_some() {
+            tools.push(Tool {
+                name: "list_models".to_string(),
+                description: Some(
+                    "List the image generation models available to this server, with descriptions and token limits."
+                        .to_string(),
+                ),
+                input_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "refresh": {
+                            "type": "boolean",
+                            "description": "Fetch the model list from the API again instead of using the cached list",
+                            "default": false
+                        }
+                    }
+                })),
+            });
+        }
+        if self.key_pool.len() > 1 {
+            tools.push(Tool {
+                name: "api_key_metrics".to_string(),
+                description: Some(
+                    "Show per-key request counts, throttling and quarantine status of the Gemini API key pool."
+                        .to_string(),
+                ),
+                input_schema: Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {}
+                })),
+            });
+        }
+        tools
+    }
+
+    /// MCPリソースのリストを取得（画像ストアに保存された生成画像、新しい順）
+    pub async fn list_resources(&self) -> Result<Vec<Resource>> {
+        Ok(self
+            .list_resource_entries()
+            .await?
+            .into_iter()
+            .map(|(_, resource)| resource)
+            .collect())
+    }
+
+    /// 画像ストアの画像をMCPリソースとして、新しい順の一覧での位置とともに取得する
+    pub async fn list_resource_entries(&self) -> Result<Vec<(KeysetPosition, Resource)>> {
+        let Some(store) = &self.image_store else {
+            return Ok(Vec::new());
+        };
+
+        let resources = store
+            .list()
+            .await?
+            .into_iter()
+            .map(|stored| {
+                let position = KeysetPosition::new(stored.stored_at, stored.id.clone());
+                let prompt = &stored.metadata.prompt;
+                let mut name: String = prompt.chars().take(RESOURCE_NAME_MAX_CHARS).collect();
+                if name.len() < prompt.len() {
+                    name.push('…');
+                }
+                let resource = Resource {
+                    uri: stored.resource_uri(),
+                    name,
+                    description: Some(format!(
+                        "{} ({}, {})",
+                        prompt,
+                        stored.metadata.model,
+                        stored.metadata.generated_at.to_rfc3339()
+                    )),
+                    mime_type: Some(stored.mime_type),
+                };
+                (position, resource)
+            })
+            .collect();
+        Ok(resources)
+    }
+
+    /// MCPリソース（`image://<SHA-256>`）の内容を取得
+    pub async fn read_resource(&self, uri: &str) -> Result<Option<ResourceContents>> {
+        let (Some(store), Some(id)) = (&self.image_store, uri.strip_prefix("image://")) else {
+            return Ok(None);
+        };
+
+        use base64::Engine;
+        Ok(store.get(id).await?.map(|(stored, data)| ResourceContents {
+            uri: stored.resource_uri(),
+            mime_type: stored.mime_type,
+            blob: base64::engine::general_purpose::STANDARD.encode(data),
+        }))
     }
 
     /// ツール呼び出しを処理
@@ -85,6 +598,43 @@ impl McpServer {
     ) -> Result<CallToolResult> {
         match name {
             "generate_image" => self.handle_generate_image(arguments).await,
+            "list_history" | "search_history" | "get_generation" if self.history.is_some() => {
+                self.handle_history_tool(name, arguments).await
+            }
+            "regenerate" if self.history.is_some() => self.handle_regenerate(arguments).await,
+            "vary" if self.history.is_some() => self.handle_vary(arguments).await,
+            "submit_image_job" | "get_image_job" | "cancel_image_job" if self.jobs.is_some() => {
+                self.handle_job_tool(name, arguments).await
+            }
+            "generate_batch" if self.file_writer.is_some() || self.image_store.is_some() => {
+                self.handle_generate_batch(arguments).await
+            }
+            "submit_gemini_batch" | "get_gemini_batch" | "cancel_gemini_batch"
+                if self.batch_client.is_some() && self.image_store.is_some() =>
+            {
+                self.handle_gemini_batch_tool(name, arguments).await
+            }
+            "usage_report" => {
+                let result = serde_json::json!(self.usage.report());
+                Ok(CallToolResult {
+                    content: vec![Content::Text {
+                        text: result.to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
+            "list_models" if self.model_catalog.is_some() => {
+                self.handle_list_models(arguments).await
+            }
+            "api_key_metrics" if self.key_pool.len() > 1 => {
+                let result = serde_json::json!({ "keys": self.key_pool.metrics() });
+                Ok(CallToolResult {
+                    content: vec![Content::Text {
+                        text: result.to_string(),
+                    }],
+                    is_error: false,
+                })
+            }
             _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
         }
     }
@@ -92,49 +642,1650 @@ impl McpServer {
     async fn handle_generate_image(&self, arguments: &serde_json::Value) -> Result<CallToolResult> {
         info!("Handling generate_image request");
 
-        // 引数のパース
+        let (output_path, filename) = self.output_arguments(arguments)?;
+        let request = self.parse_generate_request(arguments)?;
+        let request = self.attach_reference_images(request, arguments).await?;
+        self.generate(request, output_path, filename).await
+    }
+
+    async fn handle_job_tool(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<CallToolResult> {
+        let jobs = self
+            .jobs
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Image jobs require an image store"))?;
+        info!("Handling {} request", name);
+
+        let job = if name == "submit_image_job" {
+            let request = self.parse_generate_request(arguments)?;
+            let request = self.attach_reference_images(request, arguments).await?;
+            let job = jobs.submit(self.prepare_request(request).await?)?;
+            info!("Submitted image job {}", job.job_id);
+            job
+        } else {
+            let job_id = arguments
+                .get("job_id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing required parameter: job_id"))?;
+            if name == "cancel_image_job" {
+                jobs.cancel(job_id)?
+            } else {
+                jobs.get(job_id)
+                    .ok_or_else(|| anyhow::anyhow!("Job not found: {}", job_id))?
+            }
+        };
+        Ok(CallToolResult {
+            content: vec![Content::Text {
+                text: serde_json::to_string(&job)?,
+            }],
+            is_error: false,
+        })
+    }
+
+    async fn handle_generate_batch(&self, arguments: &serde_json::Value) -> Result<CallToolResult> {
+        info!("Handling generate_batch request");
+
+        let items = parse_batch_items(arguments)?;
+        let batch_id = match arguments.get("batch_id").and_then(|v| v.as_str()) {
+            Some(id) if is_valid_batch_id(id) => id.to_string(),
+            Some(id) => return Err(anyhow::anyhow!("Invalid batch_id: {}", id)),
+            None => uuid::Uuid::new_v4().to_string(),
+        };
+        let concurrency = arguments
+            .get("concurrency")
+            .and_then(|v| v.as_u64())
+            .map(|n| (n as usize).clamp(1, self.batch_concurrency));
+        let (output_path, _) = self.output_arguments(arguments)?;
+
+        // 同じbatch_idの前回の結果があれば成功済みの項目を飛ばして再開する
+        let manifest_path = self
+            .batch_manifest_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", batch_id)));
+        let previous = match &manifest_path {
+            Some(path) => BatchManifest::load(path)?,
+            None => self
+                .batch_manifests
+                .lock()
+                .await
+                .get(&batch_id)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        let manifest = self
+            .generate_batch(items, previous, concurrency, output_path, |manifest| {
+                if let Some(path) = &manifest_path {
+                    if let Err(e) = manifest.save(path) {
+                        warn!("Failed to save batch manifest: {}", e);
+                    }
+                }
+            })
+            .await?;
+        if manifest_path.is_none() {
+            // 全件成功したバッチは再開する必要がないのでメモリから外す
+            let mut manifests = self.batch_manifests.lock().await;
+            if manifest.failed == 0 {
+                manifests.remove(&batch_id);
+            } else {
+                manifests.insert(batch_id.clone(), manifest.clone());
+            }
+        }
+        info!(
+            "Batch {} finished: {} succeeded, {} failed",
+            batch_id, manifest.succeeded, manifest.failed
+        );
+
+        let mut result = serde_json::json!(manifest);
+        result["batch_id"] = serde_json::Value::String(batch_id);
+        if let Some(path) = manifest_path {
+            result["manifest_path"] = serde_json::Value::String(path.display().to_string());
+        }
+        Ok(CallToolResult {
+            content: vec![Content::Text {
+                text: result.to_string(),
+            }],
+            is_error: false,
+        })
+    }
+
+    /// 項目を上限までの並列数で生成し、項目ごとの結果の一覧を返す
+    ///
+    /// `previous`で成功している項目は生成しない。画像は出力ディレクトリ・画像ストアに保存する。
+    pub async fn generate_batch(
+        &self,
+        items: Vec<BatchItem>,
+        previous: BatchManifest,
+        concurrency: Option<usize>,
+        output_path: Option<&str>,
+        on_progress: impl FnMut(&BatchManifest),
+    ) -> Result<BatchManifest> {
+        if self.file_writer.is_none() && self.image_store.is_none() {
+            return Err(anyhow::anyhow!(
+                "Batch generation requires IMAGE_OUTPUT_DIR or IMAGE_STORE_DIR to be configured"
+            ));
+        }
+
+        let generate = |item: BatchItem| async move {
+            let generated = async {
+                let request = self.parse_generate_request(&serde_json::json!(item))?;
+                self.generate_result(request, output_path, item.filename.as_deref())
+                    .await
+            }
+            .await;
+            match generated {
+                Ok((output, _)) => BatchItemResult::succeeded(&item, output),
+                Err(e) => {
+                    warn!("Batch item failed: {}", e);
+                    BatchItemResult::failed(&item, redact(&e.to_string()))
+                }
+            }
+        };
+        Ok(batch::run_batch(
+            items,
+            previous,
+            concurrency.unwrap_or(self.batch_concurrency),
+            generate,
+            on_progress,
+        )
+        .await)
+    }
+
+    async fn handle_gemini_batch_tool(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<CallToolResult> {
+        info!("Handling {} request", name);
+        let string_arg = |key: &str| arguments.get(key).and_then(|v| v.as_str());
+
+        let result = if name == "submit_gemini_batch" {
+            let items = parse_batch_items(arguments)?;
+            let mode = match string_arg("input_mode") {
+                Some(mode) => mode.parse().map_err(anyhow::Error::msg)?,
+                None => BatchInputMode::Auto,
+            };
+            let job = self
+                .submit_gemini_batch(
+                    items,
+                    string_arg("model").map(str::to_string),
+                    mode,
+                    string_arg("display_name"),
+                )
+                .await?;
+            serde_json::json!(job)
+        } else {
+            let batch_name = string_arg("name")
+                .ok_or_else(|| anyhow::anyhow!("Missing required parameter: name"))?;
+            if name == "cancel_gemini_batch" {
+                serde_json::json!(self.cancel_gemini_batch(batch_name).await?)
+            } else {
+                let (job, manifest) = self.gemini_batch_status(batch_name).await?;
+                let mut result = serde_json::json!(job);
+                if let Some(manifest) = manifest {
+                    result["results"] = serde_json::json!(manifest);
+                }
+                result
+            }
+        };
+        Ok(CallToolResult {
+            content: vec![Content::Text {
+                text: result.to_string(),
+            }],
+            is_error: false,
+        })
+    }
+
+    /// 項目をBatch APIのバッチとして送信する（項目にモデルがない場合は`model`、それもなければデフォルト）
+    ///
+    /// 結果は終了後に`gemini_batch_status`・`wait_gemini_batch`で画像ストアに保存する。
+    pub async fn submit_gemini_batch(
+        &self,
+        items: Vec<BatchItem>,
+        model: Option<String>,
+        mode: BatchInputMode,
+        display_name: Option<&str>,
+    ) -> Result<BatchJob> {
+        let client = self.batch_client()?;
+        let mut requests = Vec::with_capacity(items.len());
+        for (i, mut item) in items.into_iter().enumerate() {
+            if item.model.is_none() {
+                item.model = model.clone();
+            }
+            let request = self
+                .prepare_request(self.parse_generate_request(&serde_json::json!(item))?)
+                .await?;
+            request
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Invalid item {}: {}", i, e))?;
+            requests.push(request);
+        }
+        // すべての項目の分を確保する（上限を超える項目があれば確保した分を戻して送信しない）
+        let mut reservations = HashMap::new();
+        if let Some(budget) = self.use_case.budget() {
+            for (i, request) in requests.iter().enumerate() {
+                let reservation = budget
+                    .reserve(request)
+                    .map_err(|e| anyhow::anyhow!("Budget exceeded at item {}: {}", i, e))?;
+                reservations.insert(request.request_id.clone(), reservation);
+            }
+        }
+
+        let display_name = display_name.map(str::to_string).unwrap_or_else(|| {
+            format!("image-batch-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S"))
+        });
+        let job = client
+            .submit(&requests, &display_name, mode)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to submit batch: {}", e))?;
+        info!(
+            "Submitted Gemini batch {} with {} requests",
+            job.name,
+            requests.len()
+        );
+        let batch = GeminiBatch {
+            key_id: client.key_id(&job.name),
+            requests: requests.iter().map(|r| r.without_image_data()).collect(),
+            submitted_at: chrono::Utc::now(),
+            manifest: None,
+            reservations,
+            collecting: Arc::default(),
+        };
+        self.save_gemini_batch(&job.name, &batch);
+        self.gemini_batches
+            .lock()
+            .await
+            .insert(job.name.clone(), batch);
+        Ok(job)
+    }
+
+    /// バッチの状態を取得し、終了していれば結果を画像ストアに保存して項目ごとの結果を返す
+    pub async fn gemini_batch_status(
+        &self,
+        name: &str,
+    ) -> Result<(BatchJob, Option<BatchManifest>)> {
+        let client = self.batch_client()?;
+        self.load_gemini_batch(client, name).await;
+        let job = client
+            .get(name)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get batch: {}", e))?;
+        let manifest = if job.state.is_finished() {
+            Some(self.collect_gemini_batch(client, &job).await?)
+        } else {
+            None
+        };
+        Ok((job, manifest))
+    }
+
+    /// バッチが終了するまで待ち、結果を画像ストアに保存して項目ごとの結果を返す
+    pub async fn wait_gemini_batch(&self, name: &str) -> Result<(BatchJob, BatchManifest)> {
+        let client = self.batch_client()?;
+        self.load_gemini_batch(client, name).await;
+        let job = client
+            .wait(name)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get batch: {}", e))?;
+        let manifest = self.collect_gemini_batch(client, &job).await?;
+        Ok((job, manifest))
+    }
+
+    /// バッチを取り消し、取り消し後の状態を返す
+    pub async fn cancel_gemini_batch(&self, name: &str) -> Result<BatchJob> {
+        let client = self.batch_client()?;
+        self.load_gemini_batch(client, name).await;
+        client
+            .cancel(name)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to cancel batch: {}", e))?;
+        info!("Cancelled Gemini batch {}", name);
+        client
+            .get(name)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get batch: {}", e))
+    }
+
+    /// バッチの情報を保存するファイル（保存先のディレクトリがない場合は`None`）
+    fn gemini_batch_path(&self, name: &str) -> Option<PathBuf> {
+        let id = name
+            .strip_prefix("batches/")
+            .filter(|id| is_valid_batch_id(id))?;
+        let dir = self.batch_manifest_dir.as_ref()?;
+        Some(dir.join(format!("gemini-{}.json", id)))
+    }
+
+    /// バッチの情報をファイルに保存する（別のプロセスでも結果を取得・保存できるように）
+    fn save_gemini_batch(&self, name: &str, batch: &GeminiBatch) {
+        let Some(path) = self.gemini_batch_path(name) else {
+            return;
+        };
+        let saved = serde_json::to_vec_pretty(batch)
+            .map_err(std::io::Error::other)
+            .and_then(|json| {
+                std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+                let temp = path.with_extension("json.tmp");
+                std::fs::write(&temp, json)?;
+                std::fs::rename(&temp, &path)
+            });
+        if let Err(e) = saved {
+            warn!("Failed to save Gemini batch {}: {}", name, e);
+        }
+    }
+
+    /// メモリにないバッチの情報をファイルから読み込み、バッチを作成したキーを復元する
+    async fn load_gemini_batch(&self, client: &BatchClient, name: &str) {
+        let mut batches = self.gemini_batches.lock().await;
+        if batches.contains_key(name) {
+            return;
+        }
+        let Some(path) = self.gemini_batch_path(name) else {
+            return;
+        };
+        let batch: GeminiBatch = match std::fs::read(&path) {
+            Ok(json) => match serde_json::from_slice(&json) {
+                Ok(batch) => batch,
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                    return;
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        if let Some(key_id) = &batch.key_id {
+            if !client.restore_key(name, key_id) {
+                warn!(
+                    "The API key that created batch {} is no longer configured",
+                    name
+                );
+            }
+        }
+        batches.insert(name.to_string(), batch);
+    }
+
+    fn batch_client(&self) -> Result<&BatchClient> {
+        match (&self.batch_client, &self.image_store) {
+            (Some(client), Some(_)) => Ok(client),
+            (None, _) => Err(anyhow::anyhow!(
+                "The Gemini Batch API requires an AI Studio API key"
+            )),
+            (Some(_), None) => Err(anyhow::anyhow!(
+                "The Gemini Batch API requires IMAGE_STORE_DIR to be configured"
+            )),
+        }
+    }
+
+    /// 終了したバッチの結果を画像ストアに保存し、項目ごとの結果の一覧を返す（保存済みなら保存しない）
+    async fn collect_gemini_batch(
+        &self,
+        client: &BatchClient,
+        job: &BatchJob,
+    ) -> Result<BatchManifest> {
+        // バッチ全体の一覧のロックは状態の読み書きの間だけ持ち、結果の取得・保存の間はこのバッチだけを待たせる
+        let collecting = {
+            let mut batches = self.gemini_batches.lock().await;
+            let batch = batches
+                .entry(job.name.clone())
+                .or_insert_with(|| GeminiBatch::unknown(client.key_id(&job.name)));
+            Arc::clone(&batch.collecting)
+        };
+        let _collecting = collecting.lock().await;
+        let (requests, elapsed, mut reservations) = {
+            let mut batches = self.gemini_batches.lock().await;
+            let batch = batches
+                .entry(job.name.clone())
+                .or_insert_with(|| GeminiBatch::unknown(client.key_id(&job.name)));
+            if let Some(manifest) = &batch.manifest {
+                return Ok(manifest.clone());
+            }
+            let elapsed = (chrono::Utc::now() - batch.submitted_at)
+                .to_std()
+                .unwrap_or_default();
+            (
+                batch.requests.clone(),
+                elapsed,
+                std::mem::take(&mut batch.reservations),
+            )
+        };
+        let responses = match client.results(job).await {
+            Ok(responses) => responses,
+            Err(e) => {
+                // 次に結果を取得するときのために確保した分を戻しておく
+                if let Some(batch) = self.gemini_batches.lock().await.get_mut(&job.name) {
+                    batch.reservations = reservations;
+                }
+                return Err(anyhow::anyhow!("Failed to get batch results: {}", e));
+            }
+        };
+
+        let indices: HashMap<&str, usize> = requests
+            .iter()
+            .enumerate()
+            .map(|(index, request)| (request.request_id.as_str(), index))
+            .collect();
+        let mut manifest = BatchManifest::default();
+        for (i, response) in responses.into_iter().enumerate() {
+            let (index, request) = match indices.get(response.key.as_str()) {
+                Some(&index) => (index, requests[index].clone()),
+                // 別のプロセスが送信したバッチはリクエストの内容が分からない
+                None => {
+                    let mut request = ImageGenerationRequest::new(format!(
+                        "({} item {})",
+                        job.name, response.key
+                    ))
+                    .with_model(job.gemini_model().unwrap_or_default());
+                    request.request_id = response.key.clone();
+                    (requests.len() + i, request)
+                }
+            };
+            let item = BatchItem::new(request.prompt.clone());
+            let stored = match response.result {
+                Ok(image) => {
+                    let reservation = reservations.remove(&request.request_id);
+                    self.store_batch_image(&request, &image, elapsed, reservation)
+                        .await
+                }
+                Err(e) => {
+                    self.record_history(GenerationRecord::failed(
+                        &request,
+                        e.kind(),
+                        redact(&e.to_string()),
+                        elapsed,
+                    ))
+                    .await;
+                    Err(anyhow::anyhow!("Image generation failed: {}", e))
+                }
+            };
+            let result = match stored {
+                Ok(output) => BatchItemResult::succeeded(&item, output),
+                Err(e) => BatchItemResult::failed(&item, redact(&e.to_string())),
+            };
+            manifest.items.push(BatchItemResult {
+                index,
+                key: request.request_id.clone(),
+                ..result
+            });
+        }
+        // 取り消された・失敗したバッチでは結果のないリクエストもある
+        let answered: HashSet<String> = manifest.items.iter().map(|r| r.key.clone()).collect();
+        for (index, request) in requests.iter().enumerate() {
+            if !answered.contains(&request.request_id) {
+                let item = BatchItem::new(request.prompt.clone());
+                manifest.items.push(BatchItemResult {
+                    index,
+                    key: request.request_id.clone(),
+                    ..BatchItemResult::failed(&item, format!("No result (batch {:?})", job.state))
+                });
+            }
+        }
+        manifest.items.sort_by_key(|r| r.index);
+        manifest.total = manifest.items.len();
+        manifest.succeeded = manifest
+            .items
+            .iter()
+            .filter(|r| r.status == BatchItemStatus::Succeeded)
+            .count();
+        manifest.failed = manifest.items.len() - manifest.succeeded;
+        info!(
+            "Gemini batch {} finished: {} succeeded, {} failed",
+            job.name, manifest.succeeded, manifest.failed
+        );
+
+        // 結果のなかった項目の確保分は戻す
+        drop(reservations);
+        let mut batches = self.gemini_batches.lock().await;
+        let batch = batches
+            .entry(job.name.clone())
+            .or_insert_with(|| GeminiBatch::unknown(client.key_id(&job.name)));
+        batch.manifest = Some(manifest.clone());
+        self.save_gemini_batch(&job.name, batch);
+        Ok(manifest)
+    }
+
+    /// Batch APIで生成した画像を集計し、画像ストアへの保存・履歴への記録を行って結果の情報を返す
+    async fn store_batch_image(
+        &self,
+        request: &ImageGenerationRequest,
+        image: &GeneratedImage,
+        elapsed: Duration,
+        reservation: Option<BudgetReservation>,
+    ) -> Result<serde_json::Value> {
+        let store = self
+            .image_store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Image store is not configured"))?;
+        // 別のプロセスで送信したバッチは確保した分がないので、ここで集計する
+        match (reservation, self.use_case.budget()) {
+            (Some(reservation), _) => {
+                reservation.commit(image);
+            }
+            (None, Some(budget)) => {
+                budget.record(request, image);
+            }
+            (None, None) => {}
+        }
+        let mut result = serde_json::json!({
+            "request_id": request.request_id,
+            "mime_type": image.mime_type,
+            "model": image.model,
+            "size_bytes": image.data.len()
+        });
+        let mut record = GenerationRecord::succeeded(request, image, elapsed);
+        if let Some(usage) = self.usage.record(image) {
+            result["usage"] = serde_json::json!(usage);
+            record = record.with_estimated_cost(usage.estimated_cost_usd);
+        }
+
+        let stored = match store.put(image, &ImageMetadata::new(request, image)).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                self.record_history(record).await;
+                return Err(anyhow::anyhow!("Failed to store image: {}", e));
+            }
+        };
+        result["image_id"] = serde_json::Value::String(stored.id.clone());
+        result["resource_uri"] = serde_json::Value::String(stored.resource_uri());
+        if let Some(url) = store.download_url(&stored) {
+            result["image_url"] = serde_json::Value::String(url);
+        }
+        self.record_history(record.with_image_id(stored.id.clone()))
+            .await;
+        Ok(result)
+    }
+
+    /// `generate_image`の引数から生成リクエストを作成
+    fn parse_generate_request(
+        &self,
+        arguments: &serde_json::Value,
+    ) -> Result<ImageGenerationRequest> {
         let prompt = arguments
             .get("prompt")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing required parameter: prompt"))?
             .to_string();
 
-        let model = arguments
-            .get("model")
+        let model = parse_model(arguments)?
+            .unwrap_or_else(|| GeminiModel::from(self.model_settings().default_model));
+
+        let mut request = ImageGenerationRequest::new(prompt)
+            .with_model(model)
+            .with_bypass_cache(parse_bypass_cache(arguments));
+        let string_arg = |key: &str| {
+            arguments
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+        if let Some(aspect_ratio) = string_arg("aspect_ratio") {
+            request = request.with_aspect_ratio(aspect_ratio);
+        }
+        if let Some(image_size) = string_arg("image_size") {
+            request = request.with_image_size(image_size);
+        }
+        if let Some(negative_prompt) = string_arg("negative_prompt") {
+            request = request.with_negative_prompt(negative_prompt);
+        }
+        if let Some(person_generation) = string_arg("person_generation") {
+            request = request.with_person_generation(person_generation);
+        }
+        Ok(request)
+    }
+
+    async fn handle_list_models(&self, arguments: &serde_json::Value) -> Result<CallToolResult> {
+        info!("Handling list_models request");
+        let Some(catalog) = &self.model_catalog else {
+            return Err(anyhow::anyhow!("Model discovery is not enabled"));
+        };
+
+        let refresh = arguments
+            .get("refresh")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let models = if refresh {
+            catalog.refresh().await
+        } else {
+            catalog.models().await
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to list models: {}", e))?;
+
+        // 許可されたモデルリストに含まれるものだけを返す
+        let default_model = self.model_settings().default_model;
+        let models: Vec<serde_json::Value> = models
+            .into_iter()
+            .filter(|m| GeminiModel::from(m.name.clone()).is_allowed())
+            .map(|m| {
+                let is_default = m.name == default_model;
+                let capabilities = ModelCapabilities::for_model(&GeminiModel::from(m.name.clone()));
+                let mut value = serde_json::json!(m);
+                value["default"] = serde_json::Value::Bool(is_default);
+                value["capabilities"] = serde_json::json!(capabilities);
+                value
+            })
+            .collect();
+        let result = serde_json::json!({
+            "models": models,
+            "default_model": default_model
+        });
+        Ok(CallToolResult {
+            content: vec![Content::Text {
+                text: result.to_string(),
+            }],
+            is_error: false,
+        })
+    }
+
+    async fn handle_regenerate(&self, arguments: &serde_json::Value) -> Result<CallToolResult> {
+        info!("Handling regenerate request");
+
+        let previous = self.find_generation(arguments).await?;
+        let (output_path, filename) = self.output_arguments(arguments)?;
+
+        // 記録されたリクエストをそのまま再実行する（新しい画像を得るため、キャッシュは使わない）
+        let request = self.restore_input_images(previous.to_request()).await?;
+        let request = request.rerun().with_bypass_cache(true);
+        self.generate(request, output_path, filename).await
+    }
+
+    /// 履歴から復元したリクエストの入力画像のデータを画像ストアから読み直す
+    async fn restore_input_images(
+        &self,
+        mut request: ImageGenerationRequest,
+    ) -> Result<ImageGenerationRequest> {
+        for image in &mut request.input_images {
+            if !image.data.is_empty() {
+                continue;
+            }
+            let Some(sha256) = image.sha256.take() else {
+                continue;
+            };
+            let store = self.image_store.as_ref().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Generation {} used input images, which cannot be restored without an image store",
+                    request.request_id
+                )
+            })?;
+            let stored = if StoredImage::is_valid_id(&sha256) {
+                store.get(&sha256).await?
+            } else {
+                None
+            };
+            let (_, data) = stored.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "An input image of generation {} is no longer available in the image store",
+                    request.request_id
+                )
+            })?;
+            image.data = data;
+        }
+        Ok(request)
+    }
+
+    async fn handle_vary(&self, arguments: &serde_json::Value) -> Result<CallToolResult> {
+        info!("Handling vary request");
+
+        let previous = self.find_generation(arguments).await?;
+        let instruction = arguments
+            .get("instruction")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing required parameter: instruction"))?
+            .to_string();
+        let model = parse_model(arguments)?.unwrap_or_else(|| previous.model.clone());
+        let (output_path, filename) = self.output_arguments(arguments)?;
+
+        // 前回の出力画像を入力画像として、変更内容のプロンプトと一緒に渡す
+        let input_image = self.load_output_image(&previous).await?;
+        let request = ImageGenerationRequest::new(instruction)
+            .with_model(model)
+            .with_input_image(input_image)
+            .with_parent_request_id(previous.request_id.clone())
+            .with_bypass_cache(parse_bypass_cache(arguments));
+        self.generate(request, output_path, filename).await
+    }
+
+    /// `request_id`引数で指定された生成の記録を取得する
+    async fn find_generation(&self, arguments: &serde_json::Value) -> Result<GenerationRecord> {
+        let history = self
+            .history
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Generation history is not configured"))?;
+        let request_id = arguments
+            .get("request_id")
             .and_then(|v| v.as_str())
-            .map(GeminiModel::try_from)
-            .transpose()
-            .map_err(|e| anyhow::anyhow!("Invalid model: {}", e))?
-            .unwrap_or_else(|| GeminiModel::from(self.default_model.clone()));
+            .ok_or_else(|| anyhow::anyhow!("Missing required parameter: request_id"))?;
+        history
+            .get(request_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Generation not found: {}", request_id))
+    }
 
-        let request = ImageGenerationRequest::new(prompt).with_model(model);
+    /// `style_images`・`subject_images`引数の参照画像を読み込んでリクエストに添付する
+    async fn attach_reference_images(
+        &self,
+        mut request: ImageGenerationRequest,
+        arguments: &serde_json::Value,
+    ) -> Result<ImageGenerationRequest> {
+        for (key, role) in [
+            ("style_images", InputImageRole::Style),
+            ("subject_images", InputImageRole::Subject),
+        ] {
+            let Some(value) = arguments.get(key) else {
+                continue;
+            };
+            let references = value
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("{} must be an array", key))?;
+            for (i, reference) in references.iter().enumerate() {
+                let image = self
+                    .load_reference_image(reference)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Invalid {}[{}]: {}", key, i, e))?;
+                if reference.get("data").is_some() {
+                    self.store_inline_reference(&request, &image).await?;
+                }
+                request = request.with_input_image(image.with_role(role));
+            }
+        }
+        Ok(request)
+    }
 
-        // ユースケースを実行
-        let image = self.use_case.execute(request).await.map_err(|e| {
-            error!("Image generation failed: {}", e);
-            anyhow::anyhow!("Image generation failed: {}", e)
-        })?;
+    /// base64で渡された参照画像を画像ストアに保存する（履歴から再生成する際に読み直せるように）
+    async fn store_inline_reference(
+        &self,
+        request: &ImageGenerationRequest,
+        image: &InputImage,
+    ) -> Result<()> {
+        let Some(store) = &self.image_store else {
+            return Ok(());
+        };
+        let image = GeneratedImage::new(image.data.clone(), request.model.clone())
+            .with_mime_type(image.mime_type.clone());
+        store
+            .put(&image, &ImageMetadata::new(request, &image))
+            .await?;
+        Ok(())
+    }
+
+    /// 参照画像を読み込む（画像ストアのID・`image://`のURI、過去の生成の`request_id`、base64の`data`）
+    async fn load_reference_image(&self, reference: &serde_json::Value) -> Result<InputImage> {
+        let field = |key: &str| reference.get(key).and_then(|v| v.as_str());
+        let image_id = reference.as_str().or_else(|| field("image_id"));
+        if let Some(image_id) = image_id {
+            let store = self
+                .image_store
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Image references require an image store"))?;
+            let image_id = image_id.strip_prefix("image://").unwrap_or(image_id);
+            if !StoredImage::is_valid_id(image_id) {
+                return Err(anyhow::anyhow!("Invalid image id: {}", image_id));
+            }
+            let (stored, data) = store
+                .get(image_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Image not found: {}", image_id))?;
+            return Ok(InputImage::new(data, stored.mime_type));
+        }
+        if let Some(request_id) = field("request_id") {
+            let history = self
+                .history
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Generation history is not enabled"))?;
+            let record = history
+                .get(request_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Generation not found: {}", request_id))?;
+            return self.load_output_image(&record).await;
+        }
+        if let Some(data) = field("data") {
+            use base64::Engine;
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| anyhow::anyhow!("Invalid base64 data: {}", e))?;
+            let mime_type = field("mime_type")
+                .ok_or_else(|| anyhow::anyhow!("Missing required parameter: mime_type"))?;
+            return Ok(InputImage::new(data, mime_type.to_string()));
+        }
+        Err(anyhow::anyhow!(
+            "Expected an image ID, an image:// URI, or an object with image_id, request_id or data"
+        ))
+    }
+
+    /// 過去の生成の出力画像を読み込む（画像ストア、出力ファイルの順に探す）
+    async fn load_output_image(&self, record: &GenerationRecord) -> Result<InputImage> {
+        if record.outcome != GenerationOutcome::Succeeded {
+            return Err(anyhow::anyhow!(
+                "Generation {} did not produce an image",
+                record.request_id
+            ));
+        }
+
+        if let (Some(store), Some(image_id)) = (&self.image_store, &record.image_id) {
+            if let Some((stored, data)) = store.get(image_id).await? {
+                return Ok(InputImage::new(data, stored.mime_type));
+            }
+        }
+        if let Some(path) = &record.file_path {
+            match tokio::fs::read(path).await {
+                Ok(data) => {
+                    let mime_type = record
+                        .mime_type
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_IMAGE_MIME_TYPE.to_string());
+                    return Ok(InputImage::new(data, mime_type));
+                }
+                Err(e) => warn!("Failed to read previous output {}: {}", path, e),
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "The output image of generation {} is no longer available",
+            record.request_id
+        ))
+    }
+
+    /// `output_path`・`filename`引数を取得する（出力ディレクトリが未設定の場合はエラー）
+    fn output_arguments<'a>(
+        &self,
+        arguments: &'a serde_json::Value,
+    ) -> Result<(Option<&'a str>, Option<&'a str>)> {
+        let output_path = arguments.get("output_path").and_then(|v| v.as_str());
+        let filename = arguments.get("filename").and_then(|v| v.as_str());
+        if self.file_writer.is_none() && (output_path.is_some() || filename.is_some()) {
+            return Err(anyhow::anyhow!(
+                "Saving images requires IMAGE_OUTPUT_DIR to be configured"
+            ));
+        }
+        Ok((output_path, filename))
+    }
+
+    /// 生成前の検証とクライアント名の付与（モデルが許可されなくなっている場合などは拒否する）
+    async fn prepare_request(
+        &self,
+        mut request: ImageGenerationRequest,
+    ) -> Result<ImageGenerationRequest> {
+        // 履歴から復元したリクエストのモデルが許可されなくなっている場合もある
+        if !request.model.is_allowed() {
+            return Err(anyhow::anyhow!(
+                "Invalid model: Model '{}' is not in the allowed list",
+                request.model
+            ));
+        }
+        // モデル一覧にないモデルはAPIを呼ぶ前に拒否する（一覧を取得できない場合は検証しない）
+        let catalog_contains = match &self.model_catalog {
+            Some(catalog) => catalog.contains(&request.model).await,
+            None => None,
+        };
+        if catalog_contains == Some(false) {
+            return Err(anyhow::anyhow!(
+                "Invalid model: Model '{}' is not available (see list_models)",
+                request.model
+            ));
+        }
+
+        let client_id = self
+            .client_id
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some(client_id) = client_id {
+            request = request.with_client_id(client_id);
+        }
+        Ok(request)
+    }
+
+    /// 画像を生成し、保存・履歴への記録を行ってツールの結果を返す
+    async fn generate(
+        &self,
+        request: ImageGenerationRequest,
+        output_path: Option<&str>,
+        filename: Option<&str>,
+    ) -> Result<CallToolResult> {
+        let (mut result, image) = self.generate_result(request, output_path, filename).await?;
 
         // 結果をbase64エンコードして返す
         use base64::Engine;
-        let base64_data = base64::engine::general_purpose::STANDARD.encode(&image.data);
+        result["image_data"] = serde_json::Value::String(
+            base64::engine::general_purpose::STANDARD.encode(&image.data),
+        );
+        Ok(CallToolResult {
+            content: vec![Content::Text {
+                text: result.to_string(),
+            }],
+            is_error: false,
+        })
+    }
+
+    /// 画像を生成して保存・履歴への記録を行い、結果の情報（画像データを除く）と画像を返す
+    async fn generate_result(
+        &self,
+        request: ImageGenerationRequest,
+        output_path: Option<&str>,
+        filename: Option<&str>,
+    ) -> Result<(serde_json::Value, GeneratedImage)> {
+        let request = self.prepare_request(request).await?;
+
+        // ユースケースを実行
+        let started = Instant::now();
+        let image = match self.use_case.execute(request.clone()).await {
+            Ok(image) => image,
+            Err(e) => {
+                error!("Image generation failed: {}", e);
+                self.record_history(GenerationRecord::failed(
+                    &request,
+                    e.kind(),
+                    redact(&e.to_string()),
+                    started.elapsed(),
+                ))
+                .await;
+                return Err(anyhow::anyhow!("Image generation failed: {}", e));
+            }
+        };
+        let mut record = GenerationRecord::succeeded(&request, &image, started.elapsed());
+
+        // 出力ディレクトリが設定されている場合はファイルに保存
+        // （生成は課金済みのため、保存に失敗しても記録と結果の返却は行う）
+        let metadata = ImageMetadata::new(&request, &image);
+        let file_path = match &self.file_writer {
+            Some(writer) => match writer.write(&image, &metadata, output_path, filename).await {
+                Ok(path) => {
+                    info!("Saved generated image to {}", path.display());
+                    Some(Ok(path))
+                }
+                Err(e) => {
+                    error!("Failed to save image: {}", e);
+                    Some(Err(format!("Failed to save image: {}", e)))
+                }
+            },
+            None => None,
+        };
+
+        let mut result = serde_json::json!({
+            "mime_type": image.mime_type,
+            "model": image.model,
+            "generated_at": image.generated_at.to_rfc3339(),
+            "size_bytes": image.data.len(),
+            "request_id": request.request_id
+        });
+        if let Some(parent) = &request.parent_request_id {
+            result["parent_request_id"] = serde_json::Value::String(parent.clone());
+        }
+        // フォールバックにより別のモデルで生成された場合は要求されたモデルも返す
+        if image.model != request.model {
+            result["requested_model"] = serde_json::json!(request.model);
+            result["fallback_used"] = serde_json::Value::Bool(true);
+        }
+        // APIを呼んで生成した場合はトークン数と推定料金を集計して返す
+        if let Some(usage) = self.usage.record(&image) {
+            record = record.with_estimated_cost(usage.estimated_cost_usd);
+            result["usage"] = serde_json::json!(usage);
+            if let Some(api_key) = &image.api_key {
+                result["usage"]["api_key"] = serde_json::Value::String(api_key.clone());
+            }
+        }
+        match file_path {
+            Some(Ok(path)) => {
+                let path = path.display().to_string();
+                result["file_path"] = serde_json::Value::String(path.clone());
+                record = record.with_file_path(path);
+            }
+            Some(Err(message)) => result["file_error"] = serde_json::Value::String(message),
+            None => {}
+        }
+
+        // 画像ストアが設定されている場合は内容アドレスで保存し、リソースとして公開する
+        if let Some(store) = &self.image_store {
+            match store.put(&image, &metadata).await {
+                Ok(stored) => {
+                    result["image_id"] = serde_json::Value::String(stored.id.clone());
+                    record = record.with_image_id(stored.id.clone());
+                    result["resource_uri"] = serde_json::Value::String(stored.resource_uri());
+                    result["storage_location"] = serde_json::Value::String(stored.location.clone());
+                    if let Some(url) = store.download_url(&stored) {
+                        result["image_url"] = serde_json::Value::String(url);
+                    }
+                }
+                Err(e) => error!("Failed to store image: {}", e),
+            }
+        }
+        self.record_history(record).await;
+        Ok((result, image))
+    }
+
+    /// 生成履歴に記録する（記録に失敗しても生成結果は返す）
+    async fn record_history(&self, record: GenerationRecord) {
+        if let Some(history) = &self.history {
+            if let Err(e) = history.record(&record).await {
+                warn!("Failed to record generation {}: {}", record.request_id, e);
+            }
+        }
+    }
+
+    async fn handle_history_tool(
+        &self,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<CallToolResult> {
+        let history = self
+            .history
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Generation history is not configured"))?;
+        info!("Handling {} request", name);
+
+        let result = if name == "get_generation" {
+            let request_id = arguments
+                .get("request_id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing required parameter: request_id"))?;
+            let record = history
+                .get(request_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Generation not found: {}", request_id))?;
+            history_record_json(&record)?
+        } else {
+            let mut query = parse_history_query(arguments)?;
+            if name == "search_history" {
+                let text = arguments
+                    .get("query")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing required parameter: query"))?;
+                query.text = Some(text.to_string());
+            } else if let Some(cursor) = arguments.get("cursor").and_then(|v| v.as_str()) {
+                let position = self.cursors.decode_keyset("history", cursor)?;
+                query.after = Some((position.timestamp, position.id));
+            }
+            let records = history.query(&query).await?;
+            let generations = records
+                .iter()
+                .map(history_record_json)
+                .collect::<Result<Vec<_>>>()?;
+            let mut result = serde_json::json!({ "generations": generations });
+            // 新しい順の一覧は、ページの間に記録が増えてもずれないよう最後の記録の位置を続きのカーソルにする
+            if name == "list_history" && records.len() == query.limit {
+                if let Some(last) = records.last() {
+                    let position = KeysetPosition::new(last.created_at, last.request_id.clone());
+                    result["next_cursor"] =
+                        serde_json::Value::String(self.cursors.encode_keyset("history", &position));
+                }
+            }
+            result
+        };
 
         Ok(CallToolResult {
             content: vec![Content::Text {
-                text: format!(
-                    r#"{{
-                        "image_data": "{}",
-                        "model": "{}",
-                        "generated_at": "{}",
-                        "size_bytes": {}
-                    }}"#,
-                    base64_data,
-                    image.model,
-                    image.generated_at.to_rfc3339(),
-                    image.data.len()
-                ),
+                text: result.to_string(),
             }],
             is_error: false,
         })
     }
 }
+
+/// 一括生成のツール
+fn batch_tool() -> Tool {
+    Tool {
+        name: "generate_batch".to_string(),
+        description: Some(
+            "Generate many images at once from a list of prompts, an inline JSONL or CSV, with bounded concurrency. Images are saved to the output directory or image store, and a per-item manifest is returned. Call again with the same batch_id to retry only the failed items."
+                .to_string(),
+        ),
+        input_schema: Some(serde_json::json!({
+            "type": "object",
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "description": "Prompts as strings, or objects with prompt and optional id, model, aspect_ratio, image_size and filename",
+                    "items": {
+                        "oneOf": [
+                            { "type": "string" },
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "id": { "type": "string" },
+                                    "prompt": { "type": "string" },
+                                    "model": { "type": "string" },
+                                    "aspect_ratio": { "type": "string" },
+                                    "image_size": { "type": "string" },
+                                    "filename": { "type": "string" }
+                                },
+                                "required": ["prompt"]
+                            }
+                        ]
+                    }
+                },
+                "input": {
+                    "type": "string",
+                    "description": "Batch items as JSONL (one object or prompt string per line) or CSV with a header row (prompt column required; id, model, aspect_ratio, image_size and filename columns optional). Used when items is not given"
+                },
+                "format": {
+                    "type": "string",
+                    "description": "Format of input",
+                    "enum": ["jsonl", "csv"],
+                    "default": "jsonl"
+                },
+                "batch_id": {
+                    "type": "string",
+                    "description": "ID of a previous batch to resume; items that already succeeded are skipped (letters, digits, - and _)"
+                },
+                "concurrency": {
+                    "type": "integer",
+                    "description": "Number of images to generate at the same time (default and maximum: BATCH_CONCURRENCY)",
+                    "minimum": 1
+                },
+                "output_path": {
+                    "type": "string",
+                    "description": "Directory to save the images in, relative to the configured output directory (IMAGE_OUTPUT_DIR)"
+                }
+            }
+        })),
+    }
+}
+
+/// Batch APIのツール
+fn gemini_batch_tools() -> Vec<Tool> {
+    // 項目の指定は`generate_batch`と同じ
+    let mut submit_schema = batch_tool().input_schema.unwrap_or_default();
+    if let Some(properties) = submit_schema["properties"].as_object_mut() {
+        properties.remove("batch_id");
+        properties.remove("concurrency");
+        properties.remove("output_path");
+        properties.insert(
+            "model".to_string(),
+            serde_json::json!({
+                "type": "string",
+                "description": "Gemini model for items without a model; all items in a batch must use the same model (default: GEMINI_DEFAULT_MODEL)"
+            }),
+        );
+        properties.insert(
+            "input_mode".to_string(),
+            serde_json::json!({
+                "type": "string",
+                "description": "Send the requests inline or as an uploaded JSONL file (auto: file when the requests exceed 20MB)",
+                "enum": ["auto", "inline", "file"],
+                "default": "auto"
+            }),
+        );
+        properties.insert(
+            "display_name".to_string(),
+            serde_json::json!({
+                "type": "string",
+                "description": "Name to show for the batch"
+            }),
+        );
+    }
+    let name_schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "name": {
+                "type": "string",
+                "description": "Batch name returned by submit_gemini_batch (batches/...)"
+            }
+        },
+        "required": ["name"]
+    });
+    vec![
+        Tool {
+            name: "submit_gemini_batch".to_string(),
+            description: Some(
+                "Submit many prompts to the Gemini Batch API, which is cheaper than generate_image but takes minutes to hours. Poll get_gemini_batch for the results."
+                    .to_string(),
+            ),
+            input_schema: Some(submit_schema),
+        },
+        Tool {
+            name: "get_gemini_batch".to_string(),
+            description: Some(
+                "Get the state of a Gemini batch. Once it has finished, the images are saved to the image store and per-item results are returned."
+                    .to_string(),
+            ),
+            input_schema: Some(name_schema.clone()),
+        },
+        Tool {
+            name: "cancel_gemini_batch".to_string(),
+            description: Some("Cancel a pending or running Gemini batch.".to_string()),
+            input_schema: Some(name_schema),
+        },
+    ]
+}
+
+/// `style_images`・`subject_images`のスキーマ（上限はモデルの対応機能による）
+fn reference_images_schema(description: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "array",
+        "description": format!(
+            "{}. Each item is an image ID or image:// URI from the image store, or an object with image_id, request_id (the output of a previous generation) or base64 data and mime_type. The maximum number depends on the model (see list_models capabilities).",
+            description
+        ),
+        "items": {
+            "oneOf": [
+                { "type": "string" },
+                {
+                    "type": "object",
+                    "properties": {
+                        "image_id": { "type": "string" },
+                        "request_id": { "type": "string" },
+                        "data": { "type": "string", "description": "Base64 encoded image" },
+                        "mime_type": { "type": "string" }
+                    }
+                }
+            ]
+        }
+    })
+}
+
+/// 生成ジョブのツール（`submit_image_job`の引数は`generate_image`から保存先を除いたもの）
+fn job_tools(submit_schema: serde_json::Value) -> Vec<Tool> {
+    let job_id_schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "job_id": {
+                "type": "string",
+                "description": "ID of the job returned by submit_image_job"
+            }
+        },
+        "required": ["job_id"]
+    });
+    vec![
+        Tool {
+            name: "submit_image_job".to_string(),
+            description: Some(
+                "Start generating an image in the background and return a job ID immediately. Poll get_image_job for the result, which is saved to the image store."
+                    .to_string(),
+            ),
+            input_schema: Some(submit_schema),
+        },
+        Tool {
+            name: "get_image_job".to_string(),
+            description: Some(
+                "Get the status and progress of an image job, and the stored image once it has succeeded."
+                    .to_string(),
+            ),
+            input_schema: Some(job_id_schema.clone()),
+        },
+        Tool {
+            name: "cancel_image_job".to_string(),
+            description: Some("Cancel a queued or running image job.".to_string()),
+            input_schema: Some(job_id_schema),
+        },
+    ]
+}
+
+/// 履歴ツールの定義
+fn history_tools() -> Vec<Tool> {
+    let filters = serde_json::json!({
+        "model": {
+            "type": "string",
+            "description": "Only include generations with this model"
+        },
+        "outcome": {
+            "type": "string",
+            "enum": ["succeeded", "failed"],
+            "description": "Only include generations with this outcome"
+        },
+        "since": {
+            "type": "string",
+            "format": "date-time",
+            "description": "Only include generations at or after this time (RFC 3339)"
+        },
+        "until": {
+            "type": "string",
+            "format": "date-time",
+            "description": "Only include generations before this time (RFC 3339)"
+        },
+        "limit": {
+            "type": "integer",
+            "minimum": 1,
+            "maximum": HISTORY_MAX_LIMIT,
+            "default": HistoryQuery::default().limit
+        },
+        "offset": {
+            "type": "integer",
+            "minimum": 0,
+            "default": 0
+        }
+    });
+
+    let request_id = serde_json::json!({
+        "type": "string",
+        "description": "request_id returned by generate_image or the history tools"
+    });
+    let output_path = serde_json::json!({
+        "type": "string",
+        "description": "Directory to save the image in, relative to the configured output directory (IMAGE_OUTPUT_DIR)"
+    });
+    let filename = serde_json::json!({
+        "type": "string",
+        "description": "File name for the saved image; the extension is derived from the image MIME type"
+    });
+
+    let mut list_properties = filters.clone();
+    list_properties["cursor"] = serde_json::json!({
+        "type": "string",
+        "description": "next_cursor from the previous page; unlike offset, pages do not shift when new generations are recorded"
+    });
+    let mut search_properties = filters.clone();
+    search_properties["query"] = serde_json::json!({
+        "type": "string",
+        "description": "Words to search for in prompts (all words must match, prefix matching)"
+    });
+
+    vec![
+        Tool {
+            name: "list_history".to_string(),
+            description: Some(
+                "List past image generation requests, newest first, including failures."
+                    .to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": list_properties
+            })),
+        },
+        Tool {
+            name: "search_history".to_string(),
+            description: Some(
+                "Full-text search past image generation requests by prompt.".to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": search_properties,
+                "required": ["query"]
+            })),
+        },
+        Tool {
+            name: "regenerate".to_string(),
+            description: Some(
+                "Re-run a past image generation request exactly, with the same prompt, model and input images (input images are reloaded from the image store)."
+                    .to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "request_id": request_id.clone(),
+                    "output_path": output_path.clone(),
+                    "filename": filename.clone()
+                },
+                "required": ["request_id"]
+            })),
+        },
+        Tool {
+            name: "vary".to_string(),
+            description: Some(
+                "Create a variation of a past generation by editing its output image with an instruction."
+                    .to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "request_id": request_id.clone(),
+                    "instruction": {
+                        "type": "string",
+                        "description": "How to modify the previous image (e.g. \"make the background blue\")"
+                    },
+                    "model": {
+                        "type": "string",
+                        "description": "Gemini model name to use (defaults to the model of the previous generation)"
+                    },
+                    "output_path": output_path,
+                    "filename": filename,
+                    "bypass_cache": bypass_cache_schema()
+                },
+                "required": ["request_id", "instruction"]
+            })),
+        },
+        Tool {
+            name: "get_generation".to_string(),
+            description: Some(
+                "Get the details of a past image generation request by its request_id.".to_string(),
+            ),
+            input_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "request_id": request_id
+                },
+                "required": ["request_id"]
+            })),
+        },
+    ]
+}
+
+/// `model`引数を取得する（許可されたモデルリストで検証）
+fn parse_model(arguments: &serde_json::Value) -> Result<Option<GeminiModel>> {
+    arguments
+        .get("model")
+        .and_then(|v| v.as_str())
+        .map(GeminiModel::try_from)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Invalid model: {}", e))
+}
+
+/// `bypass_cache`引数を取得する
+fn parse_bypass_cache(arguments: &serde_json::Value) -> bool {
+    arguments
+        .get("bypass_cache")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// `bypass_cache`引数のスキーマ
+fn bypass_cache_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "boolean",
+        "description": "Generate a new image even if an identical request has a cached result (only relevant when the response cache is enabled)",
+        "default": false
+    })
+}
+
+/// `generate_batch`の項目（`items`の配列、または`input`のJSONL・CSV）を取得する
+fn parse_batch_items(arguments: &serde_json::Value) -> Result<Vec<BatchItem>> {
+    let items = if let Some(items) = arguments.get("items").and_then(|v| v.as_array()) {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                batch::item_from_value(value.clone())
+                    .map_err(|e| anyhow::anyhow!("Invalid item {}: {}", i, e))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else if let Some(input) = arguments.get("input").and_then(|v| v.as_str()) {
+        match arguments.get("format").and_then(|v| v.as_str()) {
+            Some("csv") => batch::parse_csv(input)?,
+            Some("jsonl") | None => batch::parse_jsonl(input)?,
+            Some(other) => return Err(anyhow::anyhow!("Unsupported batch format: {}", other)),
+        }
+    } else {
+        return Err(anyhow::anyhow!(
+            "Missing required parameter: items or input"
+        ));
+    };
+    if items.is_empty() {
+        return Err(anyhow::anyhow!("The batch has no items"));
+    }
+    Ok(items)
+}
+
+/// `batch_id`はファイル名に使うため英数字・`-`・`_`のみ許可する
+fn is_valid_batch_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 履歴ツールの引数から検索条件を作成
+fn parse_history_query(arguments: &serde_json::Value) -> Result<HistoryQuery> {
+    let string_arg = |key: &str| arguments.get(key).and_then(|v| v.as_str());
+    let time_arg = |key: &str| {
+        string_arg(key)
+            .map(|s| {
+                chrono::DateTime::parse_from_rfc3339(s)
+                    .map(|t| t.with_timezone(&chrono::Utc))
+                    .map_err(|e| anyhow::anyhow!("Invalid {}: {}", key, e))
+            })
+            .transpose()
+    };
+
+    let defaults = HistoryQuery::default();
+    Ok(HistoryQuery {
+        text: None,
+        model: string_arg("model").map(str::to_string),
+        outcome: string_arg("outcome")
+            .map(str::parse::<GenerationOutcome>)
+            .transpose()?,
+        since: time_arg("since")?,
+        until: time_arg("until")?,
+        limit: arguments
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|limit| (limit as usize).clamp(1, HISTORY_MAX_LIMIT))
+            .unwrap_or(defaults.limit),
+        offset: arguments
+            .get("offset")
+            .and_then(|v| v.as_u64())
+            .map(|offset| offset as usize)
+            .unwrap_or(defaults.offset),
+        after: None,
+    })
+}
+
+/// 履歴の記録をツールの結果用のJSONにする（画像ストアに保存されていればリソースURIを付ける）
+fn history_record_json(record: &GenerationRecord) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(record)?;
+    if let Some(image_id) = &record.image_id {
+        value["resource_uri"] = serde_json::Value::String(format!("image://{}", image_id));
+    }
+    Ok(value)
+}
+
+/// 設定に従ってクライアントにキャッシュなどのデコレーターを重ねる
+///
+/// 外側から順に、同時実行のまとめ → レスポンスキャッシュ → モデルのフォールバック → レート制限 → クライアント。
+fn build_repository(
+    client: Box<dyn ImageGenerationRepository>,
+    config: &Config,
+) -> Box<dyn ImageGenerationRepository> {
+    let mut repository = client;
+    let overrides = config.rate_limit_model_overrides();
+    if !config.rate_limits().is_unlimited() || !overrides.is_empty() {
+        let mut limiter = RateLimitedRepository::new(repository, config.rate_limits())
+            .with_max_wait(config.rate_limit_max_wait());
+        for (model, limits) in overrides {
+            limiter = limiter.with_model_limits(model.clone(), *limits);
+        }
+        repository = Box::new(limiter);
+    }
+    if !config.model_fallback_chain().is_empty() {
+        repository = Box::new(
+            FallbackRepository::new(repository, config.model_fallback_chain().to_vec())
+                .with_triggers(config.model_fallback_triggers()),
+        );
+    }
+    if let Some(ttl) = config.response_cache_ttl() {
+        let mut cache = CachingRepository::new(repository).with_ttl(ttl);
+        if let Some(max_bytes) = config.response_cache_max_bytes() {
+            cache = cache.with_max_bytes(max_bytes);
+        }
+        repository = Box::new(cache);
+    }
+    if config.request_coalescing() {
+        repository = Box::new(CoalescingRepository::new(repository));
+    }
+    repository
+}
+
+/// 設定に従ってVertex AIのクライアントを作成する
+fn open_vertex_client(config: &Config) -> Result<VertexClient> {
+    let path = config.vertex_credentials_path().ok_or_else(|| {
+        anyhow::anyhow!("GOOGLE_APPLICATION_CREDENTIALS is required for the Vertex AI backend")
+    })?;
+    let key = ServiceAccountKey::from_file(path)?;
+    let location = config.vertex_location().to_string();
+    let client = match config.vertex_project_id() {
+        Some(project_id) => VertexClient::with_project(&key, project_id.to_string(), location)?,
+        None => VertexClient::new(&key, location)?,
+    };
+    Ok(match config.vertex_api_base_url() {
+        Some(url) => client.with_base_url(url.to_string()),
+        None => client,
+    })
+}
+
+/// 設定に従って出力ディレクトリへのライターを作成する（未設定・利用不可の場合は`None`）
+fn open_file_writer(config: &Config) -> Option<ImageFileWriter> {
+    let dir = config.image_output_dir()?;
+    match ImageFileWriter::new(dir) {
+        Ok(writer) => Some(writer.with_embedded_metadata(config.embed_image_metadata())),
+        Err(e) => {
+            warn!(
+                "Image output directory {} is unavailable, images will not be saved: {}",
+                dir.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// 設定に従って生成履歴のデータベースを開く（未設定・利用不可の場合は`None`）
+fn open_history(config: &Config) -> Option<Arc<dyn GenerationHistory>> {
+    let path = config.history_db_path()?;
+    match SqliteHistory::open(path) {
+        Ok(history) => Some(Arc::new(history)),
+        Err(e) => {
+            warn!(
+                "Generation history {} is unavailable, generations will not be recorded: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// 設定に従って画像ストアを開く（未設定・利用不可の場合は`None`）
+fn open_image_store(config: &Config) -> Option<Arc<dyn ImageStore>> {
+    let store: Arc<dyn ImageStore> = match config.image_store_backend() {
+        "s3" => {
+            let Some(s3_config) = config.s3_config() else {
+                warn!("IMAGE_STORE_BACKEND=s3 requires S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY");
+                return None;
+            };
+            match S3ImageStore::new(s3_config) {
+                Ok(store) => Arc::new(store.with_retention(config.image_store_retention())),
+                Err(e) => {
+                    warn!(
+                        "S3 image store is unavailable, images will not be stored: {}",
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+        "fs" => {
+            let dir = config.image_store_dir()?;
+            match FsImageStore::open(dir) {
+                Ok(store) => Arc::new(store.with_retention(config.image_store_retention())),
+                Err(e) => {
+                    warn!(
+                        "Image store {} is unavailable, images will not be stored: {}",
+                        dir.display(),
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+        other => {
+            warn!(
+                "Unknown IMAGE_STORE_BACKEND '{}', images will not be stored",
+                other
+            );
+            return None;
+        }
+    };
+    Some(store)
+}
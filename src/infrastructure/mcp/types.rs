@@ -9,6 +9,26 @@ pub struct Tool {
     pub input_schema: Option<serde_json::Value>,
 }
 
+/// MCP Resource定義
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// MCP Resourceの内容（バイナリはbase64エンコード）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub blob: String,
+}
+
 /// MCP Tool呼び出し結果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallToolResult {
@@ -53,3 +73,12 @@ pub struct JsonRpcError {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<serde_json::Value>,
 }
+
+/// JSON-RPC Notification（サーバーからクライアントへの通知、idなし）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
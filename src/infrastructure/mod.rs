@@ -1,2 +1,7 @@
+pub mod decorators;
 pub mod gemini;
+pub mod history;
 pub mod mcp;
+pub mod redaction;
+pub mod storage;
+pub mod vertex;
@@ -0,0 +1,147 @@
+use std::io;
+use std::sync::RwLock;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// 秘密情報を置き換える文字列
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 登録された秘密情報（APIキーなど）
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// これより短い値は誤って置き換えないよう登録しない
+const MIN_SECRET_LEN: usize = 8;
+
+/// Google APIキーの接頭辞と、それに続く文字数
+const GOOGLE_API_KEY_PREFIX: &str = "AIza";
+const GOOGLE_API_KEY_SUFFIX_LEN: usize = 35;
+
+/// ログやエラーメッセージから取り除く秘密情報を登録する
+pub fn register_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+        // 長いものから置き換え、一部だけが残らないようにする
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+/// 登録した秘密情報を取り除く（失効したトークンなど）
+pub fn unregister_secret(secret: &str) {
+    let secret = secret.trim();
+    SECRETS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|s| s != secret);
+}
+
+/// 更新された秘密情報を、前の値と置き換えて登録する（登録数が増え続けないようにする）
+pub fn replace_secret(previous: &str, secret: &str) {
+    if previous.trim() != secret.trim() {
+        unregister_secret(previous);
+    }
+    register_secret(secret);
+}
+
+/// 文字列から秘密情報を取り除く
+///
+/// 登録された値に加えて、URLの`key`クエリパラメータとGoogle APIキーの形式の値も置き換える。
+pub fn redact(text: &str) -> String {
+    let mut redacted = text.to_string();
+    for secret in SECRETS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        if redacted.contains(secret.as_str()) {
+            redacted = redacted.replace(secret.as_str(), REDACTED);
+        }
+    }
+    redact_google_api_keys(&redact_key_query_params(&redacted))
+}
+
+/// `key=...`（`?key=`・`&key=`）の値を置き換える
+fn redact_key_query_params(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find("key=") {
+        let is_param = rest[..pos].ends_with('?') || rest[..pos].ends_with('&');
+        result.push_str(&rest[..pos + "key=".len()]);
+        rest = &rest[pos + "key=".len()..];
+        if is_param {
+            let end = rest
+                .find(|c: char| matches!(c, '&' | '#' | ')' | '"' | '\'') || c.is_whitespace())
+                .unwrap_or(rest.len());
+            if end > 0 && &rest[..end] != REDACTED {
+                result.push_str(REDACTED);
+                rest = &rest[end..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// `AIza`で始まるGoogle APIキーの形式の値を置き換える
+fn redact_google_api_keys(text: &str) -> String {
+    let is_key_char = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'-';
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find(GOOGLE_API_KEY_PREFIX) {
+        let start = pos + GOOGLE_API_KEY_PREFIX.len();
+        let suffix = rest.as_bytes()[start..]
+            .iter()
+            .take_while(|&&c| is_key_char(c))
+            .count();
+        let preceded_by_key_char = pos > 0 && is_key_char(rest.as_bytes()[pos - 1]);
+        if suffix == GOOGLE_API_KEY_SUFFIX_LEN && !preceded_by_key_char {
+            result.push_str(&rest[..pos]);
+            result.push_str(REDACTED);
+            rest = &rest[start + suffix..];
+        } else {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 書き込む内容から秘密情報を取り除く`tracing_subscriber`のライター
+///
+/// `fmt`レイヤーは1つのイベントを1回の書き込みで出力するため、書き込みごとに置き換える。
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+/// [`RedactingMakeWriter`]が作成するライター
+pub struct RedactingWriter<W> {
+    inner: W,
+}
+
+impl<W: io::Write> io::Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,194 @@
+use crate::domain::ImageMetadata;
+
+/// PNGシグネチャ
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// JPEGのSOIマーカー
+const JPEG_SOI: &[u8] = &[0xFF, 0xD8];
+/// XMPを格納するAPP1セグメントの識別子
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// メタデータに記録するソフトウェア名
+const SOFTWARE_NAME: &str = "google-gemini-image-creator";
+
+/// メタデータ埋め込みエラー
+#[derive(Debug, thiserror::Error)]
+pub enum EmbedError {
+    #[error("Unsupported image format for metadata embedding: {0}")]
+    UnsupportedFormat(String),
+    #[error("Malformed image data: {0}")]
+    MalformedImage(String),
+    #[error("Metadata too large to embed: {0} bytes")]
+    MetadataTooLarge(usize),
+}
+
+/// 画像データにメタデータを埋め込む
+///
+/// PNGは`tEXt`/`iTXt`チャンク、JPEGはXMP（APP1セグメント）として埋め込む。
+pub fn embed_metadata(
+    data: &[u8],
+    mime_type: &str,
+    metadata: &ImageMetadata,
+) -> Result<Vec<u8>, EmbedError> {
+    match mime_type.to_ascii_lowercase().as_str() {
+        "image/png" => embed_png(data, metadata),
+        "image/jpeg" | "image/jpg" => embed_jpeg(data, metadata),
+        other => Err(EmbedError::UnsupportedFormat(other.to_string())),
+    }
+}
+
+/// IHDRチャンクの直後にテキストチャンクを挿入する
+fn embed_png(data: &[u8], metadata: &ImageMetadata) -> Result<Vec<u8>, EmbedError> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(EmbedError::MalformedImage(
+            "missing PNG signature".to_string(),
+        ));
+    }
+
+    // IHDRは必ず最初のチャンク（長さ4 + 種別4 + データ13 + CRC4）
+    let ihdr_type = PNG_SIGNATURE.len() + 4;
+    let ihdr_end = ihdr_type + 4 + 13 + 4;
+    if data.len() < ihdr_end || &data[ihdr_type..ihdr_type + 4] != b"IHDR" {
+        return Err(EmbedError::MalformedImage("missing IHDR chunk".to_string()));
+    }
+
+    let json = metadata_json(metadata);
+    let mut chunks = Vec::new();
+    chunks.extend(png_chunk(
+        b"tEXt",
+        &text_chunk_data("Software", SOFTWARE_NAME),
+    ));
+    chunks.extend(png_chunk(
+        b"iTXt",
+        &itxt_chunk_data("Description", &metadata.prompt),
+    ));
+    chunks.extend(png_chunk(
+        b"iTXt",
+        &itxt_chunk_data("GenerationMetadata", &json),
+    ));
+
+    let mut output = Vec::with_capacity(data.len() + chunks.len());
+    output.extend_from_slice(&data[..ihdr_end]);
+    output.extend_from_slice(&chunks);
+    output.extend_from_slice(&data[ihdr_end..]);
+    Ok(output)
+}
+
+/// tEXtチャンクのデータ部（キーワード\0テキスト、Latin-1）
+fn text_chunk_data(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(keyword.as_bytes());
+    data.push(0);
+    data.extend_from_slice(text.as_bytes());
+    data
+}
+
+/// 非圧縮iTXtチャンクのデータ部（UTF-8テキスト）
+fn itxt_chunk_data(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(keyword.as_bytes());
+    // null区切り, 圧縮フラグ, 圧縮方式, 言語タグ(空)\0, 翻訳キーワード(空)\0
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    data
+}
+
+/// PNGチャンク（長さ・種別・データ・CRC）を構築
+fn png_chunk(chunk_type: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(chunk_type);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// PNGで使用するCRC-32（ISO 3309）
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// SOI（とJFIFのAPP0があればその後ろ）にXMPのAPP1セグメントを挿入する
+fn embed_jpeg(data: &[u8], metadata: &ImageMetadata) -> Result<Vec<u8>, EmbedError> {
+    if !data.starts_with(JPEG_SOI) {
+        return Err(EmbedError::MalformedImage(
+            "missing JPEG SOI marker".to_string(),
+        ));
+    }
+
+    let mut insert_at = JPEG_SOI.len();
+    if data.len() >= insert_at + 4 && data[insert_at] == 0xFF && data[insert_at + 1] == 0xE0 {
+        let length = u16::from_be_bytes([data[insert_at + 2], data[insert_at + 3]]) as usize;
+        insert_at += 2 + length;
+        if insert_at > data.len() {
+            return Err(EmbedError::MalformedImage(
+                "truncated APP0 segment".to_string(),
+            ));
+        }
+    }
+
+    let packet = xmp_packet(metadata);
+    let payload_len = XMP_NAMESPACE.len() + packet.len();
+    // セグメント長は長さフィールド自身の2バイトを含めて16ビットに収まる必要がある
+    let segment_len = payload_len + 2;
+    if segment_len > u16::MAX as usize {
+        return Err(EmbedError::MetadataTooLarge(payload_len));
+    }
+
+    let mut output = Vec::with_capacity(data.len() + segment_len + 2);
+    output.extend_from_slice(&data[..insert_at]);
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&(segment_len as u16).to_be_bytes());
+    output.extend_from_slice(XMP_NAMESPACE);
+    output.extend_from_slice(packet.as_bytes());
+    output.extend_from_slice(&data[insert_at..]);
+    Ok(output)
+}
+
+/// メタデータをXMPパケットに変換
+fn xmp_packet(metadata: &ImageMetadata) -> String {
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+            " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"",
+            " xmlns:gic=\"https://github.com/Kazy1014/google_gemini_image_creator/ns/1.0/\">",
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{prompt}</rdf:li></rdf:Alt></dc:description>",
+            "<xmp:CreatorTool>{software}</xmp:CreatorTool>",
+            "<xmp:CreateDate>{generated_at}</xmp:CreateDate>",
+            "<gic:Model>{model}</gic:Model>",
+            "<gic:RequestId>{request_id}</gic:RequestId>",
+            "<gic:GenerationMetadata>{json}</gic:GenerationMetadata>",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>",
+            "<?xpacket end=\"w\"?>"
+        ),
+        prompt = xml_escape(&metadata.prompt),
+        software = SOFTWARE_NAME,
+        generated_at = metadata.generated_at.to_rfc3339(),
+        model = xml_escape(metadata.model.as_str()),
+        request_id = xml_escape(&metadata.request_id),
+        json = xml_escape(&metadata_json(metadata)),
+    )
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn metadata_json(metadata: &ImageMetadata) -> String {
+    serde_json::to_string(metadata).unwrap_or_default()
+}
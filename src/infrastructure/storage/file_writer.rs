@@ -0,0 +1,267 @@
+use crate::domain::{GeneratedImage, ImageMetadata};
+use crate::infrastructure::storage::embed::embed_metadata;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+/// 同名ファイルが存在する場合に連番を試す上限
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+
+/// ストレージエラー
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Path escapes the output directory: {0}")]
+    PathOutsideRoot(String),
+    #[error("Invalid filename: {0}")]
+    InvalidFilename(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 生成画像を出力ディレクトリ配下のファイルに書き込む
+///
+/// 書き込み先は常に出力ディレクトリ（ルート）配下に制限され、
+/// `..`やシンボリックリンクでルートの外を指すパスは拒否する。
+/// 画像の隣には来歴メタデータのサイドカーJSON（`<ファイル名>.json`）を書き込む。
+pub struct ImageFileWriter {
+    root: PathBuf,
+    embed_metadata: bool,
+}
+
+impl ImageFileWriter {
+    /// 出力ディレクトリを作成し、その絶対パスをルートとするライターを作成
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(root.as_ref())?;
+        let root = root.as_ref().canonicalize()?;
+        Ok(Self {
+            root,
+            embed_metadata: false,
+        })
+    }
+
+    /// 画像ファイル自体にもメタデータを埋め込むか（PNGのtEXt/iTXt、JPEGのXMP）
+    pub fn with_embedded_metadata(mut self, embed_metadata: bool) -> Self {
+        self.embed_metadata = embed_metadata;
+        self
+    }
+
+    /// 出力ディレクトリ（絶対パス）を取得
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 画像とサイドカーJSONを書き込み、書き込んだ画像ファイルの絶対パスを返す
+    ///
+    /// `output_path`はルートからの相対パス（またはルート配下の絶対パス）のディレクトリ、
+    /// `filename`は拡張子を除いたファイル名。拡張子は画像のMIMEタイプから決定する。
+    /// 同名のファイルが既にある場合は連番を付けて上書きを避ける。
+    pub async fn write(
+        &self,
+        image: &GeneratedImage,
+        metadata: &ImageMetadata,
+        output_path: Option<&str>,
+        filename: Option<&str>,
+    ) -> Result<PathBuf, StorageError> {
+        let dir = self.resolve_dir(output_path)?;
+        let dir = self.create_dir_within_root(&dir).await?;
+
+        let stem = match filename {
+            Some(name) => validate_filename(name)?,
+            None => default_file_stem(image),
+        };
+        let extension = image.file_extension();
+        let data = self.image_bytes(image, metadata);
+
+        for attempt in 0..MAX_NAME_ATTEMPTS {
+            let name = if attempt == 0 {
+                format!("{}.{}", stem, extension)
+            } else {
+                format!("{}-{}.{}", stem, attempt, extension)
+            };
+            let path = dir.join(name);
+
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(mut file) => {
+                    file.write_all(&data).await?;
+                    file.flush().await?;
+                    if let Err(e) = self.write_sidecar(&path, metadata).await {
+                        // サイドカーのない画像を残さない
+                        let _ = tokio::fs::remove_file(&path).await;
+                        return Err(e);
+                    }
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(StorageError::InvalidFilename(format!(
+            "Too many files named '{}'",
+            stem
+        )))
+    }
+
+    /// 書き込む画像データ（埋め込みが有効ならメタデータ入り）
+    fn image_bytes<'a>(
+        &self,
+        image: &'a GeneratedImage,
+        metadata: &ImageMetadata,
+    ) -> std::borrow::Cow<'a, [u8]> {
+        if !self.embed_metadata {
+            return std::borrow::Cow::Borrowed(&image.data);
+        }
+        match embed_metadata(&image.data, &image.mime_type, metadata) {
+            Ok(data) => std::borrow::Cow::Owned(data),
+            Err(e) => {
+                // 埋め込みに失敗してもサイドカーがあるため、画像はそのまま保存する
+                warn!("Skipping embedded metadata: {}", e);
+                std::borrow::Cow::Borrowed(&image.data)
+            }
+        }
+    }
+
+    /// 画像ファイルの隣にサイドカーJSONを書き込む
+    async fn write_sidecar(
+        &self,
+        image_path: &Path,
+        metadata: &ImageMetadata,
+    ) -> Result<(), StorageError> {
+        let mut sidecar = image_path.as_os_str().to_owned();
+        sidecar.push(".json");
+        let sidecar = PathBuf::from(sidecar);
+
+        // 既にあるサイドカーがシンボリックリンクの場合は、リンク先に関わらず書き込まない
+        if let Ok(existing) = tokio::fs::symlink_metadata(&sidecar).await {
+            if existing.file_type().is_symlink() {
+                return Err(StorageError::PathOutsideRoot(sidecar.display().to_string()));
+            }
+        }
+        // 確認した後にリンクへ差し替えられても辿らないよう、新しく作った一時ファイルを置き換える
+        let mut temp = sidecar.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+        let temp = PathBuf::from(temp);
+        let json = serde_json::to_vec_pretty(metadata).map_err(std::io::Error::other)?;
+        let written = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp)
+                .await?;
+            file.write_all(&json).await?;
+            file.flush().await?;
+            tokio::fs::rename(&temp, &sidecar).await
+        }
+        .await;
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// 出力先ディレクトリをルート配下に作成し、その実パスを返す
+    ///
+    /// 存在する最も深い祖先を実パスでルート配下か確認してから、足りないディレクトリを1つずつ作成する。
+    /// シンボリックリンク経由でルート外にディレクトリを作ることはない。
+    async fn create_dir_within_root(&self, dir: &Path) -> Result<PathBuf, StorageError> {
+        let outside = |path: &Path| StorageError::PathOutsideRoot(path.display().to_string());
+
+        let mut existing = dir.to_path_buf();
+        let mut missing = Vec::new();
+        while tokio::fs::symlink_metadata(&existing).await.is_err() {
+            let name = existing
+                .file_name()
+                .ok_or_else(|| outside(dir))?
+                .to_os_string();
+            missing.push(name);
+            if !existing.pop() {
+                return Err(outside(dir));
+            }
+        }
+
+        // シンボリックリンク経由でルート外に出ていないかを実パスで確認
+        let mut current = tokio::fs::canonicalize(&existing).await?;
+        if !current.starts_with(&self.root) {
+            return Err(outside(&current));
+        }
+        for name in missing.into_iter().rev() {
+            current.push(name);
+            match tokio::fs::create_dir(&current).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            // 作成と同時にシンボリックリンクに置き換えられていないか確認する
+            current = tokio::fs::canonicalize(&current).await?;
+            if !current.starts_with(&self.root) {
+                return Err(outside(&current));
+            }
+        }
+        Ok(current)
+    }
+
+    /// 出力先ディレクトリをルート配下のパスとして字句的に解決する
+    fn resolve_dir(&self, output_path: Option<&str>) -> Result<PathBuf, StorageError> {
+        let Some(output_path) = output_path.filter(|p| !p.trim().is_empty()) else {
+            return Ok(self.root.clone());
+        };
+
+        let requested = Path::new(output_path);
+        let joined = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.root.join(requested)
+        };
+
+        let mut resolved = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return Err(StorageError::PathOutsideRoot(output_path.to_string()));
+                    }
+                }
+                Component::CurDir => {}
+                other => resolved.push(other.as_os_str()),
+            }
+        }
+
+        if !resolved.starts_with(&self.root) {
+            return Err(StorageError::PathOutsideRoot(output_path.to_string()));
+        }
+        Ok(resolved)
+    }
+}
+
+/// ファイル名を検証し、拡張子を除いた部分を返す
+fn validate_filename(name: &str) -> Result<String, StorageError> {
+    let invalid = || StorageError::InvalidFilename(name.to_string());
+
+    let path = Path::new(name);
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => return Err(invalid()),
+    }
+    if name.contains(['/', '\\']) {
+        return Err(invalid());
+    }
+
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(invalid)?;
+    Ok(stem.to_string())
+}
+
+/// 生成時刻からデフォルトのファイル名（拡張子なし）を作成
+fn default_file_stem(image: &GeneratedImage) -> String {
+    format!("gemini-{}", image.generated_at.format("%Y%m%d-%H%M%S%3f"))
+}
@@ -0,0 +1,310 @@
+use crate::domain::models::sha256_hex;
+use crate::domain::{
+    GarbageCollectionReport, GeneratedImage, ImageMetadata, ImageStore, ImageStoreError,
+    RetentionPolicy, StoredImage,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// インデックスファイル名
+const INDEX_FILE: &str = "index.json";
+/// インデックスの変更を追記するジャーナルのファイル名
+const JOURNAL_FILE: &str = "index.journal";
+/// 画像データを置くディレクトリ名
+const OBJECTS_DIR: &str = "objects";
+/// ジャーナルがこの件数を超えたらインデックスに書き出して空にする
+const JOURNAL_COMPACT_THRESHOLD: usize = 256;
+
+/// ジャーナルの1行（インデックスへの変更）
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalEntry {
+    Put(Box<StoredImage>),
+    Remove(String),
+}
+
+/// ファイルシステム上の内容アドレス方式の画像ストア
+///
+/// 画像は`objects/<ハッシュ先頭2文字>/<SHA-256>.<拡張子>`に保存し、
+/// メタデータは`index.json`にまとめて保持する。
+/// 保存・削除のたびにインデックス全体を書き直さないよう、変更は`index.journal`に追記し、
+/// 一定の件数がたまったらインデックスに書き出す。
+pub struct FsImageStore {
+    root: PathBuf,
+    index: Mutex<HashMap<String, StoredImage>>,
+    /// ジャーナルに追記した件数（インデックスのロック中に更新する）
+    journal_len: AtomicUsize,
+    retention: RetentionPolicy,
+}
+
+impl FsImageStore {
+    /// ストアを開く（ディレクトリがなければ作成し、既存のインデックスを読み込む）
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, ImageStoreError> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(root.join(OBJECTS_DIR))?;
+
+        let mut index: HashMap<String, StoredImage> = match std::fs::read(root.join(INDEX_FILE)) {
+            Ok(bytes) => {
+                let entries: Vec<StoredImage> = serde_json::from_slice(&bytes)
+                    .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string()))?;
+                entries.into_iter().map(|e| (e.id.clone(), e)).collect()
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let journal_len = replay_journal(&root.join(JOURNAL_FILE), &mut index)?;
+
+        Ok(Self {
+            root,
+            index: Mutex::new(index),
+            journal_len: AtomicUsize::new(journal_len),
+            retention: RetentionPolicy::default(),
+        })
+    }
+
+    /// 保存のたびに適用する保持ポリシーを設定
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// ストアのルートディレクトリを取得
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn object_path(&self, id: &str, extension: &str) -> PathBuf {
+        self.root
+            .join(OBJECTS_DIR)
+            .join(&id[..2])
+            .join(format!("{}.{}", id, extension))
+    }
+
+    /// インデックスへの変更をジャーナルに追記する（たまったらインデックスに書き出す）
+    async fn append_journal(
+        &self,
+        index: &HashMap<String, StoredImage>,
+        entries: &[JournalEntry],
+    ) -> Result<(), ImageStoreError> {
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)
+                .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string()))?;
+            lines.push(b'\n');
+        }
+        let mut journal = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.root.join(JOURNAL_FILE))
+            .await?;
+        journal.write_all(&lines).await?;
+        journal.flush().await?;
+
+        let journal_len =
+            self.journal_len.fetch_add(entries.len(), Ordering::Relaxed) + entries.len();
+        if journal_len > JOURNAL_COMPACT_THRESHOLD {
+            self.persist_index(index).await?;
+        }
+        Ok(())
+    }
+
+    /// インデックスを一時ファイル経由で置き換えて保存し、ジャーナルを空にする
+    ///
+    /// ジャーナルを消す前に中断しても、読み込み時に同じ変更を適用し直すだけで済む。
+    async fn persist_index(
+        &self,
+        index: &HashMap<String, StoredImage>,
+    ) -> Result<(), ImageStoreError> {
+        let mut entries: Vec<&StoredImage> = index.values().collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        let json = serde_json::to_vec_pretty(&entries)
+            .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string()))?;
+
+        let tmp = self.root.join(format!("{}.tmp", INDEX_FILE));
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, self.root.join(INDEX_FILE)).await?;
+        remove_file_if_exists(&self.root.join(JOURNAL_FILE)).await?;
+        self.journal_len.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// インデックスから削除対象を選び、ファイルとエントリを削除する
+    async fn collect_garbage_locked(
+        &self,
+        index: &mut HashMap<String, StoredImage>,
+        policy: &RetentionPolicy,
+    ) -> Result<GarbageCollectionReport, ImageStoreError> {
+        let victims = policy.select_expired(index.values(), chrono::Utc::now());
+
+        let mut report = GarbageCollectionReport::default();
+        for id in victims {
+            if let Some(entry) = index.remove(&id) {
+                remove_file_if_exists(Path::new(&entry.location)).await?;
+                report.freed_bytes += entry.size_bytes;
+                report.removed_ids.push(id);
+            }
+        }
+
+        if !report.removed_ids.is_empty() {
+            let removed: Vec<JournalEntry> = report
+                .removed_ids
+                .iter()
+                .map(|id| JournalEntry::Remove(id.clone()))
+                .collect();
+            self.append_journal(index, &removed).await?;
+            info!(
+                "Image store garbage collection removed {} images ({} bytes)",
+                report.removed_ids.len(),
+                report.freed_bytes
+            );
+        }
+        Ok(report)
+    }
+}
+
+#[async_trait]
+impl ImageStore for FsImageStore {
+    async fn put(
+        &self,
+        image: &GeneratedImage,
+        metadata: &ImageMetadata,
+    ) -> Result<StoredImage, ImageStoreError> {
+        let id = sha256_hex(&image.data);
+        let mut index = self.index.lock().await;
+
+        let entry = match index.get_mut(&id) {
+            // 同一内容の画像は保存済みのファイルを共有し、リクエストIDだけ追記する
+            Some(existing) if Path::new(&existing.location).exists() => {
+                if !existing.request_ids.contains(&metadata.request_id) {
+                    existing.request_ids.push(metadata.request_id.clone());
+                }
+                existing.stored_at = chrono::Utc::now();
+                existing.clone()
+            }
+            _ => {
+                self.retention.check_size(image.data.len() as u64)?;
+                let path = self.object_path(&id, image.file_extension());
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let tmp = path.with_extension("tmp");
+                tokio::fs::write(&tmp, &image.data).await?;
+                tokio::fs::rename(&tmp, &path).await?;
+
+                let entry = StoredImage {
+                    id: id.clone(),
+                    mime_type: image.mime_type.clone(),
+                    size_bytes: image.data.len() as u64,
+                    stored_at: chrono::Utc::now(),
+                    metadata: metadata.clone(),
+                    request_ids: vec![metadata.request_id.clone()],
+                    location: path.display().to_string(),
+                };
+                index.insert(id.clone(), entry.clone());
+                entry
+            }
+        };
+        self.append_journal(&index, &[JournalEntry::Put(Box::new(entry.clone()))])
+            .await?;
+
+        if !self.retention.is_unbounded() {
+            let retention = self.retention.clone();
+            self.collect_garbage_locked(&mut index, &retention).await?;
+        }
+        Ok(entry)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<(StoredImage, Vec<u8>)>, ImageStoreError> {
+        let entry = match self.index.lock().await.get(id) {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+
+        match tokio::fs::read(&entry.location).await {
+            Ok(data) => Ok(Some((entry, data))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("Image {} is indexed but its file is missing", id);
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<StoredImage>, ImageStoreError> {
+        let mut entries: Vec<StoredImage> = self.index.lock().await.values().cloned().collect();
+        entries.sort_by(|a, b| b.stored_at.cmp(&a.stored_at).then(a.id.cmp(&b.id)));
+        Ok(entries)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, ImageStoreError> {
+        let mut index = self.index.lock().await;
+        let Some(entry) = index.remove(id) else {
+            return Ok(false);
+        };
+        remove_file_if_exists(Path::new(&entry.location)).await?;
+        self.append_journal(&index, &[JournalEntry::Remove(id.to_string())])
+            .await?;
+        Ok(true)
+    }
+
+    async fn collect_garbage(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<GarbageCollectionReport, ImageStoreError> {
+        let mut index = self.index.lock().await;
+        self.collect_garbage_locked(&mut index, policy).await
+    }
+}
+
+/// ジャーナルの変更をインデックスに適用し、適用した件数を返す
+///
+/// 書き込み中に中断した最後の行（改行で終わっていない行）は捨て、以降の追記と混ざらないようにする。
+fn replay_journal(
+    path: &Path,
+    index: &mut HashMap<String, StoredImage>,
+) -> Result<usize, ImageStoreError> {
+    let journal = match std::fs::read_to_string(path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let complete = journal.rfind('\n').map_or(0, |i| i + 1);
+    let mut applied = 0;
+    for line in journal[..complete].lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str(line)
+            .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string()))?
+        {
+            JournalEntry::Put(entry) => {
+                index.insert(entry.id.clone(), *entry);
+            }
+            JournalEntry::Remove(id) => {
+                index.remove(&id);
+            }
+        }
+        applied += 1;
+    }
+    if complete < journal.len() {
+        warn!(
+            "Discarding an incomplete entry at the end of {}",
+            path.display()
+        );
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    Ok(applied)
+}
+
+async fn remove_file_if_exists(path: &Path) -> Result<(), ImageStoreError> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,10 @@
+pub mod embed;
+pub mod file_writer;
+pub mod fs_store;
+pub mod s3_store;
+pub mod sigv4;
+
+pub use embed::{embed_metadata, EmbedError};
+pub use file_writer::{ImageFileWriter, StorageError};
+pub use fs_store::FsImageStore;
+pub use s3_store::S3ImageStore;
@@ -0,0 +1,536 @@
+use crate::config::S3Config;
+use crate::domain::models::sha256_hex;
+use crate::domain::{
+    GarbageCollectionReport, GeneratedImage, ImageMetadata, ImageStore, ImageStoreError,
+    RetentionPolicy, StoredImage,
+};
+use crate::infrastructure::redaction;
+use crate::infrastructure::storage::sigv4::{self, Credentials};
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// 画像データを置くキーのプレフィックス
+const OBJECTS_PREFIX: &str = "objects/";
+/// メタデータ（`StoredImage`のJSON）を置くキーのプレフィックス
+const INDEX_PREFIX: &str = "index/";
+
+/// ListObjectsV2で得たオブジェクトの情報
+struct ListedObject {
+    key: String,
+    etag: Option<String>,
+}
+
+/// ETagとともにキャッシュしたエントリ
+struct CachedEntry {
+    etag: String,
+    entry: StoredImage,
+}
+
+/// S3互換オブジェクトストレージの画像ストア
+///
+/// 画像は`<prefix>objects/<SHA-256>.<拡張子>`、
+/// エントリは`<prefix>index/<SHA-256>.json`として保存する。
+/// 一覧ではETagが変わったエントリだけを取得し直す。
+pub struct S3ImageStore {
+    endpoint: reqwest::Url,
+    bucket: String,
+    prefix: String,
+    path_style: bool,
+    presign_expires_secs: Option<u64>,
+    credentials: Credentials,
+    http_client: reqwest::Client,
+    retention: RetentionPolicy,
+    entries: Mutex<HashMap<String, CachedEntry>>,
+}
+
+impl S3ImageStore {
+    pub fn new(config: S3Config) -> Result<Self, ImageStoreError> {
+        let endpoint = reqwest::Url::parse(&config.endpoint).map_err(|e| {
+            ImageStoreError::Backend(format!("Invalid S3 endpoint '{}': {}", config.endpoint, e))
+        })?;
+        if endpoint.host_str().is_none() {
+            return Err(ImageStoreError::Backend(format!(
+                "S3 endpoint has no host: {}",
+                config.endpoint
+            )));
+        }
+
+        redaction::register_secret(&config.secret_access_key);
+
+        let prefix = config.prefix.trim_matches('/');
+        let prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", prefix)
+        };
+
+        Ok(Self {
+            endpoint,
+            bucket: config.bucket,
+            prefix,
+            path_style: config.path_style,
+            presign_expires_secs: config.presign_expires_secs,
+            credentials: Credentials {
+                access_key_id: config.access_key_id,
+                secret_access_key: config.secret_access_key,
+                region: config.region,
+            },
+            http_client: reqwest::Client::new(),
+            retention: RetentionPolicy::default(),
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 保存のたびに適用する保持ポリシーを設定
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// 画像データのオブジェクトキー
+    pub fn object_key(&self, id: &str, extension: &str) -> Result<String, ImageStoreError> {
+        Self::validate_id(id)?;
+        Ok(format!(
+            "{}{}{}.{}",
+            self.prefix, OBJECTS_PREFIX, id, extension
+        ))
+    }
+
+    fn index_key(&self, id: &str) -> Result<String, ImageStoreError> {
+        Self::validate_id(id)?;
+        Ok(format!("{}{}{}.json", self.prefix, INDEX_PREFIX, id))
+    }
+
+    /// キーに埋め込む前に画像IDの形式を確認する
+    fn validate_id(id: &str) -> Result<(), ImageStoreError> {
+        if StoredImage::is_valid_id(id) {
+            Ok(())
+        } else {
+            Err(ImageStoreError::InvalidId(id.to_string()))
+        }
+    }
+
+    /// リクエスト先のホスト（ポート付き）とパスを求める
+    fn host_and_path(&self, key: &str) -> (String, String) {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let base_path = self.endpoint.path().trim_end_matches('/');
+
+        if self.path_style {
+            let path = if key.is_empty() {
+                format!("{}/{}", base_path, self.bucket)
+            } else {
+                format!("{}/{}/{}", base_path, self.bucket, key)
+            };
+            (host, path)
+        } else {
+            (
+                format!("{}.{}", self.bucket, host),
+                format!("{}/{}", base_path, key),
+            )
+        }
+    }
+
+    fn url(&self, host: &str, path: &str, query: &str) -> String {
+        let mut url = format!(
+            "{}://{}{}",
+            self.endpoint.scheme(),
+            host,
+            sigv4::uri_encode_path(path)
+        );
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+
+    /// 署名付きURLを作成する（有効期限が設定されていない場合は`None`）
+    pub fn presigned_url(&self, key: &str) -> Option<String> {
+        self.presign_expires_secs
+            .map(|expires| self.presigned_url_at(key, expires, chrono::Utc::now()))
+    }
+
+    /// 指定時刻で署名付きURLを作成する（テスト用）
+    #[doc(hidden)]
+    pub fn presigned_url_at(
+        &self,
+        key: &str,
+        expires_secs: u64,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> String {
+        let (host, path) = self.host_and_path(key);
+        let query = sigv4::presign_query(&self.credentials, "GET", &host, &path, expires_secs, now);
+        self.url(&host, &path, &query)
+    }
+
+    /// SigV4で署名したリクエストを送信する
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(String, String)],
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::Response, ImageStoreError> {
+        let (host, path) = self.host_and_path(key);
+        let signed = sigv4::sign_request(
+            &self.credentials,
+            method.as_str(),
+            &host,
+            &path,
+            query,
+            &body,
+            chrono::Utc::now(),
+        );
+
+        let query_string = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", sigv4::uri_encode(k), sigv4::uri_encode(v)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut request = self
+            .http_client
+            .request(method.clone(), self.url(&host, &path, &query_string))
+            .header("Authorization", signed.authorization)
+            .header("x-amz-date", signed.amz_date)
+            .header("x-amz-content-sha256", signed.content_sha256);
+        if let Some(content_type) = content_type {
+            request = request.header("Content-Type", content_type);
+        }
+        if !body.is_empty() {
+            request = request.body(body);
+        }
+
+        request.send().await.map_err(|e| {
+            ImageStoreError::Backend(format!("S3 {} {} failed: {}", method, key, e.without_url()))
+        })
+    }
+
+    /// レスポンスが成功でなければエラーにする
+    async fn check(
+        response: reqwest::Response,
+        action: &str,
+        key: &str,
+    ) -> Result<reqwest::Response, ImageStoreError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ImageStoreError::Backend(format!(
+            "S3 {} {} returned status {}: {}",
+            action,
+            key,
+            status,
+            xml_values(&body, "Message")
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+        )))
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, ImageStoreError> {
+        let response = self.send(Method::GET, key, &[], Vec::new(), None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Self::check(response, "GET", key).await?;
+        let bytes = response.bytes().await.map_err(|e| {
+            ImageStoreError::Backend(format!("S3 GET {} failed: {}", key, e.without_url()))
+        })?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    /// オブジェクトを保存し、返されたETagを返す
+    async fn put_object(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+    ) -> Result<Option<String>, ImageStoreError> {
+        let response = self
+            .send(Method::PUT, key, &[], body, Some(content_type))
+            .await?;
+        let response = Self::check(response, "PUT", key).await?;
+        Ok(response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(normalize_etag))
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<(), ImageStoreError> {
+        let response = self
+            .send(Method::DELETE, key, &[], Vec::new(), None)
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        Self::check(response, "DELETE", key).await?;
+        Ok(())
+    }
+
+    async fn get_entry(&self, id: &str) -> Result<Option<StoredImage>, ImageStoreError> {
+        match self.get_object(&self.index_key(id)?).await? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    async fn put_entry(&self, entry: &StoredImage) -> Result<(), ImageStoreError> {
+        let json = serde_json::to_vec(entry)
+            .map_err(|e| ImageStoreError::CorruptedIndex(e.to_string()))?;
+        let etag = self
+            .put_object(&self.index_key(&entry.id)?, json, "application/json")
+            .await?;
+        self.cache_entry(entry, etag);
+        Ok(())
+    }
+
+    /// ETagが分かっていればエントリをキャッシュし、分からなければ破棄する
+    fn cache_entry(&self, entry: &StoredImage, etag: Option<String>) {
+        let mut cache = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match etag {
+            Some(etag) => {
+                cache.insert(
+                    entry.id.clone(),
+                    CachedEntry {
+                        etag,
+                        entry: entry.clone(),
+                    },
+                );
+            }
+            None => {
+                cache.remove(&entry.id);
+            }
+        }
+    }
+
+    /// ETagが一致するキャッシュ済みエントリを取得
+    fn cached_entry(&self, id: &str, etag: &str) -> Option<StoredImage> {
+        let cache = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .get(id)
+            .filter(|cached| cached.etag == etag)
+            .map(|cached| cached.entry.clone())
+    }
+
+    /// ListObjectsV2でプレフィックス配下のオブジェクトをすべて取得する
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<ListedObject>, ImageStoreError> {
+        let mut objects = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_string()),
+            ];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token".to_string(), token.clone()));
+            }
+
+            let response = self.send(Method::GET, "", &query, Vec::new(), None).await?;
+            let response = Self::check(response, "LIST", prefix).await?;
+            let body = response.text().await.map_err(|e| {
+                ImageStoreError::Backend(format!("S3 LIST failed: {}", e.without_url()))
+            })?;
+
+            objects.extend(
+                xml_blocks(&body, "Contents")
+                    .into_iter()
+                    .filter_map(|block| {
+                        let key = xml_values(block, "Key").into_iter().next()?;
+                        let etag = xml_values(block, "ETag")
+                            .into_iter()
+                            .next()
+                            .map(|etag| normalize_etag(&etag));
+                        Some(ListedObject { key, etag })
+                    }),
+            );
+            let truncated = xml_values(&body, "IsTruncated")
+                .first()
+                .is_some_and(|v| v == "true");
+            continuation_token = xml_values(&body, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if !truncated || continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn delete_entry(&self, entry: &StoredImage) -> Result<(), ImageStoreError> {
+        self.delete_object(&entry.location).await?;
+        self.delete_object(&self.index_key(&entry.id)?).await?;
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&entry.id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ImageStore for S3ImageStore {
+    async fn put(
+        &self,
+        image: &GeneratedImage,
+        metadata: &ImageMetadata,
+    ) -> Result<StoredImage, ImageStoreError> {
+        let id = sha256_hex(&image.data);
+
+        // 同一内容の画像はアップロードせず、エントリのリクエストIDだけ更新する
+        let entry = match self.get_entry(&id).await? {
+            Some(mut existing) => {
+                if !existing.request_ids.contains(&metadata.request_id) {
+                    existing.request_ids.push(metadata.request_id.clone());
+                }
+                existing.stored_at = chrono::Utc::now();
+                existing
+            }
+            None => {
+                self.retention.check_size(image.data.len() as u64)?;
+                let key = self.object_key(&id, image.file_extension())?;
+                self.put_object(&key, image.data.clone(), &image.mime_type)
+                    .await?;
+                StoredImage {
+                    id,
+                    mime_type: image.mime_type.clone(),
+                    size_bytes: image.data.len() as u64,
+                    stored_at: chrono::Utc::now(),
+                    metadata: metadata.clone(),
+                    request_ids: vec![metadata.request_id.clone()],
+                    location: key,
+                }
+            }
+        };
+        self.put_entry(&entry).await?;
+
+        if !self.retention.is_unbounded() {
+            let retention = self.retention.clone();
+            self.collect_garbage(&retention).await?;
+        }
+        Ok(entry)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<(StoredImage, Vec<u8>)>, ImageStoreError> {
+        let Some(entry) = self.get_entry(id).await? else {
+            return Ok(None);
+        };
+        Ok(self
+            .get_object(&entry.location)
+            .await?
+            .map(|data| (entry, data)))
+    }
+
+    async fn list(&self) -> Result<Vec<StoredImage>, ImageStoreError> {
+        let index_prefix = format!("{}{}", self.prefix, INDEX_PREFIX);
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+        for object in self.list_objects(&index_prefix).await? {
+            let Some(id) = object
+                .key
+                .strip_prefix(&index_prefix)
+                .and_then(|k| k.strip_suffix(".json"))
+                .filter(|id| StoredImage::is_valid_id(id))
+            else {
+                continue;
+            };
+            seen.insert(id.to_string());
+
+            // ETagが変わっていないエントリは取得し直さない
+            if let Some(entry) = object
+                .etag
+                .as_deref()
+                .and_then(|etag| self.cached_entry(id, etag))
+            {
+                entries.push(entry);
+                continue;
+            }
+            if let Some(entry) = self.get_entry(id).await? {
+                self.cache_entry(&entry, object.etag);
+                entries.push(entry);
+            }
+        }
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|id, _| seen.contains(id));
+        entries.sort_by(|a, b| b.stored_at.cmp(&a.stored_at).then(a.id.cmp(&b.id)));
+        Ok(entries)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool, ImageStoreError> {
+        let Some(entry) = self.get_entry(id).await? else {
+            return Ok(false);
+        };
+        self.delete_entry(&entry).await?;
+        Ok(true)
+    }
+
+    async fn collect_garbage(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<GarbageCollectionReport, ImageStoreError> {
+        let entries = self.list().await?;
+        let expired = policy.select_expired(&entries, chrono::Utc::now());
+
+        let mut report = GarbageCollectionReport::default();
+        for entry in entries.iter().filter(|e| expired.contains(&e.id)) {
+            self.delete_entry(entry).await?;
+            report.freed_bytes += entry.size_bytes;
+            report.removed_ids.push(entry.id.clone());
+        }
+        Ok(report)
+    }
+
+    fn download_url(&self, stored: &StoredImage) -> Option<String> {
+        self.presigned_url(&stored.location)
+    }
+}
+
+/// XMLから指定タグの値をすべて取り出す（ListObjectsV2のレスポンス用の簡易パーサー）
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    xml_blocks(xml, tag).into_iter().map(xml_unescape).collect()
+}
+
+/// XMLから指定タグの中身をエスケープを解かずにすべて取り出す
+fn xml_blocks<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut blocks = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        blocks.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    blocks
+}
+
+/// ETagの前後の引用符を取り除く
+fn normalize_etag(etag: &str) -> String {
+    etag.trim().trim_matches('"').to_string()
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,190 @@
+use crate::domain::models::{sha256_hex, to_hex};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 署名アルゴリズム名
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+/// 署名対象のサービス名
+const SERVICE: &str = "s3";
+/// ペイロードに署名しない場合のハッシュ値（署名付きURL用）
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// AWS Signature Version 4の認証情報
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub region: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("region", &self.region)
+            .finish()
+    }
+}
+
+/// 署名済みリクエストに付与するヘッダー
+pub struct SignedHeaders {
+    pub authorization: String,
+    pub amz_date: String,
+    pub content_sha256: String,
+}
+
+/// リクエストヘッダーにSigV4署名を付与する（署名対象: host, x-amz-content-sha256, x-amz-date）
+pub fn sign_request(
+    credentials: &Credentials,
+    method: &str,
+    host: &str,
+    path: &str,
+    query: &[(String, String)],
+    payload: &[u8],
+    now: chrono::DateTime<chrono::Utc>,
+) -> SignedHeaders {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let content_sha256 = sha256_hex(payload);
+
+    let canonical_headers = format!(
+        "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+        host, content_sha256, amz_date
+    );
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        uri_encode_path(path),
+        canonical_query(query),
+        canonical_headers,
+        signed_headers,
+        content_sha256
+    );
+
+    let scope = credential_scope(credentials, now);
+    let signature = signature(credentials, now, &scope, &canonical_request);
+
+    SignedHeaders {
+        authorization: format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, credentials.access_key_id, scope, signed_headers, signature
+        ),
+        amz_date,
+        content_sha256,
+    }
+}
+
+/// 署名付きURLのクエリ文字列を作成する（署名対象: hostのみ）
+pub fn presign_query(
+    credentials: &Credentials,
+    method: &str,
+    host: &str,
+    path: &str,
+    expires_secs: u64,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = credential_scope(credentials, now);
+
+    let query = vec![
+        ("X-Amz-Algorithm".to_string(), ALGORITHM.to_string()),
+        (
+            "X-Amz-Credential".to_string(),
+            format!("{}/{}", credentials.access_key_id, scope),
+        ),
+        ("X-Amz-Date".to_string(), amz_date),
+        ("X-Amz-Expires".to_string(), expires_secs.to_string()),
+        ("X-Amz-SignedHeaders".to_string(), "host".to_string()),
+    ];
+    let canonical_query = canonical_query(&query);
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:{}\n\nhost\n{}",
+        method,
+        uri_encode_path(path),
+        canonical_query,
+        host,
+        UNSIGNED_PAYLOAD
+    );
+
+    let signature = signature(credentials, now, &scope, &canonical_request);
+    format!("{}&X-Amz-Signature={}", canonical_query, signature)
+}
+
+fn credential_scope(credentials: &Credentials, now: chrono::DateTime<chrono::Utc>) -> String {
+    format!(
+        "{}/{}/{}/aws4_request",
+        now.format("%Y%m%d"),
+        credentials.region,
+        SERVICE
+    )
+}
+
+fn signature(
+    credentials: &Credentials,
+    now: chrono::DateTime<chrono::Utc>,
+    scope: &str,
+    canonical_request: &str,
+) -> String {
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        now.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let date_key = hmac(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        now.format("%Y%m%d").to_string().as_bytes(),
+    );
+    let region_key = hmac(&date_key, credentials.region.as_bytes());
+    let service_key = hmac(&region_key, SERVICE.as_bytes());
+    let signing_key = hmac(&service_key, b"aws4_request");
+
+    to_hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+}
+
+/// クエリパラメータをキー順に並べてエンコードする
+fn canonical_query(query: &[(String, String)]) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .iter()
+        .map(|(k, v)| (uri_encode(k), uri_encode(v)))
+        .collect();
+    pairs.sort();
+    pairs
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// パスをセグメントごとにURIエンコードする（`/`は残す）
+pub fn uri_encode_path(path: &str) -> String {
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// RFC 3986の非予約文字以外をパーセントエンコードする
+pub fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
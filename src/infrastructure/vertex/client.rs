@@ -0,0 +1,122 @@
+use crate::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest,
+};
+use crate::infrastructure::gemini::client::{
+    error_from_response, image_from_response, GeminiRequest,
+};
+use crate::infrastructure::gemini::imagen::{image_from_predict_response, PredictRequest};
+use crate::infrastructure::vertex::auth::{
+    ServiceAccountError, ServiceAccountKey, ServiceAccountTokenSource,
+};
+use async_trait::async_trait;
+
+/// Vertex AIのGeminiモデルを呼び出すクライアント（サービスアカウントで認証）
+pub struct VertexClient {
+    tokens: ServiceAccountTokenSource,
+    project_id: String,
+    location: String,
+    api_base_url: String,
+    http_client: reqwest::Client,
+}
+
+impl VertexClient {
+    /// サービスアカウントのキーからクライアントを作成（プロジェクトIDはキーの値を使う）
+    pub fn new(key: &ServiceAccountKey, location: String) -> Result<Self, ServiceAccountError> {
+        let project_id = key
+            .project_id
+            .clone()
+            .ok_or_else(|| ServiceAccountError::InvalidKey("project_id is missing".to_string()))?;
+        Self::with_project(key, project_id, location)
+    }
+
+    /// プロジェクトIDを指定してクライアントを作成
+    pub fn with_project(
+        key: &ServiceAccountKey,
+        project_id: String,
+        location: String,
+    ) -> Result<Self, ServiceAccountError> {
+        Ok(Self {
+            tokens: ServiceAccountTokenSource::new(key)?,
+            api_base_url: default_base_url(&location),
+            project_id,
+            location,
+            http_client: reqwest::Client::new(),
+        })
+    }
+
+    /// APIのベースURLを変更する（リージョナルエンドポイント以外やテスト用）
+    pub fn with_base_url(mut self, api_base_url: String) -> Self {
+        self.api_base_url = api_base_url;
+        self
+    }
+
+    /// モデルのエンドポイントのURL（Imagenは`:predict`、それ以外は`:generateContent`）
+    pub fn build_url(&self, model: &GeminiModel) -> String {
+        let method = if model.is_imagen() {
+            "predict"
+        } else {
+            "generateContent"
+        };
+        format!(
+            "{}/projects/{}/locations/{}/publishers/google/models/{}:{}",
+            self.api_base_url, self.project_id, self.location, model, method
+        )
+    }
+
+    async fn send(
+        &self,
+        request: &ImageGenerationRequest,
+        access_token: &str,
+    ) -> Result<reqwest::Response, ImageGenerationError> {
+        let http_request = self
+            .http_client
+            .post(self.build_url(&request.model))
+            .bearer_auth(access_token);
+        let http_request = if request.model.is_imagen() {
+            http_request.json(&PredictRequest::new(request)?)
+        } else {
+            http_request.json(&GeminiRequest::new(request))
+        };
+        Ok(http_request.send().await?)
+    }
+}
+
+#[async_trait]
+impl ImageGenerationRepository for VertexClient {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let mut response = self
+            .send(request, &self.tokens.access_token().await?)
+            .await?;
+
+        // キャッシュしたトークンが失効していた場合は取り直して1回だけ再試行する
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            self.tokens.invalidate().await;
+            response = self
+                .send(request, &self.tokens.access_token().await?)
+                .await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(error_from_response(response, &request.model).await);
+        }
+        let image = if request.model.is_imagen() {
+            image_from_predict_response(response, &request.model).await?
+        } else {
+            image_from_response(response, &request.model).await?
+        };
+        Ok(image.with_api_key(self.tokens.client_email().to_string()))
+    }
+}
+
+/// ロケーションに対応するエンドポイント（`global`は地域なしのホスト）
+fn default_base_url(location: &str) -> String {
+    if location == "global" {
+        "https://aiplatform.googleapis.com/v1".to_string()
+    } else {
+        format!("https://{}-aiplatform.googleapis.com/v1", location)
+    }
+}
@@ -0,0 +1,5 @@
+pub mod auth;
+pub mod client;
+
+pub use auth::{ServiceAccountError, ServiceAccountKey, ServiceAccountTokenSource};
+pub use client::VertexClient;
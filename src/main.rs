@@ -1,51 +1,350 @@
 // ライブラリとして公開されているモジュールを使用
-use google_gemini_image_creator::domain;
+use google_gemini_image_creator::config::{ApiBackend, Config};
 use google_gemini_image_creator::infrastructure;
 use google_gemini_image_creator::presentation;
 
 use anyhow::Result;
+use google_gemini_image_creator::application::batch::{self, BatchManifest};
+use infrastructure::gemini::BatchInputMode;
 use infrastructure::mcp::McpServer;
+use infrastructure::redaction::{redact, RedactingMakeWriter};
 use presentation::RequestHandler;
-use std::env;
-use tracing::{error, info};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // ログの初期化
     tracing_subscriber::fmt()
-        .with_writer(std::io::stderr)
+        .with_writer(RedactingMakeWriter::new(std::io::stderr))
         .with_ansi(false)
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
     info!("Starting Google Gemini Image Creator MCP Server");
 
-    // 環境変数からAPIキーを取得
-    let api_key = env::var("GEMINI_API_KEY")
-        .map_err(|_| anyhow::anyhow!("GEMINI_API_KEY environment variable is required"))?;
+    // MCPサーバーの初期化（Vertex AIはサービスアカウント、それ以外はAPIキーで認証）
+    // モデル設定は設定ファイルの値を含めてサーバーの作成時に初期化する
+    let config = Config::from_env();
+    let server = match config.api_backend() {
+        ApiBackend::Vertex => {
+            info!(
+                "Using Vertex AI backend in location {}",
+                config.vertex_location()
+            );
+            McpServer::with_vertex()?
+        }
+        ApiBackend::AiStudio => {
+            // 環境変数（または設定ファイル）からAPIキーを取得
+            let api_keys = config.gemini_api_keys().to_vec();
+            if api_keys.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "GEMINI_API_KEY (or GEMINI_API_KEYS / GEMINI_API_KEYS_FILE) environment variable is required"
+                ));
+            }
+            info!("Using {} Gemini API key(s)", api_keys.len());
+            McpServer::with_api_keys(api_keys)
+        }
+    };
 
-    // GeminiModelの環境変数を初期化
-    domain::GeminiModel::init_from_env();
+    // 予算の上限がある場合は、生成履歴から今月の利用量を復元する
+    server.restore_budget().await;
 
-    // MCPサーバーの初期化
-    let server = McpServer::new(api_key);
-    let handler = RequestHandler::new(server);
+    // `batch <入力ファイル>`の場合はMCPサーバーとしてではなく一括生成を実行して終了する
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("batch") {
+        return run_batch_cli(server, &args[1..]).await;
+    }
+
+    let handler = Arc::new(RequestHandler::new(server));
 
     info!("MCP Server initialized");
 
+    // 標準出力への書き込みは単一のタスクに集約する（レスポンスと通知の行が混ざらないように）
+    let (output, output_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(write_output(output_rx));
+
+    let forwarder = spawn_notification_forwarder(&handler, output.clone());
+    spawn_config_reloader(handler.clone());
+
     // MCPサーバーを起動（実際のMCPプロトコル実装に合わせて調整が必要）
     // ここでは標準入出力を使用したMCPサーバーの実装例を示す
-    run_mcp_server(handler).await?;
+    run_mcp_server(&handler, output).await?;
+
+    // 通知の転送を止めて出力チャネルを閉じ、書き込み待ちの行をすべて出力してから終了する
+    forwarder.abort();
+    let _ = forwarder.await;
+    writer.await??;
+
+    Ok(())
+}
+
+/// 一括生成のCLI（`batch <input.jsonl|input.csv> [--manifest <path>] [--concurrency <n>] [--output-path <dir>] [--gemini-batch]`）
+///
+/// 結果の一覧は項目が終わるたびに保存し、同じ一覧を指定して再実行すると成功済みの項目を飛ばして再開する。
+/// `--gemini-batch`の場合はすべての項目をBatch APIで送信し、終了まで待って画像ストアに保存する。
+/// 送信したバッチの名前も一覧に保存し、再実行すると送信し直さずに終了を待つ。
+async fn run_batch_cli(server: McpServer, args: &[String]) -> Result<()> {
+    const USAGE: &str = "usage: batch <input.jsonl|input.csv> [--manifest <path>] [--concurrency <n>] [--output-path <dir>] [--gemini-batch]";
+
+    let mut input = None;
+    let mut manifest_path = None;
+    let mut concurrency = None;
+    let mut output_path = None;
+    let mut gemini_batch = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("{} requires a value\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--manifest" => manifest_path = Some(PathBuf::from(value()?)),
+            "--concurrency" => concurrency = Some(value()?.parse::<usize>()?),
+            "--output-path" => output_path = Some(value()?),
+            "--gemini-batch" => gemini_batch = true,
+            _ if input.is_none() && !arg.starts_with("--") => input = Some(PathBuf::from(arg)),
+            _ => return Err(anyhow::anyhow!("Unexpected argument: {}\n{}", arg, USAGE)),
+        }
+    }
+    let input = input.ok_or_else(|| anyhow::anyhow!(USAGE))?;
+    if gemini_batch && (concurrency.is_some() || output_path.is_some()) {
+        return Err(anyhow::anyhow!(
+            "--concurrency and --output-path cannot be used with --gemini-batch\n{}",
+            USAGE
+        ));
+    }
+    let manifest_path = manifest_path.unwrap_or_else(|| input.with_extension("manifest.json"));
+
+    let content = std::fs::read_to_string(&input)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", input.display(), e))?;
+    let items = if is_csv(&input) {
+        batch::parse_csv(&content)?
+    } else {
+        batch::parse_jsonl(&content)?
+    };
+    info!(
+        "Running batch of {} items from {} (manifest: {})",
+        items.len(),
+        input.display(),
+        manifest_path.display()
+    );
+    let manifest = if gemini_batch {
+        // バッチの情報の保存先がなければ結果の一覧と同じディレクトリに保存する（再実行で再開できるように）
+        let server = match (server.batch_manifest_dir(), manifest_path.parent()) {
+            (None, Some(dir)) => server.with_batch_manifest_dir(dir),
+            _ => server,
+        };
+        run_gemini_batch(&server, items, &manifest_path).await?
+    } else {
+        run_local_batch(&server, items, &manifest_path, concurrency, output_path).await?
+    };
+    manifest.save(&manifest_path)?;
+
+    println!(
+        "{}",
+        serde_json::json!({
+            "total": manifest.total,
+            "succeeded": manifest.succeeded,
+            "failed": manifest.failed,
+            "manifest_path": manifest_path.display().to_string()
+        })
+    );
+    if manifest.failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} items failed; run the same command again to retry them",
+            manifest.failed,
+            manifest.total
+        ));
+    }
+    Ok(())
+}
+
+/// 項目をこのプロセスで並列に生成する（前回の結果の一覧があれば成功済みの項目を飛ばす）
+async fn run_local_batch(
+    server: &McpServer,
+    items: Vec<batch::BatchItem>,
+    manifest_path: &Path,
+    concurrency: Option<usize>,
+    output_path: Option<String>,
+) -> Result<BatchManifest> {
+    let previous = BatchManifest::load(manifest_path)?;
+    server
+        .generate_batch(
+            items,
+            previous,
+            concurrency,
+            output_path.as_deref(),
+            |manifest| {
+                if let Err(e) = manifest.save(manifest_path) {
+                    warn!("Failed to save batch manifest: {}", e);
+                }
+            },
+        )
+        .await
+}
+
+/// 項目をGemini Batch APIのバッチとして送信し、終了を待って結果を画像ストアに保存する
+///
+/// 前回送信したバッチが終了していなければ、その終了を待つ。終了していれば失敗した項目だけを送信し直す。
+async fn run_gemini_batch(
+    server: &McpServer,
+    items: Vec<batch::BatchItem>,
+    manifest_path: &Path,
+) -> Result<BatchManifest> {
+    let previous = BatchManifest::load(manifest_path)?;
+    // 結果のそろっていない一覧のバッチは、まだ終了を待っている
+    let unfinished = previous
+        .gemini_batch
+        .clone()
+        .filter(|_| previous.items.len() < previous.total);
 
+    // 前回成功した項目は送信しない（項目の位置で対応付ける）
+    let succeeded: Vec<_> = previous
+        .items
+        .into_iter()
+        .filter(|r| r.status == batch::BatchItemStatus::Succeeded && r.index < items.len())
+        .collect();
+    let done: std::collections::HashSet<usize> = succeeded.iter().map(|r| r.index).collect();
+    let pending: Vec<(usize, batch::BatchItem)> = items
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !done.contains(index))
+        .collect();
+    let total = succeeded.len() + pending.len();
+    if pending.is_empty() {
+        return Ok(BatchManifest {
+            total,
+            succeeded: total,
+            items: succeeded,
+            ..BatchManifest::default()
+        });
+    }
+
+    let (indices, pending): (Vec<usize>, Vec<batch::BatchItem>) = pending.into_iter().unzip();
+    let name = match unfinished {
+        Some(name) => {
+            info!("Resuming Gemini batch {}", name);
+            name
+        }
+        None => {
+            let job = server
+                .submit_gemini_batch(pending, None, BatchInputMode::Auto, None)
+                .await?;
+            // 待っている間に中断しても再実行で同じバッチを待てるよう、先にバッチ名を保存する
+            BatchManifest {
+                total,
+                items: succeeded.clone(),
+                succeeded: succeeded.len(),
+                gemini_batch: Some(job.name.clone()),
+                ..BatchManifest::default()
+            }
+            .save(manifest_path)?;
+            info!("Waiting for Gemini batch {}", job.name);
+            job.name
+        }
+    };
+    let (job, manifest) = server.wait_gemini_batch(&name).await?;
+    info!("Gemini batch {} is {:?}", job.name, job.state);
+
+    let mut items = succeeded;
+    items.extend(manifest.items.into_iter().map(|r| batch::BatchItemResult {
+        index: indices.get(r.index).copied().unwrap_or(total + r.index),
+        ..r
+    }));
+    items.sort_by_key(|r| r.index);
+    let succeeded = items
+        .iter()
+        .filter(|r| r.status == batch::BatchItemStatus::Succeeded)
+        .count();
+    Ok(BatchManifest {
+        total,
+        succeeded,
+        failed: items.len() - succeeded,
+        items,
+        gemini_batch: Some(job.name),
+    })
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// 出力チャネルから受け取った行を標準出力に書き込む
+async fn write_output(mut output_rx: mpsc::UnboundedReceiver<String>) -> Result<()> {
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = output_rx.recv().await {
+        stdout.write_all(line.as_bytes()).await?;
+        stdout.write_all(b"\n").await?;
+        stdout.flush().await?;
+    }
     Ok(())
 }
 
-async fn run_mcp_server(handler: RequestHandler) -> Result<()> {
-    use std::io::{self, BufRead, BufReader, Write};
+/// サーバーからの通知（tools/list_changedなど）を出力チャネルへ転送する
+fn spawn_notification_forwarder(
+    handler: &RequestHandler,
+    output: mpsc::UnboundedSender<String>,
+) -> tokio::task::JoinHandle<()> {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut notifications = handler.server().subscribe_notifications();
+    tokio::spawn(async move {
+        loop {
+            match notifications.recv().await {
+                Ok(notification) => match serde_json::to_string(&notification) {
+                    Ok(json) => {
+                        if output.send(json).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("Failed to serialize notification: {}", e),
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Dropped {} notifications", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+/// SIGHUPを受け取ったら設定を再読み込みする
+#[cfg(unix)]
+fn spawn_config_reloader(handler: Arc<RequestHandler>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            handler.server().reload_config();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_config_reloader(_handler: Arc<RequestHandler>) {}
+
+async fn run_mcp_server(
+    handler: &RequestHandler,
+    output: mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    use std::io::{self, BufRead, BufReader};
 
     let stdin = io::stdin();
     let mut reader = BufReader::new(stdin.lock());
-    let mut stdout = io::stdout();
 
     loop {
         let mut line = String::new();
@@ -75,8 +374,7 @@ async fn run_mcp_server(handler: RequestHandler) -> Result<()> {
                         match handler.handle_jsonrpc_request(request).await {
                             Ok(response) => {
                                 let response_json = serde_json::to_string(&response)?;
-                                writeln!(stdout, "{}", response_json)?;
-                                stdout.flush()?;
+                                output.send(response_json)?;
                             }
                             Err(e) => {
                                 error!("Error handling request: {}", e);
@@ -86,12 +384,11 @@ async fn run_mcp_server(handler: RequestHandler) -> Result<()> {
                                     "jsonrpc": config.jsonrpc_version(),
                                     "error": {
                                         "code": config.jsonrpc_error_codes.internal_error,
-                                        "message": format!("Internal error: {}", e)
+                                        "message": redact(&format!("Internal error: {}", e))
                                     },
                                     "id": request_id
                                 });
-                                writeln!(stdout, "{}", error_response)?;
-                                stdout.flush()?;
+                                output.send(error_response.to_string())?;
                             }
                         }
                     }
@@ -108,8 +405,7 @@ async fn run_mcp_server(handler: RequestHandler) -> Result<()> {
                             },
                             "id": null
                         });
-                        writeln!(stdout, "{}", error_response)?;
-                        stdout.flush()?;
+                        output.send(error_response.to_string())?;
                     }
                 }
             }
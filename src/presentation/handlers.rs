@@ -1,24 +1,35 @@
 use crate::config::Config;
-use crate::infrastructure::mcp::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, McpServer};
+use crate::infrastructure::mcp::{
+    CursorCodec, JsonRpcError, JsonRpcRequest, JsonRpcResponse, McpServer, Page, PaginationError,
+};
+use crate::infrastructure::redaction::redact;
 use anyhow::Result;
+use serde::Serialize;
 use tracing::{error, info};
 
 /// MCPリクエストハンドラー
 pub struct RequestHandler {
     server: McpServer,
     config: Config,
+    cursors: CursorCodec,
 }
 
 impl RequestHandler {
     pub fn new(server: McpServer) -> Self {
+        Self::with_config(server, Config::from_env())
+    }
+
+    pub fn with_config(server: McpServer, config: Config) -> Self {
         Self {
             server,
-            config: Config::from_env(),
+            config,
+            cursors: CursorCodec::new(),
         }
     }
 
-    pub fn with_config(server: McpServer, config: Config) -> Self {
-        Self { server, config }
+    /// MCPサーバーへの参照を取得
+    pub fn server(&self) -> &McpServer {
+        &self.server
     }
 
     /// JSON-RPCリクエストを処理
@@ -28,13 +39,24 @@ impl RequestHandler {
         match request.method.as_str() {
             "initialize" => {
                 info!("Handling initialize request");
+                let client_name = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.pointer("/clientInfo/name"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+                self.server.set_client_id(client_name);
                 Ok(JsonRpcResponse {
                     jsonrpc: self.config.jsonrpc_version().to_string(),
                     id,
                     result: Some(serde_json::json!({
                         "protocolVersion": "2024-11-05",
                         "capabilities": {
-                            "tools": {}
+                            "tools": {
+                                "listChanged": true
+                            },
+                            "resources": {},
+                            "logging": {}
                         },
                         "serverInfo": {
                             "name": "google-gemini-image-creator",
@@ -44,18 +66,80 @@ impl RequestHandler {
                     error: None,
                 })
             }
-            "tools/list" => {
-                info!("Handling tools/list request");
-                let tools = self.server.list_tools();
+            "logging/setLevel" => {
+                // 通知するのは予算の警告のみのため、レベルは受け付けるだけにする
                 Ok(JsonRpcResponse {
                     jsonrpc: self.config.jsonrpc_version().to_string(),
                     id,
-                    result: Some(serde_json::json!({
-                        "tools": tools
-                    })),
+                    result: Some(serde_json::json!({})),
                     error: None,
                 })
             }
+            "tools/list" => {
+                info!("Handling tools/list request");
+                let tools = self.server.list_tools();
+                let page = self.cursors.paginate(
+                    "tools",
+                    &tools,
+                    cursor_param(request.params.as_ref()),
+                    self.config.list_page_size(),
+                );
+                Ok(self.page_response(id, "tools", page))
+            }
+            "resources/list" => {
+                info!("Handling resources/list request");
+                // 新しい順のリストのため、ページの間に画像が追加されてもずれないキーセット方式のカーソルを使う
+                let entries = self.server.list_resource_entries().await?;
+                let page = self
+                    .cursors
+                    .paginate_newest_first(
+                        "resources",
+                        &entries,
+                        |(position, _)| position.clone(),
+                        cursor_param(request.params.as_ref()),
+                        self.config.list_page_size(),
+                    )
+                    .map(|page| Page {
+                        items: page
+                            .items
+                            .into_iter()
+                            .map(|(_, resource)| resource)
+                            .collect(),
+                        next_cursor: page.next_cursor,
+                    });
+                Ok(self.page_response(id, "resources", page))
+            }
+            "resources/read" => {
+                let uri = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("uri"))
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing 'uri' in params"))?;
+
+                info!("Handling resources/read request: {}", uri);
+
+                match self.server.read_resource(uri).await? {
+                    Some(contents) => Ok(JsonRpcResponse {
+                        jsonrpc: self.config.jsonrpc_version().to_string(),
+                        id,
+                        result: Some(serde_json::json!({
+                            "contents": [contents]
+                        })),
+                        error: None,
+                    }),
+                    None => Ok(JsonRpcResponse {
+                        jsonrpc: self.config.jsonrpc_version().to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: self.config.jsonrpc_error_codes.invalid_params,
+                            message: format!("Resource not found: {}", uri),
+                            data: None,
+                        }),
+                    }),
+                }
+            }
             "tools/call" => {
                 let params = request
                     .params
@@ -91,7 +175,7 @@ impl RequestHandler {
                             result: None,
                             error: Some(JsonRpcError {
                                 code: self.config.jsonrpc_error_codes.internal_error,
-                                message: format!("Internal error: {}", e),
+                                message: redact(&format!("Internal error: {}", e)),
                                 data: None,
                             }),
                         })
@@ -110,4 +194,44 @@ impl RequestHandler {
             }),
         }
     }
+
+    /// リストの1ページを返す（`nextCursor`付き）
+    fn page_response<T: Serialize>(
+        &self,
+        id: Option<serde_json::Value>,
+        key: &str,
+        page: Result<Page<T>, PaginationError>,
+    ) -> JsonRpcResponse {
+        match page {
+            Ok(page) => {
+                let mut result = serde_json::json!({ key: page.items });
+                if let Some(next_cursor) = page.next_cursor {
+                    result["nextCursor"] = serde_json::Value::String(next_cursor);
+                }
+                JsonRpcResponse {
+                    jsonrpc: self.config.jsonrpc_version().to_string(),
+                    id,
+                    result: Some(result),
+                    error: None,
+                }
+            }
+            Err(e) => JsonRpcResponse {
+                jsonrpc: self.config.jsonrpc_version().to_string(),
+                id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: self.config.jsonrpc_error_codes.invalid_params,
+                    message: e.to_string(),
+                    data: None,
+                }),
+            },
+        }
+    }
+}
+
+/// `cursor`パラメータを取得
+fn cursor_param(params: Option<&serde_json::Value>) -> Option<&str> {
+    params
+        .and_then(|p| p.get("cursor"))
+        .and_then(|v| v.as_str())
 }
@@ -8,8 +8,247 @@ fn test_mcp_server_initialization() {
     // 環境変数が設定されていない場合でもサーバーは初期化できる
     let server = McpServer::new("test-api-key".to_string());
     let tools = server.list_tools();
-    assert_eq!(tools.len(), 1);
+    assert_eq!(tools.len(), 2);
     assert_eq!(tools[0].name, "generate_image");
+    assert_eq!(tools[1].name, "usage_report");
+}
+
+#[test]
+fn test_server_starts_with_model_settings_from_config_file() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("server.env");
+    std::fs::write(
+        &config_file,
+        "GEMINI_DEFAULT_MODEL=gemini-3-pro-image-preview\n\
+         GEMINI_ALLOWED_MODELS=gemini-3-pro-image-preview,gemini-2.5-flash-image\n\
+         MODEL_DISCOVERY=false\n",
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_google-gemini-image-creator"))
+        .current_dir(dir.path())
+        .env("CONFIG_FILE", &config_file)
+        .env("GEMINI_API_KEY", "test-api-key")
+        .env_remove("GEMINI_DEFAULT_MODEL")
+        .env_remove("GEMINI_ALLOWED_MODELS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(
+        stdin,
+        r#"{{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{{}}}}"#
+    )
+    .unwrap();
+    drop(stdin);
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    child.wait().unwrap();
+
+    let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+    let tools = response["result"]["tools"].as_array().unwrap();
+    let generate = tools
+        .iter()
+        .find(|tool| tool["name"] == "generate_image")
+        .unwrap();
+    let model = &generate["inputSchema"]["properties"]["model"];
+    assert_eq!(model["default"], "gemini-3-pro-image-preview");
+    assert_eq!(
+        model["enum"],
+        serde_json::json!(["gemini-3-pro-image-preview", "gemini-2.5-flash-image"])
+    );
+}
+
+#[test]
+fn test_regenerate_restores_inline_reference_images() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    // 参照画像（04 05 06）が2回とも送信されることを確認する
+    let mut api = mockito::Server::new();
+    let generate = api
+        .mock(
+            "POST",
+            "/models/gemini-2.5-flash-image:generateContent",
+        )
+        .match_body(mockito::Matcher::Regex("BAUG".to_string()))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }] }
+                }]
+            })
+            .to_string(),
+        )
+        .expect(2)
+        .create();
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_google-gemini-image-creator"))
+        .current_dir(dir.path())
+        .env("GEMINI_API_KEY", "test-api-key")
+        .env("GEMINI_API_BASE_URL", api.url())
+        .env("IMAGE_STORE_DIR", dir.path().join("store"))
+        .env("HISTORY_DB_PATH", dir.path().join("history.db"))
+        .env("IMAGE_OUTPUT_DIR", dir.path())
+        .env_remove("CONFIG_FILE")
+        .env_remove("GEMINI_ALLOWED_MODELS")
+        .env_remove("MODEL_DISCOVERY")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut call = |id: u32, name: &str, arguments: serde_json::Value| {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments }
+        });
+        writeln!(stdin, "{}", request).unwrap();
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(response.get("error").is_none(), "{}", response);
+        let result = &response["result"];
+        assert_ne!(result["isError"], true, "{}", response);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        serde_json::from_str::<serde_json::Value>(text).unwrap()
+    };
+
+    let generated = call(
+        1,
+        "generate_image",
+        serde_json::json!({
+            "prompt": "a mascot",
+            "model": "gemini-2.5-flash-image",
+            "style_images": [{ "data": "BAUG", "mime_type": "image/png" }]
+        }),
+    );
+    let regenerated = call(
+        2,
+        "regenerate",
+        serde_json::json!({ "request_id": generated["request_id"] }),
+    );
+    assert_ne!(regenerated["request_id"], generated["request_id"]);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    generate.assert();
+}
+
+#[tokio::test]
+async fn test_initialize_advertises_tools_list_changed() {
+    use google_gemini_image_creator::infrastructure::mcp::JsonRpcRequest;
+    use google_gemini_image_creator::presentation::RequestHandler;
+
+    let handler = RequestHandler::new(McpServer::new("test-api-key".to_string()));
+    let response = handler
+        .handle_jsonrpc_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: "initialize".to_string(),
+            params: None,
+        })
+        .await
+        .unwrap();
+
+    let result = response.result.unwrap();
+    assert_eq!(result["capabilities"]["tools"]["listChanged"], true);
+    assert!(result["capabilities"]["logging"].is_object());
+}
+
+#[tokio::test]
+async fn test_tools_list_rejects_invalid_cursor() {
+    use google_gemini_image_creator::infrastructure::mcp::JsonRpcRequest;
+    use google_gemini_image_creator::presentation::RequestHandler;
+
+    let handler = RequestHandler::new(McpServer::new("test-api-key".to_string()));
+    let response = handler
+        .handle_jsonrpc_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: "tools/list".to_string(),
+            params: Some(serde_json::json!({ "cursor": "forged" })),
+        })
+        .await
+        .unwrap();
+
+    assert!(response.result.is_none());
+    assert_eq!(response.error.unwrap().code, -32602);
+}
+
+#[tokio::test]
+async fn test_resources_list_returns_page() {
+    use google_gemini_image_creator::infrastructure::mcp::JsonRpcRequest;
+    use google_gemini_image_creator::presentation::RequestHandler;
+
+    let handler = RequestHandler::new(McpServer::new("test-api-key".to_string()));
+    let response = handler
+        .handle_jsonrpc_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(serde_json::json!(1)),
+            method: "resources/list".to_string(),
+            params: None,
+        })
+        .await
+        .unwrap();
+
+    let result = response.result.unwrap();
+    assert!(result["resources"].is_array());
+    assert!(result.get("nextCursor").is_none());
+}
+
+#[tokio::test]
+async fn test_stored_images_are_exposed_as_resources() {
+    use google_gemini_image_creator::domain::{
+        GeminiModel, GeneratedImage, ImageGenerationRequest, ImageMetadata, ImageStore,
+    };
+    use google_gemini_image_creator::infrastructure::storage::FsImageStore;
+    use std::sync::Arc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(FsImageStore::open(dir.path()).unwrap());
+    let request = ImageGenerationRequest::new("a lighthouse at dusk".to_string());
+    let image = GeneratedImage::new(
+        vec![1, 2, 3],
+        GeminiModel::from("gemini-2.5-flash-image".to_string()),
+    );
+    let stored = store
+        .put(&image, &ImageMetadata::new(&request, &image))
+        .await
+        .unwrap();
+
+    let server = McpServer::new("test-api-key".to_string()).with_image_store(store);
+    let resources = server.list_resources().await.unwrap();
+    assert_eq!(resources.len(), 1);
+    assert_eq!(resources[0].uri, format!("image://{}", stored.id));
+    assert_eq!(resources[0].name, "a lighthouse at dusk");
+
+    let contents = server
+        .read_resource(&resources[0].uri)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(contents.mime_type, "image/png");
+    assert_eq!(contents.blob, "AQID");
+    assert!(server
+        .read_resource("image://missing")
+        .await
+        .unwrap()
+        .is_none());
 }
 
 // 実際のAPIを使用したE2Eテストは、APIキーが必要なため、
@@ -0,0 +1,110 @@
+use google_gemini_image_creator::application::batch::{
+    item_keys, parse_csv, parse_jsonl, run_batch, BatchItem, BatchItemResult, BatchItemStatus,
+    BatchManifest,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn test_parse_jsonl_accepts_objects_and_prompt_strings() {
+    let items = parse_jsonl(
+        "{\"prompt\":\"a cat\",\"model\":\"imagen-4.0-generate-001\",\"filename\":\"cat\"}\n\n\"a dog\"\n",
+    )
+    .unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].model.as_deref(), Some("imagen-4.0-generate-001"));
+    assert_eq!(items[0].filename.as_deref(), Some("cat"));
+    assert_eq!(items[1], BatchItem::new("a dog".to_string()));
+
+    let err = parse_jsonl("\"ok\"\n{\"model\":\"x\"}\n").unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
+
+#[test]
+fn test_parse_csv_reads_optional_columns_and_quoted_fields() {
+    let csv = "prompt,model,aspect_ratio,filename\r\n\
+               \"a fox, in the snow\",,16:9,fox\r\n\
+               \"a \"\"quoted\"\" title\nover two lines\",gemini-2.5-flash-image,,\r\n\
+               ,,,\r\n";
+    let items = parse_csv(csv).unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].prompt, "a fox, in the snow");
+    assert_eq!(items[0].model, None);
+    assert_eq!(items[0].aspect_ratio.as_deref(), Some("16:9"));
+    assert_eq!(items[0].filename.as_deref(), Some("fox"));
+    assert_eq!(items[1].prompt, "a \"quoted\" title\nover two lines");
+    assert_eq!(items[1].model.as_deref(), Some("gemini-2.5-flash-image"));
+
+    assert!(parse_csv("model\nx\n").is_err());
+}
+
+#[test]
+fn test_parse_strips_utf8_bom() {
+    let items = parse_csv("\u{feff}prompt,filename\na cat,cat\n").unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].prompt, "a cat");
+    assert_eq!(items[0].filename.as_deref(), Some("cat"));
+
+    let items = parse_jsonl("\u{feff}\"a dog\"\n").unwrap();
+    assert_eq!(items[0], BatchItem::new("a dog".to_string()));
+}
+
+#[test]
+fn test_item_keys_distinguish_duplicate_items() {
+    let items = vec![
+        BatchItem::new("same".to_string()),
+        BatchItem::new("same".to_string()),
+        BatchItem {
+            id: Some("custom".to_string()),
+            ..BatchItem::new("other".to_string())
+        },
+    ];
+    let keys = item_keys(&items);
+    assert_ne!(keys[0], keys[1]);
+    assert!(keys[1].ends_with("#2"));
+    assert_eq!(keys[2], "custom");
+}
+
+#[tokio::test]
+async fn test_partial_failures_do_not_abort_and_resume_retries_only_failures() {
+    let items: Vec<BatchItem> = ["ok 1", "fail", "ok 2"]
+        .iter()
+        .map(|p| BatchItem::new(p.to_string()))
+        .collect();
+    let calls = AtomicUsize::new(0);
+    let generate = |fail: bool| {
+        let calls = &calls;
+        move |item: BatchItem| async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            if fail && item.prompt == "fail" {
+                BatchItemResult::failed(&item, "API error".to_string())
+            } else {
+                BatchItemResult::succeeded(&item, serde_json::json!({ "prompt": item.prompt }))
+            }
+        }
+    };
+
+    let mut progress = 0;
+    let first = run_batch(
+        items.clone(),
+        BatchManifest::default(),
+        2,
+        generate(true),
+        |_| progress += 1,
+    )
+    .await;
+    assert_eq!(progress, 3);
+    assert_eq!((first.total, first.succeeded, first.failed), (3, 2, 1));
+    assert_eq!(first.items[1].status, BatchItemStatus::Failed);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // 保存した一覧から再開すると失敗した項目だけをやり直す
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.manifest.json");
+    first.save(&path).unwrap();
+    let previous = BatchManifest::load(&path).unwrap();
+    let second = run_batch(items, previous, 2, generate(false), |_| {}).await;
+    assert_eq!((second.succeeded, second.failed), (3, 0));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert_eq!(second.items[1].attempts, 2);
+    assert_eq!(second.items[0].attempts, 1);
+}
@@ -0,0 +1,178 @@
+use google_gemini_image_creator::application::{
+    BudgetGuard, BudgetLimits, BudgetPolicy, BudgetWarning, ModelPrice,
+};
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, GenerationRecord, ImageGenerationRequest,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn request(client_id: Option<&str>) -> ImageGenerationRequest {
+    let request = ImageGenerationRequest::new("a lighthouse".to_string())
+        .with_model(GeminiModel::from("priced-model".to_string()));
+    match client_id {
+        Some(client_id) => request.with_client_id(client_id.to_string()),
+        None => request,
+    }
+}
+
+/// 1枚1ドルとして数えられる生成結果
+fn image() -> GeneratedImage {
+    GeneratedImage::new(vec![1, 2, 3], GeminiModel::from("priced-model".to_string()))
+        .with_api_key("…abcd".to_string())
+}
+
+fn guard(policy: BudgetPolicy) -> BudgetGuard {
+    let prices = HashMap::from([(
+        "priced-model".to_string(),
+        ModelPrice {
+            per_image: 1.0,
+            ..ModelPrice::default()
+        },
+    )]);
+    BudgetGuard::new(policy, prices)
+}
+
+#[test]
+fn test_daily_spending_cap_refuses_once_reached() {
+    let guard = guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_usd: Some(2.0),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    });
+
+    for _ in 0..2 {
+        guard.check(&request(None)).unwrap();
+        guard.record(&request(None), &image());
+    }
+    let err = guard.check(&request(None)).unwrap_err();
+    assert!(err.contains("daily usd cap for global reached"), "{}", err);
+}
+
+#[test]
+fn test_per_client_image_cap_applies_to_each_client() {
+    let guard = guard(BudgetPolicy {
+        per_client: BudgetLimits {
+            monthly_images: Some(1),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    });
+
+    guard.record(&request(Some("alice")), &image());
+    assert!(guard.check(&request(Some("alice"))).is_err());
+    assert!(guard.check(&request(Some("bob"))).is_ok());
+    // クライアント名のないリクエストにはクライアントごとの上限を適用しない
+    assert!(guard.check(&request(None)).is_ok());
+}
+
+#[test]
+fn test_warning_is_emitted_once_when_threshold_is_crossed() {
+    let received: Arc<Mutex<Vec<BudgetWarning>>> = Arc::default();
+    let sink = Arc::clone(&received);
+    let guard = guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_images: Some(4),
+            ..BudgetLimits::default()
+        },
+        warning_thresholds: vec![0.5, 0.75],
+        ..BudgetPolicy::default()
+    })
+    .with_warning_handler(move |warning| sink.lock().unwrap().push(warning.clone()));
+
+    assert!(guard.record(&request(None), &image()).is_empty());
+    let warnings = guard.record(&request(None), &image());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].threshold, 0.5);
+    assert_eq!(warnings[0].metric, "images");
+    assert_eq!(guard.record(&request(None), &image())[0].threshold, 0.75);
+    assert!(guard.record(&request(None), &image()).is_empty());
+    assert_eq!(received.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_cached_images_do_not_count_toward_the_budget() {
+    let guard = guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_images: Some(1),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    });
+
+    guard.record(&request(None), &image().without_usage());
+    assert!(guard.check(&request(None)).is_ok());
+}
+
+#[test]
+fn test_reservations_count_until_committed_or_dropped() {
+    let guard = Arc::new(guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_images: Some(1),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    }));
+
+    // 生成中の分も使用済みとして数えるため、同時に送られたリクエストは拒否する
+    let reservation = guard.reserve(&request(None)).unwrap();
+    assert!(guard.reserve(&request(None)).is_err());
+
+    // 生成に失敗した場合は確保した分を戻す
+    drop(reservation);
+    let reservation = guard.reserve(&request(None)).unwrap();
+    reservation.commit(&image());
+    let err = guard.reserve(&request(None)).err().unwrap();
+    assert!(
+        err.contains("daily images cap for global reached"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_committing_a_cached_image_releases_the_reservation() {
+    let guard = Arc::new(guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_images: Some(1),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    }));
+
+    let reservation = guard.reserve(&request(None)).unwrap();
+    reservation.commit(&image().without_usage());
+    assert!(guard.check(&request(None)).is_ok());
+}
+
+#[test]
+fn test_spend_is_restored_from_history() {
+    let guard = guard(BudgetPolicy {
+        global: BudgetLimits {
+            daily_usd: Some(2.0),
+            ..BudgetLimits::default()
+        },
+        per_client: BudgetLimits {
+            monthly_images: Some(1),
+            ..BudgetLimits::default()
+        },
+        ..BudgetPolicy::default()
+    });
+
+    let alice = request(Some("alice"));
+    let recorded = GenerationRecord::succeeded(&alice, &image(), Duration::from_millis(10))
+        .with_estimated_cost(1.5);
+    // 推定料金のない記録（キャッシュから返したものなど）と先月の記録は数えない
+    let cached = GenerationRecord::succeeded(&alice, &image(), Duration::from_millis(10));
+    let mut last_month = recorded.clone();
+    last_month.created_at -= chrono::Duration::days(40);
+    guard.restore([&recorded, &cached, &last_month]);
+
+    assert!(guard.check(&request(Some("alice"))).is_err());
+    assert!(guard.check(&request(Some("bob"))).is_ok());
+    let reservation = Arc::new(guard).reserve(&request(None)).unwrap();
+    reservation.commit(&image());
+}
@@ -1,5 +1,7 @@
 use async_trait::async_trait;
-use google_gemini_image_creator::application::GenerateImageUseCase;
+use google_gemini_image_creator::application::{
+    BudgetGuard, BudgetLimits, BudgetPolicy, GenerateImageUseCase,
+};
 use google_gemini_image_creator::domain::{
     GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
     ImageGenerationRequest,
@@ -61,3 +63,44 @@ async fn test_generate_image_use_case_repository_error() {
     use google_gemini_image_creator::application::use_cases::generate_image::UseCaseError;
     assert!(matches!(result.unwrap_err(), UseCaseError::Repository(_)));
 }
+
+#[tokio::test]
+async fn test_generate_image_use_case_refuses_when_budget_is_exceeded() {
+    let budget = BudgetGuard::new(
+        BudgetPolicy {
+            global: BudgetLimits {
+                daily_images: Some(1),
+                ..BudgetLimits::default()
+            },
+            ..BudgetPolicy::default()
+        },
+        std::collections::HashMap::new(),
+    );
+    let repository = BillingRepository;
+    let use_case = GenerateImageUseCase::new(repository).with_budget(std::sync::Arc::new(budget));
+
+    let first = use_case
+        .execute(ImageGenerationRequest::new("test prompt".to_string()))
+        .await;
+    assert!(first.is_ok());
+    let second = use_case
+        .execute(ImageGenerationRequest::new("test prompt".to_string()))
+        .await;
+    use google_gemini_image_creator::application::use_cases::generate_image::UseCaseError;
+    let err = second.unwrap_err();
+    assert!(matches!(err, UseCaseError::BudgetExceeded(_)));
+    assert_eq!(err.kind(), "budget_exceeded");
+}
+
+/// APIを呼んだ結果として認証情報付きの画像を返すモック
+struct BillingRepository;
+
+#[async_trait]
+impl ImageGenerationRepository for BillingRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        Ok(GeneratedImage::new(vec![1], request.model.clone()).with_api_key("…abcd".to_string()))
+    }
+}
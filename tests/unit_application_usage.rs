@@ -0,0 +1,84 @@
+use google_gemini_image_creator::application::{ModelPrice, UsageTracker};
+use google_gemini_image_creator::domain::{GeminiModel, GeneratedImage, TokenUsage};
+use std::collections::HashMap;
+
+fn image(model: &str, api_key: &str, usage: Option<TokenUsage>) -> GeneratedImage {
+    let image = GeneratedImage::new(vec![1, 2, 3], GeminiModel::from(model.to_string()))
+        .with_api_key(api_key.to_string());
+    match usage {
+        Some(usage) => image.with_usage(usage),
+        None => image,
+    }
+}
+
+fn tokens(prompt_tokens: u64, candidate_tokens: u64) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens,
+        candidate_tokens,
+        total_tokens: prompt_tokens + candidate_tokens,
+    }
+}
+
+#[test]
+fn test_record_estimates_cost_from_configured_price() {
+    let prices = HashMap::from([(
+        "gemini-2.5-flash-image".to_string(),
+        ModelPrice {
+            input_per_million_tokens: 1.0,
+            output_per_million_tokens: 10.0,
+            per_image: 0.0,
+        },
+    )]);
+    let tracker = UsageTracker::new(prices);
+
+    let entry = tracker
+        .record(&image(
+            "gemini-2.5-flash-image",
+            "…abcd",
+            Some(tokens(1_000_000, 100_000)),
+        ))
+        .unwrap();
+    assert_eq!(entry.images, 1);
+    assert_eq!(entry.total_tokens, 1_100_000);
+    assert!((entry.estimated_cost_usd - 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_report_totals_by_session_key_model_and_day() {
+    let tracker = UsageTracker::new(HashMap::new());
+    tracker.record(&image(
+        "gemini-2.5-flash-image",
+        "…aaaa",
+        Some(tokens(10, 1290)),
+    ));
+    tracker.record(&image(
+        "gemini-2.5-flash-image",
+        "…bbbb",
+        Some(tokens(20, 1290)),
+    ));
+    // Imagenはトークン数を返さないため画像単価で数える
+    tracker.record(&image("imagen-4.0-generate-001", "…aaaa", None));
+
+    let report = tracker.report();
+    assert_eq!(report.session.images, 3);
+    assert_eq!(report.session.prompt_tokens, 30);
+    assert_eq!(report.by_api_key["…aaaa"].images, 2);
+    assert_eq!(report.by_api_key["…bbbb"].images, 1);
+    assert_eq!(
+        report.by_model["gemini-2.5-flash-image"].candidate_tokens,
+        2580
+    );
+    assert!((report.by_model["imagen-4.0-generate-001"].estimated_cost_usd - 0.04).abs() < 1e-9);
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    assert_eq!(report.by_day[&today].images, 3);
+}
+
+#[test]
+fn test_images_returned_without_calling_the_api_are_not_counted() {
+    let tracker = UsageTracker::new(HashMap::new());
+    let generated = image("gemini-2.5-flash-image", "…aaaa", Some(tokens(10, 10)));
+    tracker.record(&generated);
+
+    assert!(tracker.record(&generated.without_usage()).is_none());
+    assert_eq!(tracker.report().session.images, 1);
+}
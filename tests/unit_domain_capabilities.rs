@@ -0,0 +1,134 @@
+use google_gemini_image_creator::domain::{
+    CapabilityOverride, GeminiModel, ImageGenerationRequest, InputImage, ModelCapabilities,
+    ValidationError,
+};
+use std::collections::HashMap;
+
+fn request(model: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new("a harbor at dawn".to_string())
+        .with_model(GeminiModel::from(model.to_string()))
+}
+
+fn image() -> InputImage {
+    InputImage::new(vec![1, 2, 3], "image/png".to_string())
+}
+
+#[test]
+fn test_reference_image_limits_come_from_capabilities() {
+    // proは画風の参照画像を6枚、人物などの参照画像を5枚まで
+    let pro = "gemini-3-pro-image-preview";
+    let mut request_with_refs = request(pro);
+    for _ in 0..6 {
+        request_with_refs = request_with_refs.with_style_image(image());
+    }
+    for _ in 0..5 {
+        request_with_refs = request_with_refs.with_subject_image(image());
+    }
+    assert!(request_with_refs.validate().is_ok());
+    let err = request_with_refs
+        .clone()
+        .with_subject_image(image())
+        .validate()
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("6 subject images given but at most 5"),
+        "{}",
+        err
+    );
+
+    // flash-imageは役割に関係なく合計3枚まで
+    let flash = request("gemini-2.5-flash-image")
+        .with_style_image(image())
+        .with_subject_image(image())
+        .with_input_image(image());
+    assert!(flash.validate().is_ok());
+    let err = flash.with_style_image(image()).validate().unwrap_err();
+    assert!(err.to_string().contains("at most 3"), "{}", err);
+}
+
+#[test]
+fn test_builtin_capabilities_reject_unsupported_parameters() {
+    // flash-imageは解像度を指定できず、入力画像は3枚まで
+    let flash = "gemini-2.5-flash-image";
+    assert!(request(flash)
+        .with_aspect_ratio("16:9".to_string())
+        .validate()
+        .is_ok());
+    assert!(matches!(
+        request(flash).with_image_size("4K".to_string()).validate(),
+        Err(ValidationError::UnsupportedParameter(_, _))
+    ));
+    let mut too_many = request(flash);
+    for _ in 0..4 {
+        too_many = too_many.with_input_image(image());
+    }
+    let err = too_many.validate().unwrap_err();
+    assert!(err.to_string().contains("at most 3"), "{}", err);
+
+    // proは4Kに対応する
+    assert!(request("gemini-3-pro-image-preview")
+        .with_image_size("4K".to_string())
+        .validate()
+        .is_ok());
+
+    // Imagenは入力画像に対応せず、アスペクト比も限られる
+    let imagen = "imagen-4.0-generate-001";
+    assert!(request(imagen)
+        .with_input_image(image())
+        .validate()
+        .is_err());
+    let err = request(imagen)
+        .with_aspect_ratio("21:9".to_string())
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("supported: 1:1"), "{}", err);
+    assert!(request(imagen)
+        .with_negative_prompt("text".to_string())
+        .validate()
+        .is_ok());
+    assert!(request(flash)
+        .with_negative_prompt("text".to_string())
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn test_unknown_models_are_unrestricted() {
+    let capabilities = ModelCapabilities::for_model(&GeminiModel::from("custom-model".to_string()));
+    assert_eq!(capabilities, ModelCapabilities::default());
+    assert!(request("custom-model")
+        .with_aspect_ratio("7:3".to_string())
+        .with_image_size("8K".to_string())
+        .with_input_image(image())
+        .validate()
+        .is_ok());
+}
+
+#[test]
+fn test_config_overrides_take_precedence() {
+    let overrides: HashMap<String, CapabilityOverride> = serde_json::from_value(serde_json::json!({
+        "gemini-2.5-flash-image-override-test": { "aspect_ratios": ["1:1"], "max_input_images": 0 }
+    }))
+    .unwrap();
+    ModelCapabilities::set_overrides(overrides);
+
+    let capabilities = ModelCapabilities::for_model(&GeminiModel::from(
+        "gemini-2.5-flash-image-override-test".to_string(),
+    ));
+    assert_eq!(capabilities.aspect_ratios, Some(vec!["1:1".to_string()]));
+    // 指定しなかった項目は組み込みの既定値（flash-imageは解像度を指定できない）を使う
+    assert_eq!(capabilities.image_sizes, Some(Vec::new()));
+    assert!(!capabilities.negative_prompt);
+
+    let model = "gemini-2.5-flash-image-override-test";
+    assert!(request(model)
+        .with_aspect_ratio("16:9".to_string())
+        .validate()
+        .is_err());
+    assert!(request(model).with_input_image(image()).validate().is_err());
+    assert!(request(model)
+        .with_aspect_ratio("1:1".to_string())
+        .validate()
+        .is_ok());
+}
@@ -19,6 +19,12 @@ fn test_gemini_model_display() {
     assert_eq!(model2.to_string(), "gemini-3-pro-image-preview");
 }
 
+#[test]
+fn test_gemini_model_is_imagen() {
+    assert!(GeminiModel::from("imagen-4.0-generate-001".to_string()).is_imagen());
+    assert!(!GeminiModel::from("gemini-2.5-flash-image".to_string()).is_imagen());
+}
+
 #[test]
 fn test_gemini_model_try_from() {
     // 注意: OnceLockのため、他のテストが先に実行された場合は反映されない可能性がある
@@ -174,3 +180,107 @@ fn test_generated_image_new() {
     assert_eq!(image.data, data);
     assert_eq!(image.model.as_str(), model.as_str());
 }
+
+#[test]
+fn test_generated_image_file_extension() {
+    let model = GeminiModel::from("gemini-2.5-flash-image".to_string());
+    let image = GeneratedImage::new(vec![1], model);
+    assert_eq!(image.mime_type, "image/png");
+    assert_eq!(image.file_extension(), "png");
+
+    let image = image.with_mime_type("image/jpeg".to_string());
+    assert_eq!(image.file_extension(), "jpg");
+    assert_eq!(extension_for_mime_type("application/octet-stream"), "bin");
+}
+
+#[test]
+fn test_image_generation_request_serde_round_trip() {
+    let request = ImageGenerationRequest::new("make it blue".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+        .with_input_image(InputImage::new(vec![0, 1, 2], "image/png".to_string()))
+        .with_parent_request_id("parent-id".to_string());
+
+    let json = serde_json::to_value(&request).unwrap();
+    assert_eq!(json["input_images"][0]["data"], "AAEC");
+
+    let restored: ImageGenerationRequest = serde_json::from_value(json).unwrap();
+    assert_eq!(restored, request);
+
+    // 入力画像・親のないリクエストはフィールド自体を省略する
+    let plain = ImageGenerationRequest::new("plain".to_string());
+    let json = serde_json::to_value(&plain).unwrap();
+    assert!(json.get("input_images").is_none());
+    assert_eq!(
+        serde_json::from_value::<ImageGenerationRequest>(json).unwrap(),
+        plain
+    );
+}
+
+#[test]
+fn test_image_generation_request_rerun() {
+    let request = ImageGenerationRequest::new("a fox".to_string())
+        .with_input_image(InputImage::new(vec![9; 4], "image/jpeg".to_string()));
+    let rerun = request.rerun();
+
+    assert_ne!(rerun.request_id, request.request_id);
+    assert_eq!(
+        rerun.parent_request_id.as_deref(),
+        Some(request.request_id.as_str())
+    );
+    assert_eq!(rerun.prompt, request.prompt);
+    assert_eq!(rerun.input_images, request.input_images);
+
+    let parameters = rerun.parameters();
+    assert_eq!(parameters["input_images"][0]["size_bytes"], 4);
+    assert_eq!(parameters["parent_request_id"], request.request_id.as_str());
+}
+
+#[test]
+fn test_image_generation_request_fingerprint() {
+    let model = GeminiModel::from("gemini-2.5-flash-image".to_string());
+    let base = ImageGenerationRequest::new("a fox".to_string()).with_model(model.clone());
+
+    // リクエストID・親リクエスト・キャッシュの指定は結果に影響しないため含めない
+    let same = ImageGenerationRequest::new("a fox".to_string())
+        .with_model(model.clone())
+        .with_parent_request_id("parent".to_string())
+        .with_bypass_cache(true);
+    assert_eq!(base.fingerprint(), same.fingerprint());
+    assert_eq!(base.fingerprint().len(), 64);
+
+    let other_prompt = ImageGenerationRequest::new("a wolf".to_string()).with_model(model.clone());
+    assert_ne!(base.fingerprint(), other_prompt.fingerprint());
+
+    let with_image = base
+        .clone()
+        .with_input_image(InputImage::new(vec![1], "image/png".to_string()));
+    let with_other_image = base
+        .clone()
+        .with_input_image(InputImage::new(vec![2], "image/png".to_string()));
+    assert_ne!(base.fingerprint(), with_image.fingerprint());
+    assert_ne!(with_image.fingerprint(), with_other_image.fingerprint());
+}
+
+#[test]
+fn test_reference_image_roles_are_recorded() {
+    let image = || InputImage::new(vec![1], "image/png".to_string());
+    let base = ImageGenerationRequest::new("a fox".to_string());
+    let edit = base.clone().with_input_image(image());
+    let style = base.clone().with_style_image(image());
+    let subject = base.clone().with_subject_image(image());
+
+    // 役割が違えば結果も違うため、フィンガープリントも変わる
+    assert_ne!(edit.fingerprint(), style.fingerprint());
+    assert_ne!(style.fingerprint(), subject.fingerprint());
+    assert_eq!(style.input_image_count(InputImageRole::Style), 1);
+    assert_eq!(style.input_image_count(InputImageRole::Subject), 0);
+
+    // 編集する画像は従来どおり役割を記録しない
+    let json = serde_json::to_value(&edit).unwrap();
+    assert!(json["input_images"][0].get("role").is_none());
+    let json = serde_json::to_value(&subject).unwrap();
+    assert_eq!(json["input_images"][0]["role"], "subject");
+    assert_eq!(subject.parameters()["input_images"][0]["role"], "subject");
+    let restored: ImageGenerationRequest = serde_json::from_value(json).unwrap();
+    assert_eq!(restored, subject);
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest, TokenUsage,
+};
+use google_gemini_image_creator::infrastructure::decorators::CachingRepository;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 呼び出し回数を数え、回数に応じた画像（またはエラー）を返すモック
+#[derive(Default)]
+struct CountingRepository {
+    calls: AtomicUsize,
+    fail: bool,
+}
+
+#[async_trait]
+impl ImageGenerationRepository for CountingRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) as u8;
+        if self.fail {
+            return Err(ImageGenerationError::ApiError("Mock error".to_string()));
+        }
+        Ok(
+            GeneratedImage::new(vec![call; 10], request.model.clone()).with_usage(TokenUsage {
+                prompt_tokens: 1,
+                candidate_tokens: 2,
+                total_tokens: 3,
+            }),
+        )
+    }
+}
+
+fn request(prompt: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new(prompt.to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+}
+
+#[tokio::test]
+async fn test_identical_requests_are_served_from_cache() {
+    let inner = Arc::new(CountingRepository::default());
+    let cache = CachingRepository::new(Arc::clone(&inner));
+
+    let first = cache.generate_image(&request("a cat")).await.unwrap();
+    // リクエストIDが違っても内容が同じならキャッシュを使う
+    let second = cache.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(first.data, second.data);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+    // キャッシュから返した画像の利用量は数えない
+    assert!(first.usage.is_some());
+    assert!(second.usage.is_none());
+
+    cache.generate_image(&request("a dog")).await.unwrap();
+    let other_model = request("a cat").with_model(GeminiModel::from("other".to_string()));
+    cache.generate_image(&other_model).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_bypass_cache_generates_and_refreshes() {
+    let inner = Arc::new(CountingRepository::default());
+    let cache = CachingRepository::new(Arc::clone(&inner));
+
+    cache.generate_image(&request("a cat")).await.unwrap();
+    let fresh = cache
+        .generate_image(&request("a cat").with_bypass_cache(true))
+        .await
+        .unwrap();
+    assert_eq!(fresh.data, vec![1; 10]);
+
+    // バイパスした結果でキャッシュが更新される
+    let cached = cache.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(cached.data, vec![1; 10]);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cached_results_expire_after_ttl() {
+    let inner = Arc::new(CountingRepository::default());
+    let cache = CachingRepository::new(Arc::clone(&inner)).with_ttl(Duration::from_secs(60));
+
+    cache.generate_image(&request("a cat")).await.unwrap();
+    tokio::time::advance(Duration::from_secs(59)).await;
+    cache.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+    cache.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_least_recently_used_entries_are_evicted_over_max_bytes() {
+    let inner = Arc::new(CountingRepository::default());
+    let cache = CachingRepository::new(Arc::clone(&inner)).with_max_bytes(20);
+
+    cache.generate_image(&request("one")).await.unwrap();
+    tokio::time::advance(Duration::from_millis(1)).await;
+    cache.generate_image(&request("two")).await.unwrap();
+    tokio::time::advance(Duration::from_millis(1)).await;
+    // "one"を使うと、次に追い出されるのは"two"になる
+    cache.generate_image(&request("one")).await.unwrap();
+    tokio::time::advance(Duration::from_millis(1)).await;
+    cache.generate_image(&request("three")).await.unwrap();
+    assert_eq!(cache.len().await, 2);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+
+    cache.generate_image(&request("one")).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+    cache.generate_image(&request("two")).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn test_errors_are_not_cached() {
+    let inner = Arc::new(CountingRepository {
+        fail: true,
+        ..CountingRepository::default()
+    });
+    let cache = CachingRepository::new(Arc::clone(&inner));
+
+    assert!(cache.generate_image(&request("a cat")).await.is_err());
+    assert!(cache.generate_image(&request("a cat")).await.is_err());
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    assert!(cache.is_empty().await);
+}
+
+/// 常に別のモデルで生成した画像を返すモック（フォールバックを模す）
+struct FallbackModelRepository {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl ImageGenerationRepository for FallbackModelRepository {
+    async fn generate_image(
+        &self,
+        _request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(GeneratedImage::new(
+            vec![1, 2, 3],
+            GeminiModel::from("gemini-2.0-flash-image".to_string()),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_images_from_fallback_models_are_not_cached() {
+    let inner = Arc::new(FallbackModelRepository {
+        calls: AtomicUsize::new(0),
+    });
+    let cache = CachingRepository::new(Arc::clone(&inner));
+
+    cache.generate_image(&request("a cat")).await.unwrap();
+    cache.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,128 @@
+use async_trait::async_trait;
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest,
+};
+use google_gemini_image_creator::infrastructure::decorators::CoalescingRepository;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 一定時間かかる生成を模したモック（呼び出し回数に応じた画像かエラーを返す）
+#[derive(Default)]
+struct SlowRepository {
+    calls: AtomicUsize,
+    fail: bool,
+}
+
+#[async_trait]
+impl ImageGenerationRepository for SlowRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) as u8;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        if self.fail {
+            return Err(ImageGenerationError::RateLimitError {
+                message: "Rate limit exceeded".to_string(),
+                retry_after: None,
+            });
+        }
+        Ok(GeneratedImage::new(vec![call; 4], request.model.clone()))
+    }
+}
+
+fn request(prompt: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new(prompt.to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_concurrent_identical_requests_share_one_call() {
+    let inner = Arc::new(SlowRepository::default());
+    let repository = CoalescingRepository::new(Arc::clone(&inner));
+
+    let requests = [request("a cat"), request("a cat"), request("a cat")];
+    let (a, b, c) = tokio::join!(
+        repository.generate_image(&requests[0]),
+        repository.generate_image(&requests[1]),
+        repository.generate_image(&requests[2]),
+    );
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+    assert_eq!(a.unwrap().data, vec![0; 4]);
+    assert_eq!(b.unwrap().data, vec![0; 4]);
+    assert_eq!(c.unwrap().data, vec![0; 4]);
+    assert_eq!(repository.in_flight(), 0);
+
+    // 完了した後の同じ内容のリクエストは新たに実行する（結果はキャッシュしない）
+    let later = repository.generate_image(&request("a cat")).await.unwrap();
+    assert_eq!(later.data, vec![1; 4]);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_different_and_bypassing_requests_are_not_coalesced() {
+    let inner = Arc::new(SlowRepository::default());
+    let repository = CoalescingRepository::new(Arc::clone(&inner));
+
+    let requests = [
+        request("a cat"),
+        request("a dog"),
+        request("a cat").with_bypass_cache(true),
+    ];
+    let (a, b, c) = tokio::join!(
+        repository.generate_image(&requests[0]),
+        repository.generate_image(&requests[1]),
+        repository.generate_image(&requests[2]),
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_errors_are_shared_with_all_waiters() {
+    let inner = Arc::new(SlowRepository {
+        fail: true,
+        ..SlowRepository::default()
+    });
+    let repository = CoalescingRepository::new(Arc::clone(&inner));
+
+    let requests = [request("a cat"), request("a cat")];
+    let (a, b) = tokio::join!(
+        repository.generate_image(&requests[0]),
+        repository.generate_image(&requests[1]),
+    );
+    assert!(matches!(
+        a,
+        Err(ImageGenerationError::RateLimitError { .. })
+    ));
+    assert!(matches!(
+        b,
+        Err(ImageGenerationError::RateLimitError { .. })
+    ));
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_waiter_takes_over_when_first_caller_is_cancelled() {
+    let inner = Arc::new(SlowRepository::default());
+    let repository = Arc::new(CoalescingRepository::new(Arc::clone(&inner)));
+
+    let first = tokio::spawn({
+        let repository = Arc::clone(&repository);
+        async move { repository.generate_image(&request("a cat")).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let second = tokio::spawn({
+        let repository = Arc::clone(&repository);
+        async move { repository.generate_image(&request("a cat")).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    first.abort();
+
+    let image = second.await.unwrap().unwrap();
+    assert_eq!(image.data, vec![1; 4]);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    assert_eq!(repository.in_flight(), 0);
+}
@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use google_gemini_image_creator::config::FallbackTriggers;
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest,
+};
+use google_gemini_image_creator::infrastructure::decorators::FallbackRepository;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const PRO: &str = "gemini-3-pro-image-preview";
+const FLASH: &str = "gemini-2.5-flash-image";
+const LEGACY: &str = "gemini-2.0-flash-image";
+
+/// モデルごとに決まったエラーを返すモック（呼び出されたモデルを記録する）
+#[derive(Default)]
+struct PerModelRepository {
+    failures: HashMap<String, ImageGenerationError>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl PerModelRepository {
+    fn failing(failures: &[(&str, ImageGenerationError)]) -> Self {
+        Self {
+            failures: failures
+                .iter()
+                .map(|(model, error)| (model.to_string(), error.clone()))
+                .collect(),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl ImageGenerationRepository for PerModelRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        let model = request.model.as_str().to_string();
+        self.calls.lock().unwrap().push(model.clone());
+        match self.failures.get(&model) {
+            Some(error) => Err(error.clone()),
+            None => Ok(GeneratedImage::new(vec![1, 2, 3], request.model.clone())),
+        }
+    }
+}
+
+fn chain() -> Vec<String> {
+    vec![PRO.to_string(), FLASH.to_string(), LEGACY.to_string()]
+}
+
+fn request(model: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new("a lighthouse".to_string())
+        .with_model(GeminiModel::from(model.to_string()))
+}
+
+fn overloaded() -> ImageGenerationError {
+    ImageGenerationError::ModelOverloaded("model is unavailable".to_string())
+}
+
+#[tokio::test]
+async fn test_falls_back_to_next_model_when_overloaded() {
+    let inner = Arc::new(PerModelRepository::failing(&[(PRO, overloaded())]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+
+    let image = repository.generate_image(&request(PRO)).await.unwrap();
+    assert_eq!(image.model.as_str(), FLASH);
+    assert_eq!(inner.calls(), vec![PRO, FLASH]);
+}
+
+#[tokio::test]
+async fn test_walks_chain_and_returns_last_error() {
+    let inner = Arc::new(PerModelRepository::failing(&[
+        (PRO, overloaded()),
+        (
+            FLASH,
+            ImageGenerationError::RateLimitError {
+                message: "quota".to_string(),
+                retry_after: None,
+            },
+        ),
+        (LEGACY, ImageGenerationError::NoImage("blocked".to_string())),
+    ]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+
+    let error = repository.generate_image(&request(PRO)).await.unwrap_err();
+    assert_eq!(error.kind(), "no_image");
+    assert_eq!(inner.calls(), vec![PRO, FLASH, LEGACY]);
+}
+
+#[tokio::test]
+async fn test_does_not_fall_back_on_other_errors() {
+    let inner = Arc::new(PerModelRepository::failing(&[(
+        PRO,
+        ImageGenerationError::InvalidPromptError("unsafe".to_string()),
+    )]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+
+    let error = repository.generate_image(&request(PRO)).await.unwrap_err();
+    assert_eq!(error.kind(), "invalid_prompt");
+    assert_eq!(inner.calls(), vec![PRO]);
+}
+
+#[tokio::test]
+async fn test_only_configured_triggers_fall_back() {
+    let inner = Arc::new(PerModelRepository::failing(&[(
+        PRO,
+        ImageGenerationError::NoImage("blocked".to_string()),
+    )]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain())
+        .with_triggers(FallbackTriggers::from_kinds(["overloaded", "rate_limit"]));
+
+    let error = repository.generate_image(&request(PRO)).await.unwrap_err();
+    assert_eq!(error.kind(), "no_image");
+    assert_eq!(inner.calls(), vec![PRO]);
+}
+
+#[tokio::test]
+async fn test_chain_order_starts_after_requested_model() {
+    // チェーンの最後のモデルはフォールバック先がない
+    let inner = Arc::new(PerModelRepository::failing(&[(LEGACY, overloaded())]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+    assert!(repository.generate_image(&request(LEGACY)).await.is_err());
+    assert_eq!(inner.calls(), vec![LEGACY]);
+
+    // チェーンにないモデルはチェーンの先頭から試す
+    let inner = Arc::new(PerModelRepository::failing(&[
+        ("custom-model", overloaded()),
+        (PRO, overloaded()),
+    ]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+    let image = repository
+        .generate_image(&request("custom-model"))
+        .await
+        .unwrap();
+    assert_eq!(image.model.as_str(), FLASH);
+    assert_eq!(inner.calls(), vec!["custom-model", PRO, FLASH]);
+}
+
+#[tokio::test]
+async fn test_skips_fallback_models_that_do_not_support_the_request() {
+    let inner = Arc::new(PerModelRepository::failing(&[(PRO, overloaded())]));
+    let repository = FallbackRepository::new(Arc::clone(&inner), chain());
+
+    // 4Kに対応するのはProのみ
+    let mut request = request(PRO);
+    request.image_size = Some("4K".to_string());
+    let err = repository.generate_image(&request).await.unwrap_err();
+    assert_eq!(err.kind(), "overloaded");
+    assert_eq!(inner.calls(), vec![PRO]);
+}
@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use google_gemini_image_creator::config::RateLimits;
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationError, ImageGenerationRepository,
+    ImageGenerationRequest,
+};
+use google_gemini_image_creator::infrastructure::decorators::RateLimitedRepository;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 呼び出し回数と同時実行数の最大値を記録するモック
+#[derive(Default)]
+struct RecordingRepository {
+    calls: AtomicUsize,
+    running: AtomicUsize,
+    max_running: AtomicUsize,
+}
+
+#[async_trait]
+impl ImageGenerationRepository for RecordingRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(running, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        Ok(GeneratedImage::new(vec![1], request.model.clone()))
+    }
+}
+
+fn request(model: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new("a cat".to_string())
+        .with_model(GeminiModel::from(model.to_string()))
+}
+
+const MODEL: &str = "gemini-2.5-flash-image";
+
+#[tokio::test(start_paused = true)]
+async fn test_requests_per_minute_are_queued() {
+    let inner = Arc::new(RecordingRepository::default());
+    let limiter = RateLimitedRepository::new(
+        Arc::clone(&inner),
+        RateLimits {
+            requests_per_minute: Some(2),
+            ..RateLimits::default()
+        },
+    );
+
+    let started = Instant::now();
+    limiter.generate_image(&request(MODEL)).await.unwrap();
+    limiter.generate_image(&request(MODEL)).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // 2回使い切った後は、1つ分（30秒）補充されるまで待つ
+    let queued = Instant::now();
+    limiter.generate_image(&request(MODEL)).await.unwrap();
+    let waited = queued.elapsed();
+    assert!(waited >= Duration::from_secs(27), "waited {:?}", waited);
+    assert!(waited < Duration::from_secs(32), "waited {:?}", waited);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_wait_beyond_max_wait_returns_rate_limit_error_with_estimate() {
+    let inner = Arc::new(RecordingRepository::default());
+    let limiter = RateLimitedRepository::new(
+        Arc::clone(&inner),
+        RateLimits {
+            images_per_day: Some(1),
+            ..RateLimits::default()
+        },
+    )
+    .with_max_wait(Duration::from_secs(10));
+
+    limiter.generate_image(&request(MODEL)).await.unwrap();
+    let err = limiter.generate_image(&request(MODEL)).await.unwrap_err();
+
+    assert_eq!(err.kind(), "rate_limit");
+    let retry_after = err.retry_after().unwrap();
+    assert!(retry_after > Duration::from_secs(86_000));
+    let message = err.to_string();
+    assert!(message.contains("images per day"), "{}", message);
+    assert!(message.contains("estimated wait"), "{}", message);
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_max_concurrency_is_enforced() {
+    let inner = Arc::new(RecordingRepository::default());
+    let limiter = RateLimitedRepository::new(
+        Arc::clone(&inner),
+        RateLimits {
+            max_concurrency: Some(1),
+            ..RateLimits::default()
+        },
+    );
+
+    let requests = [request(MODEL), request(MODEL), request(MODEL)];
+    let (a, b, c) = tokio::join!(
+        limiter.generate_image(&requests[0]),
+        limiter.generate_image(&requests[1]),
+        limiter.generate_image(&requests[2]),
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+    assert_eq!(inner.max_running.load(Ordering::SeqCst), 1);
+
+    // 空きを待つ時間が上限を超えた場合はエラー
+    let limiter = RateLimitedRepository::new(
+        Arc::clone(&inner),
+        RateLimits {
+            max_concurrency: Some(1),
+            ..RateLimits::default()
+        },
+    )
+    .with_max_wait(Duration::from_millis(500));
+    let (first, second) = tokio::join!(
+        limiter.generate_image(&requests[0]),
+        limiter.generate_image(&requests[1]),
+    );
+    assert!(first.is_ok());
+    let err = second.unwrap_err();
+    assert!(err.to_string().contains("1 concurrent requests"));
+    assert_eq!(err.retry_after(), Some(Duration::from_millis(500)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_limits_are_tracked_per_model_with_overrides() {
+    let inner = Arc::new(RecordingRepository::default());
+    let one_per_day = RateLimits {
+        images_per_day: Some(1),
+        ..RateLimits::default()
+    };
+    let limiter = RateLimitedRepository::new(Arc::clone(&inner), one_per_day)
+        .with_model_limits("unlimited-model", RateLimits::default())
+        .with_max_wait(Duration::ZERO);
+
+    limiter.generate_image(&request(MODEL)).await.unwrap();
+    assert!(limiter.generate_image(&request(MODEL)).await.is_err());
+
+    // 別のモデルは別のバケットを使う
+    limiter
+        .generate_image(&request("other-model"))
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        limiter
+            .generate_image(&request("unlimited-model"))
+            .await
+            .unwrap();
+    }
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 5);
+}
@@ -0,0 +1,314 @@
+use google_gemini_image_creator::domain::{GeminiModel, ImageGenerationRequest};
+use google_gemini_image_creator::infrastructure::gemini::{
+    ApiKeyPool, BatchClient, BatchInputMode, BatchState,
+};
+use mockito::Matcher;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn client(server: &mockito::Server) -> BatchClient {
+    BatchClient::new(
+        Arc::new(ApiKeyPool::new(vec!["batch-key".to_string()])),
+        server.url(),
+    )
+    .with_poll_interval(Duration::from_millis(10))
+}
+
+fn request(prompt: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new(prompt.to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+}
+
+fn image_response() -> serde_json::Value {
+    serde_json::json!({
+        "candidates": [{
+            "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }] }
+        }],
+        "usageMetadata": { "promptTokenCount": 5, "candidatesTokenCount": 1290, "totalTokenCount": 1295 }
+    })
+}
+
+#[tokio::test]
+async fn test_batch_client_submits_inline_requests_and_decodes_results() {
+    let mut server = mockito::Server::new_async().await;
+    let requests = vec![request("a red fox"), request("a blue whale")];
+    let create = server
+        .mock(
+            "POST",
+            "/models/gemini-2.5-flash-image:batchGenerateContent",
+        )
+        .match_header("x-goog-api-key", "batch-key")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "batch": {
+                "displayName": "catalog",
+                "inputConfig": { "requests": { "requests": [
+                    {
+                        "request": { "contents": [{ "parts": [{ "text": "a red fox" }] }] },
+                        "metadata": { "key": requests[0].request_id }
+                    },
+                    {
+                        "request": { "contents": [{ "parts": [{ "text": "a blue whale" }] }] },
+                        "metadata": { "key": requests[1].request_id }
+                    }
+                ] } }
+            }
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "name": "batches/abc123",
+                "metadata": {
+                    "model": "models/gemini-2.5-flash-image",
+                    "displayName": "catalog",
+                    "state": "BATCH_STATE_PENDING"
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/batches/abc123")
+        .match_header("x-goog-api-key", "batch-key")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "name": "batches/abc123",
+                "done": true,
+                "metadata": {
+                    "model": "models/gemini-2.5-flash-image",
+                    "state": "BATCH_STATE_SUCCEEDED",
+                    "batchStats": { "requestCount": "2", "successfulRequestCount": "1", "failedRequestCount": "1" }
+                },
+                "response": {
+                    "inlinedResponses": { "inlinedResponses": [
+                        { "response": image_response(), "metadata": { "key": requests[0].request_id } },
+                        { "error": { "code": 3, "message": "prompt blocked" }, "metadata": { "key": requests[1].request_id } }
+                    ] }
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = client(&server);
+    let job = client
+        .submit(&requests, "catalog", BatchInputMode::Auto)
+        .await
+        .unwrap();
+    create.assert_async().await;
+    assert_eq!(job.name, "batches/abc123");
+    assert_eq!(job.state, BatchState::Pending);
+
+    let job = client.wait(&job.name).await.unwrap();
+    assert_eq!(job.state, BatchState::Succeeded);
+    assert_eq!(job.request_count, Some(2));
+    assert_eq!(job.failed_count, Some(1));
+
+    let results = client.results(&job).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].key, requests[0].request_id);
+    let image = results[0].result.as_ref().unwrap();
+    assert_eq!(image.data, vec![1, 2, 3]);
+    assert_eq!(image.model.as_str(), "gemini-2.5-flash-image");
+    assert_eq!(image.usage.unwrap().candidate_tokens, 1290);
+    assert!(image.api_key.is_some());
+    let err = results[1].result.as_ref().unwrap_err();
+    assert!(err.to_string().contains("prompt blocked"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_batch_client_uploads_file_input_and_downloads_results() {
+    let mut server = mockito::Server::new_async().await;
+    let requests = vec![request("a red fox")];
+    let start = server
+        .mock("POST", "/upload/v1beta/files")
+        .match_header("x-goog-upload-protocol", "resumable")
+        .match_header("x-goog-upload-command", "start")
+        .match_header("x-goog-upload-header-content-type", "application/jsonl")
+        .with_header(
+            "x-goog-upload-url",
+            &format!("{}/upload-session/1", server.url()),
+        )
+        .create_async()
+        .await;
+    let upload = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-command", "upload, finalize")
+        .match_body(Matcher::Regex(format!(
+            r#"^\{{"key":"{}","request":\{{.*"text":"a red fox".*\}}\}}\n$"#,
+            requests[0].request_id
+        )))
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"file":{"name":"files/input-1","uri":"https://example.com/files/input-1"}}"#)
+        .create_async()
+        .await;
+    server
+        .mock(
+            "POST",
+            "/models/gemini-2.5-flash-image:batchGenerateContent",
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "batch": { "inputConfig": { "fileName": "files/input-1" } }
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"name":"batches/file1","metadata":{"state":"BATCH_STATE_RUNNING"}}"#)
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/batches/file1")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "name": "batches/file1",
+                "done": true,
+                "metadata": {
+                    "model": "models/gemini-2.5-flash-image",
+                    "state": "BATCH_STATE_SUCCEEDED",
+                    "output": { "responsesFile": "files/output-1" }
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/download/v1beta/files/output-1:download")
+        .match_query(Matcher::UrlEncoded("alt".to_string(), "media".to_string()))
+        .with_body(format!(
+            "{}\n",
+            serde_json::json!({ "key": requests[0].request_id, "response": image_response() })
+        ))
+        .create_async()
+        .await;
+
+    let client = client(&server);
+    let job = client
+        .submit(&requests, "catalog", BatchInputMode::File)
+        .await
+        .unwrap();
+    start.assert_async().await;
+    upload.assert_async().await;
+    assert_eq!(job.state, BatchState::Running);
+
+    let job = client.get("batches/file1").await.unwrap();
+    assert_eq!(job.responses_file.as_deref(), Some("files/output-1"));
+    let results = client.results(&job).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].key, requests[0].request_id);
+    assert_eq!(results[0].result.as_ref().unwrap().data, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_batch_client_rejects_invalid_batches_before_calling_the_api() {
+    let server = mockito::Server::new_async().await;
+    let client = client(&server);
+
+    let mixed = vec![
+        request("a"),
+        ImageGenerationRequest::new("b".to_string())
+            .with_model(GeminiModel::from("gemini-3-pro-image-preview".to_string())),
+    ];
+    let err = client
+        .submit(&mixed, "mixed", BatchInputMode::Inline)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("same model"), "{}", err);
+
+    let imagen = vec![ImageGenerationRequest::new("a".to_string())
+        .with_model(GeminiModel::from("imagen-4.0-generate-001".to_string()))];
+    let err = client
+        .submit(&imagen, "imagen", BatchInputMode::Inline)
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("does not support batch"),
+        "{}",
+        err
+    );
+
+    let err = client.get("batches/../models").await.unwrap_err();
+    assert!(err.to_string().contains("Invalid batch name"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_batch_client_results_require_a_finished_batch() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/batches/running")
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"name":"batches/running","metadata":{"state":"JOB_STATE_RUNNING"}}"#)
+        .create_async()
+        .await;
+    let client = client(&server);
+
+    let job = client.get("batches/running").await.unwrap();
+    assert_eq!(job.state, BatchState::Running);
+    assert!(!job.state.is_finished());
+    let err = client.results(&job).await.unwrap_err();
+    assert!(err.to_string().contains("not finished"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_batch_client_wait_gives_up_after_the_timeout() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/batches/slow")
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"name":"batches/slow","metadata":{"state":"BATCH_STATE_RUNNING"}}"#)
+        .create_async()
+        .await;
+    let client = client(&server).with_wait_timeout(Duration::from_millis(50));
+
+    let err = client.wait("batches/slow").await.unwrap_err();
+    assert!(err.to_string().contains("did not finish"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_batch_client_unavailable_is_not_a_model_overload() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/batches/b1")
+        .with_status(503)
+        .with_body("try again later")
+        .create_async()
+        .await;
+    let client = client(&server);
+
+    let err = client.get("batches/b1").await.unwrap_err();
+    assert_eq!(err.kind(), "api");
+    assert!(
+        !err.to_string().contains("batches is unavailable"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_batch_client_restores_the_key_that_created_the_batch() {
+    use google_gemini_image_creator::infrastructure::gemini::key_pool::key_id;
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("GET", "/batches/b1")
+        .match_header("x-goog-api-key", "second-key")
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"name":"batches/b1","metadata":{"state":"BATCH_STATE_PENDING"}}"#)
+        .expect(2)
+        .create_async()
+        .await;
+    let pool = Arc::new(ApiKeyPool::new(vec![
+        "first-key".to_string(),
+        "second-key".to_string(),
+    ]));
+    let client = BatchClient::new(pool, server.url());
+
+    assert!(!client.restore_key("batches/b1", &key_id("unknown-key")));
+    assert!(client.restore_key("batches/b1", &key_id("second-key")));
+    assert_eq!(client.key_id("batches/b1"), Some(key_id("second-key")));
+    for _ in 0..2 {
+        client.get("batches/b1").await.unwrap();
+    }
+    mock.assert_async().await;
+}
@@ -0,0 +1,195 @@
+use google_gemini_image_creator::domain::GeminiModel;
+use google_gemini_image_creator::infrastructure::gemini::{ApiKeyPool, ModelCatalog};
+use std::sync::Arc;
+
+fn catalog(server: &mockito::Server) -> ModelCatalog {
+    ModelCatalog::new(
+        Arc::new(ApiKeyPool::new(vec!["catalog-key".to_string()])),
+        server.url(),
+    )
+}
+
+#[tokio::test]
+async fn test_catalog_lists_image_models_across_pages_and_caches() {
+    let mut server = mockito::Server::new_async().await;
+    let first = server
+        .mock("GET", "/models")
+        .match_header("x-goog-api-key", "catalog-key")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "pageSize".to_string(),
+            "1000".to_string(),
+        ))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "models": [
+                    {
+                        "name": "models/gemini-2.5-flash-image",
+                        "displayName": "Nano Banana",
+                        "description": "Image generation and editing",
+                        "inputTokenLimit": 32768,
+                        "outputTokenLimit": 32768,
+                        "supportedGenerationMethods": ["generateContent", "countTokens"]
+                    },
+                    {
+                        "name": "models/gemini-2.5-flash",
+                        "supportedGenerationMethods": ["generateContent"]
+                    }
+                ],
+                "nextPageToken": "page-2"
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create_async()
+        .await;
+    let second = server
+        .mock("GET", "/models")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "pageToken".to_string(),
+            "page-2".to_string(),
+        ))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "models": [
+                    { "name": "models/imagen-4.0-generate-001", "supportedGenerationMethods": ["predict"] },
+                    { "name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"] }
+                ]
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create_async()
+        .await;
+
+    let catalog = catalog(&server);
+    let models = catalog.models().await.unwrap();
+    let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["gemini-2.5-flash-image", "imagen-4.0-generate-001"]
+    );
+    assert_eq!(models[0].display_name.as_deref(), Some("Nano Banana"));
+    assert_eq!(models[0].input_token_limit, Some(32768));
+
+    // 2回目はキャッシュから返す
+    assert_eq!(catalog.models().await.unwrap(), models);
+    assert_eq!(
+        catalog
+            .contains(&GeminiModel::from("imagen-4.0-generate-001".to_string()))
+            .await,
+        Some(true)
+    );
+    assert_eq!(
+        catalog
+            .contains(&GeminiModel::from("gemini-2.5-flash".to_string()))
+            .await,
+        Some(false)
+    );
+    first.assert_async().await;
+    second.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_catalog_propagates_api_errors() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_status(401)
+        .create_async()
+        .await;
+
+    let catalog = catalog(&server);
+    let error = catalog.models().await.unwrap_err();
+    assert_eq!(error.kind(), "authentication");
+    // 一覧を取得できない場合はモデルを検証しない
+    assert_eq!(
+        catalog
+            .contains(&GeminiModel::from("gemini-2.5-flash-image".to_string()))
+            .await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_catalog_caches_failures_and_skips_validation_without_refetching() {
+    let mut server = mockito::Server::new_async().await;
+    let failing = server
+        .mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_status(500)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let catalog = catalog(&server);
+    let model = GeminiModel::from("gemini-2.5-flash-image".to_string());
+    assert_eq!(catalog.contains(&model).await, None);
+    // 失敗した直後は再取得せず、許可リストだけで判断する
+    assert_eq!(catalog.contains(&model).await, None);
+    assert!(catalog.models().await.is_err());
+    assert_eq!(catalog.cached_names(), None);
+    failing.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_catalog_notifies_when_the_model_names_change() {
+    let mut server = mockito::Server::new_async().await;
+    let body = |names: &[&str]| {
+        let models: Vec<serde_json::Value> = names
+            .iter()
+            .map(|name| {
+                serde_json::json!({
+                    "name": format!("models/{}", name),
+                    "supportedGenerationMethods": ["generateContent"]
+                })
+            })
+            .collect();
+        serde_json::json!({ "models": models }).to_string()
+    };
+    let first = server
+        .mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_body(body(&["gemini-2.5-flash-image"]))
+        .expect(2)
+        .create_async()
+        .await;
+
+    let catalog = catalog(&server);
+    let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&changes);
+    catalog.set_change_handler(move |models| {
+        let names: Vec<String> = models.iter().map(|m| m.name.clone()).collect();
+        recorded.lock().unwrap().push(names);
+    });
+
+    catalog.models().await.unwrap();
+    catalog.refresh().await.unwrap();
+    first.assert_async().await;
+    first.remove_async().await;
+
+    server
+        .mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_body(body(&[
+            "gemini-2.5-flash-image",
+            "gemini-3-pro-image-preview",
+        ]))
+        .create_async()
+        .await;
+    catalog.refresh().await.unwrap();
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            vec!["gemini-2.5-flash-image".to_string()],
+            vec![
+                "gemini-2.5-flash-image".to_string(),
+                "gemini-3-pro-image-preview".to_string()
+            ],
+        ]
+    );
+    assert_eq!(catalog.cached_names().unwrap().len(), 2);
+}
@@ -1,5 +1,8 @@
-use google_gemini_image_creator::domain::GeminiModel;
-use google_gemini_image_creator::infrastructure::gemini::GeminiClient;
+use google_gemini_image_creator::domain::{
+    GeminiModel, ImageGenerationRepository, ImageGenerationRequest, InputImage,
+};
+use google_gemini_image_creator::infrastructure::gemini::{ApiKeyPool, GeminiClient};
+use std::sync::Arc;
 
 #[tokio::test]
 async fn test_gemini_client_build_url() {
@@ -15,3 +18,425 @@ async fn test_gemini_client_authentication_error() {
     // 統合テストで実装
     // mockitoのAPIが変更されたため、実際のAPI呼び出しテストは統合テストで行う
 }
+
+#[tokio::test]
+async fn test_gemini_client_sends_input_images_as_inline_data() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_query(mockito::Matcher::Any)
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "contents": [{
+                "parts": [
+                    { "text": "make the sky purple" },
+                    { "inlineData": { "mimeType": "image/png", "data": "AQID" } }
+                ]
+            }]
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": {
+                        "parts": [{ "inlineData": { "mimeType": "image/png", "data": "BAUG" } }]
+                    }
+                }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url());
+    let request = ImageGenerationRequest::new("make the sky purple".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+        .with_input_image(InputImage::new(vec![1, 2, 3], "image/png".to_string()));
+
+    let image = client.generate_image(&request).await.unwrap();
+    assert_eq!(image.data, vec![4, 5, 6]);
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gemini_client_labels_reference_images_before_the_instruction() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/models/gemini-3-pro-image-preview:generateContent")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "contents": [{
+                "parts": [
+                    { "text": "Style reference image 1: match its visual style, color palette and rendering, not its content." },
+                    { "inlineData": { "mimeType": "image/png", "data": "AQ==" } },
+                    { "text": "Subject reference image 1: keep this subject's identity and appearance consistent in the generated image." },
+                    { "inlineData": { "mimeType": "image/jpeg", "data": "Ag==" } },
+                    { "text": "Subject reference image 2: keep this subject's identity and appearance consistent in the generated image." },
+                    { "inlineData": { "mimeType": "image/png", "data": "Aw==" } },
+                    { "text": "Instruction: the mascot waving on a beach" },
+                    { "inlineData": { "mimeType": "image/png", "data": "BA==" } }
+                ]
+            }]
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "BAUG" } }] }
+                }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url());
+    // 参照画像は役割ごとに番号を振り、編集する画像は指示の後に添付する
+    let request = ImageGenerationRequest::new("the mascot waving on a beach".to_string())
+        .with_model(GeminiModel::from("gemini-3-pro-image-preview".to_string()))
+        .with_subject_image(InputImage::new(vec![2], "image/jpeg".to_string()))
+        .with_input_image(InputImage::new(vec![4], "image/png".to_string()))
+        .with_style_image(InputImage::new(vec![1], "image/png".to_string()))
+        .with_subject_image(InputImage::new(vec![3], "image/png".to_string()));
+
+    client.generate_image(&request).await.unwrap();
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gemini_client_rate_limit_includes_retry_after() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_query(mockito::Matcher::Any)
+        .with_status(429)
+        .with_header("retry-after", "17")
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url());
+    let request = ImageGenerationRequest::new("a cat".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+
+    let err = client.generate_image(&request).await.unwrap_err();
+    assert_eq!(err.retry_after(), Some(std::time::Duration::from_secs(17)));
+}
+
+#[tokio::test]
+async fn test_gemini_client_fails_over_to_next_key() {
+    let mut server = mockito::Server::new_async().await;
+    let throttled = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_header("x-goog-api-key", "first-key")
+        .with_status(429)
+        .create_async()
+        .await;
+    let succeeded = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_header("x-goog-api-key", "second-key")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }] }
+                }]
+            })
+            .to_string(),
+        )
+        .expect(2)
+        .create_async()
+        .await;
+
+    let pool = Arc::new(ApiKeyPool::new(vec![
+        "first-key".to_string(),
+        "second-key".to_string(),
+    ]));
+    let client = GeminiClient::with_key_pool(Arc::clone(&pool), server.url());
+    let request = ImageGenerationRequest::new("a cat".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+
+    assert_eq!(
+        client.generate_image(&request).await.unwrap().data,
+        vec![1, 2, 3]
+    );
+    // 隔離されたキーは使われない
+    client.generate_image(&request).await.unwrap();
+
+    throttled.assert_async().await;
+    succeeded.assert_async().await;
+    let metrics = pool.metrics();
+    assert_eq!(metrics[0].throttled, 1);
+    assert_eq!(metrics[1].successes, 2);
+}
+
+#[tokio::test]
+async fn test_gemini_client_quarantines_only_invalid_keys() {
+    let mut server = mockito::Server::new_async().await;
+    let invalid = server
+        .mock("POST", "/models/gemini-3-pro-image-preview:generateContent")
+        .match_header("x-goog-api-key", "first-key")
+        .with_status(400)
+        .with_body(r#"{"error":{"code":400,"status":"INVALID_ARGUMENT","details":[{"reason":"API_KEY_INVALID"}]}}"#)
+        .expect(1)
+        .create_async()
+        .await;
+    let forbidden = server
+        .mock("POST", "/models/gemini-3-pro-image-preview:generateContent")
+        .match_header("x-goog-api-key", "second-key")
+        .with_status(403)
+        .with_body(r#"{"error":{"code":403,"status":"PERMISSION_DENIED"}}"#)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let pool = Arc::new(ApiKeyPool::new(vec![
+        "first-key".to_string(),
+        "second-key".to_string(),
+    ]));
+    let client = GeminiClient::with_key_pool(Arc::clone(&pool), server.url());
+    let request = ImageGenerationRequest::new("a cat".to_string())
+        .with_model(GeminiModel::from("gemini-3-pro-image-preview".to_string()));
+
+    // 無効なキーは隔離し、モデルの権限がないキーは隔離せずにエラーを返す
+    for _ in 0..2 {
+        let err = client.generate_image(&request).await.unwrap_err();
+        assert!(
+            err.to_string().contains("not permitted to use this model"),
+            "{}",
+            err
+        );
+        assert!(!err.is_invalid_api_key());
+    }
+
+    invalid.assert_async().await;
+    forbidden.assert_async().await;
+    let metrics = pool.metrics();
+    assert!(metrics[0].quarantined_for_secs.is_some());
+    assert_eq!(metrics[1].quarantined_for_secs, None);
+}
+
+#[tokio::test]
+async fn test_gemini_client_sends_api_key_in_header_not_url() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_query(mockito::Matcher::Missing)
+        .match_header("x-goog-api-key", "header-only-key")
+        .with_status(500)
+        .with_body("internal")
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("header-only-key".to_string(), server.url());
+    let request = ImageGenerationRequest::new("a boat".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+    let error = client.generate_image(&request).await.unwrap_err();
+    assert_eq!(error.kind(), "api");
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gemini_client_network_error_omits_url() {
+    // 接続できないアドレスに送り、エラーメッセージにURLが含まれないことを確認する
+    let client = GeminiClient::with_base_url(
+        "unreachable-secret-key".to_string(),
+        "http://127.0.0.1:9/v1beta".to_string(),
+    );
+    let request = ImageGenerationRequest::new("a boat".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+    let error = client.generate_image(&request).await.unwrap_err();
+    assert_eq!(error.kind(), "network");
+    let message = error.to_string();
+    assert!(!message.contains("127.0.0.1"), "{}", message);
+    assert!(!message.contains("unreachable-secret-key"), "{}", message);
+}
+
+#[tokio::test]
+async fn test_gemini_client_sends_aspect_ratio_in_generation_config() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "generationConfig": { "imageConfig": { "aspectRatio": "9:16" } }
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"candidates":[{"content":{"parts":[{"inlineData":{"mimeType":"image/png","data":"AQID"}}]}}]}"#,
+        )
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url());
+    let request = ImageGenerationRequest::new("a tall tower".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+        .with_aspect_ratio("9:16".to_string());
+    client.generate_image(&request).await.unwrap();
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gemini_client_parses_usage_metadata() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": {
+                        "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }]
+                    }
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 12,
+                    "candidatesTokenCount": 1290,
+                    "totalTokenCount": 1302
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key-1234".to_string(), server.url());
+    let request = ImageGenerationRequest::new("a fox".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+    let image = client.generate_image(&request).await.unwrap();
+
+    let usage = image.usage.unwrap();
+    assert_eq!(usage.prompt_tokens, 12);
+    assert_eq!(usage.candidate_tokens, 1290);
+    assert_eq!(usage.total_tokens, 1302);
+    assert_eq!(image.api_key.as_deref(), Some("…1234"));
+}
+
+#[tokio::test]
+async fn test_gemini_client_uploads_large_input_images_once_and_sends_file_data() {
+    let mut server = mockito::Server::new_async().await;
+    let start = server
+        .mock("POST", "/upload/v1beta/files")
+        .match_header("x-goog-upload-command", "start")
+        .match_header("x-goog-upload-header-content-type", "image/png")
+        .with_header(
+            "x-goog-upload-url",
+            &format!("{}/upload-session/1", server.url()),
+        )
+        .expect(1)
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/upload-session/1")
+        .match_body(vec![7u8; 16])
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "file": {
+                    "name": "files/big",
+                    "uri": "https://example.com/v1beta/files/big",
+                    "mimeType": "image/png"
+                }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let generate = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "contents": [{
+                "parts": [
+                    { "text": "blend these" },
+                    { "fileData": { "mimeType": "image/png", "fileUri": "https://example.com/v1beta/files/big" } },
+                    { "inlineData": { "mimeType": "image/png", "data": "AQID" } }
+                ]
+            }]
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": {
+                        "parts": [{ "inlineData": { "mimeType": "image/png", "data": "BAUG" } }]
+                    }
+                }]
+            })
+            .to_string(),
+        )
+        .expect(2)
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url())
+        .with_file_upload_threshold(8);
+    let request = ImageGenerationRequest::new("blend these".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+        .with_input_image(InputImage::new(vec![7; 16], "image/png".to_string()))
+        .with_input_image(InputImage::new(vec![1, 2, 3], "image/png".to_string()));
+
+    // 2回目はアップロード済みのファイルを再利用する
+    for _ in 0..2 {
+        let image = client.generate_image(&request).await.unwrap();
+        assert_eq!(image.data, vec![4, 5, 6]);
+    }
+    start.assert_async().await;
+    generate.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_gemini_client_uploads_again_when_a_reused_file_has_expired() {
+    let mut server = mockito::Server::new_async().await;
+    let start = server
+        .mock("POST", "/upload/v1beta/files")
+        .match_header("x-goog-upload-command", "start")
+        .with_header(
+            "x-goog-upload-url",
+            &format!("{}/upload-session/1", server.url()),
+        )
+        .expect(2)
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/upload-session/1")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "file": { "name": "files/big", "uri": "https://example.com/v1beta/files/big", "mimeType": "image/png" }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let expired = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .with_status(404)
+        .with_body("File files/big was not found")
+        .expect(1)
+        .create_async()
+        .await;
+    let generate = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "candidates": [{
+                    "content": {
+                        "parts": [{ "inlineData": { "mimeType": "image/png", "data": "BAUG" } }]
+                    }
+                }]
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = GeminiClient::with_base_url("test-key".to_string(), server.url())
+        .with_file_upload_threshold(8);
+    let request = ImageGenerationRequest::new("blend these".to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()))
+        .with_input_image(InputImage::new(vec![7; 16], "image/png".to_string()));
+
+    let image = client.generate_image(&request).await.unwrap();
+    assert_eq!(image.data, vec![4, 5, 6]);
+    start.assert_async().await;
+    expired.assert_async().await;
+    generate.assert_async().await;
+}
@@ -0,0 +1,260 @@
+use google_gemini_image_creator::infrastructure::gemini::FilesClient;
+use mockito::Matcher;
+
+/// アップロードの開始（セッションのURLを返す）
+async fn mock_start(server: &mut mockito::Server, expected: usize) -> mockito::Mock {
+    server
+        .mock("POST", "/upload/v1beta/files")
+        .match_header("x-goog-upload-protocol", "resumable")
+        .match_header("x-goog-upload-command", "start")
+        .with_header(
+            "x-goog-upload-url",
+            &format!("{}/upload-session/1", server.url()),
+        )
+        .expect(expected)
+        .create_async()
+        .await
+}
+
+fn file_body(expiration_time: &str) -> String {
+    serde_json::json!({
+        "file": {
+            "name": "files/abc",
+            "uri": "https://generativelanguage.googleapis.com/v1beta/files/abc",
+            "mimeType": "image/png",
+            "sizeBytes": "10",
+            "expirationTime": expiration_time,
+            "state": "ACTIVE"
+        }
+    })
+    .to_string()
+}
+
+fn in_two_days() -> String {
+    (chrono::Utc::now() + chrono::Duration::hours(47)).to_rfc3339()
+}
+
+/// チャンクの単位（256KiB）
+const CHUNK: usize = 256 * 1024;
+
+#[tokio::test]
+async fn test_files_client_uploads_in_chunks() {
+    let mut server = mockito::Server::new_async().await;
+    let start = mock_start(&mut server, 1).await;
+    let mut chunks = Vec::new();
+    for (offset, body, command) in [
+        (0, "a".repeat(CHUNK), "upload"),
+        (CHUNK, "b".repeat(CHUNK), "upload"),
+        (2 * CHUNK, "c".repeat(10), "upload, finalize"),
+    ] {
+        chunks.push(
+            server
+                .mock("POST", "/upload-session/1")
+                .match_header("x-goog-upload-offset", offset.to_string().as_str())
+                .match_header("x-goog-upload-command", command)
+                .match_body(body.as_str())
+                .with_header("content-type", "application/json")
+                .with_body(if command == "upload" {
+                    String::new()
+                } else {
+                    file_body(&in_two_days())
+                })
+                .create_async()
+                .await,
+        );
+    }
+
+    let client = FilesClient::new(server.url()).with_chunk_size(CHUNK);
+    let data = ["a".repeat(CHUNK), "b".repeat(CHUNK), "c".repeat(10)].concat();
+    let file = client
+        .upload("files-key", data.into_bytes(), "image/png", "input")
+        .await
+        .unwrap();
+    start.assert_async().await;
+    for chunk in chunks {
+        chunk.assert_async().await;
+    }
+    assert_eq!(file.name, "files/abc");
+    assert_eq!(file.size_bytes, Some(10));
+    assert_eq!(file.state.as_deref(), Some("ACTIVE"));
+    assert!(file.expiration_time.is_some());
+}
+
+#[tokio::test]
+async fn test_files_client_resumes_interrupted_upload() {
+    let mut server = mockito::Server::new_async().await;
+    mock_start(&mut server, 1).await;
+    server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-offset", "0")
+        .create_async()
+        .await;
+    let failed = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-offset", CHUNK.to_string().as_str())
+        .with_status(503)
+        .expect(1)
+        .create_async()
+        .await;
+    let query = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-command", "query")
+        .with_header("x-goog-upload-status", "active")
+        .with_header("x-goog-upload-size-received", CHUNK.to_string().as_str())
+        .expect(1)
+        .create_async()
+        .await;
+    let resumed = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-offset", CHUNK.to_string().as_str())
+        .match_header("x-goog-upload-command", "upload, finalize")
+        .match_body("b".repeat(CHUNK).as_str())
+        .with_header("content-type", "application/json")
+        .with_body(file_body(&in_two_days()))
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(server.url())
+        .with_chunk_size(CHUNK)
+        .with_resume_delay(std::time::Duration::from_millis(100));
+    let data = ["a".repeat(CHUNK), "b".repeat(CHUNK)].concat();
+    let started = std::time::Instant::now();
+    let file = client
+        .upload("files-key", data.into_bytes(), "image/png", "input")
+        .await
+        .unwrap();
+    // 再開する前に少し待つ
+    assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+    failed.assert_async().await;
+    query.assert_async().await;
+    resumed.assert_async().await;
+    assert_eq!(file.name, "files/abc");
+}
+
+#[tokio::test]
+async fn test_files_client_rounds_the_chunk_size_up_to_256_kib() {
+    let mut server = mockito::Server::new_async().await;
+    mock_start(&mut server, 1).await;
+    // 256KiB未満のデータは1回で送る
+    let chunk = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-offset", "0")
+        .match_header("x-goog-upload-command", "upload, finalize")
+        .match_body("0123456789")
+        .with_header("content-type", "application/json")
+        .with_body(file_body(&in_two_days()))
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(server.url()).with_chunk_size(4);
+    client
+        .upload("files-key", b"0123456789".to_vec(), "image/png", "input")
+        .await
+        .unwrap();
+    chunk.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_files_client_reuses_uploaded_files_by_hash_and_key() {
+    let mut server = mockito::Server::new_async().await;
+    let start = mock_start(&mut server, 3).await;
+    server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-command", "upload, finalize")
+        .with_header("content-type", "application/json")
+        .with_body(file_body(&in_two_days()))
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(server.url());
+    let first = client
+        .upload_cached("key-a", b"same image", "image/png")
+        .await
+        .unwrap();
+    // 同じ内容・同じキーは再利用する
+    let second = client
+        .upload_cached("key-a", b"same image", "image/png")
+        .await
+        .unwrap();
+    assert_eq!(first, second);
+    // 内容やキーが違う場合はアップロードする
+    client
+        .upload_cached("key-a", b"other image", "image/png")
+        .await
+        .unwrap();
+    client
+        .upload_cached("key-b", b"same image", "image/png")
+        .await
+        .unwrap();
+    start.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_files_client_uploads_again_when_the_file_is_about_to_expire() {
+    let mut server = mockito::Server::new_async().await;
+    let start = mock_start(&mut server, 2).await;
+    let expiring = (chrono::Utc::now() + chrono::Duration::minutes(10)).to_rfc3339();
+    server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-command", "upload, finalize")
+        .with_header("content-type", "application/json")
+        .with_body(file_body(&expiring))
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(server.url());
+    for _ in 0..2 {
+        client
+            .upload_cached("key-a", b"same image", "image/png")
+            .await
+            .unwrap();
+    }
+    start.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_files_client_does_not_resume_client_errors() {
+    let mut server = mockito::Server::new_async().await;
+    mock_start(&mut server, 1).await;
+    server
+        .mock("POST", "/upload-session/1")
+        .with_status(400)
+        .with_body("bad upload")
+        .create_async()
+        .await;
+    let query = server
+        .mock("POST", "/upload-session/1")
+        .match_header("x-goog-upload-command", "query")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(server.url());
+    let err = client
+        .upload("files-key", b"data".to_vec(), "image/png", "input")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("bad upload"), "{}", err);
+    query.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_files_client_upload_url_keeps_api_version() {
+    let mut server = mockito::Server::new_async().await;
+    let start = server
+        .mock("POST", "/upload/v1alpha/files")
+        .match_query(Matcher::Any)
+        .with_status(401)
+        .create_async()
+        .await;
+
+    let client = FilesClient::new(format!("{}/v1alpha", server.url()));
+    let err = client
+        .upload("files-key", b"data".to_vec(), "image/png", "input")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("authentication"), "{}", err);
+    start.assert_async().await;
+}
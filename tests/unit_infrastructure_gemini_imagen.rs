@@ -0,0 +1,112 @@
+use google_gemini_image_creator::domain::{
+    GeminiModel, ImageGenerationRepository, ImageGenerationRequest, InputImage,
+};
+use google_gemini_image_creator::infrastructure::gemini::{
+    GeminiClient, ImagenClient, ModelRouter,
+};
+
+const IMAGEN: &str = "imagen-4.0-generate-001";
+
+fn request(model: &str) -> ImageGenerationRequest {
+    ImageGenerationRequest::new("a paper crane".to_string())
+        .with_model(GeminiModel::from(model.to_string()))
+}
+
+#[tokio::test]
+async fn test_imagen_client_sends_predict_request() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/models/imagen-4.0-generate-001:predict")
+        .match_header("x-goog-api-key", "imagen-key")
+        .match_body(mockito::Matcher::Json(serde_json::json!({
+            "instances": [{ "prompt": "a paper crane" }],
+            "parameters": {
+                "sampleCount": 1,
+                "aspectRatio": "16:9",
+                "personGeneration": "dont_allow",
+                "negativePrompt": "text"
+            }
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "predictions": [{ "bytesBase64Encoded": "AQID", "mimeType": "image/jpeg" }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = ImagenClient::with_base_url("imagen-key".to_string(), server.url());
+    let request = request(IMAGEN)
+        .with_aspect_ratio("16:9".to_string())
+        .with_person_generation("dont_allow".to_string())
+        .with_negative_prompt("text".to_string());
+    let image = client.generate_image(&request).await.unwrap();
+
+    assert_eq!(image.data, vec![1, 2, 3]);
+    assert_eq!(image.mime_type, "image/jpeg");
+    assert_eq!(image.model.as_str(), IMAGEN);
+    mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_imagen_client_filtered_prediction_is_no_image() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/models/imagen-4.0-generate-001:predict")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "predictions": [{ "raiFilteredReason": "blocked by safety filter" }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let client = ImagenClient::with_base_url("imagen-key".to_string(), server.url());
+    let error = client.generate_image(&request(IMAGEN)).await.unwrap_err();
+    assert_eq!(error.kind(), "no_image");
+    assert!(error.to_string().contains("blocked by safety filter"));
+
+    // 入力画像には対応しない
+    let with_image =
+        request(IMAGEN).with_input_image(InputImage::new(vec![1], "image/png".to_string()));
+    let error = client.generate_image(&with_image).await.unwrap_err();
+    assert_eq!(error.kind(), "invalid_prompt");
+}
+
+#[tokio::test]
+async fn test_model_router_routes_by_model_name() {
+    let mut server = mockito::Server::new_async().await;
+    let predict = server
+        .mock("POST", "/models/imagen-4.0-generate-001:predict")
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"predictions":[{"bytesBase64Encoded":"BAUG"}]}"#)
+        .create_async()
+        .await;
+    let generate = server
+        .mock("POST", "/models/gemini-2.5-flash-image:generateContent")
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"candidates":[{"content":{"parts":[{"inlineData":{"mimeType":"image/png","data":"AQID"}}]}}]}"#,
+        )
+        .create_async()
+        .await;
+
+    let router = ModelRouter::new(
+        GeminiClient::with_base_url("key".to_string(), server.url()),
+        ImagenClient::with_base_url("key".to_string(), server.url()),
+    );
+    let imagen = router.generate_image(&request(IMAGEN)).await.unwrap();
+    assert_eq!(imagen.data, vec![4, 5, 6]);
+    let gemini = router
+        .generate_image(&request("gemini-2.5-flash-image"))
+        .await
+        .unwrap();
+    assert_eq!(gemini.data, vec![1, 2, 3]);
+
+    predict.assert_async().await;
+    generate.assert_async().await;
+}
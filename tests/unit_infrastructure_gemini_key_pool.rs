@@ -0,0 +1,100 @@
+use google_gemini_image_creator::config::KeySelection;
+use google_gemini_image_creator::infrastructure::gemini::{ApiKeyPool, QuarantineReason};
+use std::time::Duration;
+
+fn keys() -> Vec<String> {
+    vec![
+        "key-aaaa".to_string(),
+        "key-bbbb".to_string(),
+        "key-cccc".to_string(),
+    ]
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_round_robin_skips_quarantined_keys() {
+    let pool = ApiKeyPool::new(keys());
+    let order: Vec<String> = (0..4).map(|_| pool.acquire().unwrap().key).collect();
+    assert_eq!(order, vec!["key-aaaa", "key-bbbb", "key-cccc", "key-aaaa"]);
+
+    let lease = pool.acquire().unwrap();
+    assert_eq!(lease.key, "key-bbbb");
+    pool.quarantine(
+        &lease,
+        QuarantineReason::Throttled,
+        Some(Duration::from_secs(30)),
+    );
+
+    let order: Vec<String> = (0..3).map(|_| pool.acquire().unwrap().key).collect();
+    assert_eq!(order, vec!["key-cccc", "key-aaaa", "key-cccc"]);
+
+    // 隔離期間が過ぎたら再び使われる
+    tokio::time::advance(Duration::from_secs(31)).await;
+    assert_eq!(pool.acquire().unwrap().key, "key-aaaa");
+    assert_eq!(pool.acquire().unwrap().key, "key-bbbb");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_least_recently_throttled_prefers_keys_never_throttled() {
+    let pool = ApiKeyPool::new(keys()).with_selection(KeySelection::LeastRecentlyThrottled);
+
+    let first = pool.acquire().unwrap();
+    pool.quarantine(
+        &first,
+        QuarantineReason::Throttled,
+        Some(Duration::from_secs(1)),
+    );
+    tokio::time::advance(Duration::from_secs(2)).await;
+
+    // 一度もレート制限されていないキーのうち、最も使われていないもの
+    assert_eq!(pool.acquire().unwrap().key, "key-bbbb");
+    assert_eq!(pool.acquire().unwrap().key, "key-cccc");
+    assert_eq!(pool.acquire().unwrap().key, "key-bbbb");
+
+    // 全キーがレート制限されたことがある場合は、最も前に制限されたキー
+    for expected in ["key-bbbb", "key-cccc"] {
+        let throttled = pool.acquire().unwrap();
+        assert_eq!(throttled.key, expected);
+        pool.quarantine(
+            &throttled,
+            QuarantineReason::Throttled,
+            Some(Duration::from_secs(1)),
+        );
+        tokio::time::advance(Duration::from_secs(2)).await;
+    }
+    assert_eq!(pool.acquire().unwrap().key, "key-aaaa");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_all_keys_quarantined() {
+    let pool = ApiKeyPool::new(keys()[..2].to_vec())
+        .with_quarantine(Duration::from_secs(60), Duration::from_secs(600));
+
+    let a = pool.acquire().unwrap();
+    pool.quarantine(&a, QuarantineReason::Unauthorized, None);
+    let b = pool.acquire().unwrap();
+    pool.quarantine(&b, QuarantineReason::Throttled, None);
+
+    assert!(pool.acquire().is_none());
+    assert_eq!(pool.next_available_in(), Some(Duration::from_secs(60)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_metrics_are_tracked_per_key_without_exposing_keys() {
+    let pool = ApiKeyPool::new(keys()[..2].to_vec());
+    let a = pool.acquire().unwrap();
+    pool.record_success(&a);
+    let b = pool.acquire().unwrap();
+    pool.quarantine(&b, QuarantineReason::Unauthorized, None);
+
+    let metrics = pool.metrics();
+    assert_eq!(metrics[0].key, "…aaaa");
+    assert_eq!(metrics[0].requests, 1);
+    assert_eq!(metrics[0].successes, 1);
+    assert_eq!(metrics[0].quarantined_for_secs, None);
+    assert_eq!(metrics[1].unauthorized, 1);
+    assert_eq!(metrics[1].failures, 1);
+    assert_eq!(metrics[1].quarantined_for_secs, Some(600));
+
+    let json = serde_json::to_string(&metrics).unwrap();
+    assert!(!json.contains("key-aaaa"));
+}
@@ -0,0 +1,272 @@
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, GenerationHistory, GenerationOutcome, GenerationRecord,
+    HistoryQuery, ImageGenerationRequest, InputImage,
+};
+use google_gemini_image_creator::infrastructure::history::SqliteHistory;
+use std::time::Duration;
+
+fn succeeded(prompt: &str, created_at: &str) -> GenerationRecord {
+    let request = ImageGenerationRequest::new(prompt.to_string())
+        .with_model(GeminiModel::from("gemini-2.5-flash-image".to_string()));
+    let image = GeneratedImage::new(vec![1, 2, 3], request.model.clone());
+    let mut record = GenerationRecord::succeeded(&request, &image, Duration::from_millis(1200));
+    record.created_at = timestamp(created_at);
+    record
+}
+
+fn timestamp(value: &str) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .unwrap()
+        .with_timezone(&chrono::Utc)
+}
+
+#[tokio::test]
+async fn test_record_and_get_round_trip() {
+    let history = SqliteHistory::open_in_memory().unwrap();
+    let record = succeeded("a red fox", "2025-03-04T10:00:00Z")
+        .with_image_id("abc123".to_string())
+        .with_file_path("/images/fox.png".to_string())
+        .with_estimated_cost(0.039);
+    history.record(&record).await.unwrap();
+
+    let loaded = history.get(&record.request_id).await.unwrap().unwrap();
+    assert_eq!(loaded, record);
+    assert_eq!(loaded.latency_ms, 1200);
+    assert!(history.get("missing").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_query_lists_newest_first_with_filters() {
+    let history = SqliteHistory::open_in_memory().unwrap();
+    history
+        .record(&succeeded("first", "2025-03-01T00:00:00Z"))
+        .await
+        .unwrap();
+    history
+        .record(&succeeded("second", "2025-03-02T00:00:00Z"))
+        .await
+        .unwrap();
+    let request = ImageGenerationRequest::new("third".to_string());
+    let mut failed = GenerationRecord::failed(
+        &request,
+        "rate_limit",
+        "Rate limit exceeded".to_string(),
+        Duration::from_millis(30),
+    );
+    failed.created_at = timestamp("2025-03-03T00:00:00Z");
+    history.record(&failed).await.unwrap();
+
+    let all = history.query(&HistoryQuery::default()).await.unwrap();
+    let prompts: Vec<&str> = all.iter().map(|r| r.prompt.as_str()).collect();
+    assert_eq!(prompts, vec!["third", "second", "first"]);
+    assert_eq!(all[0].error_kind.as_deref(), Some("rate_limit"));
+
+    let failures = history
+        .query(&HistoryQuery {
+            outcome: Some(GenerationOutcome::Failed),
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 1);
+
+    let window = history
+        .query(&HistoryQuery {
+            since: Some(timestamp("2025-03-02T00:00:00Z")),
+            until: Some(timestamp("2025-03-03T00:00:00Z")),
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(window.len(), 1);
+    assert_eq!(window[0].prompt, "second");
+
+    let page = history
+        .query(&HistoryQuery {
+            limit: 1,
+            offset: 1,
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(page[0].prompt, "second");
+}
+
+#[tokio::test]
+async fn test_full_text_search_over_prompts() {
+    let history = SqliteHistory::open_in_memory().unwrap();
+    history
+        .record(&succeeded(
+            "Minimal logo for a coffee shop",
+            "2025-03-01T00:00:00Z",
+        ))
+        .await
+        .unwrap();
+    history
+        .record(&succeeded("Watercolor landscape", "2025-03-02T00:00:00Z"))
+        .await
+        .unwrap();
+
+    let search = |text: &str| HistoryQuery {
+        text: Some(text.to_string()),
+        ..HistoryQuery::default()
+    };
+
+    let results = history.query(&search("logo")).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].prompt, "Minimal logo for a coffee shop");
+
+    // 前方一致・大文字小文字を区別しない・すべての語を含むもの
+    assert_eq!(history.query(&search("COFF")).await.unwrap().len(), 1);
+    assert!(history
+        .query(&search("logo landscape"))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // FTS5の構文として解釈される文字を含んでもエラーにならない
+    assert!(history
+        .query(&search("\"logo\" OR (NEAR"))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(history.query(&search("   ")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_history_persists_across_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history").join("generations.db");
+    let record = succeeded("persistent prompt", "2025-03-01T00:00:00Z");
+
+    SqliteHistory::open(&path)
+        .unwrap()
+        .record(&record)
+        .await
+        .unwrap();
+
+    let reopened = SqliteHistory::open(&path).unwrap();
+    assert_eq!(
+        reopened.get(&record.request_id).await.unwrap(),
+        Some(record)
+    );
+}
+
+#[tokio::test]
+async fn test_opens_version_1_database_and_keeps_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("generations.db");
+    {
+        // 再実行用のリクエストの列がないバージョン1のスキーマ
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE generations (
+                    request_id TEXT NOT NULL UNIQUE, prompt TEXT NOT NULL, model TEXT NOT NULL,
+                    parameters TEXT NOT NULL, outcome TEXT NOT NULL, error_kind TEXT,
+                    error_message TEXT, latency_ms INTEGER NOT NULL, image_id TEXT,
+                    file_path TEXT, mime_type TEXT, size_bytes INTEGER, created_at TEXT NOT NULL
+                );
+                INSERT INTO generations VALUES ('old-id', 'old prompt', 'gemini-2.5-flash-image',
+                    '{}', 'succeeded', NULL, NULL, 5, NULL, NULL, 'image/png', 3,
+                    '2025-01-01T00:00:00.000000Z');
+                PRAGMA user_version = 1;",
+            )
+            .unwrap();
+    }
+
+    let history = SqliteHistory::open(&path).unwrap();
+    let old = history.get("old-id").await.unwrap().unwrap();
+    assert!(old.request.is_none());
+
+    // 元のリクエストがない記録はプロンプトとモデルから復元する
+    let request = old.to_request();
+    assert_eq!(request.request_id, "old-id");
+    assert_eq!(request.prompt, "old prompt");
+
+    let record = succeeded("new prompt", "2025-03-01T00:00:00Z");
+    history.record(&record).await.unwrap();
+    assert_eq!(
+        history
+            .get(&record.request_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .request,
+        record.request
+    );
+}
+
+#[tokio::test]
+async fn test_query_after_position_is_stable_when_records_are_added() {
+    let history = SqliteHistory::open_in_memory().unwrap();
+    for (prompt, created_at) in [
+        ("first", "2025-03-01T00:00:00Z"),
+        ("second", "2025-03-02T00:00:00Z"),
+        ("third", "2025-03-03T00:00:00Z"),
+    ] {
+        history
+            .record(&succeeded(prompt, created_at))
+            .await
+            .unwrap();
+    }
+    let first_page = history
+        .query(&HistoryQuery {
+            limit: 2,
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap();
+    let last = first_page.last().unwrap();
+
+    // ページの間に新しい記録が増えても、続きは最後に返した記録の後から始まる
+    history
+        .record(&succeeded("newest", "2025-03-04T00:00:00Z"))
+        .await
+        .unwrap();
+    let next_page = history
+        .query(&HistoryQuery {
+            limit: 2,
+            after: Some((last.created_at, last.request_id.clone())),
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap();
+    let prompts: Vec<&str> = next_page.iter().map(|r| r.prompt.as_str()).collect();
+    assert_eq!(prompts, vec!["first"]);
+
+    let err = history
+        .query(&HistoryQuery {
+            text: Some("first".to_string()),
+            after: Some((last.created_at, last.request_id.clone())),
+            ..HistoryQuery::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("cursor"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_input_image_data_is_not_persisted() {
+    let history = SqliteHistory::open_in_memory().unwrap();
+    let request = ImageGenerationRequest::new("make it blue".to_string())
+        .with_input_image(InputImage::new(vec![1, 2, 3], "image/png".to_string()));
+    let image = GeneratedImage::new(vec![4, 5, 6], request.model.clone());
+    let record = GenerationRecord::succeeded(&request, &image, Duration::from_millis(10));
+    history.record(&record).await.unwrap();
+
+    let loaded = history
+        .get(&record.request_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .to_request();
+    let input = &loaded.input_images[0];
+    assert!(input.data.is_empty());
+    assert_eq!(input.mime_type, "image/png");
+    // 画像データ[1, 2, 3]のSHA-256
+    assert_eq!(
+        input.sha256.as_deref(),
+        Some("039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81")
+    );
+}
@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use google_gemini_image_creator::application::{GenerateImageUseCase, UsageTracker};
+use google_gemini_image_creator::domain::{
+    GeneratedImage, ImageGenerationError, ImageGenerationRepository, ImageGenerationRequest,
+    ImageStore,
+};
+use google_gemini_image_creator::infrastructure::mcp::{ImageJob, JobError, JobManager, JobStatus};
+use google_gemini_image_creator::infrastructure::storage::FsImageStore;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 指定した時間待ってから画像（またはエラー）を返すモック
+struct SlowRepository {
+    delay: Duration,
+    fail: bool,
+}
+
+#[async_trait]
+impl ImageGenerationRepository for SlowRepository {
+    async fn generate_image(
+        &self,
+        request: &ImageGenerationRequest,
+    ) -> Result<GeneratedImage, ImageGenerationError> {
+        tokio::time::sleep(self.delay).await;
+        if self.fail {
+            return Err(ImageGenerationError::ApiError("Mock error".to_string()));
+        }
+        Ok(GeneratedImage::new(
+            request.prompt.as_bytes().to_vec(),
+            request.model.clone(),
+        ))
+    }
+}
+
+fn manager(
+    store: Arc<dyn ImageStore>,
+    delay: Duration,
+    fail: bool,
+    max_concurrency: usize,
+) -> JobManager {
+    let repository: Box<dyn ImageGenerationRepository> = Box::new(SlowRepository { delay, fail });
+    JobManager::new(
+        Arc::new(GenerateImageUseCase::new(repository)),
+        store,
+        None,
+        Arc::new(UsageTracker::new(HashMap::new())),
+        max_concurrency,
+    )
+}
+
+/// ジョブが終了するまで待つ
+async fn wait_finished(jobs: &JobManager, job_id: &str) -> ImageJob {
+    for _ in 0..200 {
+        let job = jobs.get(job_id).unwrap();
+        if job.status.is_finished() {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {} did not finish", job_id);
+}
+
+#[tokio::test]
+async fn test_submitted_job_stores_its_result_in_the_image_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let store: Arc<dyn ImageStore> = Arc::new(FsImageStore::open(dir.path()).unwrap());
+    let jobs = manager(Arc::clone(&store), Duration::from_millis(20), false, 2);
+
+    let submitted = jobs
+        .submit(ImageGenerationRequest::new("a harbor".to_string()))
+        .unwrap();
+    assert_eq!(submitted.status, JobStatus::Queued);
+
+    let job = wait_finished(&jobs, &submitted.job_id).await;
+    assert_eq!(job.status, JobStatus::Succeeded);
+    let result = job.result.unwrap();
+    assert_eq!(result.resource_uri, format!("image://{}", result.image_id));
+    let (_, data) = store.get(&result.image_id).await.unwrap().unwrap();
+    assert_eq!(data, b"a harbor".to_vec());
+}
+
+#[tokio::test]
+async fn test_jobs_wait_for_a_free_worker_and_can_be_cancelled() {
+    let dir = tempfile::tempdir().unwrap();
+    let store: Arc<dyn ImageStore> = Arc::new(FsImageStore::open(dir.path()).unwrap());
+    let jobs = manager(store, Duration::from_millis(200), false, 1);
+
+    let first = jobs
+        .submit(ImageGenerationRequest::new("first".to_string()))
+        .unwrap();
+    let second = jobs
+        .submit(ImageGenerationRequest::new("second".to_string()))
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(jobs.get(&first.job_id).unwrap().status, JobStatus::Running);
+    assert_eq!(jobs.get(&second.job_id).unwrap().status, JobStatus::Queued);
+
+    let cancelled = jobs.cancel(&second.job_id).unwrap();
+    assert_eq!(cancelled.status, JobStatus::Cancelled);
+    assert!(matches!(
+        jobs.cancel(&second.job_id),
+        Err(JobError::AlreadyFinished(_))
+    ));
+    assert!(matches!(jobs.cancel("missing"), Err(JobError::NotFound(_))));
+
+    assert_eq!(
+        wait_finished(&jobs, &first.job_id).await.status,
+        JobStatus::Succeeded
+    );
+    assert_eq!(
+        jobs.get(&second.job_id).unwrap().status,
+        JobStatus::Cancelled
+    );
+}
+
+#[tokio::test]
+async fn test_failed_job_reports_the_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let store: Arc<dyn ImageStore> = Arc::new(FsImageStore::open(dir.path()).unwrap());
+    let jobs = manager(store, Duration::from_millis(1), true, 1);
+
+    let submitted = jobs
+        .submit(ImageGenerationRequest::new("a storm".to_string()))
+        .unwrap();
+    let job = wait_finished(&jobs, &submitted.job_id).await;
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.error_kind.as_deref(), Some("api"));
+    assert!(job.result.is_none());
+}
+
+#[tokio::test]
+async fn test_submissions_beyond_the_pending_limit_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let store: Arc<dyn ImageStore> = Arc::new(FsImageStore::open(dir.path()).unwrap());
+    let jobs = manager(store, Duration::from_millis(50), false, 1).with_max_pending(2);
+
+    let first = jobs
+        .submit(ImageGenerationRequest::new("first".to_string()))
+        .unwrap();
+    jobs.submit(ImageGenerationRequest::new("second".to_string()))
+        .unwrap();
+    assert!(matches!(
+        jobs.submit(ImageGenerationRequest::new("third".to_string())),
+        Err(JobError::QueueFull(2))
+    ));
+
+    // 終了したジョブは数えない
+    wait_finished(&jobs, &first.job_id).await;
+    assert!(jobs
+        .submit(ImageGenerationRequest::new("third".to_string()))
+        .is_ok());
+}
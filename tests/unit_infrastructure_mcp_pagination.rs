@@ -0,0 +1,99 @@
+use google_gemini_image_creator::infrastructure::mcp::CursorCodec;
+
+#[test]
+fn test_paginate_first_page_has_next_cursor() {
+    let codec = CursorCodec::with_key([7; 32]);
+    let items: Vec<u32> = (0..5).collect();
+
+    let page = codec.paginate("items", &items, None, 2).unwrap();
+    assert_eq!(page.items, vec![0, 1]);
+    assert!(page.next_cursor.is_some());
+}
+
+#[test]
+fn test_paginate_follows_cursor_to_last_page() {
+    let codec = CursorCodec::with_key([7; 32]);
+    let items: Vec<u32> = (0..5).collect();
+
+    let mut collected = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = codec
+            .paginate("items", &items, cursor.as_deref(), 2)
+            .unwrap();
+        collected.extend(page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(collected, items);
+}
+
+#[test]
+fn test_decode_rejects_tampered_cursor() {
+    let codec = CursorCodec::with_key([7; 32]);
+    let cursor = codec.encode("items", 2);
+
+    let mut tampered = cursor.into_bytes();
+    tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+    let tampered = String::from_utf8(tampered).unwrap();
+
+    assert!(codec.decode("items", &tampered).is_err());
+}
+
+#[test]
+fn test_decode_rejects_cursor_from_other_key_or_list() {
+    let codec = CursorCodec::with_key([7; 32]);
+    let other = CursorCodec::with_key([8; 32]);
+    let cursor = codec.encode("items", 2);
+
+    assert_eq!(codec.decode("items", &cursor).unwrap(), 2);
+    assert!(other.decode("items", &cursor).is_err());
+    assert!(codec.decode("resources", &cursor).is_err());
+    assert!(codec.decode("items", "not-a-cursor").is_err());
+}
+
+#[test]
+fn test_keyset_pages_do_not_shift_when_items_are_added() {
+    use google_gemini_image_creator::infrastructure::mcp::KeysetPosition;
+
+    let codec = CursorCodec::with_key([7; 32]);
+    let at = |seconds: i64| chrono::DateTime::from_timestamp(seconds, 0).unwrap();
+    let position = |item: &(i64, &str)| KeysetPosition::new(at(item.0), item.1);
+    // 新しい順（同じ時刻はID順）
+    let mut items = vec![(30, "c"), (20, "a"), (20, "b"), (10, "d")];
+
+    let page = codec
+        .paginate_newest_first("resources", &items, position, None, 2)
+        .unwrap();
+    assert_eq!(page.items, vec![(30, "c"), (20, "a")]);
+    let cursor = page.next_cursor.unwrap();
+
+    // ページの間に新しい項目が増えても、続きは重複も抜けもない
+    items.insert(0, (40, "e"));
+    let page = codec
+        .paginate_newest_first("resources", &items, position, Some(&cursor), 2)
+        .unwrap();
+    assert_eq!(page.items, vec![(20, "b"), (10, "d")]);
+    assert!(page.next_cursor.is_none());
+
+    // 最後に返した項目が削除されても続きから返す
+    items.retain(|item| item.1 != "a");
+    let page = codec
+        .paginate_newest_first("resources", &items, position, Some(&cursor), 2)
+        .unwrap();
+    assert_eq!(page.items, vec![(20, "b"), (10, "d")]);
+
+    // オフセットのカーソルや別のリストのカーソルは受け付けない
+    assert!(codec
+        .paginate_newest_first(
+            "resources",
+            &items,
+            position,
+            Some(&codec.encode("resources", 2)),
+            2
+        )
+        .is_err());
+    assert!(codec.decode_keyset("tools", &cursor).is_err());
+}
@@ -1,9 +1,611 @@
+use google_gemini_image_creator::application::batch::BatchItem;
+use google_gemini_image_creator::domain::{
+    GeneratedImage, GenerationHistory, GenerationRecord, ImageGenerationRequest,
+};
+use google_gemini_image_creator::infrastructure::gemini::{
+    ApiKeyPool, BatchClient, BatchInputMode, ModelCatalog,
+};
+use google_gemini_image_creator::infrastructure::history::SqliteHistory;
+use google_gemini_image_creator::infrastructure::mcp::types::{CallToolResult, Content};
 use google_gemini_image_creator::infrastructure::mcp::McpServer;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[test]
 fn test_list_tools() {
     let server = McpServer::new("test-key".to_string());
     let tools = server.list_tools();
-    assert_eq!(tools.len(), 1);
+    assert_eq!(tools.len(), 2);
     assert_eq!(tools[0].name, "generate_image");
+    assert_eq!(tools[1].name, "usage_report");
+}
+
+/// モデル設定はプロセス全体で共有されるため、設定を変更するテストは順番に実行する
+static MODEL_SETTINGS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[test]
+fn test_update_models_notifies_tools_list_changed() {
+    let _lock = MODEL_SETTINGS_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let server = McpServer::new("test-key".to_string());
+    let mut notifications = server.subscribe_notifications();
+
+    let changed = server.update_models(
+        "gemini-3-pro-image-preview".to_string(),
+        vec![
+            "gemini-2.5-flash-image".to_string(),
+            "gemini-3-pro-image-preview".to_string(),
+        ],
+    );
+    assert!(changed);
+
+    let notification = notifications.try_recv().unwrap();
+    assert_eq!(notification.method, "notifications/tools/list_changed");
+
+    // list_toolsのスキーマは現在の設定から生成される
+    let tools = server.list_tools();
+    let model_schema = &tools[0].input_schema.as_ref().unwrap()["properties"]["model"];
+    assert_eq!(model_schema["default"], "gemini-3-pro-image-preview");
+    assert_eq!(model_schema["enum"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_update_models_without_change_does_not_notify() {
+    let _lock = MODEL_SETTINGS_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let server = McpServer::new("test-key".to_string());
+    server.update_models("gemini-2.5-flash-image".to_string(), vec![]);
+    let mut notifications = server.subscribe_notifications();
+
+    let changed = server.update_models("gemini-2.5-flash-image".to_string(), vec![]);
+    assert!(!changed);
+    assert!(notifications.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_history_tools_return_recorded_generations() {
+    let history = Arc::new(SqliteHistory::open_in_memory().unwrap());
+    let request = ImageGenerationRequest::new("a lighthouse logo".to_string());
+    let image = GeneratedImage::new(vec![1, 2, 3], request.model.clone());
+    let record = GenerationRecord::succeeded(&request, &image, Duration::from_millis(10))
+        .with_image_id("abc".to_string());
+    history.record(&record).await.unwrap();
+
+    let server = McpServer::new("test-key".to_string()).with_history(history);
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"search_history".to_string()));
+
+    let text = |result: CallToolResult| match &result.content[0] {
+        Content::Text { text } => serde_json::from_str::<serde_json::Value>(text).unwrap(),
+        _ => panic!("expected text content"),
+    };
+
+    let found = text(
+        server
+            .call_tool("search_history", &serde_json::json!({ "query": "logo" }))
+            .await
+            .unwrap(),
+    );
+    assert_eq!(found["generations"][0]["request_id"], request.request_id);
+    assert_eq!(found["generations"][0]["resource_uri"], "image://abc");
+
+    let detail = text(
+        server
+            .call_tool(
+                "get_generation",
+                &serde_json::json!({ "request_id": request.request_id }),
+            )
+            .await
+            .unwrap(),
+    );
+    assert_eq!(detail["prompt"], "a lighthouse logo");
+    assert_eq!(detail["outcome"], "succeeded");
+
+    assert!(server
+        .call_tool(
+            "get_generation",
+            &serde_json::json!({ "request_id": "nope" })
+        )
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_vary_requires_a_previous_output_image() {
+    let history = Arc::new(SqliteHistory::open_in_memory().unwrap());
+    let request = ImageGenerationRequest::new("a castle".to_string());
+    let failed = GenerationRecord::failed(
+        &request,
+        "api",
+        "API error".to_string(),
+        Duration::from_millis(5),
+    );
+    history.record(&failed).await.unwrap();
+
+    let server = McpServer::new("test-key".to_string()).with_history(history);
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"regenerate".to_string()));
+    assert!(names.contains(&"vary".to_string()));
+
+    let err = server
+        .call_tool(
+            "vary",
+            &serde_json::json!({ "request_id": request.request_id, "instruction": "at night" }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("did not produce an image"));
+
+    assert!(server
+        .call_tool(
+            "regenerate",
+            &serde_json::json!({ "request_id": "missing" })
+        )
+        .await
+        .unwrap_err()
+        .to_string()
+        .contains("Generation not found"));
+}
+
+#[tokio::test]
+async fn test_list_models_tool_and_catalog_validation() {
+    let mut api = mockito::Server::new_async().await;
+    api.mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "models": [{
+                    "name": "models/gemini-2.5-flash-image",
+                    "description": "Image generation and editing",
+                    "supportedGenerationMethods": ["generateContent"]
+                }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let catalog = ModelCatalog::new(
+        Arc::new(ApiKeyPool::new(vec!["test-key".to_string()])),
+        api.url(),
+    );
+
+    let server = McpServer::new("test-key".to_string()).with_model_catalog(Arc::new(catalog));
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"list_models".to_string()));
+
+    // 一覧を取得していなくても、一覧にないモデルはAPIを呼ぶ前に拒否する
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({ "prompt": "a fox", "model": "gemini-0-retired-image" }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("is not available"), "{}", err);
+
+    let result = server
+        .call_tool("list_models", &serde_json::json!({}))
+        .await
+        .unwrap();
+    let Content::Text { text } = &result.content[0] else {
+        panic!("expected text content");
+    };
+    let value: serde_json::Value = serde_json::from_str(text).unwrap();
+    assert_eq!(value["models"][0]["name"], "gemini-2.5-flash-image");
+    assert_eq!(
+        value["models"][0]["description"],
+        "Image generation and editing"
+    );
+
+    // 取得済みの一覧にないモデルはAPIを呼ぶ前に拒否する
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({ "prompt": "a fox", "model": "gemini-0-retired-image" }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("is not available"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_model_enum_follows_the_catalog_and_notifies_on_change() {
+    let mut api = mockito::Server::new_async().await;
+    api.mock("GET", "/models")
+        .match_query(mockito::Matcher::Any)
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "models": [{
+                    "name": "models/gemini-2.5-flash-image",
+                    "supportedGenerationMethods": ["generateContent"]
+                }]
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+    let catalog = ModelCatalog::new(
+        Arc::new(ApiKeyPool::new(vec!["test-key".to_string()])),
+        api.url(),
+    );
+    let server = McpServer::new("test-key".to_string()).with_model_catalog(Arc::new(catalog));
+    let mut notifications = server.subscribe_notifications();
+
+    server
+        .call_tool("list_models", &serde_json::json!({}))
+        .await
+        .unwrap();
+    let notification = notifications.try_recv().unwrap();
+    assert_eq!(notification.method, "notifications/tools/list_changed");
+
+    // 一覧が変わらなければ取得し直しても通知しない
+    server
+        .call_tool("list_models", &serde_json::json!({ "refresh": true }))
+        .await
+        .unwrap();
+    assert!(notifications.try_recv().is_err());
+
+    let _lock = MODEL_SETTINGS_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    server.update_models(
+        "gemini-2.5-flash-image".to_string(),
+        vec![
+            "gemini-2.5-flash-image".to_string(),
+            "gemini-3-pro-image-preview".to_string(),
+        ],
+    );
+    // 許可されたモデルのうち一覧にあるものだけを選択肢にする
+    let tools = server.list_tools();
+    let model_schema = &tools[0].input_schema.as_ref().unwrap()["properties"]["model"];
+    assert_eq!(
+        model_schema["enum"],
+        serde_json::json!(["gemini-2.5-flash-image"])
+    );
+}
+
+#[tokio::test]
+async fn test_job_tools_require_an_image_store() {
+    let server = McpServer::new("test-key".to_string());
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(!names.contains(&"submit_image_job".to_string()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let store =
+        google_gemini_image_creator::infrastructure::storage::FsImageStore::open(dir.path())
+            .unwrap();
+    let server = server.with_image_store(Arc::new(store));
+    let tools = server.list_tools();
+    let submit = tools.iter().find(|t| t.name == "submit_image_job").unwrap();
+    let properties = &submit.input_schema.as_ref().unwrap()["properties"];
+    assert!(properties.get("prompt").is_some());
+    assert!(properties.get("output_path").is_none());
+    assert!(tools.iter().any(|t| t.name == "get_image_job"));
+    assert!(tools.iter().any(|t| t.name == "cancel_image_job"));
+
+    let err = server
+        .call_tool("get_image_job", &serde_json::json!({ "job_id": "missing" }))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Job not found"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_generate_batch_requires_somewhere_to_save_images() {
+    let server = McpServer::new("test-key".to_string());
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(!names.contains(&"generate_batch".to_string()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let store =
+        google_gemini_image_creator::infrastructure::storage::FsImageStore::open(dir.path())
+            .unwrap();
+    let server = server.with_image_store(Arc::new(store));
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"generate_batch".to_string()));
+
+    let err = server
+        .call_tool(
+            "generate_batch",
+            &serde_json::json!({ "batch_id": "../x", "items": ["a"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid batch_id"), "{}", err);
+    let err = server
+        .call_tool("generate_batch", &serde_json::json!({}))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("items or input"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_gemini_batch_results_are_saved_to_the_image_store_once() {
+    let mut api = mockito::Server::new_async().await;
+    let text = |result: CallToolResult| match &result.content[0] {
+        Content::Text { text } => serde_json::from_str::<serde_json::Value>(text).unwrap(),
+        _ => panic!("expected text content"),
+    };
+    // 送信されたリクエストのキーを結果に使う
+    let keys = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let captured = Arc::clone(&keys);
+    api.mock(
+        "POST",
+        "/models/gemini-2.5-flash-image:batchGenerateContent",
+    )
+    .with_header("content-type", "application/json")
+    .with_body_from_request(move |request| {
+        let body: serde_json::Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+        let requests = body["batch"]["inputConfig"]["requests"]["requests"]
+            .as_array()
+            .unwrap();
+        *captured.lock().unwrap() = requests
+            .iter()
+            .map(|r| r["metadata"]["key"].as_str().unwrap().to_string())
+            .collect();
+        br#"{"name":"batches/b1","metadata":{"state":"BATCH_STATE_PENDING"}}"#.to_vec()
+    })
+    .create_async()
+    .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let store =
+        google_gemini_image_creator::infrastructure::storage::FsImageStore::open(dir.path())
+            .unwrap();
+    let server = McpServer::new("test-key".to_string());
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(!names.contains(&"submit_gemini_batch".to_string()));
+    let batch_client = BatchClient::new(
+        Arc::new(ApiKeyPool::new(vec!["batch-key".to_string()])),
+        api.url(),
+    );
+    let server = server
+        .with_image_store(Arc::new(store))
+        .with_batch_client(Arc::new(batch_client));
+    let names: Vec<String> = server.list_tools().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"submit_gemini_batch".to_string()));
+    assert!(names.contains(&"get_gemini_batch".to_string()));
+    assert!(names.contains(&"cancel_gemini_batch".to_string()));
+
+    let result = server
+        .call_tool(
+            "submit_gemini_batch",
+            &serde_json::json!({
+                "items": ["a red fox", "a blue whale"],
+                "model": "gemini-2.5-flash-image"
+            }),
+        )
+        .await
+        .unwrap();
+    let job = text(result);
+    assert_eq!(job["name"], "batches/b1");
+    assert_eq!(job["state"], "pending");
+
+    // 1件目は画像、2件目はエラーを返す
+    let keys = keys.lock().unwrap().clone();
+    assert_eq!(keys.len(), 2);
+    let image = serde_json::json!({
+        "candidates": [{
+            "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }] }
+        }],
+        "usageMetadata": { "promptTokenCount": 5, "candidatesTokenCount": 1290, "totalTokenCount": 1295 }
+    });
+    api.mock("GET", "/batches/b1")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "name": "batches/b1",
+                "done": true,
+                "metadata": { "model": "models/gemini-2.5-flash-image", "state": "BATCH_STATE_SUCCEEDED" },
+                "response": { "inlinedResponses": { "inlinedResponses": [
+                    { "response": image, "metadata": { "key": keys[0] } },
+                    { "error": { "message": "prompt blocked" }, "metadata": { "key": keys[1] } }
+                ] } }
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    // 2回目は保存済みの結果を返す（画像・使用量を重複して数えない）
+    for _ in 0..2 {
+        let result = server
+            .call_tool(
+                "get_gemini_batch",
+                &serde_json::json!({ "name": "batches/b1" }),
+            )
+            .await
+            .unwrap();
+        let status = text(result);
+        assert_eq!(status["state"], "succeeded");
+        let results = &status["results"];
+        assert_eq!(results["total"], 2);
+        assert_eq!(results["succeeded"], 1);
+        assert_eq!(results["failed"], 1);
+        assert_eq!(results["items"][0]["prompt"], "a red fox");
+        assert!(results["items"][0]["output"]["resource_uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("image://"));
+        assert!(results["items"][1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("prompt blocked"));
+    }
+    assert_eq!(server.list_resources().await.unwrap().len(), 1);
+    let report = server
+        .call_tool("usage_report", &serde_json::json!({}))
+        .await
+        .unwrap();
+    let report = text(report);
+    assert_eq!(report["session"]["images"], 1);
+}
+
+#[tokio::test]
+async fn test_gemini_batches_are_resumed_from_the_manifest_dir() {
+    let mut api = mockito::Server::new_async().await;
+    let keys = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let captured = Arc::clone(&keys);
+    api.mock(
+        "POST",
+        "/models/gemini-2.5-flash-image:batchGenerateContent",
+    )
+    .match_header("x-goog-api-key", "second-key")
+    .with_header("content-type", "application/json")
+    .with_body_from_request(move |request| {
+        let body: serde_json::Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+        *captured.lock().unwrap() = body["batch"]["inputConfig"]["requests"]["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["metadata"]["key"].as_str().unwrap().to_string())
+            .collect();
+        br#"{"name":"batches/b2","metadata":{"state":"BATCH_STATE_PENDING"}}"#.to_vec()
+    })
+    .create_async()
+    .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let manifest_dir = dir.path().join("manifests");
+    let url = api.url();
+    let server = |keys: Vec<&str>| {
+        let store = google_gemini_image_creator::infrastructure::storage::FsImageStore::open(
+            dir.path().join("images"),
+        )
+        .unwrap();
+        let pool = Arc::new(ApiKeyPool::new(
+            keys.into_iter().map(str::to_string).collect(),
+        ));
+        McpServer::new("test-key".to_string())
+            .with_image_store(Arc::new(store))
+            .with_batch_client(Arc::new(BatchClient::new(pool, url.clone())))
+            .with_batch_manifest_dir(&manifest_dir)
+    };
+
+    // 送信したプロセスとは別のプロセス（キーの順番も異なる）で結果を取得する
+    let job = server(vec!["second-key"])
+        .submit_gemini_batch(
+            vec![BatchItem::new("a red fox".to_string())],
+            Some("gemini-2.5-flash-image".to_string()),
+            BatchInputMode::Inline,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(job.name, "batches/b2");
+    let key = keys.lock().unwrap()[0].clone();
+    let status = api
+        .mock("GET", "/batches/b2")
+        .match_header("x-goog-api-key", "second-key")
+        .with_header("content-type", "application/json")
+        .with_body(
+            serde_json::json!({
+                "name": "batches/b2",
+                "done": true,
+                "metadata": { "model": "models/gemini-2.5-flash-image", "state": "BATCH_STATE_SUCCEEDED" },
+                "response": { "inlinedResponses": { "inlinedResponses": [{
+                    "response": {
+                        "candidates": [{
+                            "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "AQID" } }] }
+                        }]
+                    },
+                    "metadata": { "key": key }
+                }] } }
+            })
+            .to_string(),
+        )
+        .expect(2)
+        .create_async()
+        .await;
+
+    // 結果を保存した後のプロセスは保存済みの一覧を返す（画像を重複して保存しない）
+    for _ in 0..2 {
+        let restarted = server(vec!["first-key", "second-key"]);
+        let (_, manifest) = restarted.wait_gemini_batch("batches/b2").await.unwrap();
+        assert_eq!(manifest.succeeded, 1);
+        assert_eq!(manifest.items[0].prompt, "a red fox");
+        assert_eq!(restarted.list_resources().await.unwrap().len(), 1);
+    }
+    status.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_generate_image_resolves_style_and_subject_images() {
+    use google_gemini_image_creator::domain::{GeminiModel, ImageMetadata, ImageStore};
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = Arc::new(
+        google_gemini_image_creator::infrastructure::storage::FsImageStore::open(dir.path())
+            .unwrap(),
+    );
+    let request = ImageGenerationRequest::new("a mascot".to_string());
+    let image = GeneratedImage::new(vec![1, 2, 3], GeminiModel::default());
+    let stored = store
+        .put(&image, &ImageMetadata::new(&request, &image))
+        .await
+        .unwrap();
+    let server = McpServer::new("test-key".to_string()).with_image_store(store);
+
+    let tools = server.list_tools();
+    let schema = tools[0].input_schema.as_ref().unwrap();
+    assert_eq!(schema["properties"]["style_images"]["type"], "array");
+    assert_eq!(schema["properties"]["subject_images"]["type"], "array");
+
+    // 参照画像を読み込めない場合はどの引数か示す（IDの形式が正しくない場合はストアを参照しない）
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({ "prompt": "a mascot", "subject_images": ["missing"] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Invalid subject_images[0]: Invalid image id: missing"),
+        "{}",
+        err
+    );
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({ "prompt": "a mascot", "subject_images": ["0".repeat(64)] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Invalid subject_images[0]: Image not found"),
+        "{}",
+        err
+    );
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({ "prompt": "a mascot", "style_images": [{ "data": "AQID" }] }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("mime_type"), "{}", err);
+
+    // 読み込んだ参照画像の数はモデルの上限で検証される（APIは呼ばない）
+    let err = server
+        .call_tool(
+            "generate_image",
+            &serde_json::json!({
+                "prompt": "a mascot",
+                "model": "gemini-2.5-flash-image",
+                "style_images": [stored.resource_uri(), { "image_id": stored.id }],
+                "subject_images": [
+                    stored.id,
+                    { "data": "AQID", "mime_type": "image/png" }
+                ]
+            }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("at most 3"), "{}", err);
 }
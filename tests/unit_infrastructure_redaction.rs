@@ -0,0 +1,89 @@
+use google_gemini_image_creator::infrastructure::redaction::{
+    redact, register_secret, replace_secret, unregister_secret, RedactingMakeWriter, REDACTED,
+};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+
+#[test]
+fn test_redact_registered_secrets() {
+    register_secret("registered-secret-value");
+    // 短すぎる値は登録しない
+    register_secret("short");
+
+    let text = redact("auth failed for registered-secret-value (short)");
+    assert_eq!(text, format!("auth failed for {} (short)", REDACTED));
+}
+
+#[test]
+fn test_replaced_secrets_are_no_longer_redacted() {
+    register_secret("first-access-token");
+    replace_secret("first-access-token", "second-access-token");
+    assert_eq!(
+        redact("first-access-token second-access-token"),
+        format!("first-access-token {}", REDACTED)
+    );
+
+    unregister_secret("second-access-token");
+    assert_eq!(redact("second-access-token"), "second-access-token");
+}
+
+#[test]
+fn test_redact_key_query_parameter() {
+    let text = redact(
+        "error sending request for url (https://example.com/v1/models/x:generateContent?key=abc123&alt=json)",
+    );
+    assert!(
+        text.contains(&format!("?key={}&alt=json", REDACTED)),
+        "{}",
+        text
+    );
+    assert!(!text.contains("abc123"));
+
+    // クエリパラメータでない`key=`はそのまま
+    assert_eq!(redact("api_key=value"), "api_key=value");
+}
+
+#[test]
+fn test_redact_google_api_key_pattern() {
+    let key = format!("AIza{}", "a".repeat(35));
+    let text = redact(&format!("using key {} now", key));
+    assert_eq!(text, format!("using key {} now", REDACTED));
+
+    // 長さが合わない値は置き換えない
+    let not_a_key = format!("AIza{}", "b".repeat(20));
+    assert_eq!(redact(&not_a_key), not_a_key);
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_redacting_make_writer() {
+    register_secret("logged-secret-value");
+    let buffer = SharedBuffer::default();
+    let make_writer = {
+        let buffer = buffer.clone();
+        RedactingMakeWriter::new(move || buffer.clone())
+    };
+
+    let mut writer = make_writer.make_writer();
+    writer
+        .write_all(b"calling API with logged-secret-value\n")
+        .unwrap();
+    writer.flush().unwrap();
+
+    let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(written, format!("calling API with {}\n", REDACTED));
+}
@@ -0,0 +1,105 @@
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationRequest, ImageMetadata,
+};
+use google_gemini_image_creator::infrastructure::storage::{embed_metadata, EmbedError};
+
+/// 1x1ピクセルのPNG
+const TINY_PNG: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4,
+    0x89, 0x00, 0x00, 0x00, 0x0B, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0x7A, 0x5E, 0xAB, 0x3F, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44,
+    0xAE, 0x42, 0x60, 0x82,
+];
+
+fn metadata() -> ImageMetadata {
+    let request = ImageGenerationRequest::new("夕焼けの富士山".to_string());
+    let image = GeneratedImage::new(
+        TINY_PNG.to_vec(),
+        GeminiModel::from("gemini-2.5-flash-image".to_string()),
+    );
+    ImageMetadata::new(&request, &image)
+}
+
+/// PNGのチャンクを（種別, データ）の列として読み出し、CRCも検証する
+fn png_chunks(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos < data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let chunk_type = String::from_utf8(data[pos + 4..pos + 8].to_vec()).unwrap();
+        let body = data[pos + 8..pos + 8 + len].to_vec();
+        let crc = u32::from_be_bytes(data[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+        assert_eq!(
+            crc,
+            crc32(&data[pos + 4..pos + 8 + len]),
+            "bad CRC in {}",
+            chunk_type
+        );
+        chunks.push((chunk_type, body));
+        pos += 12 + len;
+    }
+    chunks
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_embed_png_adds_text_chunks_after_ihdr() {
+    let metadata = metadata();
+    let output = embed_metadata(TINY_PNG, "image/png", &metadata).unwrap();
+
+    let chunks = png_chunks(&output);
+    let types: Vec<&str> = chunks.iter().map(|(t, _)| t.as_str()).collect();
+    assert_eq!(types, vec!["IHDR", "tEXt", "iTXt", "iTXt", "IDAT", "IEND"]);
+
+    let description = String::from_utf8_lossy(&chunks[2].1).to_string();
+    assert!(description.starts_with("Description"));
+    assert!(description.contains("夕焼けの富士山"));
+
+    let json_chunk = &chunks[3].1;
+    let json_start = json_chunk.iter().position(|&b| b == b'{').unwrap();
+    let embedded: ImageMetadata = serde_json::from_slice(&json_chunk[json_start..]).unwrap();
+    assert_eq!(embedded, metadata);
+}
+
+#[test]
+fn test_embed_jpeg_inserts_xmp_segment() {
+    let jpeg = [0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x02, 0xFF, 0xD9];
+    let output = embed_metadata(&jpeg, "image/jpeg", &metadata()).unwrap();
+
+    assert_eq!(&output[..4], &[0xFF, 0xD8, 0xFF, 0xE1]);
+    let segment_len = u16::from_be_bytes([output[4], output[5]]) as usize;
+    let segment = &output[6..4 + segment_len];
+    assert!(segment.starts_with(b"http://ns.adobe.com/xap/1.0/\0"));
+    let xmp = String::from_utf8_lossy(segment);
+    assert!(xmp.contains("夕焼けの富士山"));
+    assert!(xmp.contains("<gic:Model>gemini-2.5-flash-image</gic:Model>"));
+    assert_eq!(&output[4 + segment_len..], &jpeg[2..]);
+}
+
+#[test]
+fn test_embed_rejects_unsupported_or_malformed_images() {
+    let metadata = metadata();
+    assert!(matches!(
+        embed_metadata(b"RIFF", "image/webp", &metadata),
+        Err(EmbedError::UnsupportedFormat(_))
+    ));
+    assert!(matches!(
+        embed_metadata(b"not a png", "image/png", &metadata),
+        Err(EmbedError::MalformedImage(_))
+    ));
+}
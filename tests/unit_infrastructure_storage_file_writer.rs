@@ -0,0 +1,170 @@
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationRequest, ImageMetadata,
+};
+use google_gemini_image_creator::infrastructure::storage::{ImageFileWriter, StorageError};
+
+fn jpeg_image() -> GeneratedImage {
+    GeneratedImage::new(
+        vec![0xFF, 0xD8, 0xFF],
+        GeminiModel::from("gemini-2.5-flash-image".to_string()),
+    )
+    .with_mime_type("image/jpeg".to_string())
+}
+
+fn metadata_for(image: &GeneratedImage) -> ImageMetadata {
+    let request = ImageGenerationRequest::new("a red logo".to_string());
+    ImageMetadata::new(&request, image)
+}
+
+#[tokio::test]
+async fn test_write_uses_extension_from_mime_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+
+    let path = writer
+        .write(&image, &metadata, Some("logos"), Some("brand.png"))
+        .await
+        .unwrap();
+
+    assert!(path.is_absolute());
+    assert!(path.starts_with(writer.root()));
+    assert_eq!(path.file_name().unwrap(), "brand.jpg");
+    assert_eq!(path.parent().unwrap().file_name().unwrap(), "logos");
+    assert_eq!(std::fs::read(&path).unwrap(), vec![0xFF, 0xD8, 0xFF]);
+}
+
+#[tokio::test]
+async fn test_write_does_not_overwrite_existing_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+
+    let first = writer
+        .write(&image, &metadata, None, Some("same"))
+        .await
+        .unwrap();
+    let second = writer
+        .write(&image, &metadata, None, Some("same"))
+        .await
+        .unwrap();
+
+    assert_ne!(first, second);
+    assert_eq!(second.file_name().unwrap(), "same-1.jpg");
+}
+
+#[tokio::test]
+async fn test_write_rejects_path_traversal() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path().join("out")).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+
+    let result = writer
+        .write(&image, &metadata, Some("../escape"), None)
+        .await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+
+    let result = writer.write(&image, &metadata, Some("/tmp"), None).await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+
+    let result = writer
+        .write(&image, &metadata, None, Some("../escape.png"))
+        .await;
+    assert!(matches!(result, Err(StorageError::InvalidFilename(_))));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_write_rejects_symlink_escape() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+    let result = writer.write(&image, &metadata, Some("link"), None).await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_write_does_not_create_directories_through_symlink() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+    // 拒否する前にルート外にディレクトリを作らない
+    let result = writer
+        .write(&image, &metadata, Some("link/nested/deeper"), None)
+        .await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+    assert!(!outside.path().join("nested").exists());
+
+    // ルート配下の足りないディレクトリは作成する
+    let path = writer
+        .write(&image, &metadata, Some("campaign/2025"), None)
+        .await
+        .unwrap();
+    assert!(path.starts_with(dir.path().canonicalize().unwrap().join("campaign/2025")));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_write_rejects_sidecar_symlink_escape() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let target = outside.path().join("victim.json");
+    std::fs::write(&target, "original").unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+    std::os::unix::fs::symlink(&target, dir.path().join("brand.jpg.json")).unwrap();
+
+    let result = writer.write(&image, &metadata, None, Some("brand")).await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "original");
+    assert!(!dir.path().join("brand.jpg").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_write_rejects_sidecar_symlink_within_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("notes.json");
+    std::fs::write(&target, "original").unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+    std::os::unix::fs::symlink(&target, dir.path().join("brand.jpg.json")).unwrap();
+
+    let result = writer.write(&image, &metadata, None, Some("brand")).await;
+    assert!(matches!(result, Err(StorageError::PathOutsideRoot(_))));
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "original");
+    assert!(!dir.path().join("brand.jpg").exists());
+}
+
+#[tokio::test]
+async fn test_write_creates_sidecar_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = ImageFileWriter::new(dir.path()).unwrap();
+    let image = jpeg_image();
+    let metadata = metadata_for(&image);
+
+    let path = writer
+        .write(&image, &metadata, None, Some("logo"))
+        .await
+        .unwrap();
+
+    let sidecar = path.with_file_name("logo.jpg.json");
+    let saved: ImageMetadata = serde_json::from_slice(&std::fs::read(sidecar).unwrap()).unwrap();
+    assert_eq!(saved, metadata);
+    assert_eq!(saved.prompt, "a red logo");
+    assert_eq!(saved.model.as_str(), "gemini-2.5-flash-image");
+}
@@ -0,0 +1,189 @@
+use google_gemini_image_creator::domain::{
+    GeminiModel, GeneratedImage, ImageGenerationRequest, ImageMetadata, ImageStore, RetentionPolicy,
+};
+use google_gemini_image_creator::infrastructure::storage::FsImageStore;
+
+fn image_with(data: Vec<u8>) -> (GeneratedImage, ImageMetadata) {
+    let request = ImageGenerationRequest::new("a cat".to_string());
+    let image = GeneratedImage::new(
+        data,
+        GeminiModel::from("gemini-2.5-flash-image".to_string()),
+    );
+    let metadata = ImageMetadata::new(&request, &image);
+    (image, metadata)
+}
+
+#[tokio::test]
+async fn test_put_stores_by_content_hash_and_deduplicates() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path()).unwrap();
+
+    let (image, first) = image_with(vec![1, 2, 3]);
+    let (_, second) = image_with(vec![1, 2, 3]);
+    let stored1 = store.put(&image, &first).await.unwrap();
+    let stored2 = store.put(&image, &second).await.unwrap();
+
+    assert_eq!(stored1.id.len(), 64);
+    assert_eq!(stored1.id, stored2.id);
+    assert_eq!(
+        stored2.request_ids,
+        vec![first.request_id, second.request_id]
+    );
+    assert_eq!(store.list().await.unwrap().len(), 1);
+    assert!(stored1.location.ends_with(".png"));
+
+    let (entry, data) = store.get(&stored1.id).await.unwrap().unwrap();
+    assert_eq!(data, vec![1, 2, 3]);
+    assert_eq!(entry.metadata.prompt, "a cat");
+}
+
+#[tokio::test]
+async fn test_index_persists_across_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let (image, metadata) = image_with(vec![9, 9, 9]);
+    let id = {
+        let store = FsImageStore::open(dir.path()).unwrap();
+        store.put(&image, &metadata).await.unwrap().id
+    };
+
+    let reopened = FsImageStore::open(dir.path()).unwrap();
+    let (entry, data) = reopened.get(&id).await.unwrap().unwrap();
+    assert_eq!(data, vec![9, 9, 9]);
+    assert_eq!(entry.metadata, metadata);
+
+    assert!(reopened.delete(&id).await.unwrap());
+    assert!(reopened.get(&id).await.unwrap().is_none());
+    assert!(!reopened.delete(&id).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_put_appends_to_the_journal_instead_of_rewriting_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path()).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..3u8 {
+        let (image, metadata) = image_with(vec![i, i, i]);
+        ids.push(store.put(&image, &metadata).await.unwrap().id);
+    }
+    assert!(store.delete(&ids[0]).await.unwrap());
+
+    assert!(!dir.path().join("index.json").exists());
+    let journal = std::fs::read_to_string(dir.path().join("index.journal")).unwrap();
+    assert_eq!(journal.lines().count(), 4);
+
+    // 書き込み中に中断した行は読み飛ばし、それ以降の追記と混ざらない
+    std::fs::write(
+        dir.path().join("index.journal"),
+        format!("{}{{\"put\":", journal),
+    )
+    .unwrap();
+    let reopened = FsImageStore::open(dir.path()).unwrap();
+    assert!(reopened.get(&ids[0]).await.unwrap().is_none());
+    assert!(reopened.get(&ids[2]).await.unwrap().is_some());
+    let (image, metadata) = image_with(vec![7, 7, 7]);
+    let id = reopened.put(&image, &metadata).await.unwrap().id;
+
+    let reopened = FsImageStore::open(dir.path()).unwrap();
+    assert_eq!(reopened.list().await.unwrap().len(), 3);
+    assert!(reopened.get(&id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_journal_is_compacted_into_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path()).unwrap();
+    for i in 0..=256u32 {
+        let (image, metadata) = image_with(i.to_be_bytes().to_vec());
+        store.put(&image, &metadata).await.unwrap();
+    }
+
+    assert!(dir.path().join("index.json").exists());
+    assert!(!dir.path().join("index.journal").exists());
+    let reopened = FsImageStore::open(dir.path()).unwrap();
+    assert_eq!(reopened.list().await.unwrap().len(), 257);
+}
+
+#[tokio::test]
+async fn test_garbage_collection_enforces_size_quota() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path()).unwrap();
+
+    let mut ids = Vec::new();
+    for i in 0..3u8 {
+        let (image, metadata) = image_with(vec![i; 10]);
+        ids.push(store.put(&image, &metadata).await.unwrap().id);
+    }
+
+    let report = store
+        .collect_garbage(&RetentionPolicy {
+            max_age: None,
+            max_total_bytes: Some(20),
+        })
+        .await
+        .unwrap();
+
+    // 最も古い画像から削除される
+    assert_eq!(report.removed_ids, vec![ids[0].clone()]);
+    assert_eq!(report.freed_bytes, 10);
+    assert_eq!(store.list().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_garbage_collection_by_age_and_automatic_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path()).unwrap();
+    let (image, metadata) = image_with(vec![5; 4]);
+    store.put(&image, &metadata).await.unwrap();
+
+    let report = store
+        .collect_garbage(&RetentionPolicy {
+            max_age: Some(chrono::Duration::zero()),
+            max_total_bytes: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(report.removed_ids.len(), 1);
+    assert!(store.list().await.unwrap().is_empty());
+
+    // 保持ポリシーを設定したストアは保存のたびに上限を適用する
+    let bounded = FsImageStore::open(dir.path().join("bounded"))
+        .unwrap()
+        .with_retention(RetentionPolicy {
+            max_age: None,
+            max_total_bytes: Some(8),
+        });
+    for i in 0..3u8 {
+        let (image, metadata) = image_with(vec![i; 4]);
+        bounded.put(&image, &metadata).await.unwrap();
+    }
+    assert_eq!(bounded.list().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_put_rejects_images_larger_than_the_quota() {
+    use google_gemini_image_creator::domain::ImageStoreError;
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsImageStore::open(dir.path())
+        .unwrap()
+        .with_retention(RetentionPolicy {
+            max_age: None,
+            max_total_bytes: Some(8),
+        });
+    let (small, metadata) = image_with(vec![1; 4]);
+    store.put(&small, &metadata).await.unwrap();
+
+    // 保存してもすぐに削除される画像は保存済みとして返さず、既存の画像も消さない
+    let (large, metadata) = image_with(vec![2; 16]);
+    let err = store.put(&large, &metadata).await.unwrap_err();
+    assert!(matches!(
+        err,
+        ImageStoreError::TooLarge {
+            size_bytes: 16,
+            max_bytes: 8
+        }
+    ));
+    let entries = store.list().await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].size_bytes, 4);
+}